@@ -0,0 +1,139 @@
+//! Criterion benchmarks that run on stable Rust.
+//!
+//! Run with `cargo bench --bench evaluation`. Group and benchmark names are
+//! kept stable so that results can be compared across revisions:
+//!
+//! * `parse/<comment>` and `search/<comment>` mirror the cases in
+//!   tests/compliance/benchmarks.json.
+//! * `validate_vs_compile/validate/<comment>` and
+//!   `validate_vs_compile/compile/<comment>` compare `validate`, which parses
+//!   and checks function calls, with `compile`, which parses and wraps the
+//!   AST in an `Expression`, for the same parse cases. Both parse in full.
+//! * `projection_filter/<size>` filters and projects generated arrays.
+//! * `functions/<name>` runs function-heavy pipelines.
+
+#[macro_use]
+extern crate criterion;
+extern crate jmespath;
+extern crate serde_json;
+
+use std::fs::File;
+use std::io::Read;
+
+use criterion::{BenchmarkId, Criterion, Throughput};
+use jmespath::{compile, parse, validate, Rcvar, Variable};
+use serde_json::Value;
+
+/// A benchmark case from the compliance benchmark suite.
+struct Case {
+    comment: String,
+    expression: String,
+    bench: String,
+    given: Rcvar,
+}
+
+fn load_cases() -> Vec<Case> {
+    let mut data = String::new();
+    File::open("tests/compliance/benchmarks.json")
+        .and_then(|mut f| f.read_to_string(&mut data))
+        .expect("Unable to read benchmarks");
+    let suites: Value = serde_json::from_str(&data).expect("Invalid benchmark JSON");
+    let mut cases = vec![];
+    for suite in suites.as_array().unwrap() {
+        let given = Rcvar::new(Variable::from(&suite["given"]));
+        for case in suite["cases"].as_array().unwrap() {
+            cases.push(Case {
+                comment: case["comment"].as_str().unwrap().to_owned(),
+                expression: case["expression"].as_str().unwrap().to_owned(),
+                bench: case["bench"].as_str().unwrap().to_owned(),
+                given: given.clone(),
+            });
+        }
+    }
+    cases
+}
+
+/// Generates an array of `size` objects such as `{"id": 1, "name": "n1", "even": false}`.
+fn generate_array(size: usize) -> Rcvar {
+    let values = (0..size).map(|i| Rcvar::new(Variable::from(json_object(i)))).collect();
+    Rcvar::new(Variable::Array(values))
+}
+
+fn json_object(i: usize) -> Value {
+    let mut map = serde_json::Map::new();
+    map.insert("id".to_owned(), Value::from(i));
+    map.insert("name".to_owned(), Value::from(format!("n{}", i)));
+    map.insert("even".to_owned(), Value::from(i % 2 == 0));
+    Value::Object(map)
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for case in load_cases().iter().filter(|c| c.bench == "parse" || c.bench == "full") {
+        group.bench_function(&case.comment, |b| b.iter(|| parse(&case.expression).unwrap()));
+    }
+    group.finish();
+}
+
+fn bench_validate_vs_compile(c: &mut Criterion) {
+    let mut group = c.benchmark_group("validate_vs_compile");
+    for case in load_cases().iter().filter(|c| c.bench == "parse" || c.bench == "full") {
+        group.bench_function(BenchmarkId::new("validate", &case.comment),
+                             |b| b.iter(|| validate(&case.expression).unwrap()));
+        group.bench_function(BenchmarkId::new("compile", &case.comment),
+                             |b| b.iter(|| compile(&case.expression).unwrap()));
+    }
+    group.finish();
+}
+
+fn bench_search(c: &mut Criterion) {
+    let mut group = c.benchmark_group("search");
+    for case in load_cases().iter().filter(|c| c.bench == "interpret" || c.bench == "full") {
+        let expr = compile(&case.expression).unwrap();
+        // Some "full" cases, such as "lots of summing", time an evaluation error.
+        group.bench_function(&case.comment, |b| b.iter(|| expr.search(&case.given)));
+    }
+    group.finish();
+}
+
+fn bench_projection_filter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("projection_filter");
+    group.sample_size(10);
+    let expr = compile("[?even && id > `10`].name").unwrap();
+    for &size in &[10_000, 100_000, 1_000_000] {
+        let data = generate_array(size);
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &data, |b, data| {
+            b.iter(|| expr.search(data).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_functions(c: &mut Criterion) {
+    let mut group = c.benchmark_group("functions");
+    let data = generate_array(1_000);
+    let pipelines = [("sort_by", "sort_by(@, &name)[*].id"),
+                     ("map", "map(&to_string(id), @)"),
+                     ("to_string", "[*].to_string(@)"),
+                     ("pipeline", "sort_by(@, &name) | map(&id, @) | max(@)"),
+                     // count() avoids materializing the filtered array.
+                     ("count", "count(@, &even)"),
+                     ("length_filter", "length([?even])"),
+                     // partition() scans the array once instead of twice.
+                     ("partition", "partition(@, &even)"),
+                     ("double_filter", "[[?even], [?!even]]")];
+    for &(name, expression) in &pipelines {
+        let expr = compile(expression).unwrap();
+        group.bench_function(name, |b| b.iter(|| expr.search(&data).unwrap()));
+    }
+    group.finish();
+}
+
+criterion_group!(benches,
+                 bench_parse,
+                 bench_validate_vs_compile,
+                 bench_search,
+                 bench_projection_filter,
+                 bench_functions);
+criterion_main!(benches);
@@ -163,8 +163,8 @@ fn generate_test(filename: &str,
 fn test_{}() {{
     let case: TestCase = TestCase::from_str({:?}).unwrap();
     let data = Rcvar::new(Variable::from_json({:?}).unwrap());
-    case.assert({:?}, data).unwrap();
+    case.assert({:?}, {}, data).unwrap();
 }}
 
-", fn_suffix, case_string, given_string, filename).as_bytes()).expect("Unable to write test");
+", fn_suffix, case_string, given_string, filename, case_num).as_bytes()).expect("Unable to write test");
 }
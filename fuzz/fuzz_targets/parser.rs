@@ -0,0 +1,12 @@
+//! Feeds arbitrary UTF-8 input to the parser.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate jmespath;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(expr) = std::str::from_utf8(data) {
+        let _ = jmespath::parse(expr);
+    }
+});
@@ -0,0 +1,33 @@
+//! Runs a full search using an expression and a JSON document.
+//!
+//! The input is split on the first NUL byte: the expression comes before it
+//! and the JSON document after it. Inputs are capped in size so that the
+//! fuzzer spends its time on interesting expressions rather than slow ones.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate jmespath;
+
+/// Maximum number of bytes of input that is evaluated.
+const MAX_INPUT: usize = 4096;
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() > MAX_INPUT {
+        return;
+    }
+    let split = match data.iter().position(|&b| b == 0) {
+        Some(split) => split,
+        None => return,
+    };
+    let (expr, json) = (&data[..split], &data[split + 1..]);
+    let expr = match std::str::from_utf8(expr).ok().and_then(|e| jmespath::compile(e).ok()) {
+        Some(expr) => expr,
+        None => return,
+    };
+    if let Ok(json) = std::str::from_utf8(json) {
+        if let Ok(data) = jmespath::Variable::from_json(json) {
+            let _ = expr.search(data);
+        }
+    }
+});
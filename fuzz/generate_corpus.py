@@ -0,0 +1,55 @@
+#!/usr/bin/env python3
+"""Generates the fuzzing corpus from the seeds and the compliance suite.
+
+The `lexer` and `parser` corpora get every expression of the compliance
+suite, and the `search` corpus gets each expression followed by a NUL byte
+and the JSON document it is evaluated against. The curated inputs in
+`seeds/` are added to the matching corpora. Files are named after the SHA-1
+of their contents, like the files the fuzzer adds, so duplicates collapse.
+
+Run it from anywhere; the corpus is written to `corpus/` next to this file.
+"""
+
+import glob
+import hashlib
+import json
+import os
+
+FUZZ_DIR = os.path.dirname(os.path.abspath(__file__))
+COMPLIANCE_DIR = os.path.join(FUZZ_DIR, "..", "tests", "compliance")
+
+
+def write(target, data):
+    directory = os.path.join(FUZZ_DIR, "corpus", target)
+    os.makedirs(directory, exist_ok=True)
+    name = hashlib.sha1(data).hexdigest()
+    with open(os.path.join(directory, name), "wb") as f:
+        f.write(data)
+
+
+def seeds(name):
+    for path in sorted(glob.glob(os.path.join(FUZZ_DIR, "seeds", name, "*"))):
+        with open(path, "rb") as f:
+            yield f.read()
+
+
+def main():
+    for path in sorted(glob.glob(os.path.join(COMPLIANCE_DIR, "*.json"))):
+        with open(path, encoding="utf-8") as f:
+            suites = json.load(f)
+        for suite in suites:
+            given = json.dumps(suite["given"], separators=(",", ":")).encode("utf-8")
+            for case in suite["cases"]:
+                expression = case["expression"].encode("utf-8")
+                write("lexer", expression)
+                write("parser", expression)
+                write("search", expression + b"\0" + given)
+    for expression in seeds("expressions"):
+        write("lexer", expression)
+        write("parser", expression)
+    for data in seeds("search"):
+        write("search", data)
+
+
+if __name__ == "__main__":
+    main()
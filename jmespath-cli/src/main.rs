@@ -12,10 +12,14 @@ use clap::{Arg, App};
 use jmespath::Rcvar;
 use jmespath::{Variable, compile};
 
+/// Exit code used when the expression cannot be parsed.
+const PARSE_ERROR_CODE: i32 = 2;
+
 macro_rules! die(
-    ($msg:expr) => (
+    ($msg:expr) => (die!(1, $msg));
+    ($code:expr, $msg:expr) => (
         match writeln!(&mut ::std::io::stderr(), "{}", $msg) {
-            Ok(_) => exit(1),
+            Ok(_) => exit($code),
             Err(x) => panic!("Unable to write to stderr: {}", x),
         }
     )
@@ -36,8 +40,7 @@ fn main() {
             .long("unquoted")
             .multiple(false))
         .arg(Arg::with_name("ast")
-            .help("Only print the AST of the parsed expression.  Do not rely on this output, \
-                  only useful for debugging purposes.")
+            .help("Only print the AST of the parsed expression as JSON.")
             .long("ast")
             .multiple(false))
         .arg(Arg::with_name("expr-file")
@@ -62,11 +65,15 @@ fn main() {
         } else {
             compile(matches.value_of("expression").unwrap())
         }
-        .map_err(|e| die!(e.to_string()))
+        .map_err(|e| die!(PARSE_ERROR_CODE, e.to_string()))
         .unwrap();
 
     if matches.is_present("ast") {
-        println!("{:#?}", expr.as_ast());
+        let mut out = io::stdout();
+        serde_json::to_writer_pretty(&mut out, expr.as_ast())
+            .map(|_| out.write(&['\n' as u8]))
+            .map_err(|e| die!(format!("Error converting AST to JSON: {}", e)))
+            .ok();
         exit(0);
     }
 
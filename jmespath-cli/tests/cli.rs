@@ -1,7 +1,25 @@
-use std::process::{Command, Stdio};
+extern crate jmespath;
+extern crate serde_json;
+extern crate tempfile;
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
 
 const JPBIN: &'static str = "target/debug/jp";
 
+/// Runs jp with the given arguments, writing `stdin` to its standard input.
+fn run(args: Vec<&str>, stdin: &str) -> Output {
+    let mut child = Command::new(JPBIN)
+        .args(&args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to spawn process");
+    child.stdin.as_mut().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
 fn get_output(args: Vec<&str>) -> Result<String, String> {
     let mut cmd = Command::new(JPBIN);
     for arg in args {
@@ -18,14 +36,62 @@ fn get_output(args: Vec<&str>) -> Result<String, String> {
 #[test]
 fn prints_ast() {
     let output = get_output(vec!["--ast", "foo"]).unwrap();
-    assert_eq!("Field {\n    offset: 0,\n    name: \"foo\"\n}\n", output);
+    assert_eq!("{\n  \"Field\": {\n    \"offset\": 0,\n    \"name\": \"foo\"\n  }\n}\n", output);
+}
+
+#[test]
+fn prints_ast_as_json_matching_library_serialization() {
+    let expression = "foo[?bar > `1`].baz | sort(@)";
+    let output = get_output(vec!["--ast", expression]).unwrap();
+    let printed: serde_json::Value = serde_json::from_str(&output).unwrap();
+    let ast = jmespath::parse(expression).unwrap();
+    assert_eq!(serde_json::to_value(&ast).unwrap(), printed);
+}
+
+#[test]
+fn exits_with_code_2_and_caret_for_syntax_errors() {
+    let output = run(vec!["foo{"], "{}");
+    assert_eq!(Some(2), output.status.code());
+    assert!(output.stdout.is_empty());
+    assert_eq!("Parse error: Unexpected `{`; expected `.`, `[`, `[?`, `[]`, `|`, `||`, `&&`, \
+                a comparator, an arithmetic operator, or the end of the expression \
+                (line 0, column 3)\nfoo{\n   ^\n\n",
+               String::from_utf8(output.stderr).unwrap());
+}
+
+#[test]
+fn exits_with_code_1_for_runtime_errors() {
+    let output = run(vec!["abs(foo)"], "{\"foo\": \"bar\"}");
+    assert_eq!(Some(1), output.status.code());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Argument 0 expects type number, given string"), "{}", stderr);
+}
+
+#[test]
+fn reads_expression_file_with_trailing_newline() {
+    let mut expr_file = tempfile::NamedTempFile::new().unwrap();
+    expr_file.write_all(b"foo.bar\n").unwrap();
+    let output = run(vec!["-e", expr_file.path().to_str().unwrap()],
+                     "{\"foo\": {\"bar\": [1, 2]}}");
+    assert!(output.status.success());
+    assert_eq!("[\n  1,\n  2\n]\n", String::from_utf8(output.stdout).unwrap());
+}
+
+#[test]
+fn reads_json_from_a_file() {
+    let mut json_file = tempfile::NamedTempFile::new().unwrap();
+    json_file.write_all(b"{\"foo\": true}").unwrap();
+    let output = run(vec!["-f", json_file.path().to_str().unwrap(), "foo"], "");
+    assert!(output.status.success());
+    assert_eq!("true\n", String::from_utf8(output.stdout).unwrap());
 }
 
 #[test]
 fn shows_parse_error_information_with_non_zero_rc() {
     let output = get_output(vec!["--ast", "foo{"]).unwrap_err();
-    assert_eq!("Parse error: Unexpected led token -- found Lbrace (line 0, column 3)\nfoo{\
-               \n   ^\n\n",
+    assert_eq!("Parse error: Unexpected `{`; expected `.`, `[`, `[?`, `[]`, `|`, `||`, `&&`, \
+                a comparator, an arithmetic operator, or the end of the expression \
+                (line 0, column 3)\nfoo{\n   ^\n\n",
                output);
 }
 
@@ -1,22 +1,21 @@
-//! This crate provides the `jmespath!` macro used to statically
-//! compile JMESPath expressions.
+//! This crate provides the `jmespath!` macro, which checks JMESPath
+//! expressions at compile time.
 //!
-//! By statically compiling JMESPath expressions, you pay the cost of
-//! parsing and compiling JMESPath expressions at compile time rather
-//! than at runtime, and you can be sure that the expression is valid
-//! if your program compiles.
-//!
-//! Note: This only works with a nightly compiler.
+//! The macro takes a single string literal and expands to an
+//! `Rc<jmespath::Expression>` of the expression, compiled with
+//! `jmespath::compile`. The literal is checked with `jmespath::validate`,
+//! so an expression with a syntax error, a call to an unknown function, or
+//! a call with the wrong number of arguments fails the build with the same
+//! message and position that `jmespath::validate` would report, and a typo
+//! can not make it to runtime.
 //!
 //! ```
-//! #![feature(plugin)]
-//!
-//! #![plugin(jmespath-macros)]
+//! #[macro_use]
+//! extern crate jmespath_macros;
 //! extern crate jmespath;
 //!
 //! fn main() {
-//!     // Create our statically compiled expression. The build will fail
-//!     // if the expression is invalid.
+//!     // The build fails if the expression is invalid.
 //!     let expr = jmespath!("foo.bar");
 //!
 //!     // Parse some JSON data into a JMESPath variable
@@ -26,357 +25,150 @@
 //!     assert_eq!(true, result.as_boolean().unwrap());
 //! }
 //! ```
+//!
+//! The expression is parsed the first time the macro is evaluated on a
+//! thread and cached, so evaluating the macro again, e.g., in a loop, only
+//! clones the `Rc`. Expressions are not `Sync` unless the `sync` feature
+//! of `jmespath` is enabled, so the cache is kept per thread.
+//!
+//! Malformed expressions are compile errors:
+//!
+//! ```compile_fail
+//! #[macro_use]
+//! extern crate jmespath_macros;
+//! extern crate jmespath;
+//!
+//! fn main() {
+//!     let expr = jmespath!("foo.bar[?baz > ]");
+//! }
+//! ```
+//!
+//! So are calls to unknown functions and calls with the wrong number of
+//! arguments:
+//!
+//! ```compile_fail
+//! #[macro_use]
+//! extern crate jmespath_macros;
+//! extern crate jmespath;
+//!
+//! fn main() {
+//!     let expr = jmespath!("foo.lenght(bar)");
+//! }
+//! ```
+//!
+//! ```compile_fail
+//! #[macro_use]
+//! extern crate jmespath_macros;
+//! extern crate jmespath;
+//!
+//! fn main() {
+//!     let expr = jmespath!("length(foo, bar)");
+//! }
+//! ```
+//!
+//! So is anything but a single string literal:
+//!
+//! ```compile_fail
+//! #[macro_use]
+//! extern crate jmespath_macros;
+//! extern crate jmespath;
+//!
+//! fn main() {
+//!     let source = "foo.bar";
+//!     let expr = jmespath!(source);
+//! }
+//! ```
 
-#![crate_type="dylib"]
-#![feature(plugin_registrar, quote, rustc_private)]
-
-extern crate syntax;
-extern crate rustc;
-extern crate rustc_plugin;
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
 
 extern crate jmespath;
 
-use syntax::ast;
-use syntax::codemap;
-use syntax::ext::base::{ExtCtxt, MacResult, MacEager, DummyResult};
-use syntax::parse::token;
-use syntax::print::pprust;
-use syntax::tokenstream;
-use syntax::fold::Folder;
-use rustc_plugin::Registry;
-use syntax::ptr::P;
-
-use jmespath::{Variable, Rcvar};
-use jmespath::ast::{Ast, Comparator};
+use proc_macro2::{Span, TokenStream};
+use syn::LitStr;
 
-#[plugin_registrar]
-pub fn plugin_registrar(reg: &mut Registry) {
-    reg.register_macro("jmespath", expand_jp);
+/// Compiles a JMESPath expression that is checked at compile time.
+///
+/// See the crate documentation for an example.
+#[proc_macro]
+pub fn jmespath(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    expand(input.into()).into()
 }
 
-fn expand_jp(cx: &mut ExtCtxt,
-             sp: codemap::Span,
-             tts: &[tokenstream::TokenTree])
-             -> Box<MacResult + 'static> {
-    // Parse the arguments of the macro.
-    let expression_str = match parse(cx, tts) {
-        Some(e) => e,
-        None => return DummyResult::any(sp),
-    };
-
-    // Parse the expression and show an error if needed.
-    let ast = match jmespath::parse(&expression_str) {
-        Ok(e) => e,
+/// Expands the macro, or to a compile error if the input is not a valid
+/// expression.
+fn expand(input: TokenStream) -> TokenStream {
+    let literal = match syn::parse2::<LitStr>(input) {
+        Ok(literal) => literal,
         Err(err) => {
-            cx.span_err(sp, &format!("jmespath! error: {}", err));
-            return DummyResult::any(sp);
+            return compile_error(err.span(), "jmespath! expects a single string literal");
         }
     };
-
-    let jmespath_ast = generate_ast(cx, &ast);
-
-    MacEager::expr(quote_expr!(cx, {
-        use ::jmespath::ast::Ast;
-        use ::jmespath::{Expression, DEFAULT_RUNTIME};
-        Expression::new($expression_str, $jmespath_ast, &DEFAULT_RUNTIME)
-    }))
-}
-
-/// Looks for a single string literal and returns it.
-///
-/// Based on rust-regex macro: https://github.com/rust-lang-nursery/regex
-fn parse(cx: &mut ExtCtxt, tts: &[tokenstream::TokenTree]) -> Option<String> {
-    let mut parser = cx.new_parser_from_tts(tts);
-    if let Ok(expr) = parser.parse_expr() {
-        let entry = cx.expander().fold_expr(expr);
-        let expr = match entry.node {
-            ast::ExprKind::Lit(ref lit) => {
-                match lit.node {
-                    ast::LitKind::Str(ref s, _) => s.to_string(),
-                    _ => {
-                        let message = &format!("expected string literal but got `{}`",
-                                               pprust::lit_to_string(&**lit));
-                        cx.span_err(entry.span, message);
-                        return None;
-                    }
-                }
-            }
-            _ => {
-                let message = &format!("expected string literal but got `{}`",
-                                       pprust::expr_to_string(&*entry));
-                cx.span_err(entry.span, message);
-                return None;
+    if let Err(err) = jmespath::validate(&literal.value()) {
+        return compile_error(literal.span(), &format!("jmespath! error: {}", err));
+    }
+    quote! {
+        {
+            thread_local! {
+                static EXPRESSION: ::std::rc::Rc<::jmespath::Expression<'static>> =
+                    ::std::rc::Rc::new(::jmespath::compile(#literal)
+                        .expect("jmespath! validated the expression"));
             }
-        };
-        if !parser.eat(&token::Eof) {
-            cx.span_err(parser.span, "only one string literal allowed");
-            return None;
+            EXPRESSION.with(|expression| expression.clone())
         }
-        Some(expr)
-    } else {
-        cx.parse_sess().span_diagnostic.err("failure parsing token tree");
-        None
     }
 }
 
-/// Creates a Vec that contains a number of generated Ast elements.
-fn generate_vec_ast(cx: &mut ExtCtxt, elements: &Vec<Ast>) -> P<ast::Expr> {
-    // Creates the AST expressions necessary for pushing each node onto the Vec.
-    let elements_ast = elements.iter()
-        .fold(quote_expr!(cx, {}), |acc, element| {
-            let element_expr = generate_ast(cx, &element);
-            quote_expr!(cx, {
-                $acc;
-                nodes.push($element_expr);
-            })
-        });
-    quote_expr!(cx, {
-        let mut nodes = Vec::new();
-        $elements_ast;
-        nodes
-    })
+/// Expands to a compile error at the given span.
+///
+/// `syn::Error::to_compile_error` refers to `::core`, which is not in scope
+/// in Rust 2015 crates.
+fn compile_error(span: Span, message: &str) -> TokenStream {
+    quote_spanned!(span=> compile_error!(#message))
 }
 
-/// Creates the AST nodes for a Comparator.
-fn generate_comparator_ast(cx: &mut ExtCtxt, comparator: &Comparator) -> P<ast::Expr> {
-    match *comparator {
-        Comparator::Equal => quote_expr!(cx, {::jmespath::ast::Comparator::Equal}),
-        Comparator::NotEqual => quote_expr!(cx, {::jmespath::ast::Comparator::NotEqual}),
-        Comparator::GreaterThan => quote_expr!(cx, {::jmespath::ast::Comparator::GreaterThan}),
-        Comparator::GreaterThanEqual => {
-            quote_expr!(cx, {::jmespath::ast::Comparator::GreaterThanEqual})
-        }
-        Comparator::LessThan => quote_expr!(cx, {::jmespath::ast::Comparator::LessThan}),
-        Comparator::LessThanEqual => quote_expr!(cx, {::jmespath::ast::Comparator::LessThanEqual}),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_valid_expressions_to_a_cached_compile() {
+        let expanded = expand(quote!("foo.bar")).to_string();
+        assert!(expanded.contains("thread_local !"), "{}", expanded);
+        assert!(expanded.contains(":: jmespath :: compile (\"foo.bar\")"), "{}", expanded);
     }
-}
 
-/// Generates the Rust AST expression nodes for each JMESPath AST node.
-fn generate_ast(cx: &mut ExtCtxt, ast: &Ast) -> P<ast::Expr> {
-    use jmespath::ast::Ast::*;
-    match *ast {
-        Field { offset, ref name } => {
-            quote_expr!(cx, Ast::Field {
-                offset: $offset,
-                name: $name.to_owned()
-            })
-        }
-        Subexpr { offset, ref lhs, ref rhs } => {
-            let left = generate_ast(cx, lhs);
-            let right = generate_ast(cx, rhs);
-            quote_expr!(cx, Ast::Subexpr {
-                offset: $offset,
-                lhs: Box::new($left),
-                rhs: Box::new($right)
-            })
-        }
-        Index { offset, idx } => {
-            quote_expr!(cx, Ast::Index {
-                offset: $offset,
-                idx: $idx
-            })
-        }
-        Condition { offset, ref predicate, ref then } => {
-            let predicate = generate_ast(cx, &*predicate);
-            let then = generate_ast(cx, then);
-            quote_expr!(cx, Ast::Condition {
-                offset: $offset,
-                predicate: $predicate,
-                then: $then
-            })
-        }
-        Identity { offset } => {
-            quote_expr!(cx, Ast::Identity {
-                offset: $offset
-            })
-        }
-        Expref { offset, ref ast } => {
-            let inner = generate_ast(cx, ast);
-            quote_expr!(cx, Ast::Expref {
-                offset: $offset,
-                ast: Box::new($inner)
-            })
-        }
-        Flatten { offset, ref node } => {
-            let inner = generate_ast(cx, node);
-            quote_expr!(cx, Ast::Flatten {
-                offset: $offset,
-                node: Box::new($inner)
-            })
-        }
-        Not { offset, ref node } => {
-            let inner = generate_ast(cx, node);
-            quote_expr!(cx, Ast::Not {
-                offset: $offset,
-                node: Box::new($inner)
-            })
-        }
-        Projection { offset, ref lhs, ref rhs } => {
-            let left = generate_ast(cx, lhs);
-            let right = generate_ast(cx, rhs);
-            quote_expr!(cx, Ast::Projection {
-                offset: $offset,
-                lhs: Box::new($left),
-                rhs: Box::new($right)
-            })
-        }
-        ObjectValues { offset, ref node } => {
-            let inner = generate_ast(cx, node);
-            quote_expr!(cx, Ast::ObjectValues {
-                offset: $offset,
-                node: Box::new($inner)
-            })
-        }
-        And { offset, ref lhs, ref rhs } => {
-            let left = generate_ast(cx, lhs);
-            let right = generate_ast(cx, rhs);
-            quote_expr!(cx, Ast::And {
-                offset: $offset,
-                lhs: Box::new($left),
-                rhs: Box::new($right)
-            })
-        }
-        Or { offset, ref lhs, ref rhs } => {
-            let left = generate_ast(cx, lhs);
-            let right = generate_ast(cx, rhs);
-            quote_expr!(cx, Ast::Or {
-                offset: $offset,
-                lhs: Box::new($left),
-                rhs: Box::new($right)
-            })
-        }
-        Slice { offset, start, stop, step } => {
-            let start = generate_slice_option_ast(cx, start);
-            let stop = generate_slice_option_ast(cx, stop);
-            quote_expr!(cx, Ast::Slice {
-                offset: $offset,
-                start: $start,
-                stop: $stop,
-                step: $step
-            })
-        }
-        Comparison { offset, ref comparator, ref lhs, ref rhs } => {
-            let left = generate_ast(cx, lhs);
-            let right = generate_ast(cx, rhs);
-            let comparator_ast = generate_comparator_ast(cx, comparator);
-            quote_expr!(cx, Ast::Comparison {
-                offset: $offset,
-                comparator: $comparator_ast,
-                lhs: Box::new($left),
-                rhs: Box::new($right)
-            })
-        }
-        Function { offset, ref name, ref args } => {
-            let elements_ast = generate_vec_ast(cx, args);
-            quote_expr!(cx, {
-                Ast::Function {
-                    offset: $offset,
-                    name: $name.to_owned(),
-                    args: $elements_ast,
-                }
-            })
-        }
-        MultiList { offset, ref elements } => {
-            let elements_ast = generate_vec_ast(cx, elements);
-            quote_expr!(cx, {
-                Ast::MultiList {
-                    offset: $offset,
-                    elements: $elements_ast,
-                }
-            })
-        }
-        MultiHash { offset, ref elements } => {
-            // Create the AST nodes for inserting each key value pair into the MultiHash map.
-            let elements_ast = elements.iter()
-                .fold(quote_expr!(cx, {}), |acc, element| {
-                    let element_key = element.key.to_owned();
-                    let element_expr = generate_ast(cx, &element.value);
-                    quote_expr!(cx, {
-                        $acc;
-                        nodes.push(::jmespath::ast::KeyValuePair {
-                            key: $element_key.to_owned(),
-                            value: $element_expr,
-                        });
-                    })
-                });
-            quote_expr!(cx, {
-                Ast::MultiHash {
-                    offset: $offset,
-                    elements: {
-                        let mut nodes = Vec::new();
-                        $elements_ast;
-                        nodes
-                    },
-                }
-            })
-        }
-        Literal { offset, ref value } => {
-            let value_ast = generate_var_ast(cx, value);
-            quote_expr!(cx, {
-                Ast::Literal {
-                    offset: $offset,
-                    value: ::std::rc::Rc::new($value_ast),
-                }
-            })
-        }
+    #[test]
+    fn reports_the_message_of_the_parser() {
+        let expanded = expand(quote!("foo.bar[?baz > ]")).to_string();
+        let err = jmespath::parse("foo.bar[?baz > ]").unwrap_err();
+        let message = format!("jmespath! error: {}", err);
+        assert!(expanded.starts_with("compile_error !"), "{}", expanded);
+        assert!(expanded.contains(&format!("{:?}", message)), "{}", expanded);
+        assert!(message.contains("(line 0, column 15)"), "{}", message);
     }
-}
 
-/// Generate the AST expression for creating a JMESPath variable.
-///
-/// JMESPath variables are used in Literal nodes.
-fn generate_var_ast(cx: &mut ExtCtxt, var: &Rcvar) -> P<ast::Expr> {
-    match **var {
-        Variable::Null => quote_expr!(cx, ::jmespath::Variable::Null),
-        Variable::Bool(b) => quote_expr!(cx, ::jmespath::Variable::Bool($b)),
-        Variable::String(ref s) => quote_expr!(cx, ::jmespath::Variable::String($s.to_owned())),
-        Variable::Number(n) => {
-            // f64 does not implement to_tokens, so we must parse a float from a string.
-            let float_str = n.to_string();
-            quote_expr!(cx, ::jmespath::Variable::Number($float_str.parse().unwrap()))
-        }
-        Variable::Expref(ref node) => {
-            let node_ast = generate_ast(cx, node);
-            quote_expr!(cx, ::jmespath::Variable::Expref($node_ast))
-        }
-        Variable::Array(ref array) => {
-            // Create the AST nodes for inserting each value into the array.
-            let elements_ast = array.iter()
-                .fold(quote_expr!(cx, {}), |acc, element| {
-                    let element_expr = generate_var_ast(cx, &element);
-                    quote_expr!(cx, {
-                        $acc;
-                        nodes.push(::std::rc::Rc::new($element_expr));
-                    })
-                });
-            quote_expr!(cx, ::jmespath::Variable::Array({
-                let mut nodes = Vec::new();
-                $elements_ast;
-                nodes
-            }))
-        }
-        Variable::Object(ref map) => {
-            // Create the AST nodes for inserting each key value pair into the BTreeMap.
-            let elements_ast = map.keys()
-                .fold(quote_expr!(cx, {}), |acc, key| {
-                    let element_expr = generate_var_ast(cx, map.get(key).unwrap());
-                    quote_expr!(cx, {
-                        $acc;
-                        map.insert($key.to_owned(), ::std::rc::Rc::new($element_expr));
-                    })
-                });
-            quote_expr!(cx, ::jmespath::Variable::Object({
-                let mut map = ::std::collections::BTreeMap::new();
-                $elements_ast;
-                map
-            }))
+    #[test]
+    fn reports_unknown_functions_and_wrong_arities() {
+        for expression in &["foo.lenght(bar)", "length(foo, bar)"] {
+            let expanded = expand(quote!(#expression)).to_string();
+            let err = jmespath::validate(expression).unwrap_err();
+            let message = format!("jmespath! error: {}", err);
+            assert!(expanded.starts_with("compile_error !"), "{}", expanded);
+            assert!(expanded.contains(&format!("{:?}", message)), "{}", expanded);
         }
     }
-}
 
-fn generate_slice_option_ast(cx: &mut ExtCtxt, value: Option<i32>) -> P<ast::Expr> {
-    match value {
-        Some(v) => quote_expr!(cx, Some($v)),
-        None => quote_expr!(cx, None),
+    #[test]
+    fn rejects_anything_but_a_string_literal() {
+        for input in vec![quote!(source), quote!(1), quote!("a", "b"), quote!()] {
+            let expanded = expand(input).to_string();
+            assert!(expanded.contains("jmespath! expects a single string literal"),
+                    "{}",
+                    expanded);
+        }
     }
 }
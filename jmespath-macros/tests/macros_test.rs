@@ -1,108 +1,144 @@
-#![feature(plugin)]
-
-#![plugin(jmespath_macros)]
+#[macro_use]
+extern crate jmespath_macros;
 extern crate jmespath;
 
+use std::rc::Rc;
+use std::thread;
+
+use jmespath::Variable;
+
 #[test]
 fn expands_field_subexpr_macro() {
-    assert_eq!(jmespath!("foo.bar"), jmespath::compile("foo.bar").unwrap());
+    assert_eq!(*jmespath!("foo.bar"), jmespath::compile("foo.bar").unwrap());
 }
 
 #[test]
 fn expands_or() {
-    assert_eq!(jmespath!("a || b"), jmespath::compile("a || b").unwrap());
+    assert_eq!(*jmespath!("a || b"), jmespath::compile("a || b").unwrap());
 }
 
 #[test]
 fn expands_and() {
-    assert_eq!(jmespath!("a && b"), jmespath::compile("a && b").unwrap());
+    assert_eq!(*jmespath!("a && b"), jmespath::compile("a && b").unwrap());
 }
 
 #[test]
 fn expands_index() {
-    assert_eq!(jmespath!("a[0]"), jmespath::compile("a[0]").unwrap());
+    assert_eq!(*jmespath!("a[0]"), jmespath::compile("a[0]").unwrap());
 }
 
 #[test]
 fn expands_not() {
-    assert_eq!(jmespath!("!a"), jmespath::compile("!a").unwrap());
+    assert_eq!(*jmespath!("!a"), jmespath::compile("!a").unwrap());
 }
 
 #[test]
 fn expands_expref() {
-    assert_eq!(jmespath!("&a"), jmespath::compile("&a").unwrap());
+    assert_eq!(*jmespath!("&a"), jmespath::compile("&a").unwrap());
 }
 
 #[test]
 fn expands_value_projection() {
-    assert_eq!(jmespath!("a.*.b"), jmespath::compile("a.*.b").unwrap());
+    assert_eq!(*jmespath!("a.*.b"), jmespath::compile("a.*.b").unwrap());
 }
 
 #[test]
 fn expands_array_projection() {
-    assert_eq!(jmespath!("a[*].b"), jmespath::compile("a[*].b").unwrap());
+    assert_eq!(*jmespath!("a[*].b"), jmespath::compile("a[*].b").unwrap());
 }
 
 #[test]
 fn expands_flatten_projection() {
-    assert_eq!(jmespath!("a[].b"), jmespath::compile("a[].b").unwrap());
+    assert_eq!(*jmespath!("a[].b"), jmespath::compile("a[].b").unwrap());
 }
 
 #[test]
 fn expands_slices() {
-    assert_eq!(jmespath!("[1::]"), jmespath::compile("[1::]").unwrap());
-    assert_eq!(jmespath!("[10:1:-1]"),
+    assert_eq!(*jmespath!("[1::]"), jmespath::compile("[1::]").unwrap());
+    assert_eq!(*jmespath!("[10:1:-1]"),
                jmespath::compile("[10:1:-1]").unwrap());
 }
 
 #[test]
 fn expands_multi_list() {
-    assert_eq!(jmespath!("[a, b, c]"),
+    assert_eq!(*jmespath!("[a, b, c]"),
                jmespath::compile("[a, b, c]").unwrap());
 }
 
 #[test]
 fn expands_functions() {
-    assert_eq!(jmespath!("foo(a, b, c)"),
-               jmespath::compile("foo(a, b, c)").unwrap());
+    assert_eq!(*jmespath!("not_null(a, b, c)"),
+               jmespath::compile("not_null(a, b, c)").unwrap());
 }
 
 #[test]
 fn expands_comparisons() {
-    assert_eq!(jmespath!("a > b"), jmespath::compile("a > b").unwrap());
-    assert_eq!(jmespath!("a >= b"), jmespath::compile("a >= b").unwrap());
-    assert_eq!(jmespath!("a < b"), jmespath::compile("a < b").unwrap());
-    assert_eq!(jmespath!("a <= b"), jmespath::compile("a <= b").unwrap());
-    assert_eq!(jmespath!("a == b"), jmespath::compile("a == b").unwrap());
-    assert_eq!(jmespath!("a != b"), jmespath::compile("a != b").unwrap());
+    assert_eq!(*jmespath!("a > b"), jmespath::compile("a > b").unwrap());
+    assert_eq!(*jmespath!("a >= b"), jmespath::compile("a >= b").unwrap());
+    assert_eq!(*jmespath!("a < b"), jmespath::compile("a < b").unwrap());
+    assert_eq!(*jmespath!("a <= b"), jmespath::compile("a <= b").unwrap());
+    assert_eq!(*jmespath!("a == b"), jmespath::compile("a == b").unwrap());
+    assert_eq!(*jmespath!("a != b"), jmespath::compile("a != b").unwrap());
 }
 
 #[test]
 fn expands_multi_hash() {
-    assert_eq!(jmespath!("a.{b: c}"),
+    assert_eq!(*jmespath!("a.{b: c}"),
                jmespath::compile("a.{b: c}").unwrap());
-    assert_eq!(jmespath!("{b: c}"), jmespath::compile("{b: c}").unwrap());
-    assert_eq!(jmespath!("a.{b: foo.bar, c: d}"),
+    assert_eq!(*jmespath!("{b: c}"), jmespath::compile("{b: c}").unwrap());
+    assert_eq!(*jmespath!("a.{b: foo.bar, c: d}"),
                jmespath::compile("a.{b: foo.bar, c: d}").unwrap());
 }
 
 #[test]
 fn expands_literal() {
-    assert_eq!(jmespath!("`\"foo\"`"),
+    assert_eq!(*jmespath!("`\"foo\"`"),
                jmespath::compile("`\"foo\"`").unwrap());
-    assert_eq!(jmespath!("`1`"), jmespath::compile("`1`").unwrap());
-    assert_eq!(jmespath!("`1.5`"), jmespath::compile("`1.5`").unwrap());
-    assert_eq!(jmespath!("`-1.5`"), jmespath::compile("`-1.5`").unwrap());
-    assert_eq!(jmespath!("`-1`"), jmespath::compile("`-1`").unwrap());
-    assert_eq!(jmespath!("`true`"), jmespath::compile("`true`").unwrap());
-    assert_eq!(jmespath!("`false`"), jmespath::compile("`false`").unwrap());
-    assert_eq!(jmespath!("`null`"), jmespath::compile("`null`").unwrap());
-    assert_eq!(jmespath!("`[1, 2, 3]`"),
+    assert_eq!(*jmespath!("`1`"), jmespath::compile("`1`").unwrap());
+    assert_eq!(*jmespath!("`1.5`"), jmespath::compile("`1.5`").unwrap());
+    assert_eq!(*jmespath!("`-1.5`"), jmespath::compile("`-1.5`").unwrap());
+    assert_eq!(*jmespath!("`-1`"), jmespath::compile("`-1`").unwrap());
+    assert_eq!(*jmespath!("`true`"), jmespath::compile("`true`").unwrap());
+    assert_eq!(*jmespath!("`false`"), jmespath::compile("`false`").unwrap());
+    assert_eq!(*jmespath!("`null`"), jmespath::compile("`null`").unwrap());
+    assert_eq!(*jmespath!("`[1, 2, 3]`"),
                jmespath::compile("`[1, 2, 3]`").unwrap());
-    assert_eq!(jmespath!("`{\"a\":1, \"b\":\"c\"}`"),
+    assert_eq!(*jmespath!("`{\"a\":1, \"b\":\"c\"}`"),
                jmespath::compile("`{\"a\":1, \"b\":\"c\"}`").unwrap());
 }
 
+#[test]
+fn expands_raw_string_literals() {
+    assert_eq!(*jmespath!(r#"foo."bar baz""#),
+               jmespath::compile(r#"foo."bar baz""#).unwrap());
+}
+
+#[test]
+fn evaluates_like_expression_new() {
+    let expression = "people[?age > `20`].{name: name, tags: sort(tags)} | sort_by(@, &name)";
+    let ast = jmespath::parse(expression).unwrap();
+    let expected = jmespath::Expression::new(expression, ast, &jmespath::DEFAULT_RUNTIME);
+    let data = Variable::from_json(r#"{"people": [
+        {"name": "c", "age": 30, "tags": ["y", "x"]},
+        {"name": "a", "age": 21, "tags": []},
+        {"name": "b", "age": 20, "tags": ["z"]}
+    ]}"#)
+        .unwrap();
+    let expr = jmespath!("people[?age > `20`].{name: name, tags: sort(tags)} | sort_by(@, &name)");
+    assert_eq!(expected, *expr);
+    assert_eq!(expected.search(data.clone()).unwrap(), expr.search(data).unwrap());
+}
+
+#[test]
+fn compiles_each_expression_once_per_thread() {
+    let exprs = (0..3).map(|_| jmespath!("foo.bar")).collect::<Vec<_>>();
+    assert!(Rc::ptr_eq(&exprs[0], &exprs[1]));
+    assert!(Rc::ptr_eq(&exprs[1], &exprs[2]));
+    assert!(!Rc::ptr_eq(&exprs[0], &jmespath!("foo.bar")));
+    let other = thread::spawn(|| jmespath!("foo.bar").as_str().to_owned()).join().unwrap();
+    assert_eq!("foo.bar", other);
+}
+
 #[test]
 fn basic_usage() {
     use std::collections::BTreeMap;
@@ -15,13 +15,15 @@
 //! let ast = jmespath::parse("a || b && c").unwrap();
 //! ```
 
+use std::cmp;
 use std::fmt;
 
 use Rcvar;
 use lexer::Token;
+use variable::Variable;
 
 /// A JMESPath expression abstract syntax tree.
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, Serialize)]
 pub enum Ast {
     /// Compares two nodes using a comparator, returning true/false.
     Comparison {
@@ -34,6 +36,20 @@ pub enum Ast {
         /// Right hand side of the comparison
         rhs: Box<Ast>,
     },
+    /// Applies an arithmetic operator to two numbers.
+    ///
+    /// Evaluates to null if either operand is not a number or if the result
+    /// is not finite (e.g., when dividing by zero).
+    Arithmetic {
+        /// Approximate absolute position in the parsed expression.
+        offset: usize,
+        /// Operator that combines the two results
+        operator: ArithmeticOperator,
+        /// Left hand side of the operation
+        lhs: Box<Ast>,
+        /// Right hand side of the operation
+        rhs: Box<Ast>,
+    },
     /// If `predicate` evaluates to a truthy value, returns the
     /// result `then`
     Condition {
@@ -49,6 +65,12 @@ pub enum Ast {
         /// Approximate absolute position in the parsed expression.
         offset: usize,
     },
+    /// Returns the document that was passed to the search, regardless of
+    /// the node that is currently being evaluated.
+    RootNode {
+        /// Approximate absolute position in the parsed expression.
+        offset: usize,
+    },
     /// Used by functions to dynamically evaluate argument values.
     Expref {
         /// Approximate absolute position in the parsed expression.
@@ -84,7 +106,7 @@ pub enum Ast {
         /// Approximate absolute position in the parsed expression.
         offset: usize,
         /// Index to extract
-        idx: i32,
+        idx: i64,
     },
     /// Resolves to a literal value.
     Literal {
@@ -100,7 +122,30 @@ pub enum Ast {
         /// Elements of the list
         elements: Vec<Ast>,
     },
+    /// Binds variables, then evaluates an expression in which they are
+    /// visible.
+    Let {
+        /// Approximate absolute position in the parsed expression.
+        offset: usize,
+        /// Variables to bind. Each value is evaluated against the current
+        /// node before any of the variables are bound.
+        bindings: Vec<LetBinding>,
+        /// Expression to evaluate with the variables bound.
+        expr: Box<Ast>,
+    },
+    /// Returns the value bound to a variable by an enclosing let expression.
+    VarRef {
+        /// Approximate absolute position in the parsed expression.
+        offset: usize,
+        /// Name of the variable, without the `$`.
+        name: String,
+    },
     /// Evaluates to a map of key value pairs.
+    ///
+    /// Keys keep the order of the elements. When a key is repeated, the last
+    /// value wins but the key keeps the position of its first occurrence.
+    /// Computed keys are evaluated against the current node and must
+    /// evaluate to strings.
     MultiHash {
         /// Approximate absolute position in the parsed expression.
         offset: usize,
@@ -114,6 +159,13 @@ pub enum Ast {
         /// node to negate
         node: Box<Ast>,
     },
+    /// Negates a number, evaluating to null if the node is not a number.
+    Negate {
+        /// Approximate absolute position in the parsed expression.
+        offset: usize,
+        /// node to negate
+        node: Box<Ast>,
+    },
     /// Evaluates LHS, and pushes each value through RHS.
     Projection {
         /// Approximate absolute position in the parsed expression.
@@ -153,11 +205,11 @@ pub enum Ast {
         /// Approximate absolute position in the parsed expression.
         offset: usize,
         /// Starting index
-        start: Option<i32>,
+        start: Option<i64>,
         /// Stopping index
-        stop: Option<i32>,
+        stop: Option<i64>,
         /// Step amount between extractions.
-        step: i32,
+        step: i64,
     },
     /// Evaluates RHS, then provides that value to the evaluation of RHS.
     Subexpr {
@@ -168,25 +220,449 @@ pub enum Ast {
         /// Right hand side of the expression.
         rhs: Box<Ast>,
     },
+    /// Placeholder for a part of the expression that could not be parsed.
+    ///
+    /// Only produced by `jmespath::parse_recoverable`. Evaluating it fails.
+    Error {
+        /// Absolute position of the syntax error in the parsed expression.
+        offset: usize,
+    },
+}
+
+impl Ast {
+    /// Returns the approximate position of the node in the parsed
+    /// expression.
+    pub fn offset(&self) -> usize {
+        match *self {
+            Ast::Comparison { offset, .. } |
+            Ast::Arithmetic { offset, .. } |
+            Ast::Condition { offset, .. } |
+            Ast::Identity { offset } |
+            Ast::RootNode { offset } |
+            Ast::Expref { offset, .. } |
+            Ast::Flatten { offset, .. } |
+            Ast::Function { offset, .. } |
+            Ast::Field { offset, .. } |
+            Ast::Index { offset, .. } |
+            Ast::Literal { offset, .. } |
+            Ast::Let { offset, .. } |
+            Ast::VarRef { offset, .. } |
+            Ast::MultiList { offset, .. } |
+            Ast::MultiHash { offset, .. } |
+            Ast::Not { offset, .. } |
+            Ast::Negate { offset, .. } |
+            Ast::Projection { offset, .. } |
+            Ast::ObjectValues { offset, .. } |
+            Ast::And { offset, .. } |
+            Ast::Or { offset, .. } |
+            Ast::Slice { offset, .. } |
+            Ast::Subexpr { offset, .. } |
+            Ast::Error { offset } => offset,
+        }
+    }
+
+    /// Calls `f` with the node, then with each of its descendants in the
+    /// order in which they appear in the expression. Stops at the first
+    /// error returned by `f`.
+    pub(crate) fn walk<E, F>(&self, f: &mut F) -> Result<(), E>
+        where F: FnMut(&Ast) -> Result<(), E>
+    {
+        try!(f(self));
+        for child in self.children() {
+            try!(child.walk(f));
+        }
+        Ok(())
+    }
+
+    /// Returns the children of the node in the order in which they appear
+    /// in the expression.
+    pub(crate) fn children(&self) -> Vec<&Ast> {
+        match *self {
+            Ast::Comparison { ref lhs, ref rhs, .. } |
+            Ast::Arithmetic { ref lhs, ref rhs, .. } |
+            Ast::Projection { ref lhs, ref rhs, .. } |
+            Ast::And { ref lhs, ref rhs, .. } |
+            Ast::Or { ref lhs, ref rhs, .. } |
+            Ast::Subexpr { ref lhs, ref rhs, .. } |
+            Ast::Condition { predicate: ref lhs, then: ref rhs, .. } => vec![lhs, rhs],
+            Ast::Expref { ast: ref node, .. } |
+            Ast::Flatten { ref node, .. } |
+            Ast::Not { ref node, .. } |
+            Ast::Negate { ref node, .. } |
+            Ast::ObjectValues { ref node, .. } => vec![node],
+            Ast::Function { args: ref nodes, .. } |
+            Ast::MultiList { elements: ref nodes, .. } => nodes.iter().collect(),
+            Ast::MultiHash { ref elements, .. } => {
+                let mut children: Vec<&Ast> = vec![];
+                for kvp in elements {
+                    if let Key::Expression(ref key) = kvp.key {
+                        children.push(key);
+                    }
+                    children.push(&kvp.value);
+                }
+                children
+            }
+            Ast::Let { ref bindings, ref expr, .. } => {
+                let mut children: Vec<&Ast> = bindings.iter().map(|b| &b.value).collect();
+                children.push(expr);
+                children
+            }
+            Ast::Identity { .. } |
+            Ast::RootNode { .. } |
+            Ast::Field { .. } |
+            Ast::Index { .. } |
+            Ast::Literal { .. } |
+            Ast::Slice { .. } |
+            Ast::VarRef { .. } |
+            Ast::Error { .. } => vec![],
+        }
+    }
 }
 
+/// Formats the AST as a JMESPath expression.
+///
+/// Parsing the printed expression yields the same AST (ignoring offsets)
+/// for any AST produced by the parser. Parentheses are only added where
+/// they are needed to preserve the structure of the tree. Nodes that have
+/// no syntax of their own outside of a projection (`Slice`, `Flatten`,
+/// `ObjectValues`, and `Condition`) are printed as the projection that
+/// would contain them. `Error` placeholders are printed as `<error>`, which
+/// does not parse. Use `{:?}` or `{:#?}` to print the tree itself.
 impl fmt::Display for Ast {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(fmt, "{:#?}", self)
+        write!(fmt, "{}", Printed::new(self).text)
     }
 }
 
+/// Binding power of nodes that are never split by a trailing operator.
+const ATOM: usize = ::std::usize::MAX;
+
+/// Expression text of a node along with how it binds to adjacent tokens.
+///
+/// `lead` is the binding power of the loosest operator at the top level of
+/// the text, which must exceed the right binding power of the parser when
+/// the text is used as the right hand side of an operator. `trail` is the
+/// highest left binding power of a token that can follow the text without
+/// being absorbed into it.
+struct Printed {
+    text: String,
+    lead: usize,
+    trail: usize,
+}
+
+impl Printed {
+    fn new(ast: &Ast) -> Printed {
+        match *ast {
+            Ast::Identity { .. } => Printed::atom("@".to_owned()),
+            Ast::RootNode { .. } => Printed::atom("$".to_owned()),
+            Ast::Field { ref name, .. } => Printed::atom(identifier(name)),
+            Ast::Index { idx, .. } => Printed::atom(format!("[{}]", idx)),
+            Ast::Literal { ref value, .. } => Printed::atom(literal(value)),
+            Ast::VarRef { ref name, .. } => Printed::atom(format!("${}", name)),
+            Ast::Error { .. } => Printed::atom("<error>".to_owned()),
+            Ast::Let { ref bindings, ref expr, .. } => {
+                let bindings = bindings.iter()
+                    .map(|b| format!("${} = {}", b.name, Printed::new(&b.value).text))
+                    .collect::<Vec<_>>();
+                // The body extends as far as possible, so nothing can follow it.
+                Printed {
+                    text: format!("let {} in {}", bindings.join(", "), Printed::new(expr).text),
+                    lead: ATOM,
+                    trail: 0,
+                }
+            }
+            Ast::Slice { .. } |
+            Ast::Flatten { .. } |
+            Ast::ObjectValues { .. } => Printed::projection(ast, &Ast::Identity { offset: 0 }),
+            Ast::Condition { .. } => Printed::projection(&Ast::Identity { offset: 0 }, ast),
+            Ast::MultiList { ref elements, .. } => {
+                let elements = elements.iter().map(|e| Printed::new(e).text).collect::<Vec<_>>();
+                match elements.len() {
+                    0 => Printed::atom("[ ]".to_owned()),
+                    // "[*]" is a wildcard index rather than a list of one projection.
+                    1 if elements[0] == "*" => Printed::atom("[(*)]".to_owned()),
+                    _ => Printed::atom(format!("[{}]", elements.join(", "))),
+                }
+            }
+            Ast::MultiHash { ref elements, .. } => {
+                let elements = elements.iter()
+                    .map(|kvp| {
+                        let key = match kvp.key {
+                            Key::Name(ref name) => identifier(name),
+                            Key::Expression(ref ast) => format!("({})", Printed::new(ast).text),
+                        };
+                        format!("{}: {}", key, Printed::new(&kvp.value).text)
+                    })
+                    .collect::<Vec<_>>();
+                Printed::atom(format!("{{{}}}", elements.join(", ")))
+            }
+            Ast::Function { ref name, ref args, .. } => {
+                let args = args.iter().map(|a| Printed::new(a).text).collect::<Vec<_>>();
+                Printed::atom(format!("{}({})", name, args.join(", ")))
+            }
+            Ast::Expref { ref ast, .. } => {
+                let node = Printed::new(ast);
+                let space = if node.text.starts_with('&') { " " } else { "" };
+                Printed {
+                    text: format!("&{}{}", space, node.text),
+                    lead: ATOM,
+                    trail: 0,
+                }
+            }
+            Ast::Not { ref node, .. } => {
+                let node = Printed::new(node).rhs(45);
+                Printed {
+                    text: format!("!{}", node.text),
+                    lead: ATOM,
+                    trail: cmp::min(45, node.trail),
+                }
+            }
+            Ast::Negate { ref node, .. } => {
+                let node = Printed::new(node).rhs(7);
+                Printed {
+                    text: format!("-{}", node.text),
+                    lead: ATOM,
+                    trail: cmp::min(7, node.trail),
+                }
+            }
+            Ast::Comparison { ref comparator, ref lhs, ref rhs, .. } => {
+                Printed::binary(lhs, comparator.as_str(), rhs, 5)
+            }
+            Ast::Arithmetic { ref operator, ref lhs, ref rhs, .. } => {
+                Printed::binary(lhs, operator.as_str(), rhs, operator.lbp())
+            }
+            Ast::And { ref lhs, ref rhs, .. } => Printed::binary(lhs, "&&", rhs, 3),
+            Ast::Or { ref lhs, ref rhs, .. } => Printed::binary(lhs, "||", rhs, 2),
+            Ast::Subexpr { ref lhs, ref rhs, .. } => Printed::subexpr(lhs, rhs),
+            Ast::Projection { ref lhs, ref rhs, .. } => Printed::projection(lhs, rhs),
+        }
+    }
+
+    fn atom(text: String) -> Printed {
+        Printed {
+            text: text,
+            lead: ATOM,
+            trail: ATOM,
+        }
+    }
+
+    /// Wraps the text in parentheses, making it an atom.
+    fn wrap(self) -> Printed {
+        Printed::atom(format!("({})", self.text))
+    }
+
+    /// Prepares the text to be followed by a token with the given lbp.
+    fn lhs(self, lbp: usize) -> Printed {
+        if self.trail >= lbp { self } else { self.wrap() }
+    }
+
+    /// Prepares the text to be parsed by `expr(rbp)`.
+    fn rhs(self, rbp: usize) -> Printed {
+        if self.lead > rbp { self } else { self.wrap() }
+    }
+
+    /// Returns true if the text can follow a "." in a sub-expression.
+    fn follows_dot(&self) -> bool {
+        match self.text.chars().next() {
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => true,
+            Some('"') | Some('{') | Some('&') => true,
+            _ => false,
+        }
+    }
+
+    fn binary(lhs: &Ast, op: &str, rhs: &Ast, lbp: usize) -> Printed {
+        let lhs = Printed::new(lhs).lhs(lbp);
+        let rhs = Printed::new(rhs).rhs(lbp);
+        Printed {
+            text: format!("{} {} {}", lhs.text, op, rhs.text),
+            lead: lbp,
+            trail: cmp::min(lbp, rhs.trail),
+        }
+    }
+
+    fn subexpr(lhs: &Ast, rhs: &Ast) -> Printed {
+        let lhs = Printed::new(lhs);
+        match *rhs {
+            Ast::Index { idx, .. } if lhs.trail >= 55 => {
+                return Printed {
+                    text: format!("{}[{}]", lhs.text, idx),
+                    lead: 55,
+                    trail: 55,
+                };
+            }
+            Ast::Projection { lhs: ref slice, ref rhs, .. } if lhs.trail >= 55 => {
+                if let Ast::Slice { ref start, ref stop, step, .. } = **slice {
+                    let rhs = Printed::projection_rhs(rhs, 20);
+                    return Printed {
+                        text: format!("{}{}{}", lhs.text, self::slice(start, stop, step), rhs.text),
+                        lead: 55,
+                        trail: cmp::min(9, rhs.trail),
+                    };
+                }
+            }
+            Ast::MultiList { .. } if lhs.trail >= 40 => {
+                return Printed {
+                    text: format!("{}.{}", lhs.text, Printed::new(rhs).text),
+                    lead: 40,
+                    trail: 40,
+                };
+            }
+            _ => {}
+        }
+        let printed = Printed::new(rhs);
+        if lhs.trail >= 40 && printed.follows_dot() && printed.lead > 40 {
+            return Printed {
+                text: format!("{}.{}", lhs.text, printed.text),
+                lead: 40,
+                trail: cmp::min(40, printed.trail),
+            };
+        }
+        let (lhs, printed) = (lhs.lhs(1), printed.rhs(1));
+        Printed {
+            text: format!("{} | {}", lhs.text, printed.text),
+            lead: 1,
+            trail: cmp::min(1, printed.trail),
+        }
+    }
+
+    /// Prints a projection of `rhs` over `lhs`.
+    fn projection(lhs: &Ast, rhs: &Ast) -> Printed {
+        let (text, lead, rhs, lbp) = match (lhs, rhs) {
+            (&Ast::Slice { ref start, ref stop, step, .. }, _) => {
+                (slice(start, stop, step), ATOM, rhs, 20)
+            }
+            (&Ast::ObjectValues { ref node, .. }, _) => {
+                let (text, lead) = Printed::operator(node, "*", ".*", 40);
+                (text, lead, rhs, 20)
+            }
+            (&Ast::Flatten { ref node, .. }, _) => {
+                let (text, lead) = Printed::operator(node, "[]", "[]", 9);
+                (text, lead, rhs, 9)
+            }
+            (_, &Ast::Condition { ref predicate, ref then, .. }) => {
+                let filter = format!("[?{}]", Printed::new(predicate).text);
+                let (text, lead) = Printed::operator(lhs, &filter, &filter, 21);
+                (text, lead, &**then, 21)
+            }
+            _ => {
+                let (text, lead) = Printed::operator(lhs, "[*]", "[*]", 55);
+                (text, lead, rhs, 20)
+            }
+        };
+        let rhs = Printed::projection_rhs(rhs, lbp);
+        Printed {
+            text: text + &rhs.text,
+            lead: lead,
+            trail: cmp::min(9, rhs.trail),
+        }
+    }
+
+    /// Prints the node that a projection applies to followed by `op`, the
+    /// projection operator with a left binding power of `lbp`. `bare` is
+    /// printed when the node is the current node.
+    fn operator(node: &Ast, bare: &str, op: &str, lbp: usize) -> (String, usize) {
+        match *node {
+            Ast::Identity { .. } => (bare.to_owned(), ATOM),
+            _ => (format!("{}{}", Printed::new(node).lhs(lbp).text, op), lbp),
+        }
+    }
+
+    /// Prints the right hand side of a projection parsed with the given lbp.
+    fn projection_rhs(rhs: &Ast, lbp: usize) -> Printed {
+        if let Ast::Identity { .. } = *rhs {
+            return Printed::atom(String::new());
+        }
+        let printed = Printed::new(rhs);
+        let is_list = match *rhs {
+            Ast::MultiList { .. } => true,
+            _ => false,
+        };
+        let text = if printed.text.starts_with('[') && !printed.text.starts_with("[]") &&
+                      printed.lead > lbp {
+            printed.text
+        } else if is_list || (printed.text.starts_with('*') || printed.follows_dot()) &&
+                             printed.lead > lbp {
+            format!(".{}", printed.text)
+        } else {
+            // Not expressible as a projection; parenthesize for readability.
+            format!(".({})", printed.text)
+        };
+        Printed {
+            text: text,
+            lead: ATOM,
+            trail: printed.trail,
+        }
+    }
+}
+
+/// Prints the brackets of a slice.
+fn slice(start: &Option<i64>, stop: &Option<i64>, step: i64) -> String {
+    let show = |v: &Option<i64>| v.map(|v| v.to_string()).unwrap_or_default();
+    if step == 1 {
+        format!("[{}:{}]", show(start), show(stop))
+    } else {
+        format!("[{}:{}:{}]", show(start), show(stop), step)
+    }
+}
+
+/// Prints a field name as an identifier, quoting it if necessary.
+pub(crate) fn identifier(name: &str) -> String {
+    let mut chars = name.chars();
+    let is_identifier = match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    };
+    if is_identifier {
+        name.to_owned()
+    } else {
+        Variable::String(name.to_owned()).to_string()
+    }
+}
+
+/// Prints a literal value as a raw string or a JSON literal.
+fn literal(value: &Rcvar) -> String {
+    match **value {
+        Variable::String(ref s) if !s.contains('\\') => format!("'{}'", s.replace('\'', "\\'")),
+        ref v => format!("`{}`", v.to_string().replace('`', "\\`")),
+    }
+}
+
+/// Represents a variable binding of a let expression.
+#[derive(Clone, PartialEq, Debug, Serialize)]
+pub struct LetBinding {
+    /// Name of the variable, without the `$`.
+    pub name: String,
+    /// Expression used to determine the value of the variable.
+    pub value: Ast,
+}
+
 /// Represents a key value pair in a MultiHash.
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, Serialize)]
 pub struct KeyValuePair {
-    /// Key name.
-    pub key: String,
+    /// Key of the pair.
+    pub key: Key,
     /// Value expression used to determine the value.
     pub value: Ast,
 }
 
+/// Key of a key value pair in a MultiHash.
+///
+/// Serializes as the key name itself or as the key expression.
+#[derive(Clone, PartialEq, Debug, Serialize)]
+#[serde(untagged)]
+pub enum Key {
+    /// A key name, e.g., `foo` in `{foo: bar}`.
+    Name(String),
+    /// An expression that evaluates to the key name, e.g., `(foo)` in
+    /// `{(foo): bar}`. Only parsed when `ParseOptions::computed_keys` is
+    /// set.
+    Expression(Box<Ast>),
+}
+
 /// Comparators used in Comparison nodes.
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, Serialize)]
 pub enum Comparator {
     Equal,
     NotEqual,
@@ -196,11 +672,97 @@ pub enum Comparator {
     GreaterThanEqual,
 }
 
+impl Comparator {
+    /// Returns the operator used to write the comparator in an expression.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Comparator::Equal => "==",
+            Comparator::NotEqual => "!=",
+            Comparator::LessThan => "<",
+            Comparator::LessThanEqual => "<=",
+            Comparator::GreaterThan => ">",
+            Comparator::GreaterThanEqual => ">=",
+        }
+    }
+}
+
+/// Operators used in Arithmetic nodes.
+#[derive(Clone, PartialEq, Debug, Serialize)]
+pub enum ArithmeticOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    /// Remainder of floored division, which has the sign of the divisor.
+    Modulo,
+    /// Floored division.
+    IntegerDivide,
+}
+
+impl ArithmeticOperator {
+    /// Returns the operator used to write the operation in an expression.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            ArithmeticOperator::Add => "+",
+            ArithmeticOperator::Subtract => "-",
+            ArithmeticOperator::Multiply => "*",
+            ArithmeticOperator::Divide => "/",
+            ArithmeticOperator::Modulo => "%",
+            ArithmeticOperator::IntegerDivide => "//",
+        }
+    }
+
+    /// Left binding power of the operator's token.
+    fn lbp(&self) -> usize {
+        match *self {
+            ArithmeticOperator::Add | ArithmeticOperator::Subtract => 6,
+            _ => 7,
+        }
+    }
+
+    /// Applies the operator, returning None if the result is not finite.
+    pub fn apply(&self, lhs: f64, rhs: f64) -> Option<f64> {
+        let result = match *self {
+            ArithmeticOperator::Add => lhs + rhs,
+            ArithmeticOperator::Subtract => lhs - rhs,
+            ArithmeticOperator::Multiply => lhs * rhs,
+            ArithmeticOperator::Divide => lhs / rhs,
+            ArithmeticOperator::Modulo => {
+                let remainder = lhs % rhs;
+                if remainder != 0.0 && (remainder < 0.0) != (rhs < 0.0) {
+                    remainder + rhs
+                } else {
+                    remainder
+                }
+            }
+            ArithmeticOperator::IntegerDivide => (lhs / rhs).floor(),
+        };
+        if result.is_finite() { Some(result) } else { None }
+    }
+}
+
+/// Creates an ArithmeticOperator from a Token.
+///
+/// Note: panics if the Token is invalid.
+impl<'a> From<Token<'a>> for ArithmeticOperator {
+    fn from(token: Token<'a>) -> Self {
+        match token {
+            Token::Plus => ArithmeticOperator::Add,
+            Token::Minus => ArithmeticOperator::Subtract,
+            Token::Star => ArithmeticOperator::Multiply,
+            Token::Slash => ArithmeticOperator::Divide,
+            Token::Percent => ArithmeticOperator::Modulo,
+            Token::DoubleSlash => ArithmeticOperator::IntegerDivide,
+            _ => panic!("Invalid token for arithmetic operator: {:?}", token),
+        }
+    }
+}
+
 /// Creates a Comparator from a Token.
 ///
 /// Note: panics if the Token is invalid.
-impl From<Token> for Comparator {
-    fn from(token: Token) -> Self {
+impl<'a> From<Token<'a>> for Comparator {
+    fn from(token: Token<'a>) -> Self {
         match token {
             Token::Lt => Comparator::LessThan,
             Token::Lte => Comparator::LessThanEqual,
@@ -217,13 +779,43 @@ impl From<Token> for Comparator {
 mod test {
     use super::*;
 
+    use parser::parse;
+
     #[test]
-    fn displays_pretty_printed_ast_node() {
+    fn displays_ast_node_as_expression() {
         let node = Ast::Field {
             name: "abc".to_string(),
             offset: 4,
         };
+        assert_eq!("abc", format!("{}", node));
         assert_eq!("Field {\n    offset: 4,\n    name: \"abc\",\n}",
-                   format!("{}", node));
+                   format!("{:#?}", node));
+    }
+
+    #[test]
+    fn quotes_fields_that_are_not_identifiers() {
+        let node = Ast::Field {
+            name: "a b".to_string(),
+            offset: 0,
+        };
+        assert_eq!("\"a b\"", format!("{}", node));
+    }
+
+    #[test]
+    fn adds_parentheses_only_where_needed() {
+        let printed = |expr: &str| parse(expr).unwrap().to_string();
+        assert_eq!("(a || b) && c", printed("(a || b) && c"));
+        assert_eq!("a || b && c", printed("(a || (b && c))"));
+        assert_eq!("!(a.b)", printed("!(a.b)"));
+        assert_eq!("items[?price > $.threshold]", printed("items[?price > $.threshold]"));
+        assert_eq!("map(&a || b, c) || d", printed("map(&(a || b), c) || d"));
+    }
+
+    #[test]
+    fn uses_pipes_instead_of_parenthesized_sub_expressions() {
+        let printed = |expr: &str| parse(expr).unwrap().to_string();
+        assert_eq!("a || b | c", printed("(a || b).c"));
+        assert_eq!("a[*].b | c", printed("(a[*].b).c"));
+        assert_eq!("a.b | [0]", printed("(a.b)[0]"));
     }
 }
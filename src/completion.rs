@@ -0,0 +1,436 @@
+//! Suggests completions for partially written JMESPath expressions.
+//!
+//! Completions are computed by scanning the expression up to the cursor,
+//! determining which node the cursor is positioned on, and partially
+//! evaluating the path written so far against a sample document. Keys of
+//! the resulting values are suggested as fields, and functions of the
+//! runtime are suggested wherever an expression may start.
+
+use std::collections::BTreeSet;
+
+use {Rcvar, Runtime};
+use variable::Variable;
+
+/// The kind of a suggested completion.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CompletionKind {
+    /// A key of an object found in the sample document.
+    Field,
+    /// A function registered with the runtime.
+    Function,
+    /// A JMESPath keyword, like the current node `@`.
+    Keyword,
+}
+
+/// A suggested completion.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Completion {
+    /// Text to insert. Fields that are not valid unquoted identifiers
+    /// are quoted.
+    pub text: String,
+    /// Kind of the completion.
+    pub kind: CompletionKind,
+    /// Byte offset in the expression from which `text` replaces the
+    /// partially written identifier (the cursor when nothing was written).
+    pub start: usize,
+}
+
+/// Kind of an unclosed group encountered while scanning.
+#[derive(Clone, PartialEq, Debug)]
+enum Group {
+    /// The top level of the expression.
+    Root,
+    /// "(", with the byte offset at which the preceding function name starts.
+    Paren(Option<usize>),
+    /// "[" used for multi-select lists and indices.
+    Bracket,
+    /// "[?" filter expression.
+    Filter,
+    /// "{" multi-select hash.
+    Brace,
+}
+
+/// An unclosed group and the bookkeeping needed to evaluate its context.
+#[derive(Debug)]
+struct Frame {
+    group: Group,
+    /// Byte offset of the opening character (0 for the root).
+    opener: usize,
+    /// Byte offset right after the opening token.
+    start: usize,
+    /// Byte offset at which the current operand starts.
+    segment: usize,
+    /// Byte offset of the last top-level pipe in the group.
+    pipe: Option<usize>,
+    /// Whether a multi-select hash is expecting a key.
+    in_key: bool,
+}
+
+impl Frame {
+    fn new(group: Group, opener: usize, start: usize) -> Frame {
+        let in_key = group == Group::Brace;
+        Frame {
+            group: group,
+            opener: opener,
+            start: start,
+            segment: start,
+            pipe: None,
+            in_key: in_key,
+        }
+    }
+}
+
+/// Suggests completions using the default runtime.
+///
+/// See `Runtime::complete`.
+pub fn complete(expression: &str, cursor: usize, sample: &Rcvar) -> Vec<Completion> {
+    ::DEFAULT_RUNTIME.complete(expression, cursor, sample)
+}
+
+impl Runtime {
+    /// Suggests completions for an expression at the given byte offset.
+    ///
+    /// When the cursor follows a "." or is inside an identifier, the keys
+    /// available at that point of the sample document are suggested. When
+    /// the cursor is where an expression may start (e.g., the start of the
+    /// expression, a function argument, or inside of "[?"), the keys of the
+    /// current node and the functions of the runtime are suggested.
+    pub fn complete(&self, expression: &str, cursor: usize, sample: &Rcvar) -> Vec<Completion> {
+        let cursor = if cursor > expression.len() { expression.len() } else { cursor };
+        if !expression.is_char_boundary(cursor) {
+            return vec![];
+        }
+        let prefix = &expression[..cursor];
+        let frames = match scan(prefix) {
+            Some(frames) => frames,
+            None => return vec![],
+        };
+        let frame = frames.last().unwrap();
+        if frame.in_key {
+            return vec![];
+        }
+        let start = partial_start(prefix);
+        let partial = &prefix[start..];
+        let segment = if frame.segment <= start { prefix[frame.segment..start].trim() } else { "" };
+        let context = self.frame_context(prefix, &frames, frames.len() - 1, sample);
+        let mut completions = vec![];
+        if segment.ends_with('.') {
+            let path = segment[..segment.len() - 1].trim();
+            let values = self.eval_all(path, &context);
+            push_fields(&mut completions, &values, partial, start);
+        } else if segment == "&" {
+            push_fields(&mut completions, &context, partial, start);
+        } else if segment.is_empty() {
+            push_fields(&mut completions, &context, partial, start);
+            for metadata in self.metadata() {
+                if metadata.name.starts_with(partial) {
+                    completions.push(Completion {
+                        text: metadata.name,
+                        kind: CompletionKind::Function,
+                        start: start,
+                    });
+                }
+            }
+            if partial.is_empty() {
+                completions.push(Completion {
+                    text: "@".to_owned(),
+                    kind: CompletionKind::Keyword,
+                    start: start,
+                });
+            }
+        }
+        completions
+    }
+
+    /// Computes the values the current node of a frame may take.
+    fn frame_context(&self,
+                     prefix: &str,
+                     frames: &[Frame],
+                     index: usize,
+                     sample: &Rcvar)
+                     -> Vec<Rcvar> {
+        let frame = &frames[index];
+        let mut context = if index == 0 {
+            vec![sample.clone()]
+        } else {
+            let parent = &frames[index - 1];
+            let parent_context = self.frame_context(prefix, frames, index - 1, sample);
+            match frame.group {
+                Group::Filter => {
+                    let lhs = prefix[parent.segment..frame.opener].trim();
+                    elements(&self.eval_all(lhs, &parent_context))
+                }
+                Group::Paren(Some(name_start)) => {
+                    let lhs = trim_dot(prefix[parent.segment..name_start].trim());
+                    let function_context = self.eval_all(lhs, &parent_context);
+                    let segment = prefix[frame.segment..].trim_start();
+                    if segment.starts_with('&') {
+                        // Exprefs are applied to the elements of an array argument.
+                        let args = prefix[frame.start..frame.segment].split(',');
+                        let mut result = vec![];
+                        for arg in args.map(|a| a.trim()) {
+                            if arg.is_empty() || arg.starts_with('&') {
+                                continue;
+                            }
+                            let values = self.eval_all(arg, &function_context);
+                            if values.iter().any(|v| v.is_array()) {
+                                result = elements(&values);
+                                break;
+                            }
+                        }
+                        result
+                    } else {
+                        function_context
+                    }
+                }
+                Group::Paren(None) => parent_context,
+                _ => {
+                    let lhs = trim_dot(prefix[parent.segment..frame.opener].trim());
+                    self.eval_all(lhs, &parent_context)
+                }
+            }
+        };
+        if let Some(pipe) = frame.pipe {
+            context = self.eval_all(prefix[frame.start..pipe].trim(), &context);
+        }
+        context
+    }
+
+    /// Evaluates an expression against each value, ignoring failures.
+    fn eval_all(&self, expression: &str, values: &[Rcvar]) -> Vec<Rcvar> {
+        if expression.is_empty() {
+            return values.to_vec();
+        }
+        let expr = match self.compile(expression) {
+            Ok(expr) => expr,
+            Err(_) => return vec![],
+        };
+        values.iter()
+            .filter_map(|v| expr.search(v.clone()).ok())
+            .filter(|v| !v.is_null())
+            .collect()
+    }
+}
+
+/// Scans the expression, returning the stack of unclosed groups.
+///
+/// Returns None if the prefix ends inside of a quoted token.
+fn scan(prefix: &str) -> Option<Vec<Frame>> {
+    let mut frames = vec![Frame::new(Group::Root, 0, 0)];
+    let mut iter = prefix.char_indices().peekable();
+    while let Some((pos, ch)) = iter.next() {
+        match ch {
+            '"' | '\'' | '`' => {
+                let mut closed = false;
+                while let Some((_, c)) = iter.next() {
+                    if c == '\\' {
+                        iter.next();
+                    } else if c == ch {
+                        closed = true;
+                        break;
+                    }
+                }
+                if !closed {
+                    return None;
+                }
+            }
+            '(' => {
+                let name_start = partial_start(&prefix[..pos]);
+                let name = if name_start < pos { Some(name_start) } else { None };
+                frames.push(Frame::new(Group::Paren(name), pos, pos + 1));
+            }
+            '[' => {
+                match iter.peek() {
+                    Some(&(_, '?')) => {
+                        iter.next();
+                        frames.push(Frame::new(Group::Filter, pos, pos + 2));
+                    }
+                    Some(&(_, ']')) => {
+                        iter.next();
+                    }
+                    _ => frames.push(Frame::new(Group::Bracket, pos, pos + 1)),
+                }
+            }
+            '{' => frames.push(Frame::new(Group::Brace, pos, pos + 1)),
+            ')' | ']' | '}' => {
+                if frames.len() > 1 {
+                    frames.pop();
+                }
+            }
+            ',' | ':' => {
+                let frame = frames.last_mut().unwrap();
+                frame.segment = pos + 1;
+                if frame.group == Group::Brace {
+                    frame.in_key = ch == ',';
+                }
+            }
+            '|' | '&' | '=' | '<' | '>' | '!' => {
+                let mut end = pos + 1;
+                if let Some(&(next_pos, next)) = iter.peek() {
+                    if next == ch || next == '=' {
+                        iter.next();
+                        end = next_pos + 1;
+                    }
+                }
+                let frame = frames.last_mut().unwrap();
+                if ch == '|' && end == pos + 1 {
+                    frame.pipe = Some(pos);
+                }
+                // A single "&" starts an expression reference, which is
+                // part of the operand rather than an operator.
+                if ch != '&' || end != pos + 1 {
+                    frame.segment = end;
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(frames)
+}
+
+/// Returns the byte offset at which a trailing identifier starts.
+fn partial_start(prefix: &str) -> usize {
+    prefix.char_indices()
+        .rev()
+        .take_while(|&(_, c)| is_identifier_char(c))
+        .last()
+        .map(|(pos, _)| pos)
+        .unwrap_or(prefix.len())
+}
+
+#[inline]
+fn is_identifier_char(c: char) -> bool {
+    match c {
+        'a'...'z' | 'A'...'Z' | '0'...'9' | '_' => true,
+        _ => false,
+    }
+}
+
+fn trim_dot(expression: &str) -> &str {
+    if expression.ends_with('.') {
+        expression[..expression.len() - 1].trim()
+    } else {
+        expression
+    }
+}
+
+/// Flattens arrays into their elements.
+fn elements(values: &[Rcvar]) -> Vec<Rcvar> {
+    let mut result = vec![];
+    for value in values {
+        match **value {
+            Variable::Array(ref a) => result.extend(a.iter().cloned()),
+            _ => result.push(value.clone()),
+        }
+    }
+    result
+}
+
+/// Adds the keys of objects (or of objects inside of arrays) as fields.
+fn push_fields(completions: &mut Vec<Completion>, values: &[Rcvar], partial: &str, start: usize) {
+    let mut keys = BTreeSet::new();
+    for value in elements(values) {
+        if let Some(object) = value.as_object() {
+            keys.extend(object.keys().filter(|k| k.starts_with(partial)).cloned());
+        }
+    }
+    for key in keys {
+        let is_identifier = key.chars().next().map_or(false, |c| !c.is_digit(10)) &&
+                            key.chars().all(is_identifier_char);
+        completions.push(Completion {
+            text: if is_identifier { key } else { Variable::String(key).to_string() },
+            kind: CompletionKind::Field,
+            start: start,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Rcvar;
+    use variable::Variable;
+
+    fn sample() -> Rcvar {
+        Rcvar::new(Variable::from_json(r#"{
+            "foo": {"bar": 1, "baz": 2, "qux": 3},
+            "items": [{"price": 1, "priority": "high", "name": "a"}, {"size": 2}],
+            "content-type": "json"
+        }"#).unwrap())
+    }
+
+    fn texts(expr: &str, kind: CompletionKind) -> Vec<String> {
+        complete(expr, expr.len(), &sample())
+            .into_iter()
+            .filter(|c| c.kind == kind)
+            .map(|c| c.text)
+            .collect()
+    }
+
+    #[test]
+    fn completes_fields_after_dot() {
+        assert_eq!(vec!["bar", "baz"], texts("foo.ba", CompletionKind::Field));
+        assert_eq!(vec!["bar", "baz", "qux"], texts("foo.", CompletionKind::Field));
+        assert!(texts("foo.ba", CompletionKind::Function).is_empty());
+    }
+
+    #[test]
+    fn reports_replacement_start() {
+        let completions = complete("foo.ba", 6, &sample());
+        assert_eq!(4, completions[0].start);
+    }
+
+    #[test]
+    fn completes_expref_fields_from_array_argument() {
+        assert_eq!(vec!["price", "priority"],
+                   texts("sort_by(items, &pr", CompletionKind::Field));
+        // The array argument of map() has not been written yet.
+        assert!(texts("map(&n", CompletionKind::Field).is_empty());
+        assert_eq!(vec!["name"], texts("max_by(items, &n", CompletionKind::Field));
+    }
+
+    #[test]
+    fn completes_at_start_of_expression() {
+        let completions = complete("", 0, &sample());
+        let fields = completions.iter()
+            .filter(|c| c.kind == CompletionKind::Field)
+            .map(|c| c.text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["\"content-type\"", "foo", "items"], fields);
+        assert!(completions.iter()
+            .any(|c| c.kind == CompletionKind::Function && c.text == "sort_by"));
+        assert_eq!(Some(&Completion {
+                       text: "@".to_owned(),
+                       kind: CompletionKind::Keyword,
+                       start: 0,
+                   }),
+                   completions.last());
+    }
+
+    #[test]
+    fn completes_functions_by_prefix() {
+        assert_eq!(vec!["sort", "sort_by"], texts("so", CompletionKind::Function));
+        assert_eq!(vec!["to_array", "to_number", "to_string"],
+                   texts("length(to_", CompletionKind::Function));
+    }
+
+    #[test]
+    fn completes_inside_filters() {
+        assert_eq!(vec!["price", "priority"], texts("items[?p", CompletionKind::Field));
+        assert_eq!(vec!["size"], texts("items[?price == `1` && s", CompletionKind::Field));
+    }
+
+    #[test]
+    fn completes_after_pipe_and_in_multi_select_values() {
+        assert_eq!(vec!["bar", "baz", "qux"], texts("foo | ", CompletionKind::Field));
+        assert_eq!(vec!["qux"], texts("foo.{a: q", CompletionKind::Field));
+        assert!(texts("foo.{b", CompletionKind::Field).is_empty());
+    }
+
+    #[test]
+    fn does_not_complete_inside_quotes_or_invalid_paths() {
+        assert!(complete("foo.\"ba", 7, &sample()).is_empty());
+        assert!(texts("nope.", CompletionKind::Field).is_empty());
+        assert!(complete("foo", 100, &sample()).len() > 0);
+    }
+}
@@ -8,25 +8,30 @@ use Context;
 /// JMESPath error.
 #[derive(Clone, Debug, PartialEq)]
 pub struct JmespathError {
-    /// Absolute character position.
+    /// Absolute byte offset in the expression.
     pub offset: usize,
-    /// Line number of the coordinate.
+    /// Line number of the coordinate, starting at 0.
     pub line: usize,
-    /// Column of the line number.
+    /// Column of the line number, in characters and starting at 0.
     pub column: usize,
     /// Expression being evaluated.
     pub expression: String,
     /// Error reason information.
     pub reason: ErrorReason,
+    /// Descriptions of the tokens that would have been valid at the offset
+    /// of a parse error. See `JmespathError::expected`.
+    pub(crate) expected: Vec<String>,
 }
 
 impl JmespathError {
     /// Create a new JMESPath Error.
     pub fn new(expr: &str, offset: usize, reason: ErrorReason) -> JmespathError {
         // Find each new line so we can create a formatted error message.
+        // The offset counts bytes while the column counts characters, so
+        // multi-byte characters only advance the column by one.
         let mut line: usize = 0;
         let mut column: usize = 0;
-        for c in expr.chars().take(offset) {
+        for (_, c) in expr.char_indices().take_while(|&(i, _)| i < offset) {
             match c {
                 '\n' => {
                     line += 1;
@@ -41,6 +46,7 @@ impl JmespathError {
             line: line,
             column: column,
             reason: reason,
+            expected: vec![],
         }
     }
 
@@ -48,6 +54,13 @@ impl JmespathError {
     pub fn from_ctx(ctx: &Context, reason: ErrorReason) -> JmespathError {
         JmespathError::new(ctx.expression, ctx.offset, reason)
     }
+
+    /// Returns descriptions of the tokens that would have been valid at the
+    /// offset of a parse error, e.g., "`]`" or "an identifier". Empty when
+    /// the parser cannot tell.
+    pub fn expected(&self) -> &[String] {
+        &self.expected
+    }
 }
 
 impl Error for JmespathError {
@@ -81,15 +94,27 @@ impl fmt::Display for JmespathError {
             inject_carat(self.column, &mut error_location);
         }
 
+        try!(write!(fmt, "{}", self.reason));
+        if !self.expected.is_empty() {
+            try!(write!(fmt, "; expected {}", join_alternatives(&self.expected)));
+        }
         write!(fmt,
-               "{} (line {}, column {})\n{}",
-               self.reason,
+               " (line {}, column {})\n{}",
                self.line,
                self.column,
                error_location)
     }
 }
 
+/// Joins alternatives into a list like "a, b, or c".
+fn join_alternatives(alternatives: &[String]) -> String {
+    match alternatives.len() {
+        0 | 1 => alternatives.join(""),
+        2 => format!("{} or {}", alternatives[0], alternatives[1]),
+        n => format!("{}, or {}", alternatives[..n - 1].join(", "), alternatives[n - 1]),
+    }
+}
+
 /// Error context to provide specific details about an error.
 #[derive(Clone, Debug, PartialEq)]
 pub enum ErrorReason {
@@ -149,6 +174,74 @@ pub enum RuntimeError {
         /// Which invocation iteration of the expression reference failed.
         invocation: usize,
     },
+    /// Encountered when a function argument has a valid type but an invalid value.
+    InvalidArgument {
+        /// Argument position when calling the function.
+        position: usize,
+        /// Description of why the argument is invalid.
+        reason: String,
+    },
+    /// Encountered when an expression reference fails while being evaluated
+    /// against an element of an array.
+    ExpressionFailed {
+        /// Argument position of the expression reference.
+        position: usize,
+        /// Index of the element the expression reference was evaluated against.
+        invocation: usize,
+        /// Error raised by the expression reference.
+        error: Box<RuntimeError>,
+    },
+    /// Encountered when a variable is referenced outside of a let expression
+    /// that binds it.
+    UndefinedVariable(String),
+    /// Encountered when a function or a search with `SearchOptions` would
+    /// produce more elements than allowed.
+    ResultSizeExceeded {
+        /// Maximum number of elements allowed.
+        limit: usize,
+    },
+    /// Encountered when a computed key of a multi-select hash does not
+    /// evaluate to a string.
+    InvalidKey {
+        /// Type the key evaluated to.
+        actual: String,
+    },
+    /// Encountered when evaluating an expression nests interpreter calls
+    /// deeper than the runtime allows.
+    RecursionLimitExceeded {
+        /// Maximum number of nested interpreter calls.
+        limit: usize,
+    },
+    /// Encountered in a strict search when a field is not in the object it
+    /// is selected from.
+    MissingKey {
+        /// Name of the field.
+        key: String,
+    },
+    /// Encountered in a strict search when a field is selected from a value
+    /// that is not an object.
+    InvalidField {
+        /// Name of the field.
+        key: String,
+        /// Type of the value the field was selected from.
+        actual: String,
+    },
+    /// Encountered in a strict search when an index is selected from a value
+    /// that is not an array.
+    InvalidIndex {
+        /// Index that was selected.
+        index: i64,
+        /// Type of the value the index was selected from.
+        actual: String,
+    },
+    /// Encountered in a strict search when a projection, flatten, or slice
+    /// is applied to a value that it cannot project over.
+    InvalidProjection {
+        /// Type that the projection expects.
+        expected: String,
+        /// Type of the value being projected.
+        actual: String,
+    },
 }
 
 impl fmt::Display for RuntimeError {
@@ -184,6 +277,32 @@ impl fmt::Display for RuntimeError {
                        invocation,
                        actual)
             }
+            InvalidArgument { ref position, ref reason } => {
+                write!(fmt, "Argument {} is invalid: {}", position, reason)
+            }
+            ExpressionFailed { ref position, ref invocation, ref error } => {
+                write!(fmt, "Argument {} failed on invocation {}: {}", position, invocation, error)
+            }
+            UndefinedVariable(ref name) => write!(fmt, "Reference to undefined variable ${}", name),
+            ResultSizeExceeded { ref limit } => {
+                write!(fmt, "Result size exceeds the limit of {} elements", limit)
+            }
+            InvalidKey { ref actual } => {
+                write!(fmt, "Multi-select hash keys must be strings, given {}", actual)
+            }
+            RecursionLimitExceeded { ref limit } => {
+                write!(fmt, "Evaluation depth exceeds the limit of {}", limit)
+            }
+            MissingKey { ref key } => write!(fmt, "Object has no key {:?}", key),
+            InvalidField { ref key, ref actual } => {
+                write!(fmt, "Cannot select key {:?} from {}", key, actual)
+            }
+            InvalidIndex { ref index, ref actual } => {
+                write!(fmt, "Cannot select index {} from {}", index, actual)
+            }
+            InvalidProjection { ref expected, ref actual } => {
+                write!(fmt, "Projection expects {}, given {}", expected, actual)
+            }
         }
     }
 }
@@ -285,4 +404,104 @@ mod test {
         assert_eq!("Argument 0 must return string but invocation 2 returned boolean",
                    error.to_string());
     }
+
+    #[test]
+    fn displays_result_size_exceeded_error() {
+        let error = RuntimeError::ResultSizeExceeded { limit: 10 };
+        assert_eq!("Result size exceeds the limit of 10 elements", error.to_string());
+    }
+
+    #[test]
+    fn displays_recursion_limit_exceeded_error() {
+        let error = RuntimeError::RecursionLimitExceeded { limit: 500 };
+        assert_eq!("Evaluation depth exceeds the limit of 500", error.to_string());
+    }
+
+    #[test]
+    fn displays_strict_errors() {
+        let error = RuntimeError::MissingKey { key: "foo".to_owned() };
+        assert_eq!("Object has no key \"foo\"", error.to_string());
+        let error = RuntimeError::InvalidField {
+            key: "foo".to_owned(),
+            actual: "number".to_owned(),
+        };
+        assert_eq!("Cannot select key \"foo\" from number", error.to_string());
+        let error = RuntimeError::InvalidIndex {
+            index: -1,
+            actual: "object".to_owned(),
+        };
+        assert_eq!("Cannot select index -1 from object", error.to_string());
+        let error = RuntimeError::InvalidProjection {
+            expected: "array".to_owned(),
+            actual: "null".to_owned(),
+        };
+        assert_eq!("Projection expects array, given null", error.to_string());
+    }
+
+    #[test]
+    fn displays_undefined_variable_error() {
+        let error = RuntimeError::UndefinedVariable("foo".to_owned());
+        assert_eq!("Reference to undefined variable $foo", error.to_string());
+    }
+
+    #[test]
+    fn displays_invalid_key_error() {
+        let error = RuntimeError::InvalidKey { actual: "number".to_owned() };
+        assert_eq!("Multi-select hash keys must be strings, given number", error.to_string());
+    }
+
+    #[test]
+    fn displays_invalid_argument_error() {
+        let error = RuntimeError::InvalidArgument {
+            position: 1,
+            reason: "bad pattern".to_owned(),
+        };
+        assert_eq!("Argument 1 is invalid: bad pattern", error.to_string());
+    }
+
+    #[test]
+    fn displays_expression_failed_error() {
+        let error = RuntimeError::ExpressionFailed {
+            position: 0,
+            invocation: 2,
+            error: Box::new(RuntimeError::InvalidSlice),
+        };
+        assert_eq!("Argument 0 failed on invocation 2: Invalid slice", error.to_string());
+    }
+
+    #[test]
+    fn displays_errors_on_the_last_of_several_lines() {
+        let err = ::parse("foo\n  | bar\n  | baz ~ qux").unwrap_err();
+        assert_eq!(2, err.line);
+        assert_eq!(8, err.column);
+        assert_eq!("Parse error: Invalid character: ~ (line 2, column 8)\n\
+                    foo\n  | bar\n  | baz ~ qux\n        ^\n",
+                   err.to_string());
+    }
+
+    #[test]
+    fn counts_columns_in_characters_after_multi_byte_characters() {
+        let err = ::parse("\"\u{1f600}\u{e9}\" ~").unwrap_err();
+        assert_eq!(9, err.offset);
+        assert_eq!(0, err.line);
+        assert_eq!(5, err.column);
+        assert_eq!("Parse error: Invalid character: ~ (line 0, column 5)\n\
+                    \"\u{1f600}\u{e9}\" ~\n     ^\n",
+                   err.to_string());
+        let reason = ErrorReason::Parse("Test".to_owned());
+        let err = JmespathError::new("\u{e9}\n\u{e9}\u{e9}.", 7, reason);
+        assert_eq!((1, 2), (err.line, err.column));
+    }
+
+    #[test]
+    fn displays_expected_alternatives() {
+        let mut err = JmespathError::new("a.]", 2, ErrorReason::Parse("Test".to_owned()));
+        err.expected = vec!["`*`".to_owned()];
+        assert!(err.to_string().starts_with("Parse error: Test; expected `*` (line 0"));
+        err.expected.push("`[`".to_owned());
+        assert!(err.to_string().starts_with("Parse error: Test; expected `*` or `[` (line 0"));
+        err.expected.push("an identifier".to_owned());
+        assert!(err.to_string()
+            .starts_with("Parse error: Test; expected `*`, `[`, or an identifier (line 0"));
+    }
 }
@@ -0,0 +1,339 @@
+//! Explains how a search evaluates each node of an expression.
+//!
+//! A search with a trace evaluates each node of the expression on its own,
+//! and records what the node was given and what it returned. The trace is
+//! then arranged into an `Explanation` that mirrors the AST.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::mem::{discriminant, Discriminant};
+
+use ast::Ast;
+use interpreter::SearchResult;
+use variable::Variable;
+
+/// Maximum number of characters of the JSON of a value in an explanation.
+const SNIPPET_LENGTH: usize = 80;
+
+/// How a search evaluated a node of an expression, and its children.
+///
+/// The tree mirrors the AST of the expression. A node that is evaluated
+/// several times, such as the right-hand side of a projection, appears once,
+/// with the input and output of its first evaluation. A node that is never
+/// evaluated has no input or output.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Explanation {
+    /// Kind of the node, named after its `Ast` variant (e.g., `Projection`).
+    pub kind: String,
+    /// Expression of the node, as printed from the AST.
+    pub expression: String,
+    /// Approximate position of the node in the parsed expression.
+    pub offset: usize,
+    /// Number of times the node was evaluated.
+    pub evaluations: usize,
+    /// JSON of the data that the node was first evaluated with, truncated
+    /// to 80 characters.
+    pub input: Option<String>,
+    /// JSON of the result of the first evaluation, truncated to 80
+    /// characters.
+    pub output: Option<String>,
+    /// Error of the first evaluation, if it failed.
+    pub error: Option<String>,
+    /// Number of elements that a projection visited, over all of its
+    /// evaluations.
+    pub visited: Option<usize>,
+    /// Number of elements that a filter kept.
+    pub kept: Option<usize>,
+    /// Number of elements that a filter dropped.
+    pub dropped: Option<usize>,
+    /// Explanations of the children of the node, in the order in which they
+    /// appear in the expression.
+    pub children: Vec<Explanation>,
+}
+
+/// Prints the tree with a line for each node, indenting children.
+impl fmt::Display for Explanation {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        self.write(fmt, 0)
+    }
+}
+
+impl Explanation {
+    fn write(&self, fmt: &mut fmt::Formatter, depth: usize) -> Result<(), fmt::Error> {
+        try!(write!(fmt, "{:indent$}{} {}", "", self.kind, self.expression, indent = depth * 2));
+        match (&self.input, &self.output, &self.error) {
+            (&Some(ref input), &Some(ref output), _) => {
+                try!(write!(fmt, ": {} -> {}", input, output))
+            }
+            (&Some(ref input), _, &Some(ref error)) => {
+                try!(write!(fmt, ": {} -> {}", input, error))
+            }
+            _ => try!(write!(fmt, ": not evaluated")),
+        }
+        let mut notes = vec![];
+        if self.evaluations > 1 {
+            notes.push(format!("{} evaluations", self.evaluations));
+        }
+        let counts = [(self.visited, "visited"), (self.kept, "kept"), (self.dropped, "dropped")];
+        for &(count, label) in &counts {
+            if let Some(count) = count {
+                notes.push(format!("{} {}", count, label));
+            }
+        }
+        if !notes.is_empty() {
+            try!(write!(fmt, " ({})", notes.join(", ")));
+        }
+        for child in &self.children {
+            try!(writeln!(fmt));
+            try!(child.write(fmt, depth + 1));
+        }
+        Ok(())
+    }
+}
+
+/// What a search recorded about a node.
+#[derive(Default)]
+struct Record {
+    evaluations: usize,
+    input: Option<String>,
+    output: Option<String>,
+    error: Option<String>,
+    visited: usize,
+    kept: usize,
+    dropped: usize,
+}
+
+/// Records of the nodes that a search evaluates.
+///
+/// Nodes are identified by their offset and kind rather than their address,
+/// since functions evaluate copies of the nodes of expression references.
+#[derive(Default)]
+pub(crate) struct Trace {
+    records: HashMap<(usize, Discriminant<Ast>), Record>,
+}
+
+impl Trace {
+    fn record(&mut self, node: &Ast) -> &mut Record {
+        self.records.entry((node.offset(), discriminant(node))).or_insert_with(Record::default)
+    }
+
+    /// Records that a node is evaluated with data, returning true if it is
+    /// the first evaluation of the node.
+    pub(crate) fn enter(&mut self, node: &Ast, data: &Variable) -> bool {
+        let record = self.record(node);
+        record.evaluations += 1;
+        if record.evaluations == 1 {
+            record.input = Some(snippet(data));
+        }
+        record.evaluations == 1
+    }
+
+    /// Records the result of the first evaluation of a node.
+    pub(crate) fn leave(&mut self, node: &Ast, result: &SearchResult) {
+        let record = self.record(node);
+        match *result {
+            Ok(ref value) => record.output = Some(snippet(value)),
+            Err(ref err) => record.error = Some(err.reason.to_string()),
+        }
+    }
+
+    /// Records that a projection visits a number of elements.
+    pub(crate) fn project(&mut self, node: &Ast, count: usize) {
+        self.record(node).visited += count;
+    }
+
+    /// Records whether a filter keeps an element.
+    pub(crate) fn filter(&mut self, node: &Ast, kept: bool) {
+        let record = self.record(node);
+        if kept {
+            record.kept += 1;
+        } else {
+            record.dropped += 1;
+        }
+    }
+
+    /// Arranges the records of the nodes into a tree that mirrors the AST.
+    pub(crate) fn explain(&mut self, node: &Ast) -> Explanation {
+        let record = self.records
+            .remove(&(node.offset(), discriminant(node)))
+            .unwrap_or_else(Record::default);
+        let is_projection = match *node {
+            Ast::Projection { .. } => true,
+            _ => false,
+        };
+        let is_filter = match *node {
+            Ast::Condition { .. } => true,
+            _ => false,
+        };
+        Explanation {
+            kind: kind(node).to_owned(),
+            expression: node.to_string(),
+            offset: node.offset(),
+            evaluations: record.evaluations,
+            input: record.input,
+            output: record.output,
+            error: record.error,
+            visited: if is_projection { Some(record.visited) } else { None },
+            kept: if is_filter { Some(record.kept) } else { None },
+            dropped: if is_filter { Some(record.dropped) } else { None },
+            children: node.children().into_iter().map(|child| self.explain(child)).collect(),
+        }
+    }
+}
+
+/// Returns the JSON of a value, truncated to `SNIPPET_LENGTH` characters.
+fn snippet(value: &Variable) -> String {
+    let json = value.to_string();
+    match json.char_indices().nth(SNIPPET_LENGTH) {
+        Some((end, _)) => format!("{}...", &json[..end]),
+        None => json,
+    }
+}
+
+/// Returns the name of the `Ast` variant of a node.
+fn kind(node: &Ast) -> &'static str {
+    match *node {
+        Ast::Comparison { .. } => "Comparison",
+        Ast::Arithmetic { .. } => "Arithmetic",
+        Ast::Condition { .. } => "Condition",
+        Ast::Identity { .. } => "Identity",
+        Ast::RootNode { .. } => "RootNode",
+        Ast::Expref { .. } => "Expref",
+        Ast::Flatten { .. } => "Flatten",
+        Ast::Function { .. } => "Function",
+        Ast::Field { .. } => "Field",
+        Ast::Index { .. } => "Index",
+        Ast::Literal { .. } => "Literal",
+        Ast::MultiList { .. } => "MultiList",
+        Ast::MultiHash { .. } => "MultiHash",
+        Ast::Not { .. } => "Not",
+        Ast::Negate { .. } => "Negate",
+        Ast::Projection { .. } => "Projection",
+        Ast::ObjectValues { .. } => "ObjectValues",
+        Ast::And { .. } => "And",
+        Ast::Or { .. } => "Or",
+        Ast::Slice { .. } => "Slice",
+        Ast::Subexpr { .. } => "Subexpr",
+        Ast::VarRef { .. } => "VarRef",
+        Ast::Let { .. } => "Let",
+        Ast::Error { .. } => "Error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use compile;
+
+    fn people() -> Variable {
+        Variable::from_json(r#"{"people": [
+            {"name": "d", "age": 45},
+            {"name": "a", "age": 30},
+            {"name": "c", "age": 31},
+            {"name": "b"}
+        ]}"#)
+            .unwrap()
+    }
+
+    /// Returns the kind, expression, evaluations, and output of the node.
+    fn summary(node: &Explanation) -> (&str, &str, usize, Option<&str>) {
+        (&node.kind,
+         &node.expression,
+         node.evaluations,
+         node.output.as_ref().map(|s| s.as_str()))
+    }
+
+    #[test]
+    fn explains_each_node_of_a_filter_and_pipe() {
+        let explanation = compile("people[?age > `30`].name | sort(@)").unwrap().explain(people());
+        assert_eq!(("Subexpr", "people[?age > `30`].name | sort(@)", 1, Some(r#"["c","d"]"#)),
+                   summary(&explanation));
+        let projection = &explanation.children[0];
+        assert_eq!(("Projection", "people[?age > `30`].name", 1, Some(r#"["d","c"]"#)),
+                   summary(projection));
+        assert_eq!(Some(4), projection.visited);
+        assert_eq!(("Field", "people", 1, Some(&*people().get_field("people").to_string())),
+                   summary(&projection.children[0]));
+        let filter = &projection.children[1];
+        assert_eq!(("Condition", "[?age > `30`].name", 4, Some(r#""d""#)), summary(filter));
+        assert_eq!((Some(2), Some(2)), (filter.kept, filter.dropped));
+        assert_eq!(Some(r#"{"name":"d","age":45}"#.to_owned()), filter.input);
+        let comparison = &filter.children[0];
+        assert_eq!(("Comparison", "age > `30`", 4, Some("true")), summary(comparison));
+        assert_eq!(vec![("Field", "age", 4, Some("45")), ("Literal", "`30`", 4, Some("30"))],
+                   comparison.children.iter().map(summary).collect::<Vec<_>>());
+        assert_eq!(("Field", "name", 2, Some(r#""d""#)), summary(&filter.children[1]));
+        let sort = &explanation.children[1];
+        assert_eq!(("Function", "sort(@)", 1, Some(r#"["c","d"]"#)), summary(sort));
+        assert_eq!(Some(r#"["d","c"]"#.to_owned()), sort.input);
+        assert_eq!(vec![("Identity", "@", 1, Some(r#"["d","c"]"#))],
+                   sort.children.iter().map(summary).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn displays_the_tree_with_a_line_for_each_node() {
+        let data = Variable::from_json(r#"{"a": [1, -2, 3]}"#).unwrap();
+        let explanation = compile("a[?@ > `0`] || b").unwrap().explain(data);
+        assert_eq!(vec![r#"Or a[?@ > `0`] || b: {"a":[1,-2,3]} -> [1,3]"#,
+                        r#"  Projection a[?@ > `0`]: {"a":[1,-2,3]} -> [1,3] (3 visited)"#,
+                        r#"    Field a: {"a":[1,-2,3]} -> [1,-2,3]"#,
+                        r#"    Condition [?@ > `0`]: 1 -> 1 (3 evaluations, 2 kept, 1 dropped)"#,
+                        "      Comparison @ > `0`: 1 -> true (3 evaluations)",
+                        "        Identity @: 1 -> 1 (3 evaluations)",
+                        "        Literal `0`: 1 -> 0 (3 evaluations)",
+                        "      Identity @: 1 -> 1 (2 evaluations)",
+                        "  Field b: not evaluated"],
+                   explanation.to_string().lines().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn serializes_explanations_to_json() {
+        let explanation = compile("people[0].name").unwrap().explain(people());
+        let json = ::serde_json::to_value(&explanation).unwrap();
+        assert_eq!("Subexpr", json["kind"]);
+        assert_eq!("\"d\"", json["output"]);
+        assert_eq!(::serde_json::Value::Null, json["visited"]);
+        assert_eq!("Index", json["children"][0]["children"][1]["kind"]);
+    }
+
+    #[test]
+    fn truncates_large_values() {
+        let data = Variable::Array((0..100).map(|i| ::Rcvar::new(Variable::Number(i as f64)))
+            .collect());
+        let explanation = compile("[-1]").unwrap().explain(data);
+        let input = explanation.input.unwrap();
+        assert_eq!(83, input.chars().count());
+        assert!(input.starts_with("[0,1,2,") && input.ends_with("..."), "{}", input);
+        assert_eq!(Some("99".to_owned()), explanation.output);
+    }
+
+    #[test]
+    fn explains_errors_on_the_nodes_that_raise_them() {
+        let explanation = compile("[length(people[0]), abs(people[0].name)]")
+            .unwrap()
+            .explain(people());
+        let error = "Runtime error: Argument 0 expects type number, given string";
+        assert_eq!(Some(error.to_owned()), explanation.error);
+        assert_eq!(None, explanation.output);
+        assert_eq!(Some("2".to_owned()), explanation.children[0].output);
+        assert_eq!(Some(error.to_owned()), explanation.children[1].error);
+        assert!(explanation.to_string().lines().any(|line| line.ends_with(error)));
+    }
+
+    #[test]
+    fn explains_the_same_results_as_searches() {
+        let expressions = ["people[*].age | max(@)",
+                           "people[?!age].name",
+                           "people[*].{o: age > `40` && !(age > `50`)}",
+                           "sort_by(people[?age], &age)[-1].name",
+                           "people[].*[] | [?@ == 'a' || @ == `30`]",
+                           "let $x = people[1] in people[?age < $x.age]"];
+        for expression in &expressions {
+            let expr = compile(expression).unwrap();
+            assert_eq!(Some(expr.search(people()).unwrap().to_string()),
+                       expr.explain(people()).output,
+                       "{}",
+                       expression);
+        }
+    }
+}
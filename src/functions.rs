@@ -1,21 +1,35 @@
 //! JMESPath functions.
 
-use std::collections::BTreeMap;
 use std::cmp::{max, min};
 use std::fmt;
+use std::str;
+
+use indexmap::IndexMap;
 
 use {Context, JmespathError, ErrorReason, Rcvar, RuntimeError};
+use ast::Ast;
 use interpreter::{interpret, SearchResult};
-use variable::{Variable, JmespathType};
+use variable::{Map, Variable, JmespathType};
 
 /// Represents a JMESPath function.
-pub trait Function: Sync {
+///
+/// Functions must be `Send` and `Sync` so that a `Runtime` can be shared
+/// between threads, e.g., using an `Arc<Runtime>`.
+pub trait Function: Send + Sync {
     /// Evaluates the function against an in-memory variable.
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult;
+
+    /// Returns the signature of the function, if it declares one.
+    ///
+    /// Functions that perform their own argument validation (e.g., plain
+    /// closures) do not declare a signature.
+    fn signature(&self) -> Option<&Signature> {
+        None
+    }
 }
 
 /// Function argument types used when validating.
-#[derive(Clone, PartialEq, Eq, Debug)]
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
 pub enum ArgumentType {
     Any,
     Null,
@@ -93,13 +107,13 @@ pub struct CustomFunction {
     /// Signature used to validate the function.
     signature: Signature,
     /// Function to invoke after validating the signature.
-    f: Box<Fn(&[Rcvar], &mut Context) -> SearchResult + Sync>,
+    f: Box<Fn(&[Rcvar], &mut Context) -> SearchResult + Send + Sync>,
 }
 
 impl CustomFunction {
     /// Creates a new custom function.
     pub fn new(fn_signature: Signature,
-               f: Box<Fn(&[Rcvar], &mut Context) -> SearchResult + Sync>)
+               f: Box<Fn(&[Rcvar], &mut Context) -> SearchResult + Send + Sync>)
                -> CustomFunction {
         CustomFunction {
             signature: fn_signature,
@@ -109,6 +123,10 @@ impl CustomFunction {
 }
 
 impl Function for CustomFunction {
+    fn signature(&self) -> Option<&Signature> {
+        Some(&self.signature)
+    }
+
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
         (self.f)(args, ctx)
@@ -121,7 +139,7 @@ impl Function for CustomFunction {
 /// If you wish to utilize Signatures or more complex argument
 /// validation, it is recommended to use CustomFunction.
 impl<F> Function for F
-    where F: Sync + Fn(&[Rcvar], &mut Context) -> SearchResult
+    where F: Send + Sync + Fn(&[Rcvar], &mut Context) -> SearchResult
 {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         (self)(args, ctx)
@@ -129,9 +147,15 @@ impl<F> Function for F
 }
 
 /// Represents a function's signature.
-#[derive(Clone, PartialEq, Eq, Debug)]
+///
+/// Arguments are made up of the required `inputs`, followed by any number
+/// of the optional arguments in order, followed by any number of
+/// `variadic` arguments. Signatures with optional arguments are created with
+/// `Signature::with_optional`.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
 pub struct Signature {
     pub inputs: Vec<ArgumentType>,
+    optional: Vec<ArgumentType>,
     pub variadic: Option<ArgumentType>,
 }
 
@@ -140,54 +164,78 @@ impl Signature {
     pub fn new(inputs: Vec<ArgumentType>, variadic: Option<ArgumentType>) -> Signature {
         Signature {
             inputs: inputs,
+            optional: vec![],
             variadic: variadic,
         }
     }
 
+    /// Creates a Signature that accepts the required `inputs` followed by
+    /// up to all of the `optional` arguments.
+    pub fn with_optional(inputs: Vec<ArgumentType>, optional: Vec<ArgumentType>) -> Signature {
+        Signature {
+            inputs: inputs,
+            optional: optional,
+            variadic: None,
+        }
+    }
+
+    /// Returns the types of the optional arguments that may follow the
+    /// required inputs.
+    pub fn optional(&self) -> &[ArgumentType] {
+        &self.optional
+    }
+
+    /// Returns the minimum number of arguments accepted by the signature.
+    pub fn min_arity(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// Returns the maximum number of arguments accepted by the signature,
+    /// or None if the signature is variadic.
+    pub fn max_arity(&self) -> Option<usize> {
+        match self.variadic {
+            Some(_) => None,
+            None => Some(self.inputs.len() + self.optional.len()),
+        }
+    }
+
+    /// Returns the type of the argument at the given position, or None if
+    /// the signature accepts no argument there.
+    pub fn argument_type(&self, position: usize) -> Option<&ArgumentType> {
+        self.inputs
+            .get(position)
+            .or_else(|| self.optional.get(position - self.inputs.len()))
+            .or(self.variadic.as_ref())
+    }
+
     /// Validates the arity of a function. If the arity is invalid, a runtime
     /// error is returned with the relative position of the error and the
     /// expression that was being executed.
     pub fn validate_arity(&self, actual: usize, ctx: &Context) -> Result<(), JmespathError> {
-        let expected = self.inputs.len();
-        if self.variadic.is_some() {
-            if actual >= expected {
-                Ok(())
-            } else {
-                let reason = ErrorReason::Runtime(RuntimeError::NotEnoughArguments {
-                    expected: expected,
+        let min = self.min_arity();
+        let reason = match self.max_arity() {
+            _ if actual < min => {
+                RuntimeError::NotEnoughArguments {
+                    expected: min,
                     actual: actual,
-                });
-                Err(JmespathError::from_ctx(ctx, reason))
+                }
             }
-        } else if actual == expected {
-            Ok(())
-        } else if actual < expected {
-            let reason = ErrorReason::Runtime(RuntimeError::NotEnoughArguments {
-                expected: expected,
-                actual: actual,
-            });
-            Err(JmespathError::from_ctx(ctx, reason))
-        } else {
-            let reason = ErrorReason::Runtime(RuntimeError::TooManyArguments {
-                expected: expected,
-                actual: actual,
-            });
-            Err(JmespathError::from_ctx(ctx, reason))
-        }
+            Some(max) if actual > max => {
+                RuntimeError::TooManyArguments {
+                    expected: max,
+                    actual: actual,
+                }
+            }
+            _ => return Ok(()),
+        };
+        Err(JmespathError::from_ctx(ctx, ErrorReason::Runtime(reason)))
     }
 
     /// Validates the provided function arguments against the signature.
     pub fn validate(&self, args: &[Rcvar], ctx: &Context) -> Result<(), JmespathError> {
         try!(self.validate_arity(args.len(), ctx));
-        if let Some(ref variadic) = self.variadic {
-            for (k, v) in args.iter().enumerate() {
-                let validator = self.inputs.get(k).unwrap_or(variadic);
-                try!(self.validate_arg(ctx, k, v, validator));
-            }
-        } else {
-            for (k, v) in args.iter().enumerate() {
-                try!(self.validate_arg(ctx, k, v, &self.inputs[k]));
-            }
+        for (k, v) in args.iter().enumerate() {
+            try!(self.validate_arg(ctx, k, v, self.argument_type(k).unwrap()));
         }
         Ok(())
     }
@@ -211,9 +259,84 @@ impl Signature {
     }
 }
 
+/// Serializable description of an argument type.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+pub struct ArgumentMetadata {
+    /// Argument type as an `ArgumentType` enum.
+    #[serde(rename = "type")]
+    pub argument_type: ArgumentType,
+    /// Display string of the argument type (e.g., "array[number]").
+    pub display: String,
+}
+
+impl ArgumentMetadata {
+    /// Creates argument metadata from an `ArgumentType`.
+    pub fn new(argument_type: &ArgumentType) -> ArgumentMetadata {
+        ArgumentMetadata {
+            argument_type: argument_type.clone(),
+            display: argument_type.to_string(),
+        }
+    }
+}
+
+/// Machine-readable metadata describing a registered function.
+///
+/// Functions that do not declare a `Signature` (e.g., closures) are
+/// reported with a `signature` of `None`.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+pub struct FunctionMetadata {
+    /// Name the function is registered under.
+    pub name: String,
+    /// Declared signature details, if the function has a signature.
+    pub signature: Option<SignatureMetadata>,
+}
+
+/// Serializable description of a function `Signature`.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+pub struct SignatureMetadata {
+    /// Minimum number of arguments.
+    pub min_arity: usize,
+    /// Maximum number of arguments, or None when variadic.
+    pub max_arity: Option<usize>,
+    /// Positional argument types.
+    pub inputs: Vec<ArgumentMetadata>,
+    /// Types of the optional arguments that may follow the inputs.
+    pub optional: Vec<ArgumentMetadata>,
+    /// Type of any variadic arguments.
+    pub variadic: Option<ArgumentMetadata>,
+}
+
+impl FunctionMetadata {
+    /// Creates function metadata from a name and function.
+    pub fn new(name: &str, function: &Function) -> FunctionMetadata {
+        FunctionMetadata {
+            name: name.to_owned(),
+            signature: function.signature().map(|sig| {
+                SignatureMetadata {
+                    min_arity: sig.min_arity(),
+                    max_arity: sig.max_arity(),
+                    inputs: sig.inputs.iter().map(ArgumentMetadata::new).collect(),
+                    optional: sig.optional.iter().map(ArgumentMetadata::new).collect(),
+                    variadic: sig.variadic.as_ref().map(ArgumentMetadata::new),
+                }
+            }),
+        }
+    }
+}
+
 /// Macro to more easily and quickly define a function and signature.
+///
+/// Defines the function's struct and implements `Function` for it with the
+/// given items (e.g., `evaluate`), returning the signature from `signature`.
 macro_rules! defn {
-    ($name:ident, $args:expr, $variadic:expr) => {
+    ($(#[$attr:meta])* $name:ident, $args:expr, optional $optional:expr; $($item:tt)*) => {
+        defn!(@define $(#[$attr])* $name, Signature::with_optional($args, $optional); $($item)*);
+    };
+    ($(#[$attr:meta])* $name:ident, $args:expr, $variadic:expr; $($item:tt)*) => {
+        defn!(@define $(#[$attr])* $name, Signature::new($args, $variadic); $($item)*);
+    };
+    (@define $(#[$attr:meta])* $name:ident, $signature:expr; $($item:tt)*) => {
+        $(#[$attr])*
         pub struct $name {
             signature: Signature,
         }
@@ -221,10 +344,18 @@ macro_rules! defn {
         impl $name {
             pub fn new() -> $name {
                 $name {
-                    signature: Signature::new($args, $variadic),
+                    signature: $signature,
                 }
             }
         }
+
+        impl Function for $name {
+            fn signature(&self) -> Option<&Signature> {
+                Some(&self.signature)
+            }
+
+            $($item)*
+        }
     };
 }
 
@@ -239,7 +370,7 @@ macro_rules! min_and_max_by {
             }
             let ast = $args[1].as_expref().unwrap();
             // Map over the first value to get the homogeneous required return type
-            let initial = try!(interpret(&vals[0], &ast, $ctx));
+            let initial = try!(interpret_element(&vals[0], &ast, 1, 0, $ctx));
             let entered_type = initial.get_type();
             if entered_type != JmespathType::String && entered_type != JmespathType::Number {
                 return Err(JmespathError::from_ctx($ctx,
@@ -247,14 +378,14 @@ macro_rules! min_and_max_by {
                         expected: "expression->number|expression->string".to_owned(),
                         actual: entered_type.to_string(),
                         position: 1,
-                        invocation: 1
+                        invocation: 0
                     }
                 )));
             }
             // Map over each value, finding the best candidate value and fail on error.
             let mut candidate = (vals[0].clone(), initial.clone());
             for (invocation, v) in vals.iter().enumerate().skip(1) {
-                let mapped = try!(interpret(v, &ast, $ctx));
+                let mapped = try!(interpret_element(v, &ast, 1, invocation, $ctx));
                 if mapped.get_type() != entered_type {
                     return Err(JmespathError::from_ctx($ctx,
                         ErrorReason::Runtime(RuntimeError::InvalidReturnType {
@@ -292,9 +423,214 @@ macro_rules! min_and_max {
     )
 }
 
-defn!(AbsFn, vec![arg!(number)], None);
+// Declared after the macros above so that submodules can use them.
+#[cfg(feature = "hash-functions")]
+pub mod hash;
+#[cfg(feature = "regex-functions")]
+pub mod regex;
+#[cfg(feature = "time-functions")]
+pub mod time;
+
+/// Returns a numeric argument as a non-negative integer.
+///
+/// Negative and fractional numbers are rejected with an `InvalidType` error
+/// that points at the argument's position.
+fn count_arg(args: &[Rcvar], position: usize, ctx: &Context) -> Result<usize, JmespathError> {
+    let n = args[position].as_number().unwrap();
+    if n >= 0.0 && n.fract() == 0.0 {
+        Ok(n as usize)
+    } else {
+        let reason = ErrorReason::Runtime(RuntimeError::InvalidType {
+            expected: "non-negative integer".to_owned(),
+            actual: args[position].get_type().to_string(),
+            position: position,
+        });
+        Err(JmespathError::from_ctx(ctx, reason))
+    }
+}
+
+/// Returns a numeric argument as an integer.
+///
+/// Fractional numbers are rejected with an `InvalidType` error that points at
+/// the argument's position.
+fn integer_arg(args: &[Rcvar], position: usize, ctx: &Context) -> Result<i64, JmespathError> {
+    let n = args[position].as_number().unwrap();
+    if n.fract() == 0.0 {
+        Ok(n as i64)
+    } else {
+        let reason = ErrorReason::Runtime(RuntimeError::InvalidType {
+            expected: "integer".to_owned(),
+            actual: args[position].get_type().to_string(),
+            position: position,
+        });
+        Err(JmespathError::from_ctx(ctx, reason))
+    }
+}
+
+/// Resolves an optional start or end argument of find_first and find_last.
+///
+/// Negative values count back from the end of the subject, as in Python's
+/// `str.find`. Values past the end of the subject are not clamped.
+fn find_bound(arg: Option<&Rcvar>, len: usize, default: usize) -> usize {
+    match arg.and_then(|v| v.as_number()) {
+        None => default,
+        Some(n) if n < 0.0 => max(0, len as i64 + n as i64) as usize,
+        Some(n) => n as usize,
+    }
+}
+
+/// Returns the code point index of the first or last occurrence of a
+/// substring within the optional [start, end) bounds, or null.
+fn find_substring(args: &[Rcvar], last: bool) -> Rcvar {
+    let subject = args[0].as_string().unwrap().chars().collect::<Vec<char>>();
+    let needle = args[1].as_string().unwrap().chars().collect::<Vec<char>>();
+    let start = find_bound(args.get(2), subject.len(), 0);
+    let end = min(subject.len(), find_bound(args.get(3), subject.len(), subject.len()));
+    if start > end || end - start < needle.len() {
+        return Rcvar::new(Variable::Null);
+    }
+    let mut candidates = start..(end - needle.len() + 1);
+    let found = if last {
+        candidates.rev().find(|&i| subject[i..i + needle.len()] == needle[..])
+    } else {
+        candidates.find(|&i| subject[i..i + needle.len()] == needle[..])
+    };
+    match found {
+        Some(i) => Rcvar::new(Variable::Number(i as f64)),
+        None => Rcvar::new(Variable::Null),
+    }
+}
+
+/// Trims whitespace, or the code points of an optional second argument, from
+/// either end of a string.
+fn trim_string(args: &[Rcvar], left: bool, right: bool) -> Rcvar {
+    let subject = args[0].as_string().unwrap().as_str();
+    let trimmed = match args.get(1) {
+        None => {
+            match (left, right) {
+                (true, true) => subject.trim(),
+                (true, false) => subject.trim_start(),
+                _ => subject.trim_end(),
+            }
+        }
+        Some(chars) => {
+            let chars = chars.as_string().unwrap().chars().collect::<Vec<char>>();
+            let mut result = subject;
+            if left {
+                result = result.trim_start_matches(&chars[..]);
+            }
+            if right {
+                result = result.trim_end_matches(&chars[..]);
+            }
+            result
+        }
+    };
+    Rcvar::new(Variable::String(trimmed.to_owned()))
+}
+
+/// Returns true if `values` contains an element deeply equal to `value`.
+///
+/// Numbers are compared by value, so `1` and `1.0` are considered equal.
+fn contains_value(values: &[Rcvar], value: &Rcvar) -> bool {
+    values.iter().any(|v| v == value)
+}
+
+/// Returns the distinct elements of `values` for which `keep` returns true,
+/// appended to `distinct` in their original order.
+fn collect_distinct<F>(distinct: &mut Vec<Rcvar>, values: &[Rcvar], keep: F)
+    where F: Fn(&Rcvar) -> bool
+{
+    for value in values {
+        if keep(value) && !contains_value(distinct, value) {
+            distinct.push(value.clone());
+        }
+    }
+}
+
+/// Wraps a number, mapping NaN and infinite values (which are not valid JSON)
+/// to null.
+fn finite_number(n: f64) -> Rcvar {
+    if n.is_finite() {
+        Rcvar::new(Variable::Number(n))
+    } else {
+        Rcvar::new(Variable::Null)
+    }
+}
+
+/// Interprets an expression reference against an element of an array.
+///
+/// Runtime errors are wrapped in an `ExpressionFailed` error that reports the
+/// argument position of the expression reference and the element's index,
+/// except for reaching the runtime's recursion limit.
+fn interpret_element(value: &Rcvar,
+                     ast: &Ast,
+                     position: usize,
+                     invocation: usize,
+                     ctx: &mut Context)
+                     -> SearchResult {
+    interpret(value, ast, ctx).map_err(|e| match e.reason {
+        // The limit applies to the whole search rather than to an element.
+        ErrorReason::Runtime(RuntimeError::RecursionLimitExceeded { .. }) => e,
+        ErrorReason::Runtime(error) => {
+            JmespathError {
+                reason: ErrorReason::Runtime(RuntimeError::ExpressionFailed {
+                    position: position,
+                    invocation: invocation,
+                    error: Box::new(error),
+                }),
+                ..e
+            }
+        }
+        ErrorReason::Parse(_) => e,
+    })
+}
+
+/// Interprets an expression reference against each element of an array,
+/// requiring every result to be a number.
+fn map_numbers(values: &[Rcvar], ast: &Ast, ctx: &mut Context) -> Result<Vec<f64>, JmespathError> {
+    let mut numbers = Vec::with_capacity(values.len());
+    for (invocation, v) in values.iter().enumerate() {
+        let mapped = try!(interpret_element(v, ast, 1, invocation, ctx));
+        match mapped.as_number() {
+            Some(n) => numbers.push(n),
+            None => {
+                return Err(JmespathError::from_ctx(ctx,
+                    ErrorReason::Runtime(RuntimeError::InvalidReturnType {
+                        expected: "expression->number".to_owned(),
+                        actual: mapped.get_type().to_string(),
+                        position: 1,
+                        invocation: invocation,
+                    }
+                )));
+            }
+        }
+    }
+    Ok(numbers)
+}
+
+/// Returns the elements of an array of numbers in ascending order.
+fn sorted_numbers(value: &Rcvar) -> Vec<f64> {
+    let mut numbers = value.as_array().unwrap().iter()
+        .map(|n| n.as_number().unwrap())
+        .collect::<Vec<f64>>();
+    // JSON numbers are never NaN, so every pair of elements is comparable.
+    numbers.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    numbers
+}
+
+/// Returns the `p`th percentile (between 0 and 100) of a non-empty slice of
+/// sorted numbers, interpolating linearly between the two closest ranks.
+fn percentile_of(sorted: &[f64], p: f64) -> f64 {
+    let rank = p / 100.0 * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    let fraction = rank - lower as f64;
+    sorted[lower] * (1.0 - fraction) + sorted[upper] * fraction
+}
+
+defn! {
+    AbsFn, vec![arg!(number)], None;
 
-impl Function for AbsFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
         match *args[0] {
@@ -304,22 +640,56 @@ impl Function for AbsFn {
     }
 }
 
-defn!(AvgFn, vec![arg!(array_number)], None);
+defn! {
+    AvgFn, vec![arg!(array_number)], None;
 
-impl Function for AvgFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
         let values = args[0].as_array().unwrap();
+        if values.is_empty() {
+            return Ok(Rcvar::new(Variable::Null));
+        }
         let sum = values.iter()
             .map(|n| n.as_number().unwrap())
             .fold(0f64, |a, ref b| a + b);
-        Ok(Rcvar::new(Variable::Number(sum / (values.len() as f64))))
+        Ok(finite_number(sum / (values.len() as f64)))
+    }
+}
+
+defn! {
+    AvgByFn, vec![arg!(array), arg!(expref)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let ast = args[1].as_expref().unwrap();
+        let numbers = try!(map_numbers(args[0].as_array().unwrap(), &ast, ctx));
+        if numbers.is_empty() {
+            Ok(Rcvar::new(Variable::Null))
+        } else {
+            let sum = numbers.iter().fold(0.0, |a, n| a + n);
+            Ok(finite_number(sum / numbers.len() as f64))
+        }
+    }
+}
+
+defn! {
+    ByteLengthFn, vec![arg!(array | object | string)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        match *args[0] {
+            Variable::Array(ref a) => Ok(Rcvar::new(Variable::Number(a.len() as f64))),
+            Variable::Object(ref m) => Ok(Rcvar::new(Variable::Number(m.len() as f64))),
+            // Unlike length, strings are measured in UTF-8 bytes.
+            Variable::String(ref s) => Ok(Rcvar::new(Variable::Number(s.len() as f64))),
+            _ => unreachable!(),
+        }
     }
 }
 
-defn!(CeilFn, vec![arg!(number)], None);
+defn! {
+    CeilFn, vec![arg!(number)], None;
 
-impl Function for CeilFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
         let n = args[0].as_number().unwrap();
@@ -327,9 +697,31 @@ impl Function for CeilFn {
     }
 }
 
-defn!(ContainsFn, vec![arg!(string | array), arg!(any)], None);
+defn! {
+    ChunkFn, vec![arg!(array), arg!(number)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let size = args[1].as_number().unwrap();
+        if size < 1.0 || size.fract() != 0.0 {
+            let reason = ErrorReason::Runtime(RuntimeError::InvalidType {
+                expected: "positive integer".to_owned(),
+                actual: args[1].get_type().to_string(),
+                position: 1,
+            });
+            return Err(JmespathError::from_ctx(ctx, reason));
+        }
+        let chunks = args[0].as_array().unwrap()
+            .chunks(size as usize)
+            .map(|chunk| Rcvar::new(Variable::Array(chunk.to_vec())))
+            .collect();
+        Ok(Rcvar::new(Variable::Array(chunks)))
+    }
+}
+
+defn! {
+    ContainsFn, vec![arg!(string | array), arg!(any)], None;
 
-impl Function for ContainsFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
         let haystack = &args[0];
@@ -347,303 +739,2275 @@ impl Function for ContainsFn {
     }
 }
 
-defn!(EndsWithFn, vec![arg!(string), arg!(string)], None);
+defn! {
+    CountFn, vec![arg!(array), arg!(expref)], None;
 
-impl Function for EndsWithFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
-        let subject = args[0].as_string().unwrap();
-        let search = args[1].as_string().unwrap();
-        Ok(Rcvar::new(Variable::Bool(subject.ends_with(search))))
+        let ast = args[1].as_expref().unwrap();
+        let mut count = 0;
+        for (invocation, value) in args[0].as_array().unwrap().iter().enumerate() {
+            if try!(interpret_element(value, &ast, 1, invocation, ctx)).is_truthy() {
+                count += 1;
+            }
+        }
+        Ok(Rcvar::new(Variable::Number(count as f64)))
     }
 }
 
-defn!(FloorFn, vec![arg!(number)], None);
+defn! {
+    DifferenceFn, vec![arg!(array), arg!(array)], None;
 
-impl Function for FloorFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
-        let n = args[0].as_number().unwrap();
-        Ok(Rcvar::new(Variable::Number(n.floor())))
+        let first = args[0].as_array().unwrap();
+        let second = args[1].as_array().unwrap();
+        let mut result = vec![];
+        collect_distinct(&mut result, first, |v| !contains_value(second, v));
+        Ok(Rcvar::new(Variable::Array(result)))
     }
 }
 
-defn!(JoinFn, vec![arg!(string), arg!(array_string)], None);
+defn! {
+    EndsWithFn, vec![arg!(string), arg!(string)], None;
 
-impl Function for JoinFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
-        let glue = args[0].as_string().unwrap();
-        let values = args[1].as_array().unwrap();
-        let result = values.iter()
-            .map(|v| v.as_string().unwrap())
-            .cloned()
-            .collect::<Vec<String>>()
-            .join(&glue);
-        Ok(Rcvar::new(Variable::String(result)))
+        let subject = args[0].as_string().unwrap();
+        let search = args[1].as_string().unwrap();
+        Ok(Rcvar::new(Variable::Bool(subject.ends_with(search))))
     }
 }
 
-defn!(KeysFn, vec![arg!(object)], None);
+defn! {
+    EnumerateFn, vec![arg!(array)], None;
 
-impl Function for KeysFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
-        let object = args[0].as_object().unwrap();
-        let keys = object.keys()
-            .map(|k| Rcvar::new(Variable::String((*k).clone())))
-            .collect::<Vec<Rcvar>>();
-        Ok(Rcvar::new(Variable::Array(keys)))
+        let pairs = args[0].as_array().unwrap().iter()
+            .enumerate()
+            .map(|(i, v)| {
+                let index = Rcvar::new(Variable::Number(i as f64));
+                Rcvar::new(Variable::Array(vec![index, v.clone()]))
+            })
+            .collect();
+        Ok(Rcvar::new(Variable::Array(pairs)))
     }
 }
 
-defn!(LengthFn, vec![arg!(array | object | string)], None);
+defn! {
+    FindFirstFn, vec![arg!(string), arg!(string)], optional vec![arg!(number), arg!(number)];
 
-impl Function for LengthFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
-        match *args[0] {
-            Variable::Array(ref a) => Ok(Rcvar::new(Variable::Number(a.len() as f64))),
-            Variable::Object(ref m) => Ok(Rcvar::new(Variable::Number(m.len() as f64))),
-            // Note that we need to count the code points not the number of unicode characters
-            Variable::String(ref s) => Ok(Rcvar::new(Variable::Number(s.chars().count() as f64))),
-            _ => unreachable!(),
-        }
+        Ok(find_substring(args, false))
     }
 }
 
-defn!(MapFn, vec![arg!(expref), arg!(array)], None);
+defn! {
+    FindLastFn, vec![arg!(string), arg!(string)], optional vec![arg!(number), arg!(number)];
 
-impl Function for MapFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
-        let ast = args[0].as_expref().unwrap();
-        let values = args[1].as_array().unwrap();
-        let mut results = vec![];
-        for value in values {
-            results.push(try!(interpret(&value, &ast, ctx)));
-        }
-        Ok(Rcvar::new(Variable::Array(results)))
+        Ok(find_substring(args, true))
     }
 }
 
-defn!(MaxFn, vec![arg!(array_string | array_number)], None);
+defn! {
+    FirstFn, vec![arg!(array)], None;
 
-impl Function for MaxFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
-        min_and_max!(max, args)
+        match args[0].as_array().unwrap().first() {
+            Some(value) => Ok(value.clone()),
+            None => Ok(Rcvar::new(Variable::Null)),
+        }
     }
 }
 
-defn!(MinFn, vec![arg!(array_string | array_number)], None);
+defn! {
+    FlattenFn, vec![arg!(array)], optional vec![arg!(number)];
 
-impl Function for MinFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
-        min_and_max!(min, args)
+        let depth = match args.get(1).and_then(|d| d.as_number()) {
+            None => 1,
+            Some(n) if n < 0.0 => usize::max_value(),
+            Some(_) => try!(count_arg(args, 1, ctx)),
+        };
+        // Walk the nested arrays with an explicit stack of (array, next index,
+        // remaining depth) so that deeply nested input cannot overflow the stack.
+        let mut result = vec![];
+        let mut stack = vec![(args[0].as_array().unwrap(), 0, depth)];
+        while let Some((array, index, remaining)) = stack.pop() {
+            if index == array.len() {
+                continue;
+            }
+            stack.push((array, index + 1, remaining));
+            match *array[index] {
+                Variable::Array(ref inner) if remaining > 0 => {
+                    stack.push((inner, 0, remaining - 1));
+                }
+                _ => result.push(array[index].clone()),
+            }
+        }
+        Ok(Rcvar::new(Variable::Array(result)))
     }
 }
 
-defn!(MaxByFn, vec![arg!(array), arg!(expref)], None);
+defn! {
+    FloorFn, vec![arg!(number)], None;
 
-impl Function for MaxByFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
-        min_and_max_by!(ctx, gt, args)
+        let n = args[0].as_number().unwrap();
+        Ok(Rcvar::new(Variable::Number(n.floor())))
     }
 }
 
-defn!(MinByFn, vec![arg!(array), arg!(expref)], None);
+defn! {
+    /// Replaces each `{}` placeholder in a template with the next element of
+    /// an array of arguments.
+    ///
+    /// Strings are inserted as is and other values are inserted as JSON, as
+    /// with `to_string`. `{{` and `}}` produce literal braces. Running out of
+    /// arguments is an error, while extra arguments are ignored.
+    FormatFn, vec![arg!(string), arg!(array)], None;
 
-impl Function for MinByFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
-        min_and_max_by!(ctx, lt, args)
+        let template = args[0].as_string().unwrap();
+        let values = args[1].as_array().unwrap();
+        let mut formatted = String::with_capacity(template.len());
+        let mut placeholder = 0;
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            let reason = match (c, chars.peek().cloned()) {
+                ('{', Some('{')) | ('}', Some('}')) => {
+                    chars.next();
+                    formatted.push(c);
+                    continue;
+                }
+                ('{', Some('}')) => {
+                    chars.next();
+                    match values.get(placeholder) {
+                        Some(value) => {
+                            match **value {
+                                Variable::String(ref s) => formatted.push_str(s),
+                                _ => formatted.push_str(&value.to_string()),
+                            }
+                            placeholder += 1;
+                            continue;
+                        }
+                        None => RuntimeError::InvalidArgument {
+                            position: 1,
+                            reason: format!("no argument for placeholder {}", placeholder),
+                        },
+                    }
+                }
+                ('{', _) | ('}', _) => RuntimeError::InvalidArgument {
+                    position: 0,
+                    reason: format!("unmatched '{}' in template; use '{}{}' for a literal",
+                                    c, c, c),
+                },
+                _ => {
+                    formatted.push(c);
+                    continue;
+                }
+            };
+            return Err(JmespathError::from_ctx(ctx, ErrorReason::Runtime(reason)));
+        }
+        Ok(Rcvar::new(Variable::String(formatted)))
     }
 }
 
-defn!(MergeFn, vec![arg!(object)], Some(arg!(object)));
+defn! {
+    GetFn, vec![arg!(object), arg!(string)], optional vec![arg!(any)];
 
-impl Function for MergeFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
-        let mut result = BTreeMap::new();
-        for arg in args {
-            result.extend(arg.as_object().unwrap().clone());
+        let object = args[0].as_object().unwrap();
+        // Unlike `||`, only a missing or null value falls back to the default.
+        match object.get(args[1].as_string().unwrap()) {
+            Some(value) if !value.is_null() => Ok(value.clone()),
+            _ => Ok(args.get(2).cloned().unwrap_or_else(|| Rcvar::new(Variable::Null))),
         }
-        Ok(Rcvar::new(Variable::Object(result)))
     }
 }
 
-defn!(NotNullFn, vec![arg!(any)], Some(arg!(any)));
+defn! {
+    /// Groups the elements of an array by the string that the expression
+    /// returns for them. Groups appear in the order of their first element.
+    GroupByFn, vec![arg!(array), arg!(expref)], None;
 
-impl Function for NotNullFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
-        for arg in args {
-            if !arg.is_null() {
-                return Ok(arg.clone());
+        let vals = args[0].as_array().unwrap();
+        let ast = args[1].as_expref().unwrap();
+        let mut groups: IndexMap<String, Vec<Rcvar>> = IndexMap::new();
+        for (invocation, v) in vals.iter().enumerate() {
+            let key = try!(interpret_element(v, &ast, 1, invocation, ctx));
+            match *key {
+                Variable::String(ref s) => {
+                    groups.entry(s.clone()).or_insert_with(Vec::new).push(v.clone());
+                }
+                _ => {
+                    return Err(JmespathError::from_ctx(ctx,
+                        ErrorReason::Runtime(RuntimeError::InvalidReturnType {
+                            expected: "expression->string".to_owned(),
+                            actual: key.get_type().to_string(),
+                            position: 1,
+                            invocation: invocation,
+                        }
+                    )));
+                }
             }
         }
-        Ok(Rcvar::new(Variable::Null))
+        let result = groups.into_iter()
+            .map(|(k, v)| (k, Rcvar::new(Variable::Array(v))))
+            .collect();
+        Ok(Rcvar::new(Variable::Object(result)))
     }
 }
 
-defn!(ReverseFn, vec![arg!(array | string)], None);
+defn! {
+    IndexOfFn, vec![arg!(array), arg!(any)], optional vec![arg!(number)];
 
-impl Function for ReverseFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
-        if args[0].is_array() {
-            let mut values = args[0].as_array().unwrap().clone();
-            values.reverse();
-            Ok(Rcvar::new(Variable::Array(values)))
+        let start = if args.len() == 3 {
+            try!(count_arg(args, 2, ctx))
         } else {
-            let word: String = args[0].as_string().unwrap().chars().rev().collect();
-            Ok(Rcvar::new(Variable::String(word)))
+            0
+        };
+        // Uses the same deep equality as contains().
+        let found = args[0].as_array().unwrap().iter()
+            .enumerate()
+            .skip(start)
+            .find(|&(_, v)| *v == args[1]);
+        match found {
+            Some((i, _)) => Ok(Rcvar::new(Variable::Number(i as f64))),
+            None => Ok(Rcvar::new(Variable::Null)),
         }
     }
 }
 
-defn!(SortFn, vec![arg!(array_string | array_number)], None);
+defn! {
+    IntersectionFn, vec![arg!(array), arg!(array)], None;
 
-impl Function for SortFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
-        let mut values = args[0].as_array().unwrap().clone();
-        values.sort();
-        Ok(Rcvar::new(Variable::Array(values)))
+        let first = args[0].as_array().unwrap();
+        let second = args[1].as_array().unwrap();
+        let mut result = vec![];
+        collect_distinct(&mut result, first, |v| contains_value(second, v));
+        Ok(Rcvar::new(Variable::Array(result)))
     }
 }
 
-defn!(SortByFn, vec![arg!(array), arg!(expref)], None);
+defn! {
+    InvertFn, vec![arg!(object)], None;
 
-impl Function for SortByFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
-        let vals = args[0].as_array().unwrap().clone();
-        if vals.is_empty() {
-            return Ok(Rcvar::new(Variable::Array(vals)));
-        }
-        let ast = args[1].as_expref().unwrap();
-        let mut mapped: Vec<(Rcvar, Rcvar)> = vec![];
-        let first_value = try!(interpret(&vals[0], &ast, ctx));
-        let first_type = first_value.get_type();
-        if first_type != JmespathType::String && first_type != JmespathType::Number {
-            let reason = ErrorReason::Runtime(RuntimeError::InvalidReturnType {
-                expected: "expression->string|expression->number".to_owned(),
-                actual: first_type.to_string(),
-                position: 1,
-                invocation: 1,
-            });
-            return Err(JmespathError::from_ctx(ctx, reason));
+        let mut result = Map::new();
+        // Later keys win when two values invert to the same key.
+        for (key, value) in args[0].as_object().unwrap() {
+            let inverted = match **value {
+                Variable::String(ref s) => s.clone(),
+                Variable::Number(_) => value.to_string(),
+                _ => {
+                    // The type stays a type name; the key goes with the
+                    // expected type of its value.
+                    let reason = ErrorReason::Runtime(RuntimeError::InvalidType {
+                        expected: format!("string|number at key {:?}", key),
+                        actual: value.get_type().to_string(),
+                        position: 0,
+                    });
+                    return Err(JmespathError::from_ctx(ctx, reason));
+                }
+            };
+            result.insert(inverted, Rcvar::new(Variable::String(key.clone())));
         }
-        mapped.push((vals[0].clone(), first_value.clone()));
-        for (invocation, v) in vals.iter().enumerate().skip(1) {
-            let mapped_value = try!(interpret(v, &ast, ctx));
-            if mapped_value.get_type() != first_type {
-                return Err(JmespathError::from_ctx(ctx,
-                    ErrorReason::Runtime(RuntimeError::InvalidReturnType {
-                        expected: format!("expression->{}", first_type),
-                        actual: mapped_value.get_type().to_string(),
-                        position: 1,
-                        invocation: invocation
-                    }
-                )));
-            }
-            mapped.push((v.clone(), mapped_value));
+        Ok(Rcvar::new(Variable::Object(result)))
+    }
+}
+
+/// Returns true if the argument is of a given type.
+///
+/// Backs the is_string, is_number, is_array, is_object, is_bool, and is_null
+/// functions.
+pub struct IsTypeFn {
+    signature: Signature,
+    expected: JmespathType,
+}
+
+impl IsTypeFn {
+    /// Creates a predicate function that tests for the given type.
+    pub fn new(expected: JmespathType) -> IsTypeFn {
+        IsTypeFn {
+            signature: Signature::new(vec![arg!(any)], None),
+            expected: expected,
         }
-        mapped.sort_by(|a, b| a.1.cmp(&b.1));
-        let result = mapped.iter().map(|tuple| tuple.0.clone()).collect();
-        Ok(Rcvar::new(Variable::Array(result)))
     }
 }
 
-defn!(StartsWithFn, vec![arg!(string), arg!(string)], None);
+impl Function for IsTypeFn {
+    fn signature(&self) -> Option<&Signature> {
+        Some(&self.signature)
+    }
 
-impl Function for StartsWithFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
-        let subject = args[0].as_string().unwrap();
-        let search = args[1].as_string().unwrap();
-        Ok(Rcvar::new(Variable::Bool(subject.starts_with(search))))
+        Ok(Rcvar::new(Variable::Bool(args[0].get_type() == self.expected)))
     }
 }
 
-defn!(SumFn, vec![arg!(array_number)], None);
+defn! {
+    /// Returns the `[key, value]` pairs of an object in the object's key
+    /// order, i.e., the order of the JSON document or multi-select hash it
+    /// came from.
+    ItemsFn, vec![arg!(object)], None;
 
-impl Function for SumFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
-        let result = args[0]
-            .as_array()
-            .unwrap()
-            .iter()
-            .fold(0.0, |acc, item| acc + item.as_number().unwrap());
-        Ok(Rcvar::new(Variable::Number(result)))
+        let object = args[0].as_object().unwrap();
+        let items = object.iter()
+            .map(|(k, v)| {
+                let key = Rcvar::new(Variable::String(k.clone()));
+                Rcvar::new(Variable::Array(vec![key, v.clone()]))
+            })
+            .collect::<Vec<Rcvar>>();
+        Ok(Rcvar::new(Variable::Array(items)))
     }
 }
 
-defn!(ToArrayFn, vec![arg!(any)], None);
+defn! {
+    JoinFn, vec![arg!(string), arg!(array_string)], None;
 
-impl Function for ToArrayFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
-        match *args[0] {
-            Variable::Array(_) => Ok(args[0].clone()),
-            _ => Ok(Rcvar::new(Variable::Array(vec![args[0].clone()]))),
+        let glue = args[0].as_string().unwrap();
+        let values = args[1].as_array().unwrap();
+        let result = values.iter()
+            .map(|v| v.as_string().unwrap())
+            .cloned()
+            .collect::<Vec<String>>()
+            .join(&glue);
+        Ok(Rcvar::new(Variable::String(result)))
+    }
+}
+
+defn! {
+    JsonParseFn, vec![arg!(string)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        match Variable::from_json(args[0].as_string().unwrap()) {
+            Ok(value) => Ok(Rcvar::new(value)),
+            Err(_) => Ok(Rcvar::new(Variable::Null)),
+        }
+    }
+}
+
+defn! {
+    /// Serializes a value to compact JSON.
+    ///
+    /// Object keys are written in the object's key order rather than sorted.
+    JsonSerializeFn, vec![arg!(any)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        // Variable's Display is compact JSON, and objects keep their key order.
+        Ok(Rcvar::new(Variable::String(args[0].to_string())))
+    }
+}
+
+defn! {
+    /// Returns the keys of an object in the object's key order.
+    KeysFn, vec![arg!(object)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let object = args[0].as_object().unwrap();
+        let keys = object.keys()
+            .map(|k| Rcvar::new(Variable::String((*k).clone())))
+            .collect::<Vec<Rcvar>>();
+        Ok(Rcvar::new(Variable::Array(keys)))
+    }
+}
+
+defn! {
+    LastFn, vec![arg!(array)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        match args[0].as_array().unwrap().last() {
+            Some(value) => Ok(value.clone()),
+            None => Ok(Rcvar::new(Variable::Null)),
         }
     }
 }
 
-defn!(ToNumberFn, vec![arg!(any)], None);
+defn! {
+    LengthFn, vec![arg!(array | object | string)], None;
 
-impl Function for ToNumberFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
         match *args[0] {
-            Variable::Number(_) => Ok(args[0].clone()),
-            Variable::String(ref s) => {
-                match Variable::from_json(s) {
-                    Ok(f) => Ok(Rcvar::new(f)),
-                    Err(_) => Ok(Rcvar::new(Variable::Null)),
-                }
+            Variable::Array(ref a) => Ok(Rcvar::new(Variable::Number(a.len() as f64))),
+            Variable::Object(ref m) => Ok(Rcvar::new(Variable::Number(m.len() as f64))),
+            // Note that we need to count the code points not the number of unicode characters
+            Variable::String(ref s) => Ok(Rcvar::new(Variable::Number(s.chars().count() as f64))),
+            _ => unreachable!(),
+        }
+    }
+}
+
+defn! {
+    LogFn, vec![arg!(number)], optional vec![arg!(number)];
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let n = args[0].as_number().unwrap();
+        match args.get(1) {
+            Some(base) => Ok(finite_number(n.log(base.as_number().unwrap()))),
+            None => Ok(finite_number(n.ln())),
+        }
+    }
+}
+
+defn! {
+    LowerFn, vec![arg!(string)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        Ok(Rcvar::new(Variable::String(args[0].as_string().unwrap().to_lowercase())))
+    }
+}
+
+defn! {
+    MapFn, vec![arg!(expref), arg!(array)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let ast = args[0].as_expref().unwrap();
+        let values = args[1].as_array().unwrap();
+        let mut results = vec![];
+        for (invocation, value) in values.iter().enumerate() {
+            results.push(try!(interpret_element(value, &ast, 0, invocation, ctx)));
+        }
+        Ok(Rcvar::new(Variable::Array(results)))
+    }
+}
+
+defn! {
+    MapWithIndexFn, vec![arg!(expref), arg!(array)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let ast = args[0].as_expref().unwrap();
+        let values = args[1].as_array().unwrap();
+        let mut results = vec![];
+        for (index, value) in values.iter().enumerate() {
+            let mut wrapped = Map::new();
+            wrapped.insert("index".to_owned(), Rcvar::new(Variable::Number(index as f64)));
+            wrapped.insert("value".to_owned(), value.clone());
+            let wrapped = Rcvar::new(Variable::Object(wrapped));
+            results.push(try!(interpret_element(&wrapped, &ast, 0, index, ctx)));
+        }
+        Ok(Rcvar::new(Variable::Array(results)))
+    }
+}
+
+defn! {
+    MaxFn, vec![arg!(array_string | array_number)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        min_and_max!(max, args)
+    }
+}
+
+defn! {
+    MinFn, vec![arg!(array_string | array_number)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        min_and_max!(min, args)
+    }
+}
+
+defn! {
+    MaxByFn, vec![arg!(array), arg!(expref)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        min_and_max_by!(ctx, gt, args)
+    }
+}
+
+defn! {
+    MinByFn, vec![arg!(array), arg!(expref)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        min_and_max_by!(ctx, lt, args)
+    }
+}
+
+defn! {
+    MedianFn, vec![arg!(array_number)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let sorted = sorted_numbers(&args[0]);
+        if sorted.is_empty() {
+            return Ok(Rcvar::new(Variable::Null));
+        }
+        Ok(finite_number(percentile_of(&sorted, 50.0)))
+    }
+}
+
+defn! {
+    /// Merges objects from left to right.
+    ///
+    /// The value of a repeated key comes from the last object that has it,
+    /// while the key keeps the position of the first object that has it.
+    MergeFn, vec![arg!(object)], Some(arg!(object));
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let mut result = Map::new();
+        for arg in args {
+            result.extend(arg.as_object().unwrap().clone());
+        }
+        Ok(Rcvar::new(Variable::Object(result)))
+    }
+}
+
+defn! {
+    ModFn, vec![arg!(number), arg!(number)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let a = args[0].as_number().unwrap();
+        let b = args[1].as_number().unwrap();
+        Ok(finite_number(a % b))
+    }
+}
+
+defn! {
+    NotNullFn, vec![arg!(any)], Some(arg!(any));
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        for arg in args {
+            if !arg.is_null() {
+                return Ok(arg.clone());
             }
-            _ => Ok(Rcvar::new(Variable::Null)),
         }
+        Ok(Rcvar::new(Variable::Null))
     }
 }
 
-defn!(ToStringFn,
-      vec![arg!(object | array | bool | number | string | null)],
-      None);
+defn! {
+    PartitionFn, vec![arg!(array), arg!(expref)], None;
 
-impl Function for ToStringFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
-        match *args[0] {
-            Variable::String(_) => Ok(args[0].clone()),
-            _ => Ok(Rcvar::new(Variable::String(args[0].to_string()))),
+        let ast = args[1].as_expref().unwrap();
+        let mut matching = vec![];
+        let mut non_matching = vec![];
+        for (invocation, value) in args[0].as_array().unwrap().iter().enumerate() {
+            if try!(interpret_element(value, &ast, 1, invocation, ctx)).is_truthy() {
+                matching.push(value.clone());
+            } else {
+                non_matching.push(value.clone());
+            }
         }
+        Ok(Rcvar::new(Variable::Array(vec![Rcvar::new(Variable::Array(matching)),
+                                           Rcvar::new(Variable::Array(non_matching))])))
     }
 }
 
-defn!(TypeFn, vec![arg!(any)], None);
+defn! {
+    PercentileFn, vec![arg!(array_number), arg!(number)], None;
 
-impl Function for TypeFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
-        Ok(Rcvar::new(Variable::String(args[0].get_type().to_string())))
+        let p = args[1].as_number().unwrap();
+        if p < 0.0 || p > 100.0 {
+            let reason = ErrorReason::Runtime(RuntimeError::InvalidArgument {
+                position: 1,
+                reason: format!("percentile must be between 0 and 100, given {}", args[1]),
+            });
+            return Err(JmespathError::from_ctx(ctx, reason));
+        }
+        let sorted = sorted_numbers(&args[0]);
+        if sorted.is_empty() {
+            return Ok(Rcvar::new(Variable::Null));
+        }
+        Ok(finite_number(percentile_of(&sorted, p)))
     }
 }
 
-defn!(ValuesFn, vec![arg!(object)], None);
+defn! {
+    PowFn, vec![arg!(number), arg!(number)], None;
 
-impl Function for ValuesFn {
     fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
         try!(self.signature.validate(args, ctx));
-        let map = args[0].as_object().unwrap();
-        Ok(Rcvar::new(Variable::Array(map.values().cloned().collect::<Vec<Rcvar>>())))
+        let base = args[0].as_number().unwrap();
+        let exp = args[1].as_number().unwrap();
+        Ok(finite_number(base.powf(exp)))
+    }
+}
+
+/// Default maximum number of elements that `range()` will generate.
+pub const DEFAULT_RANGE_LIMIT: usize = 1_000_000;
+
+/// Generates an array of numbers from `range(stop)`, `range(start, stop)`,
+/// or `range(start, stop, step)`.
+///
+/// The number of generated elements is capped to guard against runaway
+/// allocations. Use `RangeFn::with_limit` to register a different cap.
+pub struct RangeFn {
+    signature: Signature,
+    limit: usize,
+}
+
+impl RangeFn {
+    pub fn new() -> RangeFn {
+        RangeFn::with_limit(DEFAULT_RANGE_LIMIT)
+    }
+
+    /// Creates a range function that generates at most `limit` elements.
+    pub fn with_limit(limit: usize) -> RangeFn {
+        RangeFn {
+            signature: Signature::with_optional(vec![arg!(number)],
+                                                vec![arg!(number), arg!(number)]),
+            limit: limit,
+        }
+    }
+}
+
+impl Function for RangeFn {
+    fn signature(&self) -> Option<&Signature> {
+        Some(&self.signature)
+    }
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let numbers = args.iter().map(|a| a.as_number().unwrap()).collect::<Vec<f64>>();
+        let (start, stop, step) = match numbers.len() {
+            1 => (0.0, numbers[0], 1.0),
+            2 => (numbers[0], numbers[1], 1.0),
+            _ => (numbers[0], numbers[1], numbers[2]),
+        };
+        if step == 0.0 {
+            let reason = ErrorReason::Runtime(RuntimeError::InvalidArgument {
+                position: 2,
+                reason: "step must not be 0".to_owned(),
+            });
+            return Err(JmespathError::from_ctx(ctx, reason));
+        }
+        let len = ((stop - start) / step).ceil();
+        if !(len <= self.limit as f64) {
+            let reason = ErrorReason::Runtime(RuntimeError::ResultSizeExceeded {
+                limit: self.limit,
+            });
+            return Err(JmespathError::from_ctx(ctx, reason));
+        }
+        // Multiply rather than accumulate so that fractional steps do not drift.
+        let result = (0..len.max(0.0) as usize)
+            .map(|i| Rcvar::new(Variable::Number(start + i as f64 * step)))
+            .collect();
+        Ok(Rcvar::new(Variable::Array(result)))
+    }
+}
+
+defn! {
+    ReduceFn, vec![arg!(array), arg!(expref), arg!(any)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let ast = args[1].as_expref().unwrap();
+        let mut accumulated = args[2].clone();
+        for (invocation, value) in args[0].as_array().unwrap().iter().enumerate() {
+            let mut state = Map::new();
+            state.insert("accumulated".to_owned(), accumulated);
+            state.insert("current".to_owned(), value.clone());
+            let state = Rcvar::new(Variable::Object(state));
+            accumulated = try!(interpret_element(&state, &ast, 1, invocation, ctx));
+        }
+        Ok(accumulated)
+    }
+}
+
+defn! {
+    ReplaceFn, vec![arg!(string), arg!(string), arg!(string)], optional vec![arg!(number)];
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let subject = args[0].as_string().unwrap();
+        let old = args[1].as_string().unwrap();
+        // An empty pattern would match between every character, so leave the
+        // subject untouched instead.
+        if old.is_empty() {
+            return Ok(args[0].clone());
+        }
+        let new = args[2].as_string().unwrap();
+        let replaced = if args.len() == 4 {
+            subject.replacen(old.as_str(), new, try!(count_arg(args, 3, ctx)))
+        } else {
+            subject.replace(old.as_str(), new)
+        };
+        Ok(Rcvar::new(Variable::String(replaced)))
+    }
+}
+
+defn! {
+    ReverseFn, vec![arg!(array | string)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        if args[0].is_array() {
+            let mut values = args[0].as_array().unwrap().clone();
+            values.reverse();
+            Ok(Rcvar::new(Variable::Array(values)))
+        } else {
+            let word: String = args[0].as_string().unwrap().chars().rev().collect();
+            Ok(Rcvar::new(Variable::String(word)))
+        }
+    }
+}
+
+defn! {
+    /// Rounds a number to an optional number of decimal places.
+    ///
+    /// Halfway cases are rounded away from zero, so 2.5 rounds to 3 and -2.5
+    /// rounds to -3. A negative precision rounds to tens, hundreds, and so on.
+    RoundFn, vec![arg!(number)], optional vec![arg!(number)];
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let n = args[0].as_number().unwrap();
+        let precision = if args.len() == 2 {
+            try!(integer_arg(args, 1, ctx))
+        } else {
+            0
+        };
+        // Below this precision the factor underflows to 0, but every finite
+        // number is also less than half of 10^309, so it rounds to 0.
+        if precision < -308 {
+            return Ok(Rcvar::new(Variable::Number(0.0)));
+        }
+        let factor = 10f64.powf(precision as f64);
+        let rounded = (n * factor).round() / factor;
+        // Large precisions overflow, and leave the input as is since it has
+        // fewer decimal places than that.
+        if rounded.is_finite() {
+            Ok(Rcvar::new(Variable::Number(rounded)))
+        } else {
+            Ok(args[0].clone())
+        }
+    }
+}
+
+defn! {
+    /// Sorts an array of numbers by value or an array of strings by code point.
+    ///
+    /// The sort is stable. Comparing UTF-8 bytes orders strings by code point,
+    /// so characters outside the Basic Multilingual Plane sort after U+FFFF.
+    SortFn, vec![arg!(array_string | array_number)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let mut values = args[0].as_array().unwrap().clone();
+        values.sort();
+        Ok(Rcvar::new(Variable::Array(values)))
+    }
+}
+
+defn! {
+    SortByFn, vec![arg!(array), arg!(expref)], Some(arg!(expref));
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let vals = args[0].as_array().unwrap();
+        // Each element is paired with its sort keys, one per expression reference.
+        let mut mapped: Vec<(Rcvar, Vec<Rcvar>)> = vals.iter()
+            .map(|v| (v.clone(), Vec::with_capacity(args.len() - 1)))
+            .collect();
+        for (position, arg) in args.iter().enumerate().skip(1) {
+            let ast = arg.as_expref().unwrap();
+            let mut key_type = None;
+            for (invocation, entry) in mapped.iter_mut().enumerate() {
+                let key = try!(interpret_element(&entry.0, &ast, position, invocation, ctx));
+                let actual = key.get_type();
+                let expected = match key_type {
+                    None if actual == JmespathType::String || actual == JmespathType::Number => {
+                        key_type = Some(actual);
+                        None
+                    }
+                    None => Some("expression->string|expression->number".to_owned()),
+                    Some(t) if t == actual => None,
+                    Some(t) => Some(format!("expression->{}", t)),
+                };
+                if let Some(expected) = expected {
+                    return Err(JmespathError::from_ctx(ctx,
+                        ErrorReason::Runtime(RuntimeError::InvalidReturnType {
+                            expected: expected,
+                            actual: actual.to_string(),
+                            position: position,
+                            invocation: invocation,
+                        }
+                    )));
+                }
+                entry.1.push(key);
+            }
+        }
+        // Vec::sort_by is stable, so equal composite keys keep their input order.
+        mapped.sort_by(|a, b| a.1.cmp(&b.1));
+        let result = mapped.into_iter().map(|tuple| tuple.0).collect();
+        Ok(Rcvar::new(Variable::Array(result)))
+    }
+}
+
+defn! {
+    SplitFn, vec![arg!(string), arg!(string)], optional vec![arg!(number)];
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let subject = args[0].as_string().unwrap();
+        let separator = args[1].as_string().unwrap();
+        let limit = if args.len() == 3 {
+            Some(try!(count_arg(args, 2, ctx)))
+        } else {
+            None
+        };
+        let parts: Vec<String> = if separator.is_empty() {
+            // Split into code points, keeping any text past the limit intact.
+            let splits = limit.unwrap_or(usize::max_value());
+            let mut parts = vec![];
+            let mut rest = subject.as_str();
+            while let Some(c) = rest.chars().next() {
+                if parts.len() == splits {
+                    break;
+                }
+                parts.push(c.to_string());
+                rest = &rest[c.len_utf8()..];
+            }
+            if !rest.is_empty() {
+                parts.push(rest.to_owned());
+            }
+            parts
+        } else {
+            match limit {
+                // Limits as large as usize::MAX must not overflow.
+                Some(n) => {
+                    subject.splitn(n.saturating_add(1), separator.as_str())
+                        .map(String::from)
+                        .collect()
+                }
+                None => subject.split(separator.as_str()).map(String::from).collect(),
+            }
+        };
+        let result = parts.into_iter().map(|p| Rcvar::new(Variable::String(p))).collect();
+        Ok(Rcvar::new(Variable::Array(result)))
+    }
+}
+
+defn! {
+    SqrtFn, vec![arg!(number)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        Ok(finite_number(args[0].as_number().unwrap().sqrt()))
+    }
+}
+
+defn! {
+    StartsWithFn, vec![arg!(string), arg!(string)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let subject = args[0].as_string().unwrap();
+        let search = args[1].as_string().unwrap();
+        Ok(Rcvar::new(Variable::Bool(subject.starts_with(search))))
+    }
+}
+
+defn! {
+    StddevFn, vec![arg!(array_number)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let values = args[0].as_array().unwrap();
+        if values.is_empty() {
+            return Ok(Rcvar::new(Variable::Null));
+        }
+        let count = values.len() as f64;
+        let mean = values.iter().map(|n| n.as_number().unwrap()).sum::<f64>() / count;
+        let variance = values.iter()
+            .map(|n| (n.as_number().unwrap() - mean).powi(2))
+            .sum::<f64>() / count;
+        // Overflowing sums produce infinities or NaN, which become null.
+        Ok(finite_number(variance.sqrt()))
+    }
+}
+
+defn! {
+    SubstringFn, vec![arg!(string), arg!(number)], optional vec![arg!(number)];
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let subject = args[0].as_string().unwrap();
+        let len = subject.chars().count() as i64;
+        // Negative indices count from the end, and all indices are clamped.
+        let resolve = |i: i64| if i < 0 { max(0, len + i) } else { min(len, i) };
+        let start = resolve(try!(integer_arg(args, 1, ctx)));
+        let stop = if args.len() == 3 {
+            resolve(try!(integer_arg(args, 2, ctx)))
+        } else {
+            len
+        };
+        let result = subject.chars()
+            .skip(start as usize)
+            .take(max(0, stop - start) as usize)
+            .collect::<String>();
+        Ok(Rcvar::new(Variable::String(result)))
+    }
+}
+
+defn! {
+    SumFn, vec![arg!(array_number)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let result = args[0]
+            .as_array()
+            .unwrap()
+            .iter()
+            .fold(0.0, |acc, item| acc + item.as_number().unwrap());
+        Ok(finite_number(result))
+    }
+}
+
+defn! {
+    SumByFn, vec![arg!(array), arg!(expref)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let ast = args[1].as_expref().unwrap();
+        let numbers = try!(map_numbers(args[0].as_array().unwrap(), &ast, ctx));
+        Ok(finite_number(numbers.iter().fold(0.0, |a, n| a + n)))
+    }
+}
+
+defn! {
+    ToArrayFn, vec![arg!(any)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        match *args[0] {
+            Variable::Array(_) => Ok(args[0].clone()),
+            _ => Ok(Rcvar::new(Variable::Array(vec![args[0].clone()]))),
+        }
+    }
+}
+
+defn! {
+    ToNumberFn, vec![arg!(any)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        match *args[0] {
+            Variable::Number(_) => Ok(args[0].clone()),
+            Variable::String(ref s) => {
+                // Only a JSON number is accepted. serde_json would skip
+                // surrounding whitespace, and it rejects values that are out
+                // of range rather than producing an infinity.
+                if s.trim() != s {
+                    return Ok(Rcvar::new(Variable::Null));
+                }
+                match ::serde_json::from_str::<f64>(s) {
+                    Ok(n) => Ok(Rcvar::new(Variable::Number(n))),
+                    Err(_) => Ok(Rcvar::new(Variable::Null)),
+                }
+            }
+            _ => Ok(Rcvar::new(Variable::Null)),
+        }
+    }
+}
+
+defn! {
+    ToStringFn, vec![arg!(object | array | bool | number | string | null)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        match *args[0] {
+            Variable::String(_) => Ok(args[0].clone()),
+            _ => Ok(Rcvar::new(Variable::String(args[0].to_string()))),
+        }
+    }
+}
+
+defn! {
+    TrimFn, vec![arg!(string)], optional vec![arg!(string)];
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        Ok(trim_string(args, true, true))
+    }
+}
+
+defn! {
+    TrimLeftFn, vec![arg!(string)], optional vec![arg!(string)];
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        Ok(trim_string(args, true, false))
+    }
+}
+
+defn! {
+    TrimRightFn, vec![arg!(string)], optional vec![arg!(string)];
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        Ok(trim_string(args, false, true))
+    }
+}
+
+defn! {
+    TypeFn, vec![arg!(any)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        Ok(Rcvar::new(Variable::String(args[0].get_type().to_string())))
+    }
+}
+
+defn! {
+    UnionFn, vec![arg!(array), arg!(array)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let first = args[0].as_array().unwrap();
+        let second = args[1].as_array().unwrap();
+        let mut result = vec![];
+        collect_distinct(&mut result, first, |_| true);
+        collect_distinct(&mut result, second, |_| true);
+        Ok(Rcvar::new(Variable::Array(result)))
+    }
+}
+
+defn! {
+    UniqueFn, vec![arg!(array)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        // Compare each element against the distinct elements found so far. This
+        // is O(n^2), but only requires deep `Variable` equality.
+        let mut distinct = vec![];
+        collect_distinct(&mut distinct, args[0].as_array().unwrap(), |_| true);
+        Ok(Rcvar::new(Variable::Array(distinct)))
+    }
+}
+
+defn! {
+    UpperFn, vec![arg!(string)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        Ok(Rcvar::new(Variable::String(args[0].as_string().unwrap().to_uppercase())))
+    }
+}
+
+defn! {
+    /// Percent-decodes a string.
+    ///
+    /// Both `%20` and a literal space decode to a space; `+` is left as is.
+    /// Invalid percent sequences and bytes that are not UTF-8 produce null.
+    UrlDecodeFn, vec![arg!(string)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let bytes = args[0].as_string().unwrap().as_bytes();
+        let mut decoded = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] != b'%' {
+                decoded.push(bytes[i]);
+                i += 1;
+                continue;
+            }
+            let hex = bytes.get(i + 1..i + 3).filter(|h| h.iter().all(u8::is_ascii_hexdigit));
+            match hex.map(|h| u8::from_str_radix(str::from_utf8(h).unwrap(), 16).unwrap()) {
+                Some(byte) => decoded.push(byte),
+                None => return Ok(Rcvar::new(Variable::Null)),
+            }
+            i += 3;
+        }
+        match String::from_utf8(decoded) {
+            Ok(s) => Ok(Rcvar::new(Variable::String(s))),
+            Err(_) => Ok(Rcvar::new(Variable::Null)),
+        }
+    }
+}
+
+defn! {
+    /// Percent-encodes a string using the UTF-8 bytes of each code point.
+    ///
+    /// Only the RFC 3986 unreserved characters (`A-Z`, `a-z`, `0-9`, `-`,
+    /// `.`, `_`, and `~`) are left as is. Spaces encode to `%20`, not `+`.
+    UrlEncodeFn, vec![arg!(string)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let s = args[0].as_string().unwrap();
+        let mut encoded = String::with_capacity(s.len());
+        for &byte in s.as_bytes() {
+            match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    encoded.push(byte as char)
+                }
+                _ => encoded.push_str(&format!("%{:02X}", byte)),
+            }
+        }
+        Ok(Rcvar::new(Variable::String(encoded)))
+    }
+}
+
+defn! {
+    /// Returns the values of an object in the object's key order.
+    ValuesFn, vec![arg!(object)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let map = args[0].as_object().unwrap();
+        Ok(Rcvar::new(Variable::Array(map.values().cloned().collect::<Vec<Rcvar>>())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {compile, Context, ErrorReason, Rcvar, Runtime, RuntimeError, DEFAULT_RUNTIME};
+    use interpreter::interpret;
+    use variable::Variable;
+    use super::*;
+
+    fn search(expr: &str, data: &str) -> Rcvar {
+        compile(expr).unwrap().search(Variable::from_json(data).unwrap()).unwrap()
+    }
+
+    const MATH: fn(&mut Runtime) = Runtime::register_math_functions;
+    const ENCODING: fn(&mut Runtime) = Runtime::register_encoding_functions;
+    const STATS: fn(&mut Runtime) = Runtime::register_stats_functions;
+    const EXTENSION: fn(&mut Runtime) = Runtime::register_extension_functions;
+
+    fn runtime_with(register: fn(&mut Runtime)) -> Runtime {
+        let mut runtime = Runtime::new();
+        runtime.register_builtin_functions();
+        register(&mut runtime);
+        runtime
+    }
+
+    fn search_with(register: fn(&mut Runtime), expr: &str, data: &str) -> Rcvar {
+        runtime_with(register).compile(expr).unwrap().search(json(data)).unwrap()
+    }
+
+    fn search_err(expr: &str, data: &str) -> String {
+        compile(expr).unwrap().search(Variable::from_json(data).unwrap()).unwrap_err().to_string()
+    }
+
+    fn json(data: &str) -> Rcvar {
+        Rcvar::new(Variable::from_json(data).unwrap())
+    }
+
+    #[test]
+    fn group_by_buckets_elements_in_order() {
+        let data = r#"[{"s": "on", "id": 1}, {"s": "off", "id": 2}, {"s": "on", "id": 3}]"#;
+        assert_eq!(json(r#"{"off": [{"s": "off", "id": 2}],
+                             "on": [{"s": "on", "id": 1}, {"s": "on", "id": 3}]}"#),
+                   search("group_by(@, &s)", data));
+    }
+
+    #[test]
+    fn group_by_keeps_groups_in_first_occurrence_order() {
+        let data = r#"[{"s": "on"}, {"s": "off"}, {"s": "on"}, {"s": "idle"}]"#;
+        assert_eq!(json(r#"["on", "off", "idle"]"#), search("keys(group_by(@, &s))", data));
+    }
+
+    #[test]
+    fn group_by_empty_array_returns_empty_object() {
+        assert_eq!(json("{}"), search("group_by(@, &s)", "[]"));
+    }
+
+    #[test]
+    fn group_by_requires_string_keys() {
+        let err = search_err("group_by(@, &s)", r#"[{"s": "a"}, {"s": 1}]"#);
+        assert!(err.contains("expression->string"), err);
+        assert!(err.contains("invocation 1"), err);
+    }
+
+    #[test]
+    fn items_returns_pairs_in_object_order() {
+        assert_eq!(json(r#"[["b", {"c": [true]}], ["a", 1]]"#),
+                   search("items(@)", r#"{"b": {"c": [true]}, "a": 1}"#));
+        assert_eq!(json(r#"[["foo", 2]]"#),
+                   search("items(@)[?[0] == 'foo']", r#"{"bar": 1, "foo": 2}"#));
+    }
+
+    #[test]
+    fn object_functions_follow_document_order() {
+        let data = r#"{"b": 1, "a": 2, "c": 3}"#;
+        assert_eq!(json(r#"["b", "a", "c"]"#), search("keys(@)", data));
+        assert_eq!(json("[1, 2, 3]"), search("values(@)", data));
+        assert_eq!(json(r#""{\"b\":1,\"a\":2,\"c\":3}""#), search("json_serialize(@)", data));
+        assert_eq!(json(r#"["b", "a", "c", "d"]"#),
+                   search(r#"keys(merge(@, `{"d": 4, "a": 5}`))"#, data));
+        assert_eq!(json("[1, 5, 3, 4]"), search(r#"values(merge(@, `{"d": 4, "a": 5}`))"#, data));
+    }
+
+    #[test]
+    fn items_of_empty_object_is_empty_array() {
+        assert_eq!(json("[]"), search("items(@)", "{}"));
+    }
+
+    #[test]
+    fn items_shares_values() {
+        // Search through the interpreter directly: `Expression::search` would
+        // re-serialize the input when the `specialized` feature is disabled.
+        let data = json(r#"{"a": {"nested": [1, 2]}}"#);
+        let expr = compile("items(@)").unwrap();
+        let mut ctx = Context::new(expr.as_str(), &DEFAULT_RUNTIME);
+        let result = interpret(&data, expr.as_ast(), &mut ctx).unwrap();
+        let pair = result.as_array().unwrap()[0].clone();
+        let value = &pair.as_array().unwrap()[1];
+        assert!(Rcvar::ptr_eq(value, data.as_object().unwrap().get("a").unwrap()));
+    }
+
+    #[test]
+    fn items_requires_a_single_argument() {
+        assert!(search_err("items(@, @)", "{}").contains("Too many arguments"));
+        assert!(search_err("items()", "{}").contains("Not enough arguments"));
+    }
+
+    #[test]
+    fn find_first_and_last_count_code_points() {
+        assert_eq!(json("2"), search("find_first(@, 'b')", r#""éébéb""#));
+        assert_eq!(json("4"), search("find_last(@, 'b')", r#""éébéb""#));
+        assert_eq!(json("null"), search("find_first(@, 'x')", r#""éébéb""#));
+        assert_eq!(json("null"), search("find_last(@, 'x')", r#""éébéb""#));
+    }
+
+    #[test]
+    fn find_with_empty_and_long_needles() {
+        assert_eq!(json("0"), search("find_first(@, '')", r#""abc""#));
+        assert_eq!(json("3"), search("find_last(@, '')", r#""abc""#));
+        assert_eq!(json("1"), search("find_first(@, '', `1`)", r#""abc""#));
+        assert_eq!(json("0"), search("find_first('', '')", "null"));
+        assert_eq!(json("null"), search("find_first(@, 'abcd')", r#""abc""#));
+        assert_eq!(json("null"), search("find_last(@, 'abcd')", r#""abc""#));
+    }
+
+    #[test]
+    fn find_respects_bounds() {
+        let data = r#""abcabc""#;
+        assert_eq!(json("3"), search("find_first(@, 'a', `1`)", data));
+        assert_eq!(json("0"), search("find_last(@, 'a', `0`, `3`)", data));
+        assert_eq!(json("null"), search("find_first(@, 'a', `1`, `3`)", data));
+        assert_eq!(json("3"), search("find_first(@, 'a', `-3`)", data));
+        assert_eq!(json("0"), search("find_first(@, 'a', `-100`)", data));
+        assert_eq!(json("3"), search("find_last(@, 'a', `0`, `100`)", data));
+        assert_eq!(json("null"), search("find_first(@, '', `7`)", data));
+        assert_eq!(json("null"), search("find_first(@, 'a', `4`, `2`)", data));
+    }
+
+    #[test]
+    fn find_validates_arity() {
+        assert!(search_err("find_first('a')", "null").contains("Not enough arguments"));
+        assert!(search_err("find_last('a', 'a', `0`, `1`, `2`)", "null")
+            .contains("Too many arguments: expected 4, found 5"));
+        assert!(search_err("find_first('a', 'a', '0')", "null").contains("expects type"));
+    }
+
+    #[test]
+    fn replace_replaces_all_occurrences() {
+        assert_eq!(json(r#"["5551234", "5559876"]"#),
+                   search("people[*].replace(phone, '-', '')",
+                          r#"{"people": [{"phone": "555-1234"}, {"phone": "555-98-76"}]}"#));
+        assert_eq!(json(r#""é-é-é""#), search("replace(@, 'ü', '-')", r#""éüéüé""#));
+    }
+
+    #[test]
+    fn replace_limits_replacements() {
+        assert_eq!(json(r#""xx-xx-a""#), search("replace(@, 'a', 'xx', `2`)", r#""a-a-a""#));
+        assert_eq!(json(r#""b-a-a""#), search("replace(@, 'a', 'b', `1`)", r#""a-a-a""#));
+        assert_eq!(json(r#""a-a-a""#), search("replace(@, 'a', 'b', `0`)", r#""a-a-a""#));
+        assert!(search_err("replace(@, 'a', 'b', `-1`)", r#""a""#)
+            .contains("Argument 3 expects type non-negative integer"));
+        assert!(search_err("replace(@, 'a', 'b', `1.5`)", r#""a""#)
+            .contains("Argument 3 expects type non-negative integer"));
+    }
+
+    #[test]
+    fn replace_uses_non_overlapping_matches() {
+        assert_eq!(json(r#""ba""#), search("replace(@, 'aa', 'b')", r#""aaa""#));
+        assert_eq!(json(r#""bb""#), search("replace(@, 'aa', 'b')", r#""aaaa""#));
+    }
+
+    #[test]
+    fn replace_with_empty_pattern_is_identity() {
+        assert_eq!(json(r#""abc""#), search("replace(@, '', 'x')", r#""abc""#));
+        assert!(search_err("replace(@, `1`, 'x')", r#""abc""#).contains("expects type string"));
+    }
+
+    #[test]
+    fn split_on_separator() {
+        assert_eq!(json(r#"["a", "b", "", "c"]"#), search("split(@, ',')", r#""a,b,,c""#));
+        assert_eq!(json(r#"["a", "b"]"#), search("split(@, '→')", r#""a→b""#));
+        assert_eq!(json(r#"["abc"]"#), search("split(@, ',')", r#""abc""#));
+        assert_eq!(json(r#"[""]"#), search("split(@, ',')", r#""""#));
+    }
+
+    #[test]
+    fn split_on_empty_separator_yields_code_points() {
+        assert_eq!(json(r#"["a", "é", "𝄞"]"#), search("split(@, '')", r#""aé𝄞""#));
+        assert_eq!(json("[]"), search("split(@, '')", r#""""#));
+        assert_eq!(json(r#"["a", "é𝄞"]"#), search("split(@, '', `1`)", r#""aé𝄞""#));
+    }
+
+    #[test]
+    fn split_with_limit() {
+        assert_eq!(json(r#"["a", "b,c"]"#), search("split(@, ',', `1`)", r#""a,b,c""#));
+        assert_eq!(json(r#"["a,b,c"]"#), search("split(@, ',', `0`)", r#""a,b,c""#));
+        assert_eq!(json(r#"["aé𝄞"]"#), search("split(@, '', `0`)", r#""aé𝄞""#));
+        assert_eq!(json(r#"["a", "b", "c"]"#), search("split(@, ',', `10`)", r#""a,b,c""#));
+        assert_eq!(json(r#"["a", "b"]"#), search("split(@, ',', `1e300`)", r#""a,b""#));
+        assert_eq!(json(r#"["a", "b"]"#), search("split(@, '', `1e300`)", r#""ab""#));
+        assert!(search_err("split(@, ',', `-1`)", r#""a""#).contains("non-negative integer"));
+        assert!(search_err("split(@, ',', `1`, `2`)", r#""a""#).contains("Too many arguments"));
+    }
+
+    #[test]
+    fn trim_strips_unicode_whitespace() {
+        let data = r#""\u3000\t a b \n\u2003""#;
+        assert_eq!(json(r#""a b""#), search("trim(@)", data));
+        assert_eq!(json(r#""a b \n\u2003""#), search("trim_left(@)", data));
+        assert_eq!(json(r#""\u3000\t a b""#), search("trim_right(@)", data));
+        assert_eq!(json(r#""""#), search("trim(@)", r#""  ""#));
+    }
+
+    #[test]
+    fn trim_strips_a_set_of_code_points() {
+        let data = r#""𝄞é-a-é𝄞""#;
+        assert_eq!(json(r#""a""#), search("trim(@, '-é𝄞')", data));
+        assert_eq!(json(r#""a-é𝄞""#), search("trim_left(@, '-é𝄞')", data));
+        assert_eq!(json(r#""𝄞é-a""#), search("trim_right(@, '𝄞é-')", data));
+        assert_eq!(json(r#"" a ""#), search("trim(@, '')", r#"" a ""#));
+    }
+
+    #[test]
+    fn trim_validates_arguments() {
+        assert!(search_err("trim(@, ' ', ' ')", r#""a""#).contains("Too many arguments"));
+        assert!(search_err("trim_left(@, `1`)", r#""a""#).contains("expects type string"));
+        assert!(search_err("trim_right(`1`)", r#""a""#).contains("expects type string"));
+    }
+
+    #[test]
+    fn lower_and_upper_use_unicode_case_mapping() {
+        assert_eq!(json(r#""STRASSE""#), search("upper(@)", r#""straße""#));
+        assert_eq!(json(r#""ὀδυσσεύς""#), search("lower(@)", r#""ὈΔΥΣΣΕΎΣ""#));
+        assert_eq!(json(r#""ÉCOLE""#), search("upper(@)", r#""école""#));
+        assert_eq!(json(r#""""#), search("lower(@)", r#""""#));
+        assert_eq!(json(r#"[{"name": "Foo"}]"#),
+                   search("[?lower(name) == 'foo']", r#"[{"name": "Foo"}, {"name": "bar"}]"#));
+    }
+
+    #[test]
+    fn lower_and_upper_require_strings() {
+        let err = compile("lower(@)").unwrap().search(1).unwrap_err();
+        assert_eq!(ErrorReason::Runtime(RuntimeError::InvalidType {
+                       expected: "string".to_owned(),
+                       actual: "number".to_owned(),
+                       position: 0,
+                   }),
+                   err.reason);
+        assert!(search_err("upper(@)", "null").contains("Argument 0 expects type string"));
+    }
+
+    #[test]
+    fn unique_keeps_first_occurrences() {
+        assert_eq!(json(r#"[3, 1, 2]"#), search("unique(@)", "[3, 1, 3, 2, 1]"));
+        assert_eq!(json("[]"), search("unique(@)", "[]"));
+        assert_eq!(json(r#"[null, "a"]"#), search("unique(@)", r#"[null, "a", null]"#));
+    }
+
+    #[test]
+    fn unique_uses_deep_equality() {
+        assert_eq!(json("[1, 2]"), search("unique(@)", "[1, 1.0, 2, 2e0, 1]"));
+        assert_eq!(json(r#"[{"a": [1, {"b": 2}]}, [1, 2], [2, 1]]"#),
+                   search("unique(@)",
+                          r#"[{"a": [1, {"b": 2}]}, [1, 2], {"a": [1.0, {"b": 2}]}, [2, 1]]"#));
+        assert_eq!(json(r#"[1, "1", true]"#), search("unique(@)", r#"[1, "1", true, true]"#));
+    }
+
+    #[test]
+    fn flatten_one_level_by_default() {
+        let data = "[1, [2, [3, [4]]], null, [null], {\"a\": [5]}]";
+        assert_eq!(json(r#"[1, 2, [3, [4]], null, null, {"a": [5]}]"#),
+                   search("flatten(@)", data));
+        assert_eq!(json(data), search("flatten(@, `0`)", data));
+        assert_eq!(json("[[]]"), search("flatten(@)", "[[], [[]]]"));
+    }
+
+    #[test]
+    fn flatten_to_a_depth() {
+        let data = "[[1, [2, [3, [4]]]], [[[5]]], 6]";
+        assert_eq!(json("[1, 2, [3, [4]], [5], 6]"), search("flatten(@, `2`)", data));
+        assert_eq!(json("[1, 2, 3, 4, 5, 6]"), search("flatten(@, `-1`)", data));
+        assert_eq!(json("[1, 2, 3, 4, 5, 6]"), search("flatten(@, `1000000`)", data));
+        assert!(search_err("flatten(@, `1.5`)", data).contains("non-negative integer"));
+    }
+
+    #[test]
+    fn flatten_deeply_nested_arrays() {
+        let depth = 100_000;
+        let mut value = Variable::Number(1.0);
+        for _ in 0..depth {
+            value = Variable::Array(vec![Rcvar::new(value)]);
+        }
+        let data = Rcvar::new(value);
+        let expr = compile("flatten(@, `-1`)").unwrap();
+        let mut ctx = Context::new(expr.as_str(), &DEFAULT_RUNTIME);
+        let result = interpret(&data, expr.as_ast(), &mut ctx).unwrap();
+        assert_eq!(json("[1]"), result);
+        // Dropping the input recursively would overflow the stack, so take it
+        // apart one level at a time, after the context releases the root.
+        drop(ctx);
+        let mut next = Some(data);
+        while let Some(value) = next.take() {
+            if let Ok(Variable::Array(mut inner)) = Rcvar::try_unwrap(value) {
+                next = inner.pop();
+            }
+        }
+    }
+
+    #[test]
+    fn chunk_splits_arrays_into_groups() {
+        assert_eq!(json("[[1, 2], [3, 4], [5]]"), search("chunk(@, `2`)", "[1, 2, 3, 4, 5]"));
+        assert_eq!(json("[[1, 2]]"), search("chunk(@, `5`)", "[1, 2]"));
+        assert_eq!(json("[]"), search("chunk(@, `3`)", "[]"));
+    }
+
+    #[test]
+    fn chunk_requires_a_positive_size() {
+        for size in &["`0`", "`-2`", "`1.5`"] {
+            let err = compile(&format!("chunk(@, {})", size)).unwrap().search(vec![1]).unwrap_err();
+            assert_eq!(ErrorReason::Runtime(RuntimeError::InvalidType {
+                           expected: "positive integer".to_owned(),
+                           actual: "number".to_owned(),
+                           position: 1,
+                       }),
+                       err.reason);
+        }
+    }
+
+    #[test]
+    fn chunk_shares_elements() {
+        let data = json(r#"[{"a": 1}, {"b": 2}]"#);
+        let expr = compile("chunk(@, `1`)").unwrap();
+        let mut ctx = Context::new(expr.as_str(), &DEFAULT_RUNTIME);
+        let result = interpret(&data, expr.as_ast(), &mut ctx).unwrap();
+        let second = result.as_array().unwrap()[1].clone();
+        assert!(Rcvar::ptr_eq(&second.as_array().unwrap()[0], &data.as_array().unwrap()[1]));
+    }
+
+    #[test]
+    fn range_generates_numbers() {
+        assert_eq!(json("[0, 1, 2]"), search("range(`3`)", "null"));
+        assert_eq!(json(r#""[0,1,2]""#), search("to_string(range(`0`, `3`))", "null"));
+        assert_eq!(json("[2, 4, 6]"), search("range(`2`, `8`, `2`)", "null"));
+        assert_eq!(json("[2, 4, 6, 8]"), search("range(`2`, `9`, `2`)", "null"));
+        assert_eq!(json("[0, 0.5, 1, 1.5]"), search("range(`0`, `2`, `0.5`)", "null"));
+        assert_eq!(json("[]"), search("range(`0`)", "null"));
+        assert_eq!(json("[]"), search("range(`3`, `1`)", "null"));
+    }
+
+    #[test]
+    fn range_counts_down_with_a_negative_step() {
+        assert_eq!(json("[3, 2, 1]"), search("range(`3`, `0`, `-1`)", "null"));
+        assert_eq!(json("[]"), search("range(`0`, `3`, `-1`)", "null"));
+    }
+
+    #[test]
+    fn range_rejects_invalid_arguments() {
+        assert!(search_err("range(`0`, `3`, `0`)", "null")
+            .contains("Argument 2 is invalid: step must not be 0"));
+        assert!(search_err("range(`0`, `3`, `1`, `1`)", "null").contains("Too many arguments"));
+        assert!(search_err("range('3')", "null").contains("expects type number"));
+    }
+
+    #[test]
+    fn range_limits_the_number_of_elements() {
+        let err = compile("range(`1e12`)").unwrap().search(()).unwrap_err();
+        assert_eq!(ErrorReason::Runtime(RuntimeError::ResultSizeExceeded {
+                       limit: DEFAULT_RANGE_LIMIT,
+                   }),
+                   err.reason);
+        let mut runtime = Runtime::new();
+        runtime.register_builtin_functions();
+        runtime.register_function("range", Box::new(RangeFn::with_limit(2)));
+        assert!(runtime.compile("range(`2`)").unwrap().search(()).is_ok());
+        assert!(runtime.compile("range(`3`)").unwrap().search(()).is_err());
+    }
+
+    #[test]
+    fn count_counts_truthy_results() {
+        let data = r#"[{"a": true}, {"a": 0}, {"a": ""}, {"a": []}, {"a": "x"}, {}]"#;
+        assert_eq!(json("3"), search("count(@, &a)", data));
+        assert_eq!(json("2"), search("count(@, &a == `true` || a == `0`)", data));
+        assert_eq!(json("0"), search("count(@, &a)", "[]"));
+    }
+
+    #[test]
+    fn count_propagates_expression_errors() {
+        assert!(search_err("count(@, &length(@))", r#"["a", 1]"#).contains("expects type"));
+        assert!(search_err("count(@, a)", "[]").contains("Argument 1 expects type expref"));
+    }
+
+    #[test]
+    fn first_and_last_share_elements() {
+        let data = json(r#"[{"a": 1}, {"b": 2}]"#);
+        for &(expression, index) in &[("first(@)", 0), ("last(@)", 1)] {
+            let expr = compile(expression).unwrap();
+            let mut ctx = Context::new(expr.as_str(), &DEFAULT_RUNTIME);
+            let result = interpret(&data, expr.as_ast(), &mut ctx).unwrap();
+            assert!(Rcvar::ptr_eq(&result, &data.as_array().unwrap()[index]));
+        }
+    }
+
+    #[test]
+    fn partition_splits_by_truthiness() {
+        assert_eq!(json(r#"[[{"a": 0}, {"a": 3}], [{"a": false}, {"b": 2}]]"#),
+                   search("partition(@, &a)", r#"[{"a": 0}, {"a": false}, {"b": 2}, {"a": 3}]"#));
+        assert_eq!(json("[[3, 4], [1, 2]]"), search("partition(@, &@ > `2`)", "[1, 3, 2, 4]"));
+        assert_eq!(json("[[], []]"), search("partition(@, &a)", "[]"));
+    }
+
+    #[test]
+    fn partition_propagates_expression_errors() {
+        assert!(search_err("partition(@, &abs(@))", r#"[1, "a"]"#).contains("expects type"));
+    }
+
+    #[test]
+    fn enumerate_pairs_elements_with_indices() {
+        assert_eq!(json(r#"[[0, "a"], [1, {"b": 1}]]"#),
+                   search("enumerate(@)", r#"["a", {"b": 1}]"#));
+        assert_eq!(json("[]"), search("enumerate(@)", "[]"));
+        assert_eq!(json(r#""[[0,\"a\"]]""#), search("to_string(enumerate(@))", r#"["a"]"#));
+    }
+
+    #[test]
+    fn enumerate_in_filters_and_projections() {
+        let data = r#"{"items": ["a", "b", "c", "d"]}"#;
+        assert_eq!(json(r#"["c", "d"]"#), search("enumerate(items)[?[0] >= `2`][1]", data));
+        assert_eq!(json(r#"[{"index": 0, "value": "a"}, {"index": 1, "value": "b"}]"#),
+                   search("enumerate(items)[:2].{index: [0], value: [1]}", data));
+        assert!(search_err("enumerate(items, items)", data).contains("Too many arguments"));
+        assert!(search_err("enumerate(@)", data).contains("expects type array"));
+    }
+
+    #[test]
+    fn enumerate_shares_elements() {
+        let data = json(r#"[{"a": 1}]"#);
+        let expr = compile("enumerate(@)[0][1]").unwrap();
+        let mut ctx = Context::new(expr.as_str(), &DEFAULT_RUNTIME);
+        let result = interpret(&data, expr.as_ast(), &mut ctx).unwrap();
+        assert!(Rcvar::ptr_eq(&result, &data.as_array().unwrap()[0]));
+    }
+
+    #[test]
+    fn invert_swaps_keys_and_values() {
+        assert_eq!(json(r#"{"x": "a", "1": "b", "1.5": "c"}"#),
+                   search("invert(@)", r#"{"a": "x", "b": 1, "c": 1.5}"#));
+        assert_eq!(json("{}"), search("invert(@)", "{}"));
+    }
+
+    #[test]
+    fn invert_collisions_keep_the_last_key() {
+        assert_eq!(json(r#"{"1": "c", "x": "b"}"#),
+                   search("invert(@)", r#"{"a": 1, "b": "x", "c": "1"}"#));
+    }
+
+    #[test]
+    fn invert_rejects_other_value_types() {
+        let data = json(r#"{"a": "x", "b": [1]}"#);
+        let err = compile("invert(@)").unwrap().search(data).unwrap_err();
+        assert_eq!(ErrorReason::Runtime(RuntimeError::InvalidType {
+                       expected: "string|number at key \"b\"".to_owned(),
+                       actual: "array".to_owned(),
+                       position: 0,
+                   }),
+                   err.reason);
+        assert!(search_err("invert(@)", r#"{"a": null}"#)
+            .contains("Argument 0 expects type string|number at key \"a\", given null"));
+    }
+
+    #[test]
+    fn set_operations_on_scalars() {
+        let data = r#"{"a": [3, 1, 2, 1], "b": [2, 4.0, 3, 4]}"#;
+        assert_eq!(json("[3, 1, 2, 4]"), search("union(a, b)", data));
+        assert_eq!(json("[3, 2]"), search("intersection(a, b)", data));
+        assert_eq!(json("[1]"), search("difference(a, b)", data));
+        assert_eq!(json("[4]"), search("difference(b, a)", data));
+        assert_eq!(json("[]"), search("intersection(a, `[]`)", data));
+        assert_eq!(json("[1, 2]"), search("union(`[1, 1.0]`, `[2.0, 1e0]`)", data));
+    }
+
+    #[test]
+    fn set_operations_on_objects() {
+        let data = r#"{"a": [{"id": 1}, {"id": 2, "tags": ["x"]}, {"id": 1}],
+                       "b": [{"tags": ["x"], "id": 2.0}, {"id": 3}]}"#;
+        assert_eq!(json(r#"[{"id": 1}, {"id": 2, "tags": ["x"]}, {"id": 3}]"#),
+                   search("union(a, b)", data));
+        assert_eq!(json(r#"[{"id": 2, "tags": ["x"]}]"#), search("intersection(a, b)", data));
+        assert_eq!(json(r#"[{"id": 1}]"#), search("difference(a, b)", data));
+        assert!(search_err("union(a, `1`)", data).contains("Argument 1 expects type array"));
+    }
+
+    #[test]
+    fn sort_by_multiple_keys() {
+        let data = r#"[{"l": "b", "f": "x", "n": 1}, {"l": "a", "f": "z", "n": 2},
+                       {"l": "b", "f": "a", "n": 3}, {"l": "a", "f": "z", "n": 4}]"#;
+        assert_eq!(json("[2, 4, 3, 1]"), search("sort_by(@, &l, &f)[*].n", data));
+        assert_eq!(json("[2, 4, 1, 3]"), search("sort_by(@, &l)[*].n", data));
+        assert_eq!(json("[4, 2, 3, 1]"), search("sort_by(reverse(@), &l, &f)[*].n", data));
+    }
+
+    #[test]
+    fn sort_by_reports_key_position_and_invocation() {
+        let data = r#"[{"a": "x", "b": 1}, {"a": "x", "b": "2"}]"#;
+        let err = compile("sort_by(@, &a, &b)").unwrap().search(json(data)).unwrap_err();
+        assert_eq!(ErrorReason::Runtime(RuntimeError::InvalidReturnType {
+                       expected: "expression->number".to_owned(),
+                       actual: "string".to_owned(),
+                       position: 2,
+                       invocation: 1,
+                   }),
+                   err.reason);
+        assert!(search_err("sort_by(@, &a, &c)", data)
+            .contains("Argument 2 must return expression->string|expression->number but \
+                       invocation 0 returned null"));
+        assert!(search_err("sort_by(@, &a, b)", data).contains("Argument 2 expects type expref"));
+    }
+
+    #[test]
+    fn round_rounds_half_away_from_zero() {
+        assert_eq!(json("3"), search("round(`2.5`)", "null"));
+        assert_eq!(json("-3"), search("round(`-2.5`)", "null"));
+        assert_eq!(json("2"), search("round(`2.4`)", "null"));
+        assert_eq!(json(r#""3""#), search("to_string(round(`2.5`))", "null"));
+    }
+
+    #[test]
+    fn round_to_a_precision() {
+        assert_eq!(json("1.24"), search("round(`1.235`, `2`)", "null"));
+        assert_eq!(json("-1.5"), search("round(`-1.45`, `1`)", "null"));
+        assert_eq!(json("1200"), search("round(`1234.5`, `-2`)", "null"));
+        assert_eq!(json("0"), search("round(`49`, `-2`)", "null"));
+        assert_eq!(json("7"), search("round(`7`, `400`)", "null"));
+        assert_eq!(json("1.5"), search("round(`1.5`, `308`)", "null"));
+        assert_eq!(json("1e308"), search("round(`1e308`, `-308`)", "null"));
+        assert_eq!(json("0"), search("round(`1e307`, `-308`)", "null"));
+        assert_eq!(json("0"), search("round(`1234`, `-400`)", "null"));
+        assert_eq!(json("0"), search("round(`-1.7e308`, `-309`)", "null"));
+        assert!(search_err("round(`1`, `0.5`)", "null")
+            .contains("Argument 1 expects type integer, given number"));
+        assert!(search_err("round('1')", "null").contains("Argument 0 expects type number"));
+    }
+
+    #[test]
+    fn math_functions_are_opt_in() {
+        assert!(search_err("sqrt(`4`)", "null").contains("Call to undefined function sqrt"));
+        assert_eq!(json("2"), search_with(MATH, "sqrt(`4`)", "{}"));
+    }
+
+    #[test]
+    fn math_functions_compute_results() {
+        assert_eq!(json("8"), search_with(MATH, "pow(`2`, `3`)", "{}"));
+        assert_eq!(json("0.5"), search_with(MATH, "pow(`4`, `-0.5`)", "{}"));
+        assert_eq!(json("1.5"), search_with(MATH, "sqrt(`2.25`)", "{}"));
+        assert_eq!(json("0"), search_with(MATH, "log(`1`)", "{}"));
+        assert_eq!(json("1"), search_with(MATH, "log(`2.718281828459045`)", "{}"));
+        assert_eq!(json("3"), search_with(MATH, "log(`8`, `2`)", "{}"));
+        assert_eq!(json("1"), search_with(MATH, "mod(`7`, `3`)", "{}"));
+        assert_eq!(json("-1"), search_with(MATH, "mod(`-7`, `3`)", "{}"));
+        assert_eq!(json("0.5"), search_with(MATH, "mod(`2.5`, `1`)", "{}"));
+    }
+
+    #[test]
+    fn math_functions_return_null_for_non_finite_results() {
+        assert_eq!(json("null"), search_with(MATH, "sqrt(`-1`)", "{}"));
+        assert_eq!(json("null"), search_with(MATH, "log(`0`)", "{}"));
+        assert_eq!(json("null"), search_with(MATH, "log(`-1`)", "{}"));
+        assert_eq!(json("null"), search_with(MATH, "log(`8`, `1`)", "{}"));
+        assert_eq!(json("null"), search_with(MATH, "mod(`1`, `0`)", "{}"));
+        assert_eq!(json("null"), search_with(MATH, "pow(`10`, `400`)", "{}"));
+        assert_eq!(json("null"), search_with(MATH, "pow(`-8`, `0.5`)", "{}"));
+        assert_eq!(json(r#""[null,2]""#),
+                   search_with(MATH, "to_string([sqrt(`-1`), sqrt(`4`)])", "{}"));
+    }
+
+    #[test]
+    fn math_functions_validate_arguments() {
+        let runtime = runtime_with(MATH);
+        let err = |expr: &str| runtime.compile(expr).unwrap().search(()).unwrap_err().to_string();
+        assert!(err("pow(`2`)").contains("Not enough arguments"));
+        assert!(err("log(`2`, `2`, `2`)").contains("Too many arguments"));
+        assert!(err("mod(`2`, '1')").contains("Argument 1 expects type number"));
+    }
+
+    #[test]
+    fn substring_slices_code_points() {
+        let data = r#""héllo""#;
+        assert_eq!(json(r#""llo""#), search("substring(@, `2`)", data));
+        assert_eq!(json(r#""él""#), search("substring(@, `1`, `3`)", data));
+        assert_eq!(json(r#""😀🎉""#), search("substring(@, `1`, `3`)", r#""a😀🎉b""#));
+        assert_eq!(json(r#""""#), search("substring(@, `0`, `0`)", data));
+    }
+
+    #[test]
+    fn substring_with_negative_and_out_of_range_indices() {
+        let data = r#""héllo""#;
+        assert_eq!(json(r#""lo""#), search("substring(@, `-2`)", data));
+        assert_eq!(json(r#""éll""#), search("substring(@, `-4`, `-1`)", data));
+        assert_eq!(json(r#""héllo""#), search("substring(@, `-100`, `100`)", data));
+        assert_eq!(json(r#""""#), search("substring(@, `10`)", data));
+        assert_eq!(json(r#""""#), search("substring(@, `3`, `1`)", data));
+        assert_eq!(json(r#""""#), search("substring(@, `-1`, `-3`)", data));
+    }
+
+    #[test]
+    fn substring_validates_arguments() {
+        assert!(search_err("substring(@, `1.5`)", r#""a""#)
+            .contains("Argument 1 expects type integer"));
+        assert!(search_err("substring(@, `0`, `1`, `2`)", r#""a""#).contains("Too many arguments"));
+        assert!(search_err("substring(@)", r#""a""#).contains("Not enough arguments"));
+    }
+
+    #[test]
+    fn reduce_accumulates_values() {
+        let data = r#"{"n": [1, 2, 3, 4], "s": ["a", "b", "c"]}"#;
+        assert_eq!(json("10"), search("reduce(n, &sum([accumulated, current]), `0`)", data));
+        assert_eq!(json(r#""abc""#),
+                   search("reduce(s, &join('', [accumulated, current]), '')", data));
+        assert_eq!(json("4"), search("reduce(n, &max([accumulated, current]), `0`)", data));
+    }
+
+    #[test]
+    fn reduce_with_object_accumulators() {
+        let data = r#"[{"a": 1}, {"b": {"c": [2]}}, {"a": 3}]"#;
+        assert_eq!(json(r#"{"a": 3, "b": {"c": [2]}, "z": true}"#),
+                   search("reduce(@, &merge(accumulated, current), `{\"z\": true}`)", data));
+    }
+
+    #[test]
+    fn reduce_of_empty_array_returns_initial_value() {
+        let data = json(r#"{"items": [], "init": {"deep": [1, 2]}}"#);
+        let expr = compile("reduce(items, &current, init)").unwrap();
+        let mut ctx = Context::new(expr.as_str(), &DEFAULT_RUNTIME);
+        let result = interpret(&data, expr.as_ast(), &mut ctx).unwrap();
+        assert!(Rcvar::ptr_eq(&result, data.as_object().unwrap().get("init").unwrap()));
+    }
+
+    #[test]
+    fn reduce_validates_arguments() {
+        assert!(search_err("reduce(@, &a)", "[]").contains("Not enough arguments"));
+        assert!(search_err("reduce(@, &abs(current), `0`)", r#"["x"]"#).contains("expects type"));
+    }
+
+    #[test]
+    fn map_with_index_exposes_indices() {
+        let data = r#"[{"name": "a"}, {"name": "b"}, {}]"#;
+        assert_eq!(json(r#"["0-a", "1-b"]"#),
+                   search("map_with_index(&join('-', [to_string(index), value.name]), @[:2])",
+                          data));
+        assert_eq!(json(r#"["a", "b", null]"#), search("map_with_index(&value.name, @)", data));
+        assert_eq!(json("[]"), search("map_with_index(&index, @)", "[]"));
+    }
+
+    #[test]
+    fn map_with_index_feeds_sort_by() {
+        let data = r#"["c", "a", "b"]"#;
+        assert_eq!(json("[1, 2, 0]"),
+                   search("map_with_index(&{i: index, v: value}, @) | sort_by(@, &v)[*].i", data));
+        assert_eq!(json("[0, 2, 1]"),
+                   search("reverse(sort_by(map_with_index(&{i: index, v: value}, @), &v))[*].i",
+                          data));
+        assert!(search_err("map_with_index(@, &index)", data).contains("expects type expref"));
+    }
+
+    #[test]
+    fn sum_by_and_avg_by_aggregate_expression_results() {
+        let data = r#"[{"amount": 1.5}, {"amount": 2}, {"amount": 4.5}]"#;
+        assert_eq!(json("8"), search("sum_by(@, &amount)", data));
+        assert_eq!(json("2.6666666666666665"), search("avg_by(@, &amount)", data));
+        assert_eq!(json("16"), search("sum_by(@, &sum([amount, amount]))", data));
+    }
+
+    #[test]
+    fn sum_by_and_avg_by_of_empty_arrays() {
+        assert_eq!(json("0"), search("sum_by(@, &amount)", "[]"));
+        assert_eq!(json("null"), search("avg_by(@, &amount)", "[]"));
+    }
+
+    #[test]
+    fn sum_by_and_avg_by_require_numbers() {
+        let data = json(r#"[{"amount": 1}, {"price": 2}]"#);
+        for name in &["sum_by", "avg_by"] {
+            let expr = compile(&format!("{}(@, &amount)", name)).unwrap();
+            let err = expr.search(data.clone()).unwrap_err();
+            assert_eq!(ErrorReason::Runtime(RuntimeError::InvalidReturnType {
+                           expected: "expression->number".to_owned(),
+                           actual: "null".to_owned(),
+                           position: 1,
+                           invocation: 1,
+                       }),
+                       err.reason);
+        }
+    }
+
+    #[test]
+    fn get_returns_value_or_default() {
+        let data = r#"{"a": 1, "n": null, "o": {"x": [1]}}"#;
+        assert_eq!(json("1"), search("get(@, 'a', `5`)", data));
+        assert_eq!(json(r#"{"x": [1]}"#), search("get(@, 'o', `5`)", data));
+        assert_eq!(json("5"), search("get(@, 'missing', `5`)", data));
+        assert_eq!(json("5"), search("get(@, 'n', `5`)", data));
+        assert_eq!(json("null"), search("get(@, 'missing')", data));
+    }
+
+    #[test]
+    fn get_keeps_falsey_values_unlike_or() {
+        let data = r#"{"f": false, "z": 0, "e": "", "l": []}"#;
+        assert_eq!(json("false"), search("get(@, 'f', 'default')", data));
+        assert_eq!(json(r#""default""#), search("f || 'default'", data));
+        assert_eq!(json(r#""""#), search("get(@, 'e', 'default')", data));
+        assert_eq!(json(r#""default""#), search("e || 'default'", data));
+        assert_eq!(json("[]"), search("get(@, 'l', 'default')", data));
+        assert_eq!(json(r#""default""#), search("l || 'default'", data));
+        assert_eq!(json("0"), search("get(@, 'z', 'default')", data));
+    }
+
+    #[test]
+    fn get_validates_arguments() {
+        assert!(search_err("get(@, `1`)", "{}").contains("Argument 1 expects type string"));
+        assert!(search_err("get(`[]`, 'a')", "{}").contains("Argument 0 expects type object"));
+        assert!(search_err("get(@, 'a', `1`, `2`)", "{}").contains("Too many arguments"));
+    }
+
+    #[test]
+    fn index_of_finds_deeply_equal_elements() {
+        let data = r#"[1, "a", {"b": [1, 2]}, [3], 1.0]"#;
+        assert_eq!(json("0"), search("index_of(@, `1.0`)", data));
+        assert_eq!(json("1"), search("index_of(@, 'a')", data));
+        assert_eq!(json("2"), search("index_of(@, `{\"b\": [1.0, 2]}`)", data));
+        assert_eq!(json("3"), search("index_of(@, `[3]`)", data));
+        assert_eq!(json("null"), search("index_of(@, `[1, 2]`)", data));
+        assert_eq!(json("null"), search("index_of(@, `null`)", "[]"));
+    }
+
+    #[test]
+    fn index_of_from_a_start_offset() {
+        let data = "[1, 2, 1, 2]";
+        assert_eq!(json("2"), search("index_of(@, `1`, `1`)", data));
+        assert_eq!(json("2"), search("index_of(@, `1`, `2`)", data));
+        assert_eq!(json("null"), search("index_of(@, `1`, `3`)", data));
+        assert_eq!(json("null"), search("index_of(@, `1`, `10`)", data));
+        assert!(search_err("index_of(@, `1`, `-1`)", data).contains("non-negative integer"));
+    }
+
+    #[test]
+    fn index_of_agrees_with_contains() {
+        let data = r#"[{"a": 1}, [2.0], 3, "x", null]"#;
+        for needle in &["`{\"a\": 1.0}`", "`[2]`", "`3.0`", "'x'", "`null`", "`{}`", "'y'"] {
+            let contained = search(&format!("contains(@, {})", needle), data);
+            let index = search(&format!("index_of(@, {})", needle), data);
+            assert_eq!(contained.as_boolean().unwrap(), !index.is_null(), "{}", needle);
+        }
+    }
+
+    #[test]
+    fn json_parse_decodes_embedded_json() {
+        let data = r#"{"payload": "{\"user\": {\"id\": 7}}", "bad": "{nope"}"#;
+        assert_eq!(json("7"), search("json_parse(payload).user.id", data));
+        assert_eq!(json("null"), search("json_parse(bad)", data));
+        assert_eq!(json(r#""s""#), search("json_parse('\"s\"')", data));
+        assert_eq!(json("[1, 2.5, null]"), search("json_parse('[1, 2.5, null]')", data));
+        assert!(search_err("json_parse(`1`)", data).contains("expects type string"));
+    }
+
+    #[test]
+    fn json_serialize_is_compact_and_ordered() {
+        assert_eq!(json(r#""{\"c\":{},\"b\":\"x\",\"a\":[1,2.5,null,true]}""#),
+                   search("json_serialize(@)",
+                          r#"{"c": {}, "b": "x", "a": [1, 2.5, null, true]}"#));
+        assert_eq!(json(r#""\"é\\n\"""#), search("json_serialize(@)", r#""é\n""#));
+        assert_eq!(json(r#""null""#), search("json_serialize(missing)", "{}"));
+    }
+
+    #[test]
+    fn json_round_trips_nested_values() {
+        let mut nested = String::from("1");
+        for i in 0..40 {
+            nested = format!(r#"{{"k{}": [{}, "v"]}}"#, i % 3, nested);
+        }
+        assert_eq!(json(&nested), search("json_parse(json_serialize(@))", &nested));
+        let serialized = search("json_serialize(@)", &nested);
+        assert_eq!(serialized, search("json_serialize(json_parse(@))", &serialized.to_string()));
+    }
+
+    #[test]
+    fn encoding_functions_are_opt_in() {
+        let mut runtime = Runtime::new();
+        runtime.register_builtin_functions();
+        assert!(runtime.get_function("url_encode").is_none());
+        assert!(runtime.get_function("url_decode").is_none());
+        let err = runtime.compile("url_encode(@)").unwrap().search("a b").unwrap_err();
+        assert!(err.to_string().contains("Call to undefined function url_encode"));
+        assert_eq!(json(r#""a%20b""#), search_with(ENCODING, "url_encode(@)", r#""a b""#));
+    }
+
+    #[test]
+    fn url_encode_escapes_reserved_characters() {
+        assert_eq!(json(r#""a%20b%2Bc%26d%3De%2Ff%3F""#),
+                   search_with(ENCODING, "url_encode(@)", r#""a b+c&d=e/f?""#));
+        assert_eq!(json(r#""AZaz09-._~""#),
+                   search_with(ENCODING, "url_encode(@)", r#""AZaz09-._~""#));
+        assert_eq!(json(r#""caf%C3%A9%20%E2%98%83""#),
+                   search_with(ENCODING, "url_encode(@)", r#""café ☃""#));
+        assert_eq!(json(r#""q=a%20b&lang=en""#),
+                   search_with(ENCODING, "join('&', [join('=', ['q', url_encode(q)]), 'lang=en'])",
+                               r#"{"q": "a b"}"#));
+    }
+
+    #[test]
+    fn url_encode_double_encodes_percent_signs() {
+        assert_eq!(json(r#""a%2520b""#), search_with(ENCODING, "url_encode(@)", r#""a%20b""#));
+        assert_eq!(json(r#""a%20b""#),
+                   search_with(ENCODING, "url_decode(url_encode(@))", r#""a%20b""#));
+        assert_eq!(json(r#""a-b""#),
+                   search_with(ENCODING, "url_encode(url_encode(@))", r#""a-b""#));
+    }
+
+    #[test]
+    fn url_decode_reverses_url_encode() {
+        assert_eq!(json(r#""café ☃""#),
+                   search_with(ENCODING, "url_decode(@)", r#""caf%c3%A9%20%E2%98%83""#));
+        assert_eq!(json(r#""a+b c""#), search_with(ENCODING, "url_decode(@)", r#""a+b c""#));
+        let data = r#""~!*'();:@&=+$,/?#[] é\u0000""#;
+        assert_eq!(json(data), search_with(ENCODING, "url_decode(url_encode(@))", data));
+    }
+
+    #[test]
+    fn url_decode_returns_null_for_invalid_input() {
+        assert_eq!(json("null"), search_with(ENCODING, "url_decode(@)", r#""100%""#));
+        assert_eq!(json("null"), search_with(ENCODING, "url_decode(@)", r#""%2""#));
+        assert_eq!(json("null"), search_with(ENCODING, "url_decode(@)", r#""%zz""#));
+        assert_eq!(json("null"), search_with(ENCODING, "url_decode(@)", r#""%+1""#));
+        assert_eq!(json("null"), search_with(ENCODING, "url_decode(@)", r#""%C3%28""#));
+        let runtime = runtime_with(ENCODING);
+        let expr = runtime.compile("url_decode(`1`)").unwrap();
+        assert!(expr.search(()).unwrap_err().to_string().contains("expects type string"));
+    }
+
+    #[test]
+    fn stats_functions_are_opt_in() {
+        assert!(search_err("median(@)", "[1]").contains("Call to undefined function median"));
+        assert_eq!(json("1"), search_with(STATS, "median(@)", "[1]"));
+    }
+
+    #[test]
+    fn median_interpolates_even_lengths() {
+        assert_eq!(json("3"), search_with(STATS, "median(@)", "[5, 1, 3]"));
+        assert_eq!(json("2.5"), search_with(STATS, "median(@)", "[4, 1, 3, 2]"));
+        assert_eq!(json("-7"), search_with(STATS, "median(@)", "[-7]"));
+        assert_eq!(json("null"), search_with(STATS, "median(@)", "[]"));
+    }
+
+    #[test]
+    fn percentile_interpolates_linearly() {
+        let latencies = "[15, 20, 35, 40, 50]";
+        assert_eq!(json("15"), search_with(STATS, "percentile(@, `0`)", latencies));
+        assert_eq!(json("50"), search_with(STATS, "percentile(@, `100`)", latencies));
+        assert_eq!(json("35"), search_with(STATS, "percentile(@, `50`)", latencies));
+        assert_eq!(json("29"), search_with(STATS, "percentile(@, `40`)", latencies));
+        assert_eq!(json("48"), search_with(STATS, "percentile(@, `95`)", latencies));
+        assert_eq!(json("9"), search_with(STATS, "percentile(@, `90`)", "[9]"));
+        assert_eq!(json("null"), search_with(STATS, "percentile(@, `50`)", "[]"));
+        assert_eq!(json("1e308"), search_with(STATS, "percentile(`[1e308, 1e308]`, `50`)", "null"));
+    }
+
+    #[test]
+    fn percentile_rejects_out_of_range_percentiles() {
+        let runtime = runtime_with(STATS);
+        for p in &["-1", "100.5"] {
+            let expr = runtime.compile(&format!("percentile(@, `{}`)", p)).unwrap();
+            let err = expr.search(json("[1, 2]")).unwrap_err();
+            match err.reason {
+                ErrorReason::Runtime(RuntimeError::InvalidArgument { position, .. }) => {
+                    assert_eq!(1, position)
+                }
+                ref reason => panic!("unexpected error: {}", reason),
+            }
+        }
+    }
+
+    #[test]
+    fn stddev_is_the_population_standard_deviation() {
+        assert_eq!(json("2"), search_with(STATS, "stddev(@)", "[2, 4, 4, 4, 5, 5, 7, 9]"));
+        assert_eq!(json("0"), search_with(STATS, "stddev(@)", "[3]"));
+        assert_eq!(json("0.5"), search_with(STATS, "stddev(@)", "[1.5, 2.5]"));
+        assert_eq!(json("null"), search_with(STATS, "stddev(@)", "[]"));
+        assert_eq!(json("null"), search_with(STATS, "stddev(`[1e308, -1e308, 1e308]`)", "null"));
+    }
+
+    #[test]
+    fn stats_functions_validate_arguments() {
+        let runtime = runtime_with(STATS);
+        let err = |expr: &str| runtime.compile(expr).unwrap().search(json(r#"[1, "a"]"#))
+            .unwrap_err().to_string();
+        assert!(err("median(@)").contains("Argument 0 expects type array[number]"));
+        assert!(err("stddev(@)").contains("Argument 0 expects type array[number]"));
+        assert!(err("percentile(`[1]`, '50')").contains("Argument 1 expects type number"));
+    }
+
+    #[test]
+    fn format_replaces_placeholders_in_order() {
+        let data = r#"{"name": "disk", "id": 7, "tags": {"b": [1, null], "a": true}}"#;
+        assert_eq!(json(r#""disk (7)""#), search("format('{} ({})', [name, id])", data));
+        assert_eq!(json(r#""disk: {\"b\":[1,null],\"a\":true}""#),
+                   search("format('{}: {}', [name, tags])", data));
+        assert_eq!(json(r#""null false""#), search("format('{} {}', [missing, `false`])", data));
+        assert_eq!(json(r#""no placeholders""#), search("format('no placeholders', [id])", data));
+    }
+
+    #[test]
+    fn format_supports_escapes_and_unicode() {
+        let data = r#"{"name": "café"}"#;
+        assert_eq!(json(r#""{café}""#), search("format('{{{}}}', [name])", data));
+        assert_eq!(json(r#""{} and }{""#), search("format('{{}} and }}{{', `[]`)", data));
+        assert_eq!(json(r#""→ café ☃""#), search("format('→ {} ☃', [name, 'ignored'])", data));
+    }
+
+    #[test]
+    fn format_reports_missing_arguments() {
+        let err = compile("format('{} {} {}', [a, b])").unwrap().search(json("{}")).unwrap_err();
+        match err.reason {
+            ErrorReason::Runtime(RuntimeError::InvalidArgument { position, ref reason }) => {
+                assert_eq!(1, position);
+                assert_eq!("no argument for placeholder 2", reason);
+            }
+            ref reason => panic!("unexpected error: {}", reason),
+        }
+        assert!(search_err("format('{', `[]`)", "null").contains("unmatched '{' in template"));
+        assert!(search_err("format('a}b', `[]`)", "null").contains("unmatched '}' in template"));
+        assert!(search_err("format('{}', 'a')", "null").contains("Argument 1 expects type array"));
+    }
+
+    #[test]
+    fn custom_functions_validate_like_builtins() {
+        let mut runtime = Runtime::new();
+        runtime.register_builtin_functions();
+        runtime.register_function("my_abs", Box::new(CustomFunction::new(
+            Signature::new(vec![ArgumentType::Number], None),
+            Box::new(|args: &[Rcvar], _: &mut Context| Ok(args[0].clone())))));
+        runtime.register_function("my_join", Box::new(CustomFunction::new(
+            Signature::new(vec![ArgumentType::String,
+                                ArgumentType::TypedArray(Box::new(ArgumentType::String))],
+                           None),
+            Box::new(|args: &[Rcvar], _: &mut Context| Ok(args[0].clone())))));
+        let reason = |expr: &str| {
+            runtime.compile(expr).unwrap().search(json(r#"["a", 1]"#)).unwrap_err().reason
+        };
+        for args in &["", "`1`, `2`", "'a'"] {
+            assert_eq!(reason(&format!("abs({})", args)), reason(&format!("my_abs({})", args)));
+        }
+        for args in &["','", "',', @, @", "`1`, @", "',', @"] {
+            assert_eq!(reason(&format!("join({})", args)), reason(&format!("my_join({})", args)));
+        }
+    }
+
+    #[test]
+    fn avg_of_an_empty_array_is_null() {
+        assert_eq!(json("null"), search("avg(`[]`)", "null"));
+        assert_eq!(json("null"), search("avg(@)", "[]"));
+        assert_eq!(json("null"), search("avg_by(@, &a)", "[]"));
+        assert_eq!(json("0"), search("sum(`[]`)", "null"));
+        assert_eq!(json(r#""null""#), search("to_string(avg(`[]`))", "null"));
+    }
+
+    #[test]
+    fn overflowing_sums_and_averages_are_null() {
+        let huge = "`[1e308, 1e308]`";
+        assert_eq!(json("null"), search(&format!("sum({})", huge), "null"));
+        assert_eq!(json("null"), search(&format!("avg({})", huge), "null"));
+        assert_eq!(json("null"), search(&format!("sum_by({}, &@)", huge), "null"));
+        assert_eq!(json("null"), search(&format!("avg_by({}, &@)", huge), "null"));
+        assert_eq!(json("2.5e307"), search("avg(`[1e308, -1e308, 1e308, 0]`)", "null"));
+    }
+
+    #[test]
+    fn integral_results_stringify_as_integers() {
+        let data = r#"{"ints": [1, 2, 3], "mixed": [1, 2.5], "n": -3, "f": 2.5}"#;
+        assert_eq!(json(r#""6""#), search("to_string(sum(ints))", data));
+        assert_eq!(json(r#""3.5""#), search("to_string(sum(mixed))", data));
+        assert_eq!(json(r#""3""#), search("to_string(abs(n))", data));
+        assert_eq!(json(r#""3""#), search("to_string(ceil(f))", data));
+        assert_eq!(json(r#""2""#), search("to_string(floor(f))", data));
+        assert_eq!(json(r#""-3""#), search("to_string(ceil(n))", data));
+        assert_eq!(json(r#""0""#), search("to_string(ceil(`-0.5`))", data));
+        assert_eq!(json(r#"["6","3","3"]"#),
+                   search("[sum(ints), abs(n), ceil(f)][*].to_string(@)", data));
+    }
+
+    #[test]
+    fn to_number_only_accepts_json_numbers() {
+        let cases = [("4", "4"), ("-4", "-4"), ("1.5", "1.5"), ("-0", "0"), ("1e3", "1000"),
+                     ("2.5E-1", "0.25"), ("9007199254740992", "9007199254740992"),
+                     ("1e308", "1e308"), (" 1", "null"), ("1 ", "null"), ("\n1", "null"),
+                     ("+1", "null"), ("0x1A", "null"), ("1abc", "null"), ("", "null"),
+                     ("true", "null"), ("null", "null"), ("\"1\"", "null"), ("[1]", "null"),
+                     ("1e400", "null"), ("-1e400", "null"), ("NaN", "null"), ("inf", "null"),
+                     (".5", "null"), ("1.", "null")];
+        let expr = compile("to_number(@)").unwrap();
+        for &(input, expected) in &cases {
+            let result = expr.search(Variable::String(input.to_owned())).unwrap();
+            assert_eq!(json(expected), result, "to_number({:?})", input);
+        }
+    }
+
+    #[test]
+    fn expression_errors_report_the_failing_element() {
+        let err = compile("map(&length(@), @)").unwrap()
+            .search(json(r#"["a", [1], 3, "b"]"#)).unwrap_err();
+        assert_eq!(ErrorReason::Runtime(RuntimeError::ExpressionFailed {
+                       position: 0,
+                       invocation: 2,
+                       error: Box::new(RuntimeError::InvalidType {
+                           expected: "array|object|string".to_owned(),
+                           actual: "number".to_owned(),
+                           position: 0,
+                       }),
+                   }),
+                   err.reason);
+        assert!(err.to_string().contains("Argument 0 failed on invocation 2: Argument 0 expects"));
+    }
+
+    #[test]
+    fn expref_functions_wrap_element_errors() {
+        let data = r#"[{"a": "x"}, {"a": "y"}, {"a": 1}]"#;
+        let cases = [("sort_by(@, &length(a))", 1), ("min_by(@, &length(a))", 1),
+                     ("max_by(@, &length(a))", 1), ("group_by(@, &upper(a))", 1),
+                     ("count(@, &upper(a))", 1), ("partition(@, &upper(a))", 1),
+                     ("sum_by(@, &length(a))", 1), ("avg_by(@, &length(a))", 1),
+                     ("map_with_index(&upper(value.a), @)", 0),
+                     ("reduce(@, &upper(current.a), '')", 1)];
+        for &(expression, expected_position) in &cases {
+            let err = compile(expression).unwrap().search(json(data)).unwrap_err();
+            match err.reason {
+                ErrorReason::Runtime(RuntimeError::ExpressionFailed { position,
+                                                                      invocation,
+                                                                      .. }) => {
+                    assert_eq!((expected_position, 2), (position, invocation), "{}", expression);
+                }
+                ref reason => panic!("unexpected error for {}: {}", expression, reason),
+            }
+        }
+    }
+
+    #[test]
+    fn sort_min_and_max_order_strings_by_code_point() {
+        let data = r#"["😀", "￿", "b", "é", "𐀀", "a", "B"]"#;
+        assert_eq!(json(r#"["B", "a", "b", "é", "￿", "𐀀", "😀"]"#),
+                   search("sort(@)", data));
+        assert_eq!(json(r#""B""#), search("min(@)", data));
+        assert_eq!(json(r#""😀""#), search("max(@)", data));
+        assert_eq!(json(r#"["", "a", "aa", "ab"]"#), search("sort(@)", r#"["ab", "aa", "", "a"]"#));
+    }
+
+    #[test]
+    fn sort_min_and_max_compare_numbers_by_value() {
+        let data = "[18446744073709551615, 1.0, -2, 1, 9007199254740993, 0.5, -1e300]";
+        assert_eq!(json("[-1e300, -2, 0.5, 1, 1, 9007199254740993, 18446744073709551615]"),
+                   search("sort(@)", data));
+        assert_eq!(json("-1e300"), search("min(@)", data));
+        assert_eq!(json("18446744073709551615"), search("max(@)", data));
+        assert_eq!(search("sort(@)", data), search("sort(reverse(@))", data));
+    }
+
+    #[test]
+    fn sort_is_stable_for_equal_numbers() {
+        let values = vec![Variable::Number(2.0), Variable::Number(1.0), Variable::Number(1.0)];
+        let data = Rcvar::new(Variable::Array(values.into_iter().map(Rcvar::new).collect()));
+        let expr = compile("sort(@)").unwrap();
+        let mut ctx = Context::new(expr.as_str(), &DEFAULT_RUNTIME);
+        let sorted = interpret(&data, expr.as_ast(), &mut ctx).unwrap();
+        let input = data.as_array().unwrap();
+        let output = sorted.as_array().unwrap();
+        assert!(Rcvar::ptr_eq(&input[1], &output[0]));
+        assert!(Rcvar::ptr_eq(&input[2], &output[1]));
+        assert!(Rcvar::ptr_eq(&input[0], &output[2]));
+    }
+
+    #[test]
+    fn byte_length_counts_utf8_bytes() {
+        assert!(search_err("byte_length('a')", "null").contains("undefined function byte_length"));
+        assert_eq!(json("3"), search_with(EXTENSION, "byte_length(@)", r#""abc""#));
+        assert_eq!(json("5"), search_with(EXTENSION, "byte_length(@)", r#""café""#));
+        assert_eq!(json("4"), search_with(EXTENSION, "length(@)", r#""café""#));
+        assert_eq!(json("8"), search_with(EXTENSION, "byte_length(@)", r#""😀😀""#));
+        assert_eq!(json("2"), search_with(EXTENSION, "length(@)", r#""😀😀""#));
+        assert_eq!(json("0"), search_with(EXTENSION, "byte_length(@)", r#""""#));
+    }
+
+    #[test]
+    fn byte_length_counts_elements_and_keys() {
+        assert_eq!(json("2"), search_with(EXTENSION, "byte_length(@)", r#"["é", "😀"]"#));
+        assert_eq!(json("1"), search_with(EXTENSION, "byte_length(@)", r#"{"é": "😀"}"#));
+        assert_eq!(json(r#"["😀"]"#),
+                   search_with(EXTENSION, "[?byte_length(@) > `3`]", r#"["abc", "😀", "é"]"#));
+        let runtime = runtime_with(EXTENSION);
+        let err = runtime.compile("byte_length(`1`)").unwrap().search(()).unwrap_err();
+        assert!(err.to_string().contains("Argument 0 expects type array|object|string"));
+    }
+
+    #[test]
+    fn type_predicates_test_argument_types() {
+        let data = r#"["a", 1, [], {}, true, null]"#;
+        let cases = [("is_string", "[true, false, false, false, false, false]"),
+                     ("is_number", "[false, true, false, false, false, false]"),
+                     ("is_array", "[false, false, true, false, false, false]"),
+                     ("is_object", "[false, false, false, true, false, false]"),
+                     ("is_bool", "[false, false, false, false, true, false]"),
+                     ("is_null", "[false, false, false, false, false, true]")];
+        for &(function, expected) in &cases {
+            let expr = format!("map(&{}(@), @)", function);
+            assert_eq!(json(expected), search_with(EXTENSION, &expr, data), "{}", function);
+        }
+    }
+
+    #[test]
+    fn type_predicates_in_filters() {
+        let data = r#"[{"v": 7}, {"v": "7"}, {"v": 3}, {}, {"v": null}]"#;
+        assert_eq!(json("[7]"), search_with(EXTENSION, "[?is_number(v) && v > `5`].v", data));
+        assert_eq!(json(r#"[{}, {"v": null}]"#), search_with(EXTENSION, "[?is_null(v)]", data));
+        assert_eq!(json("false"), search_with(EXTENSION, "is_string(missing)", "{}"));
+        assert_eq!(json("true"), search_with(EXTENSION, "is_null(missing)", "{}"));
+        assert!(search_err("is_null(`1`)", "null").contains("undefined function is_null"));
     }
 }
@@ -0,0 +1,112 @@
+//! Optional hash functions.
+//!
+//! These functions are not part of the JMESPath specification. They are
+//! available with the `hash-functions` feature and are registered using
+//! `Runtime::register_hash_functions`. Each function hashes the UTF-8 bytes
+//! of a string and returns the lowercase hex digest.
+
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use {Context, Rcvar};
+use interpreter::SearchResult;
+use variable::Variable;
+use super::{Function, Signature, ArgumentType};
+
+/// Hashes a string argument and returns the lowercase hex digest.
+fn hex_digest<D: Digest>(value: &Rcvar) -> Rcvar {
+    let digest = D::digest(value.as_string().unwrap().as_bytes());
+    let hex = digest.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    Rcvar::new(Variable::String(hex))
+}
+
+defn! {
+    Md5Fn, vec![arg!(string)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        Ok(hex_digest::<Md5>(&args[0]))
+    }
+}
+
+defn! {
+    Sha1Fn, vec![arg!(string)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        Ok(hex_digest::<Sha1>(&args[0]))
+    }
+}
+
+defn! {
+    Sha256Fn, vec![arg!(string)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        Ok(hex_digest::<Sha256>(&args[0]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {Rcvar, Runtime};
+    use variable::Variable;
+
+    fn runtime() -> Runtime {
+        let mut runtime = Runtime::new();
+        runtime.register_builtin_functions();
+        runtime.register_hash_functions();
+        runtime
+    }
+
+    fn search(expr: &str, data: &str) -> Rcvar {
+        let runtime = runtime();
+        let expr = runtime.compile(expr).unwrap();
+        expr.search(Variable::from_json(data).unwrap()).unwrap()
+    }
+
+    fn json(data: &str) -> Rcvar {
+        Rcvar::new(Variable::from_json(data).unwrap())
+    }
+
+    #[test]
+    fn digests_known_vectors() {
+        let cases = [("md5", "", "d41d8cd98f00b204e9800998ecf8427e"),
+                     ("md5", "abc", "900150983cd24fb0d6963f7d28e17f72"),
+                     ("sha1", "", "da39a3ee5e6b4b0d3255bfef95601890afd80709"),
+                     ("sha1", "abc", "a9993e364706816aba3e25717850c26c9cd0d89d"),
+                     ("sha256", "",
+                      "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+                     ("sha256", "abc",
+                      "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")];
+        for &(function, input, digest) in &cases {
+            let expr = format!("{}(@)", function);
+            assert_eq!(json(&format!("{:?}", digest)), search(&expr, &format!("{:?}", input)));
+        }
+    }
+
+    #[test]
+    fn hashes_utf8_bytes() {
+        assert_eq!(json(r#""4a99557e4033c3539de2eb65472017cad5f9557f7a0625a09f1c3f6e2ba69c4c""#),
+                   search("sha256(@)", r#""\u00e9""#));
+    }
+
+    #[test]
+    fn hashes_inside_projections() {
+        let data = r#"{"items": [{"user": "a", "ts": "1"}]}"#;
+        let expected = search("sha256(@)", r#""a:1""#);
+        let result = search("items[*].{id: sha256(join(':', [user, ts]))}", data);
+        assert_eq!(expected, result.as_array().unwrap()[0].get_field("id"));
+    }
+
+    #[test]
+    fn rejects_non_string_arguments() {
+        let runtime = runtime();
+        for function in &["md5", "sha1", "sha256"] {
+            let expr = runtime.compile(&format!("{}(@)", function)).unwrap();
+            let err = expr.search(1).unwrap_err().to_string();
+            assert!(err.contains("Argument 0 expects type string, given number"), err);
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! Optional regular expression functions.
+//!
+//! These functions are not part of the JMESPath specification. They are
+//! available with the `regex-functions` feature and are registered using
+//! `Runtime::register_regex_functions`.
+//!
+//! Patterns use the syntax of the `regex` crate, which matches in linear
+//! time and does not support backtracking features like look-around or
+//! backreferences. Compiling a pattern can still be expensive, so patterns
+//! are limited to `MAX_PATTERN_LENGTH` bytes and a bounded compiled size.
+//! Compiled patterns are cached for the duration of a search, so using a
+//! regex function inside a projection only compiles each pattern once.
+
+use regex::{Regex, RegexBuilder};
+
+use {Context, ErrorReason, JmespathError, Rcvar, RuntimeError};
+use interpreter::SearchResult;
+use variable::Variable;
+use super::{Function, Signature, ArgumentType};
+
+/// Maximum length of a pattern, in bytes.
+pub const MAX_PATTERN_LENGTH: usize = 1024;
+
+/// Maximum size of a compiled pattern, in bytes.
+const COMPILED_SIZE_LIMIT: usize = 1 << 20;
+
+/// Returns the compiled pattern found at the given argument position.
+///
+/// Invalid or oversized patterns produce an `InvalidArgument` error.
+fn pattern_arg(args: &[Rcvar],
+               position: usize,
+               ctx: &mut Context)
+               -> Result<Regex, JmespathError> {
+    let pattern = args[position].as_string().unwrap();
+    if let Some(regex) = ctx.regex_cache.get(pattern) {
+        return Ok(regex.clone());
+    }
+    let compiled = if pattern.len() > MAX_PATTERN_LENGTH {
+        Err(format!("pattern is longer than {} bytes", MAX_PATTERN_LENGTH))
+    } else {
+        RegexBuilder::new(pattern)
+            .size_limit(COMPILED_SIZE_LIMIT)
+            .build()
+            .map_err(|e| e.to_string())
+    };
+    match compiled {
+        Ok(regex) => {
+            ctx.regex_cache.insert(pattern.clone(), regex.clone());
+            Ok(regex)
+        }
+        Err(reason) => {
+            let reason = ErrorReason::Runtime(RuntimeError::InvalidArgument {
+                position: position,
+                reason: reason,
+            });
+            Err(JmespathError::from_ctx(ctx, reason))
+        }
+    }
+}
+
+defn! {
+    RegexMatchFn, vec![arg!(string), arg!(string)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let regex = try!(pattern_arg(args, 1, ctx));
+        Ok(Rcvar::new(Variable::Bool(regex.is_match(args[0].as_string().unwrap()))))
+    }
+}
+
+defn! {
+    RegexSearchFn, vec![arg!(string), arg!(string)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let regex = try!(pattern_arg(args, 1, ctx));
+        match regex.find(args[0].as_string().unwrap()) {
+            Some(m) => Ok(Rcvar::new(Variable::String(m.as_str().to_owned()))),
+            None => Ok(Rcvar::new(Variable::Null)),
+        }
+    }
+}
+
+defn! {
+    RegexReplaceFn, vec![arg!(string), arg!(string), arg!(string)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        let regex = try!(pattern_arg(args, 1, ctx));
+        // Replaces every match. The replacement may refer to capture groups
+        // using `$1` or `$name`.
+        let replaced = regex.replace_all(args[0].as_string().unwrap(),
+                                         args[2].as_string().unwrap().as_str());
+        Ok(Rcvar::new(Variable::String(replaced.into_owned())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {Context, ErrorReason, Rcvar, Runtime, RuntimeError};
+    use interpreter::interpret;
+    use variable::Variable;
+    use super::MAX_PATTERN_LENGTH;
+
+    fn runtime() -> Runtime {
+        let mut runtime = Runtime::new();
+        runtime.register_builtin_functions();
+        runtime.register_regex_functions();
+        runtime
+    }
+
+    fn search(expr: &str, data: &str) -> Rcvar {
+        let runtime = runtime();
+        let expr = runtime.compile(expr).unwrap();
+        expr.search(Variable::from_json(data).unwrap()).unwrap()
+    }
+
+    fn json(data: &str) -> Rcvar {
+        Rcvar::new(Variable::from_json(data).unwrap())
+    }
+
+    #[test]
+    fn regex_match_tests_subjects() {
+        assert_eq!(json("true"), search(r"regex_match(@, '^\d{3}-\d{4}$')", r#""555-1234""#));
+        assert_eq!(json("false"), search(r"regex_match(@, '^\d+$')", r#""555-1234""#));
+        assert_eq!(json(r#"["ERROR disk"]"#),
+                   search("[?regex_match(@, '^(ERROR|FATAL) ')]",
+                          r#"["INFO ok", "ERROR disk"]"#));
+    }
+
+    #[test]
+    fn regex_search_returns_first_match() {
+        assert_eq!(json(r#""123""#), search(r"regex_search(@, '\d+')", r#""ab123cd45""#));
+        assert_eq!(json("null"), search(r"regex_search(@, '\d+')", r#""abcd""#));
+    }
+
+    #[test]
+    fn regex_replace_supports_capture_groups() {
+        assert_eq!(json(r#""b-a d-c""#),
+                   search(r"regex_replace(@, '(\w)(\w)', '$2-$1')", r#""ab cd""#));
+        assert_eq!(json(r#""x_x_x""#),
+                   search("regex_replace(@, '[- ]+', '_')", r#""x - x  x""#));
+    }
+
+    #[test]
+    fn invalid_patterns_are_runtime_errors() {
+        let runtime = runtime();
+        let err = runtime.compile("regex_match(@, '(')").unwrap().search("a").unwrap_err();
+        match err.reason {
+            ErrorReason::Runtime(RuntimeError::InvalidArgument { position, .. }) => {
+                assert_eq!(1, position)
+            }
+            ref reason => panic!("unexpected error: {}", reason),
+        }
+        let long = format!("regex_search(@, '{}')", "a".repeat(MAX_PATTERN_LENGTH + 1));
+        let err = runtime.compile(&long).unwrap().search("a").unwrap_err();
+        assert!(err.to_string().contains("Argument 1 is invalid: pattern is longer than"));
+        let err = runtime.compile("regex_search(@, 'a{1000}{1000}')").unwrap()
+            .search("a").unwrap_err();
+        assert!(err.to_string().contains("Argument 1 is invalid"));
+    }
+
+    #[test]
+    fn patterns_are_cached_per_search() {
+        let runtime = runtime();
+        let expr = runtime.compile(r"[*].regex_search(@, '\d+')").unwrap();
+        let mut ctx = Context::new(expr.as_str(), &runtime);
+        let data = json(r#"["a1", "b22", "c"]"#);
+        let result = interpret(&data, expr.as_ast(), &mut ctx).unwrap();
+        assert_eq!(json(r#"["1", "22"]"#), result);
+        assert_eq!(1, ctx.regex_cache.len());
+    }
+}
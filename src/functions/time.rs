@@ -0,0 +1,193 @@
+//! Optional date and time functions.
+//!
+//! These functions are not part of the JMESPath specification. They are
+//! available with the `time-functions` feature and are registered using
+//! `Runtime::register_time_functions`.
+//!
+//! Times are represented as seconds since the Unix epoch, which may have a
+//! fractional part. Strings that cannot be parsed produce null rather than an
+//! error so that filters over messy data degrade gracefully.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat, Utc};
+
+use {Context, Rcvar};
+use interpreter::SearchResult;
+use variable::Variable;
+use super::{Function, Signature, ArgumentType};
+
+/// Source of the current time, in seconds since the Unix epoch.
+pub type Clock = Box<Fn() -> f64 + Send + Sync>;
+
+/// Returns a clock that reads the system time.
+pub fn system_clock() -> Clock {
+    Box::new(|| {
+        let elapsed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9
+    })
+}
+
+/// Parses an ISO 8601 timestamp into seconds since the Unix epoch.
+///
+/// Accepts RFC 3339 timestamps with an offset, as well as dates and date
+/// times without an offset, which are treated as UTC.
+fn parse_iso8601(s: &str) -> Option<f64> {
+    let naive = DateTime::parse_from_rfc3339(s)
+        .map(|dt| dt.naive_utc())
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f"))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d").map(|d| d.and_hms_opt(0, 0, 0).unwrap())
+        });
+    naive.ok().map(|dt| {
+        let utc = dt.and_utc();
+        utc.timestamp() as f64 + utc.timestamp_subsec_nanos() as f64 / 1e9
+    })
+}
+
+/// Formats seconds since the Unix epoch as an RFC 3339 timestamp in UTC.
+fn format_iso8601(seconds: f64) -> Option<String> {
+    if !seconds.is_finite() {
+        return None;
+    }
+    let whole = seconds.floor();
+    let nanos = ((seconds - whole) * 1e9).round() as u32;
+    if whole < i64::min_value() as f64 || whole > i64::max_value() as f64 {
+        return None;
+    }
+    // Rounding the fraction may carry into the next second.
+    let (whole, nanos) = if nanos >= 1_000_000_000 {
+        (whole as i64 + 1, 0)
+    } else {
+        (whole as i64, nanos)
+    };
+    DateTime::<Utc>::from_timestamp(whole, nanos)
+        .map(|dt| dt.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+}
+
+defn! {
+    ParseIso8601Fn, vec![arg!(string)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        match parse_iso8601(args[0].as_string().unwrap()) {
+            Some(seconds) => Ok(Rcvar::new(Variable::Number(seconds))),
+            None => Ok(Rcvar::new(Variable::Null)),
+        }
+    }
+}
+
+defn! {
+    FormatIso8601Fn, vec![arg!(number)], None;
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        match format_iso8601(args[0].as_number().unwrap()) {
+            Some(s) => Ok(Rcvar::new(Variable::String(s))),
+            None => Ok(Rcvar::new(Variable::Null)),
+        }
+    }
+}
+
+/// Returns the current time from a `Clock`.
+pub struct NowFn {
+    signature: Signature,
+    clock: Clock,
+}
+
+impl NowFn {
+    /// Creates a now function that reads the time from the given clock.
+    pub fn new(clock: Clock) -> NowFn {
+        NowFn {
+            signature: Signature::new(vec![], None),
+            clock: clock,
+        }
+    }
+}
+
+impl Function for NowFn {
+    fn signature(&self) -> Option<&Signature> {
+        Some(&self.signature)
+    }
+
+    fn evaluate(&self, args: &[Rcvar], ctx: &mut Context) -> SearchResult {
+        try!(self.signature.validate(args, ctx));
+        Ok(Rcvar::new(Variable::Number((self.clock)())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {Rcvar, Runtime};
+    use variable::Variable;
+    use super::*;
+
+    fn runtime() -> Runtime {
+        let mut runtime = Runtime::new();
+        runtime.register_builtin_functions();
+        runtime.register_time_functions(Some(Box::new(|| 1600000000.5)));
+        runtime
+    }
+
+    fn search(expr: &str, data: &str) -> Rcvar {
+        let runtime = runtime();
+        let expr = runtime.compile(expr).unwrap();
+        expr.search(Variable::from_json(data).unwrap()).unwrap()
+    }
+
+    fn json(data: &str) -> Rcvar {
+        Rcvar::new(Variable::from_json(data).unwrap())
+    }
+
+    #[test]
+    fn parses_timestamps_with_offsets() {
+        assert_eq!(Some(0.0), parse_iso8601("1970-01-01T00:00:00Z"));
+        assert_eq!(Some(1600000000.0), parse_iso8601("2020-09-13T12:26:40Z"));
+        assert_eq!(Some(1600000000.0), parse_iso8601("2020-09-13T14:26:40+02:00"));
+        assert_eq!(Some(1600000000.0), parse_iso8601("2020-09-13T07:56:40-04:30"));
+        assert_eq!(Some(-1.0), parse_iso8601("1969-12-31T23:59:59Z"));
+    }
+
+    #[test]
+    fn parses_fractional_seconds_and_naive_times() {
+        assert_eq!(Some(1600000000.25), parse_iso8601("2020-09-13T12:26:40.25Z"));
+        assert_eq!(Some(1600000000.5), parse_iso8601("2020-09-13T12:26:40.500"));
+        assert_eq!(Some(1599955200.0), parse_iso8601("2020-09-13"));
+    }
+
+    #[test]
+    fn invalid_timestamps_parse_to_null() {
+        assert_eq!(None, parse_iso8601("yesterday"));
+        assert_eq!(None, parse_iso8601("2020-13-01T00:00:00Z"));
+        assert_eq!(json("null"), search("parse_iso8601(@)", r#""not a date""#));
+    }
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!(Some("2020-09-13T12:26:40Z".to_owned()), format_iso8601(1600000000.0));
+        assert_eq!(Some("2020-09-13T12:26:40.250Z".to_owned()), format_iso8601(1600000000.25));
+        assert_eq!(Some("1969-12-31T23:59:59.500Z".to_owned()), format_iso8601(-0.5));
+        assert_eq!(None, format_iso8601(1e20));
+        assert_eq!(json("null"), search("format_iso8601(`1e300`)", "null"));
+    }
+
+    #[test]
+    fn filters_on_parsed_timestamps() {
+        let data = r#"[{"id": 1, "created_at": "2020-09-13T12:26:41Z"},
+                       {"id": 2, "created_at": "2019-01-01T00:00:00+01:00"},
+                       {"id": 3, "created_at": "garbage"}]"#;
+        assert_eq!(json("[1]"),
+                   search("[?parse_iso8601(created_at) > `1600000000`].id", data));
+        assert_eq!(json(r#""2020-09-13T12:26:41Z""#),
+                   search("format_iso8601(parse_iso8601(@[0].created_at))", data));
+    }
+
+    #[test]
+    fn now_reads_the_injected_clock() {
+        assert_eq!(json("1600000000.5"), search("now()", "null"));
+        let mut runtime = Runtime::new();
+        runtime.register_time_functions(None);
+        assert!(runtime.get_function("now").is_none());
+        assert!(runtime.get_function("parse_iso8601").is_some());
+    }
+}
@@ -1,142 +1,641 @@
 //! Interprets JMESPath expressions.
 
-use std::collections::BTreeMap;
-
 use super::{Rcvar, JmespathError, ErrorReason, RuntimeError};
 use super::Context;
-use super::ast::Ast;
-use super::variable::Variable;
+use super::ast::{Ast, Key, LetBinding};
+use super::variable::{Map, Variable};
 
 /// Result of searching data using a JMESPath Expression.
 pub type SearchResult = Result<Rcvar, JmespathError>;
 
 /// Interprets the given data using an AST node.
+///
+/// Chains of subexpressions, pipes, `||`, `&&`, filter conditions, negations,
+/// flattens, and the left-hand sides of projections are evaluated in a loop
+/// that keeps the work left to do in a heap-allocated stack, so arbitrarily
+/// long chains do not overflow the thread's stack. Nodes with several
+/// independent children, such as function calls, multi-selects, and the
+/// right-hand side of a projection, which is evaluated once per element, are
+/// interpreted recursively, up to the runtime's maximum evaluation depth. So
+/// is the left-hand side of `||` in a strict search, which falls back to the
+/// right-hand side when the left fails.
 pub fn interpret(data: &Rcvar, node: &Ast, ctx: &mut Context) -> SearchResult {
+    // The first node interpreted with a context receives the root document.
+    if ctx.root.is_none() {
+        ctx.root = Some(data.clone());
+    }
+    evaluate(data, node, ctx, &mut vec![])
+}
+
+/// Interprets a node, sharing the stack of deferred work with the caller.
+///
+/// Every nested call of the interpreter goes through here. The depth is
+/// limited by the runtime so that deeply nested evaluation fails rather than
+/// overflowing the stack.
+#[inline]
+fn evaluate<'a>(data: &Rcvar,
+                node: &'a Ast,
+                ctx: &mut Context,
+                pending: &mut Vec<Pending<'a>>)
+                -> SearchResult {
+    if ctx.depth == ctx.runtime.max_evaluation_depth() {
+        ctx.offset = node.offset();
+        let reason = RuntimeError::RecursionLimitExceeded { limit: ctx.depth };
+        return Err(JmespathError::from_ctx(ctx, ErrorReason::Runtime(reason)));
+    }
+    ctx.depth += 1;
+    let result = if ctx.trace.is_some() {
+        interpret_traced(data, node, ctx, pending)
+    } else if is_chained(node) {
+        interpret_chain(data, node, ctx, pending)
+    } else {
+        interpret_node(data, node, ctx, pending)
+    };
+    ctx.depth -= 1;
+    result
+}
+
+/// Interprets a node one step at a time, recording it in the trace of the
+/// search.
+fn interpret_traced<'a>(data: &Rcvar,
+                        node: &'a Ast,
+                        ctx: &mut Context,
+                        pending: &mut Vec<Pending<'a>>)
+                        -> SearchResult {
+    let first = ctx.trace.as_mut().map_or(false, |trace| trace.enter(node, data));
+    let result = if is_chained(node) {
+        interpret_step(data, node, ctx, pending)
+    } else {
+        interpret_node(data, node, ctx, pending)
+    };
+    if first {
+        if let Some(ref mut trace) = ctx.trace {
+            trace.leave(node, &result);
+        }
+    }
+    result
+}
+
+/// Interprets a node that `interpret` evaluates in its loop, evaluating each
+/// of its children on its own, so that the trace records each of them.
+fn interpret_step<'a>(data: &Rcvar,
+                      node: &'a Ast,
+                      ctx: &mut Context,
+                      pending: &mut Vec<Pending<'a>>)
+                      -> SearchResult {
     match *node {
-        Ast::Field { ref name, .. } => Ok(data.get_field(name)),
         Ast::Subexpr { ref lhs, ref rhs, .. } => {
-            let left_result = try!(interpret(data, lhs, ctx));
-            interpret(&left_result, rhs, ctx)
-        }
-        Ast::Identity { .. } => Ok(data.clone()),
-        Ast::Literal { ref value, .. } => Ok(value.clone()),
-        Ast::Index { idx, .. } => {
-            if idx >= 0 {
-                Ok(data.get_index(idx as usize))
-            } else {
-                Ok(data.get_negative_index((-1 * idx) as usize))
-            }
+            let left = try!(evaluate(data, lhs, ctx, pending));
+            evaluate(&left, rhs, ctx, pending)
         }
         Ast::Or { ref lhs, ref rhs, .. } => {
-            let left = try!(interpret(data, lhs, ctx));
+            let left = evaluate(data, lhs, ctx, pending);
+            let left = try!(if ctx.options.strict { or_null(left) } else { left });
             if left.is_truthy() {
                 Ok(left)
             } else {
-                interpret(data, rhs, ctx)
+                evaluate(data, rhs, ctx, pending)
             }
         }
         Ast::And { ref lhs, ref rhs, .. } => {
-            let left = try!(interpret(data, lhs, ctx));
+            let left = try!(evaluate(data, lhs, ctx, pending));
             if !left.is_truthy() {
                 Ok(left)
             } else {
-                interpret(data, rhs, ctx)
+                evaluate(data, rhs, ctx, pending)
             }
         }
-        Ast::Not { ref node, .. } => {
-            let result = try!(interpret(data, node, ctx));
-            Ok(Rcvar::new(Variable::Bool(!result.is_truthy())))
-        }
-        // Returns the resut of RHS if cond yields truthy value.
         Ast::Condition { ref predicate, ref then, .. } => {
-            let cond_result = try!(interpret(data, predicate, ctx));
-            if cond_result.is_truthy() {
-                interpret(data, then, ctx)
+            let kept = try!(evaluate(data, predicate, ctx, pending)).is_truthy();
+            if let Some(ref mut trace) = ctx.trace {
+                trace.filter(node, kept);
+            }
+            if kept {
+                evaluate(data, then, ctx, pending)
             } else {
                 Ok(Rcvar::new(Variable::Null))
             }
         }
-        Ast::Comparison { ref comparator, ref lhs, ref rhs, .. } => {
-            let left = try!(interpret(data, lhs, ctx));
-            let right = try!(interpret(data, rhs, ctx));
-            Ok(left.compare(comparator, &*right)
-                .map_or(Rcvar::new(Variable::Null),
-                        |result| Rcvar::new(Variable::Bool(result))))
-        }
-        // Converts an object into a JSON array of its values.
-        Ast::ObjectValues { ref node, .. } => {
-            let subject = try!(interpret(data, node, ctx));
-            match *subject {
-                Variable::Object(ref v) => {
-                    Ok(Rcvar::new(Variable::Array(v.values().cloned().collect::<Vec<Rcvar>>())))
-                }
-                _ => Ok(Rcvar::new(Variable::Null)),
-            }
-        }
-        // Passes the results of lhs into rhs if lhs yields an array and
-        // each node of lhs that passes through rhs yields a non-null value.
-        Ast::Projection { ref lhs, ref rhs, .. } => {
-            match try!(interpret(data, lhs, ctx)).as_array() {
-                None => Ok(Rcvar::new(Variable::Null)),
-                Some(left) => {
-                    let mut collected = vec![];
-                    for element in left {
-                        let current = try!(interpret(element, rhs, ctx));
-                        if !current.is_null() {
-                            collected.push(current);
-                        }
-                    }
-                    Ok(Rcvar::new(Variable::Array(collected)))
+        Ast::Not { node: ref operand, .. } => {
+            let value = try!(evaluate(data, operand, ctx, pending));
+            Ok(Rcvar::new(Variable::Bool(!value.is_truthy())))
+        }
+        Ast::Negate { node: ref operand, .. } => {
+            Ok(negate(&try!(evaluate(data, operand, ctx, pending))))
+        }
+        Ast::ObjectValues { node: ref operand, offset } => {
+            let value = try!(evaluate(data, operand, ctx, pending));
+            object_values(&value, offset, ctx)
+        }
+        Ast::Flatten { node: ref operand, offset } => {
+            let value = try!(evaluate(data, operand, ctx, pending));
+            flatten(&value, offset, ctx)
+        }
+        Ast::Projection { ref lhs, ref rhs, offset } => {
+            let left = try!(evaluate(data, lhs, ctx, pending));
+            if let (Some(array), Some(trace)) = (left.as_array(), ctx.trace.as_mut()) {
+                trace.project(node, array.len());
+            }
+            match (left.is_array(), &**lhs) {
+                (true, _) => project(&left, offset, rhs, ctx, pending),
+                (false, &Ast::Slice { .. }) if left.is_string() => {
+                    evaluate(&left, rhs, ctx, pending)
                 }
+                (false, _) if ctx.options.strict => {
+                    Err(invalid_projection(ctx, offset, "array", &left))
+                }
+                (false, _) => Ok(Rcvar::new(Variable::Null)),
             }
         }
-        Ast::Flatten { ref node, .. } => {
-            match try!(interpret(data, node, ctx)).as_array() {
-                None => Ok(Rcvar::new(Variable::Null)),
-                Some(a) => {
-                    let mut collected: Vec<Rcvar> = vec![];
-                    for element in a {
-                        match element.as_array() {
-                            Some(array) => collected.extend(array.iter().cloned()),
-                            _ => collected.push(element.clone()),
+        _ => unreachable!("{:?} is not evaluated in the loop of interpret", node),
+    }
+}
+
+/// Interprets a chain of nodes, keeping deferred work above the entries that
+/// are already in `pending`. The entries below are left as they were found,
+/// so that a stack can be shared by nested chains.
+fn interpret_chain<'a>(data: &Rcvar,
+                       node: &'a Ast,
+                       ctx: &mut Context,
+                       pending: &mut Vec<Pending<'a>>)
+                       -> SearchResult {
+    let base = pending.len();
+    let result = interpret_chain_above(base, data, node, ctx, pending);
+    // Errors leave the deferred work of the chain behind.
+    pending.truncate(base);
+    result
+}
+
+/// Interprets a chain of nodes, finishing once the deferred work above `base`
+/// is done.
+fn interpret_chain_above<'a>(base: usize,
+                             data: &Rcvar,
+                             node: &'a Ast,
+                             ctx: &mut Context,
+                             pending: &mut Vec<Pending<'a>>)
+                             -> SearchResult {
+    let mut data = data.clone();
+    let mut node = node;
+    loop {
+        // Descend along the linear parts of the tree, deferring what is done
+        // with the result of each child until it is known.
+        let mut result = match *node {
+            // The common case of a simple lhs (e.g., `foo.bar`) needs no stack.
+            Ast::Subexpr { ref lhs, ref rhs, .. } if !is_chained(lhs) => {
+                data = try!(interpret_node(&data, lhs, ctx, pending));
+                node = rhs;
+                continue;
+            }
+            Ast::Subexpr { ref lhs, ref rhs, .. } => {
+                pending.push(Pending::Apply(rhs));
+                node = lhs;
+                continue;
+            }
+            // A strict search evaluates the lhs of `||` on its own, so that
+            // its strict errors can fall back to the rhs.
+            Ast::Or { ref lhs, ref rhs, .. } if ctx.options.strict => {
+                let left = try!(or_null(evaluate(&data, lhs, ctx, pending)));
+                if left.is_truthy() {
+                    left
+                } else {
+                    node = rhs;
+                    continue;
+                }
+            }
+            // Simple operands of `||`, `&&`, and filters are interpreted
+            // directly too, which is the same as what `Pending` does below.
+            Ast::Or { ref lhs, ref rhs, .. } if !is_chained(lhs) => {
+                let left = try!(interpret_node(&data, lhs, ctx, pending));
+                if left.is_truthy() {
+                    left
+                } else {
+                    node = rhs;
+                    continue;
+                }
+            }
+            Ast::And { ref lhs, ref rhs, .. } if !is_chained(lhs) => {
+                let left = try!(interpret_node(&data, lhs, ctx, pending));
+                if !left.is_truthy() {
+                    left
+                } else {
+                    node = rhs;
+                    continue;
+                }
+            }
+            Ast::Condition { ref predicate, ref then, .. } if !is_chained(predicate) => {
+                if try!(interpret_node(&data, predicate, ctx, pending)).is_truthy() {
+                    node = then;
+                    continue;
+                }
+                Rcvar::new(Variable::Null)
+            }
+            Ast::Or { ref lhs, ref rhs, .. } => {
+                pending.push(Pending::Or(rhs, data.clone()));
+                node = lhs;
+                continue;
+            }
+            Ast::And { ref lhs, ref rhs, .. } => {
+                pending.push(Pending::And(rhs, data.clone()));
+                node = lhs;
+                continue;
+            }
+            Ast::Condition { ref predicate, ref then, .. } => {
+                pending.push(Pending::Condition(then, data.clone()));
+                node = predicate;
+                continue;
+            }
+            Ast::Not { node: ref operand, .. } => {
+                pending.push(Pending::Not);
+                node = operand;
+                continue;
+            }
+            Ast::Negate { node: ref operand, .. } => {
+                pending.push(Pending::Negate);
+                node = operand;
+                continue;
+            }
+            Ast::ObjectValues { node: ref operand, offset } => {
+                pending.push(Pending::ObjectValues(offset));
+                node = operand;
+                continue;
+            }
+            Ast::Flatten { node: ref operand, offset } => {
+                pending.push(Pending::Flatten(offset));
+                node = operand;
+                continue;
+            }
+            Ast::Projection { ref lhs, ref rhs, offset } => {
+                pending.push(Pending::Project(offset, lhs, rhs));
+                node = lhs;
+                continue;
+            }
+            _ => try!(interpret_node(&data, node, ctx, pending)),
+        };
+        // Finish the deferred work until a node has to be descended into.
+        loop {
+            if pending.len() == base {
+                return Ok(result);
+            }
+            match pending.pop().expect("pending is not empty") {
+                Pending::Apply(rhs) => {
+                    data = result;
+                    node = rhs;
+                    break;
+                }
+                // Returns the result of lhs if it is truthy, else of rhs.
+                Pending::Or(rhs, current) => {
+                    if !result.is_truthy() {
+                        data = current;
+                        node = rhs;
+                        break;
+                    }
+                }
+                // Returns the result of lhs if it is falsy, else of rhs.
+                Pending::And(rhs, current) => {
+                    if result.is_truthy() {
+                        data = current;
+                        node = rhs;
+                        break;
+                    }
+                }
+                // Returns the result of then if the predicate is truthy.
+                Pending::Condition(then, current) => {
+                    if result.is_truthy() {
+                        data = current;
+                        node = then;
+                        break;
+                    }
+                    result = Rcvar::new(Variable::Null);
+                }
+                Pending::Not => result = Rcvar::new(Variable::Bool(!result.is_truthy())),
+                Pending::Negate => result = negate(&result),
+                Pending::ObjectValues(offset) => result = try!(object_values(&result, offset, ctx)),
+                Pending::Flatten(offset) => result = try!(flatten(&result, offset, ctx)),
+                // Passes the results of lhs into rhs if lhs yields an array and
+                // each node of lhs that passes through rhs yields a non-null value.
+                Pending::Project(offset, lhs, rhs) => {
+                    match (result.is_array(), lhs) {
+                        (true, _) => result = try!(project(&result, offset, rhs, ctx, pending)),
+                        // A slice of a string is not projected.
+                        (false, &Ast::Slice { .. }) if result.is_string() => {
+                            data = result;
+                            node = rhs;
+                            break;
                         }
+                        (false, _) if ctx.options.strict => {
+                            return Err(invalid_projection(ctx, offset, "array", &result));
+                        }
+                        (false, _) => result = Rcvar::new(Variable::Null),
                     }
-                    Ok(Rcvar::new(Variable::Array(collected)))
                 }
             }
         }
-        Ast::MultiList { ref elements, .. } => {
+    }
+}
+
+/// Returns true if `interpret` evaluates the node in its loop rather than
+/// with `interpret_node`.
+#[inline]
+fn is_chained(node: &Ast) -> bool {
+    match *node {
+        Ast::Subexpr { .. } |
+        Ast::Or { .. } |
+        Ast::And { .. } |
+        Ast::Condition { .. } |
+        Ast::Not { .. } |
+        Ast::Negate { .. } |
+        Ast::ObjectValues { .. } |
+        Ast::Flatten { .. } |
+        Ast::Projection { .. } => true,
+        _ => false,
+    }
+}
+
+/// Work deferred by `interpret` until the result of a child node is known.
+enum Pending<'a> {
+    /// Interprets the node with the result as its data.
+    Apply(&'a Ast),
+    /// Interprets the node with the saved data if the result is falsy.
+    Or(&'a Ast, Rcvar),
+    /// Interprets the node with the saved data if the result is truthy.
+    And(&'a Ast, Rcvar),
+    /// Interprets the node with the saved data if the result is truthy,
+    /// yielding null otherwise.
+    Condition(&'a Ast, Rcvar),
+    Not,
+    Negate,
+    ObjectValues(usize),
+    Flatten(usize),
+    /// Projects the node over the result, which the first node produced.
+    Project(usize, &'a Ast, &'a Ast),
+}
+
+/// Interprets each element of an array with rhs, skipping null results.
+fn project<'a>(array: &Rcvar,
+              offset: usize,
+              rhs: &'a Ast,
+              ctx: &mut Context,
+              pending: &mut Vec<Pending<'a>>)
+              -> SearchResult {
+    let mut collected = vec![];
+    for element in array.as_array().expect("projections are only applied to arrays") {
+        let current = try!(evaluate(element, rhs, ctx, pending));
+        if !current.is_null() {
+            try!(ctx.collect(offset, 1));
+            collected.push(current);
+        }
+    }
+    Ok(Rcvar::new(Variable::Array(collected)))
+}
+
+fn negate(value: &Rcvar) -> Rcvar {
+    Rcvar::new(value.as_number().map_or(Variable::Null, |n| Variable::Number(-n)))
+}
+
+/// Converts an object into a JSON array of its values.
+fn object_values(value: &Rcvar, offset: usize, ctx: &mut Context) -> SearchResult {
+    match **value {
+        Variable::Object(ref v) => {
+            try!(ctx.allocate(offset, v.len()));
+            Ok(Rcvar::new(Variable::Array(v.values().cloned().collect::<Vec<Rcvar>>())))
+        }
+        ref value if ctx.options.strict => Err(invalid_projection(ctx, offset, "object", value)),
+        _ => Ok(Rcvar::new(Variable::Null)),
+    }
+}
+
+/// Merges the elements of nested arrays into an array, or returns null if
+/// the value is not an array.
+fn flatten(value: &Rcvar, offset: usize, ctx: &mut Context) -> SearchResult {
+    match value.as_array() {
+        None if ctx.options.strict => Err(invalid_projection(ctx, offset, "array", value)),
+        None => Ok(Rcvar::new(Variable::Null)),
+        Some(a) => {
+            let count = a.iter().map(|element| element.as_array().map_or(1, |array| array.len()));
+            try!(ctx.collect(offset, count.sum()));
+            let mut collected: Vec<Rcvar> = vec![];
+            for element in a {
+                match element.as_array() {
+                    Some(array) => collected.extend(array.iter().cloned()),
+                    _ => collected.push(element.clone()),
+                }
+            }
+            Ok(Rcvar::new(Variable::Array(collected)))
+        }
+    }
+}
+
+/// Returns null in place of the errors that only strict searches raise.
+fn or_null(result: SearchResult) -> SearchResult {
+    match result {
+        Err(ref err) if is_strict_error(&err.reason) => Ok(Rcvar::new(Variable::Null)),
+        result => result,
+    }
+}
+
+/// Returns true if a search only fails with the error because it is strict,
+/// including when the error failed an expression reference.
+fn is_strict_error(reason: &ErrorReason) -> bool {
+    let mut error = match *reason {
+        ErrorReason::Runtime(ref error) => error,
+        _ => return false,
+    };
+    while let RuntimeError::ExpressionFailed { error: ref inner, .. } = *error {
+        error = inner;
+    }
+    match *error {
+        RuntimeError::MissingKey { .. } |
+        RuntimeError::InvalidField { .. } |
+        RuntimeError::InvalidIndex { .. } |
+        RuntimeError::InvalidProjection { .. } => true,
+        _ => false,
+    }
+}
+
+fn strict_error(ctx: &mut Context, offset: usize, reason: RuntimeError) -> JmespathError {
+    ctx.offset = offset;
+    JmespathError::from_ctx(ctx, ErrorReason::Runtime(reason))
+}
+
+fn invalid_projection(ctx: &mut Context,
+                      offset: usize,
+                      expected: &str,
+                      value: &Variable)
+                      -> JmespathError {
+    let reason = RuntimeError::InvalidProjection {
+        expected: expected.to_owned(),
+        actual: value.get_type().to_string(),
+    };
+    strict_error(ctx, offset, reason)
+}
+
+/// Selects a field in a strict search, which fails unless the value is an
+/// object with the field.
+fn strict_field(data: &Rcvar, name: &str, offset: usize, ctx: &mut Context) -> SearchResult {
+    let reason = match **data {
+        Variable::Object(ref map) => {
+            match map.get(name) {
+                Some(value) => return Ok(value.clone()),
+                None => RuntimeError::MissingKey { key: name.to_owned() },
+            }
+        }
+        ref value => {
+            RuntimeError::InvalidField {
+                key: name.to_owned(),
+                actual: value.get_type().to_string(),
+            }
+        }
+    };
+    Err(strict_error(ctx, offset, reason))
+}
+
+/// Interprets the nodes that `interpret` does not evaluate in its loop.
+fn interpret_node<'a>(data: &Rcvar,
+                      node: &'a Ast,
+                      ctx: &mut Context,
+                      pending: &mut Vec<Pending<'a>>)
+                      -> SearchResult {
+    match *node {
+        Ast::Field { ref name, offset } => {
+            if let Some(ref mut provenance) = ctx.provenance {
+                provenance.record_field(data, name);
+            }
+            if ctx.options.strict {
+                strict_field(data, name, offset, ctx)
+            } else {
+                Ok(data.get_field(name))
+            }
+        }
+        Ast::Identity { .. } => Ok(data.clone()),
+        Ast::RootNode { .. } => Ok(ctx.root.clone().unwrap_or_else(|| data.clone())),
+        Ast::Literal { ref value, .. } => Ok(value.clone()),
+        Ast::VarRef { ref name, offset } => {
+            match ctx.scopes.iter().rev().filter_map(|scope| scope.get(name)).next() {
+                Some(value) => Ok(value.clone()),
+                None => {
+                    ctx.offset = offset;
+                    let reason = RuntimeError::UndefinedVariable(name.to_owned());
+                    Err(JmespathError::from_ctx(ctx, ErrorReason::Runtime(reason)))
+                }
+            }
+        }
+        Ast::Error { offset } => {
+            ctx.offset = offset;
+            let reason = ErrorReason::Parse("Cannot evaluate an expression with syntax errors"
+                .to_owned());
+            Err(JmespathError::from_ctx(ctx, reason))
+        }
+        Ast::Let { ref bindings, ref expr, .. } => {
+            let mut scope = Map::new();
+            for binding in bindings {
+                let value = try!(evaluate(data, &binding.value, ctx, pending));
+                scope.insert(binding.name.clone(), value);
+            }
+            ctx.scopes.push(scope);
+            let result = evaluate(data, expr, ctx, pending);
+            ctx.scopes.pop();
+            result
+        }
+        Ast::Index { idx, offset } => {
+            if let Some(ref mut provenance) = ctx.provenance {
+                provenance.record_index(data, idx);
+            }
+            if ctx.options.strict && !data.is_array() {
+                let reason = RuntimeError::InvalidIndex {
+                    index: idx,
+                    actual: data.get_type().to_string(),
+                };
+                Err(strict_error(ctx, offset, reason))
+            } else if idx >= 0 {
+                Ok(data.get_index(idx as usize))
+            } else {
+                // Negating through u64 cannot overflow, even for i64::MIN.
+                Ok(data.get_negative_index((idx as u64).wrapping_neg() as usize))
+            }
+        }
+        Ast::Arithmetic { ref operator, ref lhs, ref rhs, .. } => {
+            let left = try!(evaluate(data, lhs, ctx, pending));
+            let right = try!(evaluate(data, rhs, ctx, pending));
+            let result = match (left.as_number(), right.as_number()) {
+                (Some(a), Some(b)) => operator.apply(a, b),
+                _ => None,
+            };
+            Ok(Rcvar::new(result.map_or(Variable::Null, Variable::Number)))
+        }
+        Ast::Comparison { ref comparator, ref lhs, ref rhs, .. } => {
+            let left = try!(evaluate(data, lhs, ctx, pending));
+            let right = try!(evaluate(data, rhs, ctx, pending));
+            let result = if ctx.options.lenient_comparisons {
+                left.compare_leniently(comparator, &*right)
+            } else {
+                left.compare(comparator, &*right)
+            };
+            Ok(result.map_or(Rcvar::new(Variable::Null),
+                             |result| Rcvar::new(Variable::Bool(result))))
+        }
+        Ast::MultiList { ref elements, offset } => {
             if data.is_null() {
                 Ok(Rcvar::new(Variable::Null))
             } else {
+                try!(ctx.allocate(offset, elements.len()));
                 let mut collected = vec![];
                 for node in elements {
-                    collected.push(try!(interpret(data, node, ctx)));
+                    collected.push(try!(evaluate(data, node, ctx, pending)));
                 }
                 Ok(Rcvar::new(Variable::Array(collected)))
             }
         }
-        Ast::MultiHash { ref elements, .. } => {
+        Ast::MultiHash { ref elements, offset } => {
             if data.is_null() {
                 Ok(Rcvar::new(Variable::Null))
             } else {
-                let mut collected = BTreeMap::new();
+                try!(ctx.allocate(offset, elements.len()));
+                let mut collected = Map::new();
                 for kvp in elements {
-                    let value = try!(interpret(data, &kvp.value, ctx));
-                    collected.insert(kvp.key.clone(), value);
+                    let key = match kvp.key {
+                        Key::Name(ref name) => name.clone(),
+                        Key::Expression(ref ast) => {
+                            let key = try!(evaluate(data, ast, ctx, pending));
+                            match *key {
+                                Variable::String(ref name) => name.clone(),
+                                ref key => {
+                                    ctx.offset = ast.offset();
+                                    let actual = key.get_type().to_string();
+                                    let reason = RuntimeError::InvalidKey { actual: actual };
+                                    let reason = ErrorReason::Runtime(reason);
+                                    return Err(JmespathError::from_ctx(ctx, reason));
+                                }
+                            }
+                        }
+                    };
+                    let value = try!(evaluate(data, &kvp.value, ctx, pending));
+                    collected.insert(key, value);
                 }
                 Ok(Rcvar::new(Variable::Object(collected)))
             }
         }
         Ast::Function { ref name, ref args, offset } => {
+            // not_null is how a strict search provides fallbacks for missing
+            // values, so its arguments are null rather than strict errors.
+            let fallback = ctx.options.strict && name == "not_null";
             let mut fn_args: Vec<Rcvar> = vec![];
             for arg in args {
-                fn_args.push(try!(interpret(data, arg, ctx)));
+                let value = evaluate(data, arg, ctx, pending);
+                fn_args.push(try!(if fallback { or_null(value) } else { value }));
             }
             // Reset the offset so that it points to the function being evaluated.
             ctx.offset = offset;
             match ctx.runtime.get_function(name) {
-                Some(f) => f.evaluate(&fn_args, ctx),
+                Some(f) => {
+                    let result = try!(f.evaluate(&fn_args, ctx));
+                    let count = match *result {
+                        Variable::Array(ref a) => a.len(),
+                        Variable::Object(ref o) => o.len(),
+                        _ => 0,
+                    };
+                    try!(ctx.allocate(offset, count));
+                    Ok(result)
+                }
                 None => {
                     let reason =
                         ErrorReason::Runtime(RuntimeError::UnknownFunction(name.to_owned()));
@@ -144,7 +643,39 @@ pub fn interpret(data: &Rcvar, node: &Ast, ctx: &mut Context) -> SearchResult {
                 }
             }
         }
-        Ast::Expref { ref ast, .. } => Ok(Rcvar::new(Variable::Expref(*ast.clone()))),
+        Ast::Expref { ref ast, offset } => {
+            if ctx.scopes.is_empty() {
+                return Ok(Rcvar::new(Variable::Expref(*ast.clone())));
+            }
+            // Capture the variables that the expref refers to, since it may
+            // be evaluated after the let expressions that bind them.
+            let mut bindings: Vec<LetBinding> = vec![];
+            let _ = ast.walk(&mut |node| -> Result<(), ()> {
+                if let Ast::VarRef { ref name, .. } = *node {
+                    if bindings.iter().all(|binding| binding.name != *name) {
+                        let scopes = ctx.scopes.iter().rev();
+                        if let Some(value) = scopes.filter_map(|scope| scope.get(name)).next() {
+                            bindings.push(LetBinding {
+                                name: name.clone(),
+                                value: Ast::Literal {
+                                    offset: offset,
+                                    value: value.clone(),
+                                },
+                            });
+                        }
+                    }
+                }
+                Ok(())
+            });
+            if bindings.is_empty() {
+                return Ok(Rcvar::new(Variable::Expref(*ast.clone())));
+            }
+            Ok(Rcvar::new(Variable::Expref(Ast::Let {
+                offset: offset,
+                bindings: bindings,
+                expr: ast.clone(),
+            })))
+        }
         Ast::Slice { ref start, ref stop, step, offset } => {
             if step == 0 {
                 ctx.offset = offset;
@@ -152,10 +683,23 @@ pub fn interpret(data: &Rcvar, node: &Ast, ctx: &mut Context) -> SearchResult {
                 Err(JmespathError::from_ctx(ctx, reason))
             } else {
                 match data.slice(start, stop, step) {
-                    Some(array) => Ok(Rcvar::new(Variable::Array(array))),
+                    Some(array) => {
+                        try!(ctx.allocate(offset, array.len()));
+                        Ok(Rcvar::new(Variable::Array(array)))
+                    }
+                    None if ctx.runtime.string_slices() => {
+                        match data.slice_string(start, stop, step) {
+                            Some(s) => Ok(Rcvar::new(Variable::String(s))),
+                            None => Ok(Rcvar::new(Variable::Null)),
+                        }
+                    }
+                    None if ctx.options.strict => {
+                        Err(invalid_projection(ctx, offset, "array", data))
+                    }
                     None => Ok(Rcvar::new(Variable::Null)),
                 }
             }
         }
+        _ => unreachable!("interpret evaluates {:?} in its loop", node),
     }
 }
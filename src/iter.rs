@@ -0,0 +1,221 @@
+//! Iterator adapters that apply a compiled expression to streams of values.
+//!
+//! The adapters are created using `Expression::map_iter`,
+//! `Expression::filter_iter`, and `Expression::filter_map_iter`. They are
+//! lazy: nothing is evaluated until the adapter is consumed. Runtime errors
+//! are yielded as `Err` items rather than being swallowed.
+
+use {Context, Expression, Rcvar, ToJmespath};
+use interpreter::{interpret, SearchResult};
+
+/// Evaluation state shared across every item of an adapter.
+struct Evaluator<'e, 'a: 'e> {
+    expression: &'e Expression<'a>,
+    ctx: Context<'e>,
+}
+
+impl<'e, 'a> Evaluator<'e, 'a> {
+    fn new(expression: &'e Expression<'a>) -> Evaluator<'e, 'a> {
+        Evaluator {
+            expression: expression,
+            ctx: Context::new(expression.as_str(), expression.runtime),
+        }
+    }
+
+    #[inline]
+    fn search(&mut self, data: &Rcvar) -> SearchResult {
+        self.ctx.offset = 0;
+        self.ctx.root = Some(data.clone());
+        interpret(data, self.expression.as_ast(), &mut self.ctx)
+    }
+}
+
+/// Yields the search result of each item.
+///
+/// Created using `Expression::map_iter`.
+pub struct MapIter<'e, 'a: 'e, I> {
+    evaluator: Evaluator<'e, 'a>,
+    iter: I,
+}
+
+impl<'e, 'a, I> MapIter<'e, 'a, I> {
+    #[inline]
+    pub(crate) fn new(expression: &'e Expression<'a>, iter: I) -> MapIter<'e, 'a, I> {
+        MapIter {
+            evaluator: Evaluator::new(expression),
+            iter: iter,
+        }
+    }
+}
+
+impl<'e, 'a, I> Iterator for MapIter<'e, 'a, I>
+    where I: Iterator,
+          I::Item: ToJmespath
+{
+    type Item = SearchResult;
+
+    fn next(&mut self) -> Option<SearchResult> {
+        self.iter.next().map(|item| self.evaluator.search(&item.to_jmespath()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+/// Yields each item for which the expression returns a truthy value.
+///
+/// Created using `Expression::filter_iter`.
+pub struct FilterIter<'e, 'a: 'e, I> {
+    evaluator: Evaluator<'e, 'a>,
+    iter: I,
+}
+
+impl<'e, 'a, I> FilterIter<'e, 'a, I> {
+    #[inline]
+    pub(crate) fn new(expression: &'e Expression<'a>, iter: I) -> FilterIter<'e, 'a, I> {
+        FilterIter {
+            evaluator: Evaluator::new(expression),
+            iter: iter,
+        }
+    }
+}
+
+impl<'e, 'a, I> Iterator for FilterIter<'e, 'a, I>
+    where I: Iterator,
+          I::Item: ToJmespath
+{
+    type Item = SearchResult;
+
+    fn next(&mut self) -> Option<SearchResult> {
+        while let Some(item) = self.iter.next() {
+            let data = item.to_jmespath();
+            match self.evaluator.search(&data) {
+                Ok(ref result) if result.is_truthy() => return Some(Ok(data)),
+                Ok(_) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+/// Yields the search result of each item for which the result is truthy.
+///
+/// Created using `Expression::filter_map_iter`.
+pub struct FilterMapIter<'e, 'a: 'e, I> {
+    evaluator: Evaluator<'e, 'a>,
+    iter: I,
+}
+
+impl<'e, 'a, I> FilterMapIter<'e, 'a, I> {
+    #[inline]
+    pub(crate) fn new(expression: &'e Expression<'a>, iter: I) -> FilterMapIter<'e, 'a, I> {
+        FilterMapIter {
+            evaluator: Evaluator::new(expression),
+            iter: iter,
+        }
+    }
+}
+
+impl<'e, 'a, I> Iterator for FilterMapIter<'e, 'a, I>
+    where I: Iterator,
+          I::Item: ToJmespath
+{
+    type Item = SearchResult;
+
+    fn next(&mut self) -> Option<SearchResult> {
+        while let Some(item) = self.iter.next() {
+            match self.evaluator.search(&item.to_jmespath()) {
+                Ok(ref result) if !result.is_truthy() => continue,
+                result => return Some(result),
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use compile;
+    use variable::Variable;
+
+    fn documents() -> Vec<Variable> {
+        vec![Variable::from_json(r#"{"name": "a", "age": 10}"#).unwrap(),
+             Variable::from_json(r#"{"name": "b", "age": 40}"#).unwrap(),
+             Variable::from_json(r#"{"name": "c"}"#).unwrap()]
+    }
+
+    #[test]
+    fn maps_items_to_search_results() {
+        let expr = compile("name").unwrap();
+        let names = expr.map_iter(documents())
+            .map(|r| r.unwrap().as_string().unwrap().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["a", "b", "c"], names);
+    }
+
+    #[test]
+    fn filters_items_by_truthiness() {
+        let expr = compile("age > `20`").unwrap();
+        let matched = expr.filter_iter(documents()).map(|r| r.unwrap()).collect::<Vec<_>>();
+        assert_eq!(1, matched.len());
+        assert_eq!("b", matched[0].get_field("name").as_string().unwrap());
+    }
+
+    #[test]
+    fn filter_maps_items() {
+        let expr = compile("age").unwrap();
+        let ages = expr.filter_map_iter(documents())
+            .map(|r| r.unwrap().as_number().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(vec![10.0, 40.0], ages);
+    }
+
+    #[test]
+    fn accepts_serde_json_values() {
+        use serde_json::Value;
+        let values: Vec<Value> = vec![::serde_json::from_str("{\"a\": 1}").unwrap(),
+                                      ::serde_json::from_str("{\"a\": 2}").unwrap()];
+        let expr = compile("a").unwrap();
+        let sum = expr.map_iter(values.iter())
+            .fold(0.0, |acc, r| acc + r.unwrap().as_number().unwrap());
+        assert_eq!(3.0, sum);
+    }
+
+    #[test]
+    fn adapters_are_lazy() {
+        let consumed = Cell::new(0);
+        let expr = compile("@").unwrap();
+        let mut iter = expr.map_iter((0..3).map(|i| {
+            consumed.set(consumed.get() + 1);
+            i
+        }));
+        assert_eq!(0, consumed.get());
+        iter.next().unwrap().unwrap();
+        assert_eq!(1, consumed.get());
+    }
+
+    #[test]
+    fn propagates_errors_mid_stream() {
+        let expr = compile("length(@)").unwrap();
+        let items = vec![Variable::String("ab".to_owned()),
+                         Variable::Bool(true),
+                         Variable::String("c".to_owned())];
+        let results = expr.filter_map_iter(items).collect::<Vec<_>>();
+        assert_eq!(3, results.len());
+        assert!(results[0].is_ok());
+        assert!(results[1].as_ref().unwrap_err().to_string().contains("expects type"));
+        assert_eq!(1.0, results[2].as_ref().unwrap().as_number().unwrap());
+    }
+}
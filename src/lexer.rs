@@ -1,29 +1,46 @@
 //! Module for tokenizing JMESPath expressions.
 //!
 //! The lexer returns a VecDeque of tuples where each tuple contains the
-//! character position in the original string from which the lexeme originates
-//! followed by the token itself. The VecDeque is then consumed by the parser.
-//! A VecDeque is utilized in order to pop owned tokens and provide arbitrary
-//! token lookahead in the parser.
+//! span of the lexeme in the original string (from the position where it
+//! starts up to the position where it ends) followed by the token itself.
+//! The VecDeque is then consumed by the parser. A VecDeque is utilized in
+//! order to pop owned tokens and provide arbitrary token lookahead in the
+//! parser.
+//!
+//! `tokenize_all` is meant for tools that show diagnostics: it keeps going
+//! after lexical errors and reports all of them. `lex_classify` builds on it
+//! to classify every byte of an expression, e.g., for syntax highlighting.
 
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
 use std::iter::Peekable;
+use std::ops::Range;
 use std::str::CharIndices;
 use std::collections::VecDeque;
 
 use {Rcvar, JmespathError, ErrorReason};
+use parser::ParseOptions;
 use variable::Variable;
 use self::Token::*;
 
 /// Represents a lexical token of a JMESPath expression.
 #[derive(Clone, PartialEq, Debug)]
-pub enum Token {
-    Identifier(String),
-    QuotedIdentifier(String),
-    Number(i32),
+pub enum Token<'a> {
+    Identifier(Cow<'a, str>),
+    QuotedIdentifier(Cow<'a, str>),
+    /// A variable reference, e.g., `$foo`, without the `$`.
+    Variable(Cow<'a, str>),
+    Number(i64),
     Literal(Rcvar),
     Dot,
     Star,
     Flatten,
+    Plus,
+    Minus,
+    Slash,
+    DoubleSlash,
+    Percent,
     And,
     Or,
     Pipe,
@@ -40,6 +57,9 @@ pub enum Token {
     Lt,
     Lte,
     At,
+    Dollar,
+    /// The `=` of a let binding, which only follows a variable reference.
+    Assign,
     Ampersand,
     Lparen,
     Rparen,
@@ -48,7 +68,7 @@ pub enum Token {
     Eof,
 }
 
-impl Token {
+impl<'a> Token<'a> {
     /// Provides the left binding power of the token.
     ///
     /// This is used in the parser to determine whether or not
@@ -66,8 +86,13 @@ impl Token {
             Gte => 5,
             Lte => 5,
             Ne => 5,
+            Plus => 6,
+            Minus => 6,
+            Star => 7,
+            Slash => 7,
+            DoubleSlash => 7,
+            Percent => 7,
             Flatten => 9,
-            Star => 20,
             Filter => 21,
             Dot => 40,
             Not => 45,
@@ -77,19 +102,211 @@ impl Token {
             _ => 0,
         }
     }
+
+    /// Returns true if the token is a variable reference.
+    #[inline]
+    pub fn is_variable(&self) -> bool {
+        match *self {
+            Variable(_) => true,
+            _ => false,
+        }
+    }
 }
 
-/// A tuple of the token position and the token.
-pub type TokenTuple = (usize, Token);
+/// A tuple of the token span and the token.
+///
+/// The span covers the whole lexeme, e.g., both quotes of a quoted
+/// identifier, so `expr[span]` is the source text of the token.
+pub type TokenTuple<'a> = (Range<usize>, Token<'a>);
 
 /// Tokenizes a JMESPath expression.
-pub fn tokenize(expr: &str) -> Result<VecDeque<TokenTuple>, JmespathError> {
-    Lexer::new(expr).tokenize()
+pub fn tokenize<'a>(expr: &'a str) -> Result<VecDeque<TokenTuple<'a>>, JmespathError> {
+    tokenize_with_options(expr, &ParseOptions::default())
+}
+
+/// Tokenizes a JMESPath expression using the given options.
+pub fn tokenize_with_options<'a>(expr: &'a str,
+                                 options: &ParseOptions)
+                                 -> Result<VecDeque<TokenTuple<'a>>, JmespathError> {
+    let mut lexer = Lexer::new(expr);
+    lexer.options = *options;
+    lexer.tokenize()
+}
+
+/// A lexical error reported by `tokenize_all` or `tokenize_result`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct LexError {
+    /// Span of the expression that was skipped because of the error. The
+    /// start is the byte offset of the error.
+    pub span: Range<usize>,
+    /// Source text of the span.
+    pub lexeme: String,
+    /// Description of the error.
+    pub message: String,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(fmt, "{} ({:?} at offset {})", self.message, self.lexeme, self.span.start)
+    }
+}
+
+impl Error for LexError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+/// Tokenizes a JMESPath expression, returning the first lexical error as a
+/// `LexError`.
+///
+/// Unlike `tokenize`, the tokens are returned in a `Vec` and errors carry the
+/// offending lexeme.
+pub fn tokenize_result<'a>(expr: &'a str) -> Result<Vec<TokenTuple<'a>>, LexError> {
+    let mut lexer = Lexer::new(expr);
+    let mut tokens = vec![];
+    loop {
+        match lexer.next_token() {
+            Ok((span, Eof)) => {
+                tokens.push((span, Eof));
+                return Ok(tokens);
+            }
+            Ok(token) => tokens.push(token),
+            Err(err) => return Err(lexer.recover(err)),
+        }
+    }
+}
+
+/// Tokenizes a JMESPath expression, reporting every lexical error.
+///
+/// Rather than stopping at the first error like `tokenize`, the lexer
+/// skips to the next whitespace or structural character (e.g., `.`, `|`,
+/// or `[`) and keeps going. The returned tokens always end with `Eof`.
+pub fn tokenize_all<'a>(expr: &'a str) -> (Vec<TokenTuple<'a>>, Vec<LexError>) {
+    let mut lexer = Lexer::new(expr);
+    let mut tokens = vec![];
+    let mut errors = vec![];
+    loop {
+        match lexer.next_token() {
+            Ok((span, Eof)) => {
+                tokens.push((span, Eof));
+                return (tokens, errors);
+            }
+            Ok(token) => tokens.push(token),
+            Err(err) => errors.push(lexer.recover(err)),
+        }
+    }
+}
+
+/// The kind of a span returned by `lex_classify`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TokenKind {
+    /// An unquoted identifier, e.g., `foo`.
+    Identifier,
+    /// A quoted identifier, e.g., `"foo bar"`.
+    QuotedIdentifier,
+    /// A variable reference, e.g., `$foo`.
+    Variable,
+    /// A JSON literal or a raw string literal, e.g., `` `[1]` `` or `'foo'`.
+    Literal,
+    /// An integer used in an index or slice.
+    Number,
+    /// Any other token, e.g., `.`, `||`, `==`, or `@`.
+    Operator,
+    /// An opening or closing bracket, brace, or parenthesis, including
+    /// `[?` and `[]`.
+    Bracket,
+    /// Whitespace between tokens.
+    Whitespace,
+    /// Text that could not be tokenized.
+    Error,
+}
+
+/// A classified span of an expression.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TokenSpan {
+    /// Byte offset where the span starts.
+    pub start: usize,
+    /// Byte offset where the span ends (exclusive).
+    pub end: usize,
+    /// Kind of the span.
+    pub kind: TokenKind,
+    /// Description of the error for `TokenKind::Error` spans.
+    pub error: Option<String>,
+}
+
+/// Classifies every part of an expression, e.g., for syntax highlighting.
+///
+/// The spans are ordered and contiguous: they start at 0, each span starts
+/// where the previous one ends, and the last one ends at `expr.len()`.
+/// Text that cannot be tokenized is reported as a `TokenKind::Error` span
+/// that extends to the point where lexing resumes, as in `tokenize_all`.
+/// This never panics, whatever the input.
+pub fn lex_classify(expr: &str) -> Vec<TokenSpan> {
+    let mut lexer = Lexer::new(expr);
+    let mut spans = vec![];
+    loop {
+        let before = lexer.position();
+        // Whitespace is skipped before lexing a token or failing to.
+        let rest = &expr[before..];
+        let start = before + rest.len() - rest.trim_start_matches(is_whitespace).len();
+        let (end, kind, error) = match lexer.next_token() {
+            Ok((_, Eof)) => break,
+            Ok((span, token)) => (span.end, classify(&token), None),
+            Err(err) => {
+                let err = lexer.recover(err);
+                (err.span.end, TokenKind::Error, Some(err.message))
+            }
+        };
+        if start > before {
+            spans.push(TokenSpan {
+                start: before,
+                end: start,
+                kind: TokenKind::Whitespace,
+                error: None,
+            });
+        }
+        spans.push(TokenSpan {
+            start: start,
+            end: end,
+            kind: kind,
+            error: error,
+        });
+    }
+    // Trailing whitespace is skipped before reaching the end.
+    let end = spans.last().map(|span| span.end).unwrap_or(0);
+    if end < expr.len() {
+        spans.push(TokenSpan {
+            start: end,
+            end: expr.len(),
+            kind: TokenKind::Whitespace,
+            error: None,
+        });
+    }
+    spans
+}
+
+fn classify(token: &Token) -> TokenKind {
+    match *token {
+        Identifier(_) => TokenKind::Identifier,
+        QuotedIdentifier(_) => TokenKind::QuotedIdentifier,
+        Variable(_) => TokenKind::Variable,
+        Literal(_) => TokenKind::Literal,
+        Number(_) => TokenKind::Number,
+        Lbracket | Rbracket | Filter | Flatten | Lbrace | Rbrace | Lparen | Rparen => {
+            TokenKind::Bracket
+        }
+        _ => TokenKind::Operator,
+    }
 }
 
 struct Lexer<'a> {
     iter: Peekable<CharIndices<'a>>,
     expr: &'a str,
+    options: ParseOptions,
+    /// Whether the last token was a variable reference, which is the only
+    /// token that `=` can follow.
+    after_variable: bool,
 }
 
 impl<'a> Lexer<'a> {
@@ -97,38 +314,54 @@ impl<'a> Lexer<'a> {
         Lexer {
             expr: expr,
             iter: expr.char_indices().peekable(),
+            options: ParseOptions::default(),
+            after_variable: false,
         }
     }
 
-    fn tokenize(&mut self) -> Result<VecDeque<TokenTuple>, JmespathError> {
+    fn tokenize(&mut self) -> Result<VecDeque<TokenTuple<'a>>, JmespathError> {
         let mut tokens = VecDeque::new();
-        let last_position = self.expr.len();
+        loop {
+            let token = try!(self.next_token());
+            let is_eof = token.1 == Eof;
+            tokens.push_back(token);
+            if is_eof {
+                return Ok(tokens);
+            }
+        }
+    }
+
+    // Consumes the next token, skipping any leading whitespace.
+    fn next_token(&mut self) -> Result<TokenTuple<'a>, JmespathError> {
         loop {
             match self.iter.next() {
                 Some((pos, ch)) => {
-                    match ch {
-                        'a'...'z' | 'A'...'Z' | '_' => {
-                            tokens.push_back((pos, self.consume_identifier(ch)))
-                        }
-                        '.' => tokens.push_back((pos, Dot)),
-                        '[' => tokens.push_back((pos, self.consume_lbracket())),
-                        '*' => tokens.push_back((pos, Star)),
-                        '|' => tokens.push_back((pos, self.alt(&'|', Or, Pipe))),
-                        '@' => tokens.push_back((pos, At)),
-                        ']' => tokens.push_back((pos, Rbracket)),
-                        '{' => tokens.push_back((pos, Lbrace)),
-                        '}' => tokens.push_back((pos, Rbrace)),
-                        '&' => tokens.push_back((pos, self.alt(&'&', And, Ampersand))),
-                        '(' => tokens.push_back((pos, Lparen)),
-                        ')' => tokens.push_back((pos, Rparen)),
-                        ',' => tokens.push_back((pos, Comma)),
-                        ':' => tokens.push_back((pos, Colon)),
-                        '"' => tokens.push_back((pos, try!(self.consume_quoted_identifier(pos)))),
-                        '\'' => tokens.push_back((pos, try!(self.consume_raw_string(pos)))),
-                        '`' => tokens.push_back((pos, try!(self.consume_literal(pos)))),
+                    let token = match ch {
+                        'a'...'z' | 'A'...'Z' | '_' => self.consume_identifier(pos),
+                        '.' => Dot,
+                        '[' => self.consume_lbracket(),
+                        '*' => Star,
+                        '|' => self.alt(&'|', Or, Pipe),
+                        '@' => At,
+                        '$' => self.consume_dollar(pos),
+                        ']' => Rbracket,
+                        '{' => Lbrace,
+                        '}' => Rbrace,
+                        '&' => self.alt(&'&', And, Ampersand),
+                        '(' => Lparen,
+                        ')' => Rparen,
+                        ',' => Comma,
+                        ':' => Colon,
+                        '"' => try!(self.consume_quoted_identifier(pos)),
+                        '\'' => try!(self.consume_raw_string(pos)),
+                        '`' => try!(self.consume_literal(pos)),
                         '=' => {
-                            match self.iter.next() {
-                                Some((_, c)) if c == '=' => tokens.push_back((pos, Eq)),
+                            match self.iter.peek() {
+                                Some(&(_, '=')) => {
+                                    self.iter.next();
+                                    Eq
+                                }
+                                _ if self.after_variable => Assign,
                                 _ => {
                                     let message = "'=' is not valid. Did you mean '=='?";
                                     let reason = ErrorReason::Parse(message.to_owned());
@@ -136,48 +369,114 @@ impl<'a> Lexer<'a> {
                                 }
                             }
                         }
-                        '>' => tokens.push_back((pos, self.alt(&'=', Gte, Gt))),
-                        '<' => tokens.push_back((pos, self.alt(&'=', Lte, Lt))),
-                        '!' => tokens.push_back((pos, self.alt(&'=', Ne, Not))),
-                        '0'...'9' => tokens.push_back((pos, self.consume_number(ch, false))),
-                        '-' => tokens.push_back((pos, try!(self.consume_negative_number(pos)))),
+                        '>' => self.alt(&'=', Gte, Gt),
+                        '<' => self.alt(&'=', Lte, Lt),
+                        '!' => self.alt(&'=', Ne, Not),
+                        '0'...'9' if self.options.lenient_identifiers &&
+                                     starts_identifier(&self.expr[pos..]) => {
+                            self.consume_identifier(pos)
+                        }
+                        '0'...'9' => try!(self.consume_number(pos, ch)),
+                        '-' => try!(self.consume_minus(pos)),
+                        '+' => Plus,
+                        '/' => self.alt(&'/', DoubleSlash, Slash),
+                        '%' => Percent,
                         // Skip whitespace tokens
-                        ' ' | '\n' | '\t' | '\r' => {}
+                        c if is_whitespace(c) => continue,
+                        '#' if self.options.comments => {
+                            self.consume_while(pos, |c| c != '\n');
+                            continue;
+                        }
                         c => {
                             let reason = ErrorReason::Parse(format!("Invalid character: {}", c));
                             return Err(JmespathError::new(self.expr, pos, reason));
                         }
-                    }
+                    };
+                    self.after_variable = match token {
+                        Variable(_) => true,
+                        _ => false,
+                    };
+                    // Each consumer stops right after the end of its lexeme.
+                    let end = self.position();
+                    return Ok((pos..end, token));
                 }
                 None => {
-                    tokens.push_back((last_position, Eof));
-                    return Ok(tokens);
+                    let last_position = self.expr.len();
+                    return Ok((last_position..last_position, Eof));
+                }
+            }
+        }
+    }
+
+    // Converts an error into a LexError that spans up to the point where
+    // lexing can resume.
+    fn recover(&mut self, err: JmespathError) -> LexError {
+        self.resynchronize();
+        let span = err.offset..self.position();
+        LexError {
+            lexeme: self.expr[span.clone()].to_owned(),
+            span: span,
+            message: match err.reason {
+                ErrorReason::Parse(message) => message,
+                reason => reason.to_string(),
+            },
+        }
+    }
+
+    // Skips to the next whitespace or structural character after an error.
+    fn resynchronize(&mut self) {
+        while let Some(&(_, c)) = self.iter.peek() {
+            match c {
+                ' ' | '\n' | '\t' | '\r' | '.' | ',' | ':' | '|' | '&' | '(' | ')' | '[' |
+                ']' | '{' | '}' => break,
+                _ => {
+                    self.iter.next();
                 }
             }
         }
     }
 
-    // Consumes characters while the predicate function returns true.
+    // Returns the position of the next character that has not been consumed.
     #[inline]
-    fn consume_while<F>(&mut self, mut buffer: String, predicate: F) -> String
+    fn position(&mut self) -> usize {
+        match self.iter.peek() {
+            Some(&(pos, _)) => pos,
+            None => self.expr.len(),
+        }
+    }
+
+    // Consumes characters while the predicate function returns true,
+    // returning the slice of the expression from `start` up to that point.
+    #[inline]
+    fn consume_while<F>(&mut self, start: usize, predicate: F) -> &'a str
         where F: Fn(char) -> bool
     {
         loop {
             match self.iter.peek() {
-                None => break,
-                Some(&(_, c)) if !predicate(c) => break,
-                Some(&(_, c)) => {
-                    buffer.push(c);
+                Some(&(_, c)) if predicate(c) => {
                     self.iter.next();
                 }
+                _ => break,
             }
         }
-        buffer
+        &self.expr[start..self.position()]
+    }
+
+    // Consumes "$", or a variable reference when "$" is followed by an
+    // identifier.
+    #[inline]
+    fn consume_dollar(&mut self, pos: usize) -> Token<'a> {
+        match self.iter.peek() {
+            Some(&(_, c)) if c.is_ascii_alphabetic() || c == '_' => {
+                Variable(Cow::Borrowed(self.consume_while(pos + 1, is_identifier_char)))
+            }
+            _ => Dollar,
+        }
     }
 
     // Consumes "[", "[]", "[?
     #[inline]
-    fn consume_lbracket(&mut self) -> Token {
+    fn consume_lbracket(&mut self) -> Token<'a> {
         match self.iter.peek() {
             Some(&(_, ']')) => {
                 self.iter.next();
@@ -192,37 +491,68 @@ impl<'a> Lexer<'a> {
     }
 
     // Consume identifiers: ( ALPHA / "_" ) *( DIGIT / ALPHA / "_" )
+    //
+    // Lenient identifiers may also contain dashes between identifier
+    // characters, e.g., "content-type".
     #[inline]
-    fn consume_identifier(&mut self, first_char: char) -> Token {
-        Identifier(self.consume_while(first_char.to_string(), |c| {
-            match c {
-                'a'...'z' | '_' | 'A'...'Z' | '0'...'9' => true,
-                _ => false,
+    fn consume_identifier(&mut self, pos: usize) -> Token<'a> {
+        loop {
+            self.consume_while(pos, is_identifier_char);
+            if !self.options.lenient_identifiers || self.iter.peek().map(|p| p.1) != Some('-') {
+                break;
             }
-        }))
+            let dash = self.position();
+            match self.expr[dash + 1..].chars().next() {
+                Some(c) if is_identifier_char(c) => {
+                    self.iter.next();
+                }
+                _ => break,
+            }
+        }
+        Identifier(Cow::Borrowed(&self.expr[pos..self.position()]))
     }
 
     // Consumes numbers: *"-" "0" / ( %x31-39 *DIGIT )
     #[inline]
-    fn consume_number(&mut self, first_char: char, is_negative: bool) -> Token {
-        let lexeme = self.consume_while(first_char.to_string(), |c| c.is_digit(10));
-        let numeric_value: i32 = lexeme.parse().expect("Expected valid number");
-        if is_negative {
-            Number(numeric_value * -1)
-        } else {
-            Number(numeric_value)
+    fn consume_number(&mut self, pos: usize, first_char: char) -> Result<Token<'a>, JmespathError> {
+        // The lexeme includes the negative sign, if any.
+        let lexeme = self.consume_while(pos, |c| c.is_digit(10));
+        if first_char == '0' && lexeme.len() > 1 {
+            let message = format!("Invalid number {}: numbers are either 0 or start with a \
+                                   digit from 1 to 9",
+                                  lexeme);
+            return Err(JmespathError::new(self.expr, pos, ErrorReason::Parse(message)));
+        }
+        lexeme.parse::<i64>().map(Number).map_err(|_| {
+            let reason = ErrorReason::Parse(format!("Number out of range: {}", lexeme));
+            JmespathError::new(self.expr, pos, reason)
+        })
+    }
+
+    // Consumes a negative number, or a minus operator when "-" is not
+    // followed by a digit. Operands of arithmetic expressions never start
+    // with a digit because JSON numbers must be written as literals.
+    #[inline]
+    fn consume_minus(&mut self, pos: usize) -> Result<Token<'a>, JmespathError> {
+        match self.iter.peek() {
+            Some(&(_, c)) if c.is_ascii_digit() => self.consume_negative_number(pos),
+            _ => Ok(Minus),
         }
     }
 
     // Consumes a negative number
     #[inline]
-    fn consume_negative_number(&mut self, pos: usize) -> Result<Token, JmespathError> {
+    fn consume_negative_number(&mut self, pos: usize) -> Result<Token<'a>, JmespathError> {
         // Ensure that the next value is a number > 0
         match self.iter.next() {
-            Some((_, c)) if c.is_numeric() && c != '0' => Ok(self.consume_number(c, true)),
-            _ => {
-                let reason = ErrorReason::Parse("'-' must be followed by numbers 1-9".to_owned());
-                Err(JmespathError::new(self.expr, pos, reason))
+            Some((_, c)) if c.is_digit(10) && c != '0' => self.consume_number(pos, c),
+            next => {
+                let found = match next {
+                    Some((_, c)) => format!("'{}'", c),
+                    None => "the end of the expression".to_owned(),
+                };
+                let message = format!("'-' must be followed by numbers 1-9, found {}", found);
+                Err(JmespathError::new(self.expr, pos, ErrorReason::Parse(message)))
             }
         }
     }
@@ -234,44 +564,45 @@ impl<'a> Lexer<'a> {
                          pos: usize,
                          wrapper: char,
                          invoke: F)
-                         -> Result<Token, JmespathError>
-        where F: Fn(String) -> Result<Token, String>
+                         -> Result<Token<'a>, JmespathError>
+        where F: Fn(&str) -> Result<Token<'a>, String>
     {
-        let mut buffer = String::new();
-        while let Some((_, c)) = self.iter.next() {
+        let buffer = try!(self.consume_delimited(pos, wrapper));
+        invoke(buffer).map_err(|e| JmespathError::new(self.expr, pos, ErrorReason::Parse(e)))
+    }
+
+    // Consumes the characters up to a closing character, returning them
+    // verbatim, including any escaping "\" characters.
+    #[inline]
+    fn consume_delimited(&mut self, pos: usize, wrapper: char) -> Result<&'a str, JmespathError> {
+        // The opening character is a single byte.
+        let start = pos + 1;
+        while let Some((i, c)) = self.iter.next() {
             if c == wrapper {
-                return invoke(buffer)
-                    .map_err(|e| JmespathError::new(self.expr, pos, ErrorReason::Parse(e)));
+                return Ok(&self.expr[start..i]);
             } else if c == '\\' {
-                buffer.push(c);
-                if let Some((_, c)) = self.iter.next() {
-                    buffer.push(c);
-                }
-            } else {
-                buffer.push(c)
+                self.iter.next();
             }
         }
         // The token was not closed, so error with the string, including the
         // wrapper (e.g., '"foo').
-        let message = format!("Unclosed {} delimiter: {}{}", wrapper, wrapper, buffer);
+        let message = format!("Unclosed {} delimiter: {}{}", wrapper, wrapper, &self.expr[start..]);
         Err(JmespathError::new(self.expr, pos, ErrorReason::Parse(message)))
     }
 
     // Consume and parse a quoted identifier token.
     #[inline]
-    fn consume_quoted_identifier(&mut self, pos: usize) -> Result<Token, JmespathError> {
-        self.consume_inside(pos, '"', |s| {
-            // JSON decode the string to expand escapes
-            match Variable::from_json(format!(r##""{}""##, s).as_ref()) {
-                // Convert the JSON value into a string literal.
-                Ok(j) => Ok(QuotedIdentifier(j.as_string().unwrap().clone())),
-                Err(e) => Err(format!("Unable to parse quoted identifier {}: {}", s, e)),
-            }
+    fn consume_quoted_identifier(&mut self, pos: usize) -> Result<Token<'a>, JmespathError> {
+        // Offsets into the verbatim slice are relative to the character
+        // after the opening quote.
+        let raw = try!(self.consume_delimited(pos, '"'));
+        unescape_identifier(raw).map(QuotedIdentifier).map_err(|(offset, message)| {
+            JmespathError::new(self.expr, pos + 1 + offset, ErrorReason::Parse(message))
         })
     }
 
     #[inline]
-    fn consume_raw_string(&mut self, pos: usize) -> Result<Token, JmespathError> {
+    fn consume_raw_string(&mut self, pos: usize) -> Result<Token<'a>, JmespathError> {
         // Note: we need to unescape here because the backslashes are passed through.
         self.consume_inside(pos, '\'', |s| {
             Ok(Literal(Rcvar::new(Variable::String(s.replace("\\'", "'")))))
@@ -280,18 +611,24 @@ impl<'a> Lexer<'a> {
 
     // Consume and parse a literal JSON token.
     #[inline]
-    fn consume_literal(&mut self, pos: usize) -> Result<Token, JmespathError> {
+    fn consume_literal(&mut self, pos: usize) -> Result<Token<'a>, JmespathError> {
+        let legacy = self.options.legacy_literals;
         self.consume_inside(pos, '`', |s| {
             let unescaped = s.replace("\\`", "`");
             match Variable::from_json(unescaped.as_ref()) {
                 Ok(j) => Ok(Literal(Rcvar::new(j))),
-                Err(err) => Err(format!("Unable to parse literal JSON {}: {}", s, err)),
+                Err(err) => {
+                    match if legacy { legacy_literal(&unescaped) } else { None } {
+                        Some(j) => Ok(Literal(Rcvar::new(j))),
+                        None => Err(format!("Unable to parse literal JSON {}: {}", s, err)),
+                    }
+                }
             }
         })
     }
 
     #[inline]
-    fn alt(&mut self, expected: &char, match_type: Token, else_type: Token) -> Token {
+    fn alt(&mut self, expected: &char, match_type: Token<'a>, else_type: Token<'a>) -> Token<'a> {
         match self.iter.peek() {
             Some(&(_, c)) if c == *expected => {
                 self.iter.next();
@@ -302,6 +639,125 @@ impl<'a> Lexer<'a> {
     }
 }
 
+/// Parses the contents of a literal that is not valid JSON as a string,
+/// which JMESPath allowed before JEP-12.
+fn legacy_literal(s: &str) -> Option<Variable> {
+    let s = s.trim_start();
+    if s.starts_with('[') || s.starts_with('{') {
+        return None;
+    }
+    Variable::from_json(&format!("\"{}\"", s.replace('"', "\\\""))).ok()
+}
+
+#[inline]
+fn is_whitespace(c: char) -> bool {
+    match c {
+        ' ' | '\n' | '\t' | '\r' => true,
+        _ => false,
+    }
+}
+
+#[inline]
+fn is_identifier_char(c: char) -> bool {
+    match c {
+        'a'...'z' | '_' | 'A'...'Z' | '0'...'9' => true,
+        _ => false,
+    }
+}
+
+/// Returns true if `s` starts with digits followed by a letter or "_",
+/// which lenient identifiers allow (e.g., "2fa_enabled").
+fn starts_identifier(s: &str) -> bool {
+    match s.trim_start_matches(|c: char| c.is_ascii_digit()).chars().next() {
+        Some(c) => c.is_ascii_alphabetic() || c == '_',
+        None => false,
+    }
+}
+
+/// Expands the escape sequences of a quoted identifier.
+///
+/// Quoted identifiers use JSON string escapes. UTF-16 surrogate pairs are
+/// combined into a single character, while unpaired surrogates, unknown
+/// escapes, and unescaped control characters are rejected. Errors carry the
+/// byte offset of the offending sequence within `s`. Identifiers without
+/// escapes are borrowed from `s`.
+fn unescape_identifier(s: &str) -> Result<Cow<str>, (usize, String)> {
+    if !s.bytes().any(|b| b == b'\\' || b < b' ') {
+        return Ok(Cow::Borrowed(s));
+    }
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                let unescaped = match chars.next() {
+                    Some((_, '"')) => '"',
+                    Some((_, '\\')) => '\\',
+                    Some((_, '/')) => '/',
+                    Some((_, 'b')) => '\u{8}',
+                    Some((_, 'f')) => '\u{c}',
+                    Some((_, 'n')) => '\n',
+                    Some((_, 'r')) => '\r',
+                    Some((_, 't')) => '\t',
+                    Some((_, 'u')) => {
+                        let (unescaped, len) = try!(unescape_unicode(s, i));
+                        // Skip the hex digits (and a trailing pair), which are ASCII.
+                        chars.nth(len - 3);
+                        unescaped
+                    }
+                    Some((_, c)) => {
+                        return Err((i, format!("Invalid escape in quoted identifier: \\{}", c)))
+                    }
+                    None => return Err((i, "Unterminated escape in quoted identifier".to_owned())),
+                };
+                result.push(unescaped);
+            }
+            c if c < ' ' => {
+                return Err((i, format!("Unescaped control character in quoted identifier: {:?}",
+                                       c)))
+            }
+            c => result.push(c),
+        }
+    }
+    Ok(Cow::Owned(result))
+}
+
+/// Decodes the `\\u` escape that starts at byte `i` of `s`, along with the
+/// low surrogate that must follow a high surrogate.
+///
+/// Returns the character and the number of bytes the escape spans.
+fn unescape_unicode(s: &str, i: usize) -> Result<(char, usize), (usize, String)> {
+    let hex = |at: usize| {
+        s.get(at..at + 4)
+            .filter(|digits| digits.chars().all(|c| c.is_digit(16)))
+            .map(|digits| u32::from_str_radix(digits, 16).unwrap())
+    };
+    let escape = |len: usize| s.get(i..i + len).unwrap_or(&s[i..]).to_owned();
+    let high = match hex(i + 2) {
+        Some(code) => code,
+        None => return Err((i, format!("Invalid unicode escape: {}", escape(6)))),
+    };
+    match high {
+        0xD800..=0xDBFF => {
+            let low = match s.get(i + 6..i + 8) {
+                Some("\\u") => hex(i + 8),
+                _ => None,
+            };
+            match low {
+                Some(low @ 0xDC00..=0xDFFF) => {
+                    let code = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+                    Ok((::std::char::from_u32(code).unwrap(), 12))
+                }
+                _ => Err((i, format!("Unpaired surrogate in unicode escape: {}", escape(6)))),
+            }
+        }
+        0xDC00..=0xDFFF => {
+            Err((i, format!("Unpaired surrogate in unicode escape: {}", escape(6))))
+        }
+        code => Ok((::std::char::from_u32(code).unwrap(), 6)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use ::Rcvar;
@@ -319,49 +775,50 @@ mod tests {
 
     #[test]
     fn tokenize_basic_test() {
-        assert_eq!(tokenize_queue("."), vec![(0, Dot), (1, Eof)]);
-        assert_eq!(tokenize_queue("*"), vec![(0, Star), (1, Eof)]);
-        assert_eq!(tokenize_queue("@"), vec![(0, At), (1, Eof)]);
-        assert_eq!(tokenize_queue("]"), vec![(0, Rbracket), (1, Eof)]);
-        assert_eq!(tokenize_queue("{"), vec![(0, Lbrace), (1, Eof)]);
-        assert_eq!(tokenize_queue("}"), vec![(0, Rbrace), (1, Eof)]);
-        assert_eq!(tokenize_queue("("), vec![(0, Lparen), (1, Eof)]);
-        assert_eq!(tokenize_queue(")"), vec![(0, Rparen), (1, Eof)]);
-        assert_eq!(tokenize_queue(","), vec![(0, Comma), (1, Eof)]);
+        assert_eq!(tokenize_queue("."), vec![(0..1, Dot), (1..1, Eof)]);
+        assert_eq!(tokenize_queue("*"), vec![(0..1, Star), (1..1, Eof)]);
+        assert_eq!(tokenize_queue("@"), vec![(0..1, At), (1..1, Eof)]);
+        assert_eq!(tokenize_queue("$"), vec![(0..1, Dollar), (1..1, Eof)]);
+        assert_eq!(tokenize_queue("]"), vec![(0..1, Rbracket), (1..1, Eof)]);
+        assert_eq!(tokenize_queue("{"), vec![(0..1, Lbrace), (1..1, Eof)]);
+        assert_eq!(tokenize_queue("}"), vec![(0..1, Rbrace), (1..1, Eof)]);
+        assert_eq!(tokenize_queue("("), vec![(0..1, Lparen), (1..1, Eof)]);
+        assert_eq!(tokenize_queue(")"), vec![(0..1, Rparen), (1..1, Eof)]);
+        assert_eq!(tokenize_queue(","), vec![(0..1, Comma), (1..1, Eof)]);
     }
 
     #[test]
     fn tokenize_lbracket_test() {
-        assert_eq!(tokenize_queue("["), vec![(0, Lbracket), (1, Eof)]);
-        assert_eq!(tokenize_queue("[]"), vec![(0, Flatten), (2, Eof)]);
-        assert_eq!(tokenize_queue("[?"), vec![(0, Filter), (2, Eof)]);
+        assert_eq!(tokenize_queue("["), vec![(0..1, Lbracket), (1..1, Eof)]);
+        assert_eq!(tokenize_queue("[]"), vec![(0..2, Flatten), (2..2, Eof)]);
+        assert_eq!(tokenize_queue("[?"), vec![(0..2, Filter), (2..2, Eof)]);
     }
 
     #[test]
     fn tokenize_pipe_test() {
-        assert_eq!(tokenize_queue("|"), vec![(0, Pipe), (1, Eof)]);
-        assert_eq!(tokenize_queue("||"), vec![(0, Or), (2, Eof)]);
+        assert_eq!(tokenize_queue("|"), vec![(0..1, Pipe), (1..1, Eof)]);
+        assert_eq!(tokenize_queue("||"), vec![(0..2, Or), (2..2, Eof)]);
     }
 
     #[test]
     fn tokenize_and_ampersand_test() {
-        assert_eq!(tokenize_queue("&"), vec![(0, Ampersand), (1, Eof)]);
-        assert_eq!(tokenize_queue("&&"), vec![(0, And), (2, Eof)]);
+        assert_eq!(tokenize_queue("&"), vec![(0..1, Ampersand), (1..1, Eof)]);
+        assert_eq!(tokenize_queue("&&"), vec![(0..2, And), (2..2, Eof)]);
     }
 
     #[test]
     fn tokenize_lt_gt_test() {
-        assert_eq!(tokenize_queue("<"), vec![(0, Lt), (1, Eof)]);
-        assert_eq!(tokenize_queue("<="), vec![(0, Lte), (2, Eof)]);
-        assert_eq!(tokenize_queue(">"), vec![(0, Gt), (1, Eof)]);
-        assert_eq!(tokenize_queue(">="), vec![(0, Gte), (2, Eof)]);
+        assert_eq!(tokenize_queue("<"), vec![(0..1, Lt), (1..1, Eof)]);
+        assert_eq!(tokenize_queue("<="), vec![(0..2, Lte), (2..2, Eof)]);
+        assert_eq!(tokenize_queue(">"), vec![(0..1, Gt), (1..1, Eof)]);
+        assert_eq!(tokenize_queue(">="), vec![(0..2, Gte), (2..2, Eof)]);
     }
 
     #[test]
     fn tokenize_eq_ne_test() {
-        assert_eq!(tokenize_queue("=="), vec![(0, Eq), (2, Eof)]);
-        assert_eq!(tokenize_queue("!"), vec![(0, Not), (1, Eof)]);
-        assert_eq!(tokenize_queue("!="), vec![(0, Ne), (2, Eof)]);
+        assert_eq!(tokenize_queue("=="), vec![(0..2, Eq), (2..2, Eof)]);
+        assert_eq!(tokenize_queue("!"), vec![(0..1, Not), (1..1, Eof)]);
+        assert_eq!(tokenize_queue("!="), vec![(0..2, Ne), (2..2, Eof)]);
     }
 
     #[test]
@@ -372,7 +829,7 @@ mod tests {
     #[test]
     fn skips_whitespace() {
         let tokens = tokenize_queue(" \t\n\r\t. (");
-        assert_eq!(tokens, vec![(5, Dot), (7, Lparen), (8, Eof)]);
+        assert_eq!(tokens, vec![(5..6, Dot), (7..8, Lparen), (8..8, Eof)]);
     }
 
     #[test]
@@ -392,35 +849,38 @@ mod tests {
     #[test]
     fn tokenize_identifier_test() {
         assert_eq!(tokenize_queue("foo_bar"),
-                   vec![(0, Identifier("foo_bar".to_string())), (7, Eof)]);
+                   vec![(0..7, Identifier("foo_bar".into())), (7..7, Eof)]);
         assert_eq!(tokenize_queue("a"),
-                   vec![(0, Identifier("a".to_string())), (1, Eof)]);
+                   vec![(0..1, Identifier("a".into())), (1..1, Eof)]);
         assert_eq!(tokenize_queue("_a"),
-                   vec![(0, Identifier("_a".to_string())), (2, Eof)]);
+                   vec![(0..2, Identifier("_a".into())), (2..2, Eof)]);
     }
 
     #[test]
     fn tokenize_quoted_identifier_test() {
         assert_eq!(tokenize_queue("\"foo\""),
-                   vec![(0, QuotedIdentifier("foo".to_string())), (5, Eof)]);
+                   vec![(0..5, QuotedIdentifier("foo".into())), (5..5, Eof)]);
         assert_eq!(tokenize_queue("\"\""),
-                   vec![(0, QuotedIdentifier("".to_string())), (2, Eof)]);
+                   vec![(0..2, QuotedIdentifier("".into())), (2..2, Eof)]);
         assert_eq!(tokenize_queue("\"a_b\""),
-                   vec![(0, QuotedIdentifier("a_b".to_string())), (5, Eof)]);
+                   vec![(0..5, QuotedIdentifier("a_b".into())), (5..5, Eof)]);
         assert_eq!(tokenize_queue("\"a\\nb\""),
-                   vec![(0, QuotedIdentifier("a\nb".to_string())), (6, Eof)]);
+                   vec![(0..6, QuotedIdentifier("a\nb".into())), (6..6, Eof)]);
         assert_eq!(tokenize_queue("\"a\\\\nb\""),
-                   vec![(0, QuotedIdentifier("a\\nb".to_string())), (7, Eof)]);
+                   vec![(0..7, QuotedIdentifier("a\\nb".into())), (7..7, Eof)]);
     }
 
     #[test]
     fn tokenize_raw_string_test() {
         assert_eq!(tokenize_queue("'foo'"),
-                   vec![(0, Literal(Rcvar::new(Variable::String("foo".to_string())))), (5, Eof)]);
+                   vec![(0..5, Literal(Rcvar::new(Variable::String("foo".to_string())))),
+                        (5..5, Eof)]);
         assert_eq!(tokenize_queue("''"),
-                   vec![(0, Literal(Rcvar::new(Variable::String("".to_string())))), (2, Eof)]);
+                   vec![(0..2, Literal(Rcvar::new(Variable::String("".to_string())))),
+                        (2..2, Eof)]);
         assert_eq!(tokenize_queue("'a\\nb'"),
-                   vec![(0, Literal(Rcvar::new(Variable::String("a\\nb".to_string())))), (6, Eof)]);
+                   vec![(0..6, Literal(Rcvar::new(Variable::String("a\\nb".to_string())))),
+                        (6..6, Eof)]);
     }
 
     #[test]
@@ -428,21 +888,23 @@ mod tests {
         // Must enclose in quotes. See JEP 12.
         assert!(tokenize("`a`").unwrap_err().to_string().contains("Unable to parse"));
         assert_eq!(tokenize_queue("`\"a\"`"),
-                   vec![(0, Literal(Rcvar::new(Variable::String("a".to_string())))), (5, Eof)]);
+                   vec![(0..5, Literal(Rcvar::new(Variable::String("a".to_string())))),
+                        (5..5, Eof)]);
         assert_eq!(tokenize_queue("`\"a b\"`"),
-                   vec![(0, Literal(Rcvar::new(Variable::String("a b".to_string())))), (7, Eof)]);
+                   vec![(0..7, Literal(Rcvar::new(Variable::String("a b".to_string())))),
+                        (7..7, Eof)]);
     }
 
     #[test]
     fn tokenize_number_test() {
-        assert_eq!(tokenize_queue("0"), vec![(0, Number(0)), (1, Eof)]);
-        assert_eq!(tokenize_queue("1"), vec![(0, Number(1)), (1, Eof)]);
-        assert_eq!(tokenize_queue("123"), vec![(0, Number(123)), (3, Eof)]);
+        assert_eq!(tokenize_queue("0"), vec![(0..1, Number(0)), (1..1, Eof)]);
+        assert_eq!(tokenize_queue("1"), vec![(0..1, Number(1)), (1..1, Eof)]);
+        assert_eq!(tokenize_queue("123"), vec![(0..3, Number(123)), (3..3, Eof)]);
     }
 
     #[test]
     fn tokenize_negative_number_test() {
-        assert_eq!(tokenize_queue("-10"), vec![(0, Number(-10)), (3, Eof)]);
+        assert_eq!(tokenize_queue("-10"), vec![(0..3, Number(-10)), (3..3, Eof)]);
     }
 
     #[test]
@@ -450,26 +912,399 @@ mod tests {
         assert!(tokenize("-01").unwrap_err().to_string().contains("'-'"));
     }
 
+    #[test]
+    fn tokenize_negative_number_requires_ascii_digits() {
+        assert_eq!(tokenize_queue("-a"),
+                   vec![(0..1, Minus), (1..2, Identifier("a".into())), (2..2, Eof)]);
+        assert!(tokenize("-\u{663}").unwrap_err().to_string().contains("Invalid character"));
+    }
+
+    #[test]
+    fn tokenize_arithmetic_operators_test() {
+        assert_eq!(tokenize_queue("+"), vec![(0..1, Plus), (1..1, Eof)]);
+        assert_eq!(tokenize_queue("-"), vec![(0..1, Minus), (1..1, Eof)]);
+        assert_eq!(tokenize_queue("/"), vec![(0..1, Slash), (1..1, Eof)]);
+        assert_eq!(tokenize_queue("//"), vec![(0..2, DoubleSlash), (2..2, Eof)]);
+        assert_eq!(tokenize_queue("%"), vec![(0..1, Percent), (1..1, Eof)]);
+        assert_eq!(tokenize_queue("a - `1`"),
+                   vec![(0..1, Identifier("a".into())), (2..3, Minus),
+                        (4..7, Literal(Rcvar::new(Variable::Number(1.0)))), (7..7, Eof)]);
+    }
+
+    #[test]
+    fn tokenize_number_out_of_range_test() {
+        assert_eq!(tokenize_queue("2147483648"), vec![(0..10, Number(2147483648)), (10..10, Eof)]);
+        assert_eq!(tokenize_queue("-9223372036854775808"),
+                   vec![(0..20, Number(i64::min_value())), (20..20, Eof)]);
+        assert_eq!(tokenize_queue("9223372036854775807"),
+                   vec![(0..19, Number(i64::max_value())), (19..19, Eof)]);
+        let err = tokenize("foo[9223372036854775808]").unwrap_err();
+        assert_eq!(4, err.offset);
+        assert!(err.to_string().contains("Number out of range: 9223372036854775808"));
+        assert!(tokenize("-9223372036854775809").unwrap_err().to_string().contains("out of range"));
+        let digits = "1".repeat(100);
+        assert!(tokenize(&digits).unwrap_err().to_string().contains("out of range"));
+        let err = tokenize(&format!("[-{}]", digits)).unwrap_err();
+        assert!(err.to_string().contains("out of range"));
+    }
+
     #[test]
     fn tokenize_successive_test() {
         let expr = "foo.bar || `\"a\"` | 10";
         let tokens = tokenize_queue(expr);
-        assert_eq!(tokens[0], (0, Identifier("foo".to_string())));
-        assert_eq!(tokens[1], (3, Dot));
-        assert_eq!(tokens[2], (4, Identifier("bar".to_string())));
-        assert_eq!(tokens[3], (8, Or));
+        assert_eq!(tokens[0], (0..3, Identifier("foo".into())));
+        assert_eq!(tokens[1], (3..4, Dot));
+        assert_eq!(tokens[2], (4..7, Identifier("bar".into())));
+        assert_eq!(tokens[3], (8..10, Or));
         assert_eq!(tokens[4],
-                   (11, Literal(Rcvar::new(Variable::String("a".to_string())))));
-        assert_eq!(tokens[5], (17, Pipe));
-        assert_eq!(tokens[6], (19, Number(10)));
-        assert_eq!(tokens[7], (21, Eof));
+                   (11..16, Literal(Rcvar::new(Variable::String("a".to_string())))));
+        assert_eq!(tokens[5], (17..18, Pipe));
+        assert_eq!(tokens[6], (19..21, Number(10)));
+        assert_eq!(tokens[7], (21..21, Eof));
+        let lexemes = tokens.iter().map(|t| &expr[t.0.clone()]).collect::<Vec<_>>();
+        assert_eq!(vec!["foo", ".", "bar", "||", "`\"a\"`", "|", "10", ""], lexemes);
     }
 
     #[test]
     fn tokenizes_slices() {
         let tokens = tokenize_queue("foo[0::-1]");
-        assert_eq!("[(0, Identifier(\"foo\")), (3, Lbracket), (4, Number(0)), (5, Colon), \
-                     (6, Colon), (7, Number(-1)), (9, Rbracket), (10, Eof)]",
+        assert_eq!("[(0..3, Identifier(\"foo\")), (3..4, Lbracket), (4..5, Number(0)), \
+                     (5..6, Colon), (6..7, Colon), (7..9, Number(-1)), (9..10, Rbracket), \
+                     (10..10, Eof)]",
                    format!("{:?}", tokens));
     }
+
+    #[test]
+    fn spans_cover_escaped_and_multibyte_lexemes() {
+        let expr = "\"a\\\"é\" && 'x\\'y' | `[1, \"\\u00e9\"]`";
+        let lexemes = tokenize_queue(expr)
+            .iter()
+            .map(|t| &expr[t.0.clone()])
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["\"a\\\"é\"", "&&", "'x\\'y'", "|", "`[1, \"\\u00e9\"]`", ""],
+                   lexemes);
+    }
+
+    #[test]
+    fn unescapes_quoted_identifiers() {
+        assert_eq!(tokenize_queue(r#""\uD83D\uDE00""#),
+                   vec![(0..14, QuotedIdentifier("\u{1F600}".into())), (14..14, Eof)]);
+        assert_eq!(tokenize_queue(r#""\u00e9\"\/\b\f\r\t""#),
+                   vec![(0..20, QuotedIdentifier("\u{e9}\"/\u{8}\u{c}\r\t".into())),
+                        (20..20, Eof)]);
+    }
+
+    #[test]
+    fn rejects_invalid_escapes_in_quoted_identifiers() {
+        let error = |expr: &str| {
+            let err = tokenize(expr).unwrap_err();
+            (err.offset, err.to_string())
+        };
+        let (offset, message) = error(r#"foo."a\x""#);
+        assert_eq!(6, offset);
+        assert!(message.contains("Invalid escape in quoted identifier: \\x"));
+        let (offset, message) = error(r#""ab\u12""#);
+        assert_eq!(3, offset);
+        assert!(message.contains("Invalid unicode escape: \\u12"));
+        let (offset, message) = error(r#""\uD800""#);
+        assert_eq!(1, offset);
+        assert!(message.contains("Unpaired surrogate in unicode escape: \\uD800"));
+        let (offset, _) = error(r#""\uD800\u0041""#);
+        assert_eq!(1, offset);
+        let (offset, message) = error(r#""a\uDE00\uD83D""#);
+        assert_eq!(2, offset);
+        assert!(message.contains("Unpaired surrogate in unicode escape: \\uDE00"));
+        let (offset, message) = error("\"a\tb\"");
+        assert_eq!(2, offset);
+        assert!(message.contains("Unescaped control character"));
+    }
+
+    #[test]
+    fn tokenizes_dollar_identifiers_as_variables() {
+        assert_eq!(tokenize_queue("$.a"),
+                   vec![(0..1, Dollar), (1..2, Dot), (2..3, Identifier("a".into())),
+                        (3..3, Eof)]);
+        assert_eq!(tokenize_queue("b == $foo_1"),
+                   vec![(0..1, Identifier("b".into())), (2..4, Eq),
+                        (5..11, Variable("foo_1".into())), (11..11, Eof)]);
+        assert_eq!(tokenize_queue("$1"), vec![(0..1, Dollar), (1..2, Number(1)), (2..2, Eof)]);
+    }
+
+    #[test]
+    fn tokenizes_assignments_only_after_variables() {
+        assert_eq!(tokenize_queue("let $a = b in $a"),
+                   vec![(0..3, Identifier("let".into())), (4..6, Variable("a".into())),
+                        (7..8, Assign), (9..10, Identifier("b".into())),
+                        (11..13, Identifier("in".into())), (14..16, Variable("a".into())),
+                        (16..16, Eof)]);
+        assert_eq!(tokenize_queue("$a == b")[1], (3..5, Eq));
+        let err = tokenize("a = b").unwrap_err();
+        assert!(err.to_string().contains("'=' is not valid. Did you mean '=='?"));
+        assert!(tokenize("$a.b = c").is_err());
+    }
+
+    #[test]
+    fn tokenize_all_reports_every_error() {
+        let (tokens, errors) = tokenize_all("foo ~ bar = baz | \"unclosed");
+        assert_eq!(tokens,
+                   vec![(0..3, Identifier("foo".into())),
+                        (6..9, Identifier("bar".into())),
+                        (12..15, Identifier("baz".into())),
+                        (16..17, Pipe),
+                        (27..27, Eof)]);
+        assert_eq!(errors,
+                   vec![LexError {
+                            span: 4..5,
+                            lexeme: "~".to_string(),
+                            message: "Invalid character: ~".to_string(),
+                        },
+                        LexError {
+                            span: 10..11,
+                            lexeme: "=".to_string(),
+                            message: "'=' is not valid. Did you mean '=='?".to_string(),
+                        },
+                        LexError {
+                            span: 18..27,
+                            lexeme: "\"unclosed".to_string(),
+                            message: "Unclosed \" delimiter: \"unclosed".to_string(),
+                        }]);
+    }
+
+    #[test]
+    fn tokenize_all_skips_to_structural_characters() {
+        let (tokens, errors) = tokenize_all("a.b~c.d[-0]");
+        assert_eq!(vec![Identifier("a".into()), Dot, Identifier("b".into()), Dot,
+                        Identifier("d".into()), Lbracket, Rbracket, Eof],
+                   tokens.into_iter().map(|t| t.1).collect::<Vec<_>>());
+        assert_eq!(vec![3..5, 8..10], errors.into_iter().map(|e| e.span).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn tokenize_all_matches_tokenize_without_errors() {
+        let expr = "foo[?a == `1`].\"b\" | [0]";
+        let (tokens, errors) = tokenize_all(expr);
+        assert!(errors.is_empty());
+        assert_eq!(tokenize_queue(expr), tokens);
+    }
+
+    #[test]
+    fn rejects_numbers_with_leading_zeros() {
+        let err = tokenize("007").unwrap_err();
+        assert_eq!(0, err.offset);
+        assert!(err.to_string().contains("Invalid number 007: numbers are either 0 or start"));
+        let err = ::parser::parse("foo[00]").unwrap_err();
+        assert_eq!(4, err.offset);
+        assert!(err.to_string().contains("Invalid number 00"));
+    }
+
+    #[test]
+    fn reports_the_character_after_a_negative_sign() {
+        let err = tokenize("foo[-0]").unwrap_err();
+        assert_eq!(4, err.offset);
+        assert!(err.to_string().contains("'-' must be followed by numbers 1-9, found '0'"));
+        // A minus that is not followed by a digit is an arithmetic operator.
+        assert_eq!(tokenize_queue("-a"),
+                   vec![(0..1, Minus), (1..2, Identifier("a".into())), (2..2, Eof)]);
+        assert_eq!(tokenize_queue("- 5"),
+                   vec![(0..1, Minus), (2..3, Number(5)), (3..3, Eof)]);
+    }
+
+    #[test]
+    fn tokenize_result_returns_tokens_or_the_first_error() {
+        assert_eq!(tokenize_queue("a.b"), tokenize_result("a.b").unwrap());
+        let err = tokenize_result("a.b~c | 007").unwrap_err();
+        assert_eq!(3..5, err.span);
+        assert_eq!("~c", err.lexeme);
+        assert_eq!("Invalid character: ~", err.message);
+        assert_eq!("Invalid character: ~ (\"~c\" at offset 3)", err.to_string());
+        let err: Box<::std::error::Error> = Box::new(tokenize_result("a | 007").unwrap_err());
+        assert!(err.to_string().contains("\"007\" at offset 4"));
+    }
+
+    #[test]
+    fn borrows_lexemes_without_escapes() {
+        let tokens = tokenize_queue(r#"foo."b r"."a\nb""#);
+        match (&tokens[0].1, &tokens[2].1, &tokens[4].1) {
+            (&Identifier(Cow::Borrowed("foo")),
+             &QuotedIdentifier(Cow::Borrowed("b r")),
+             &QuotedIdentifier(Cow::Owned(ref escaped))) => assert_eq!("a\nb", escaped),
+            _ => panic!("unexpected tokens: {:?}", tokens),
+        }
+    }
+
+    fn lenient_tokens(expr: &str) -> Vec<Token> {
+        let options = ParseOptions { lenient_identifiers: true, ..ParseOptions::default() };
+        tokenize_with_options(expr, &options).unwrap().into_iter().map(|t| t.1).collect()
+    }
+
+    #[test]
+    fn lenient_identifiers_contain_inner_dashes() {
+        assert_eq!(vec![Identifier("content-type".into()), Eof], lenient_tokens("content-type"));
+        assert_eq!(vec![Identifier("a-b-c".into()), Dot, Identifier("d".into()), Eof],
+                   lenient_tokens("a-b-c.d"));
+        // Dashes next to anything other than identifier characters subtract.
+        assert_eq!(vec![Identifier("a".into()), Minus, Identifier("b".into()), Eof],
+                   lenient_tokens("a - b"));
+        assert_eq!(vec![Identifier("a".into()), Minus, Identifier("b".into()), Eof],
+                   lenient_tokens("a -b"));
+        assert_eq!(vec![Identifier("a".into()), Minus, Minus, Identifier("b".into()), Eof],
+                   lenient_tokens("a--b"));
+        assert_eq!(vec![Identifier("a".into()), Minus, Eof], lenient_tokens("a-"));
+        // A dash followed by a digit is part of the identifier.
+        assert_eq!(vec![Identifier("a-1".into()), Eof], lenient_tokens("a-1"));
+    }
+
+    #[test]
+    fn lenient_identifiers_start_with_digits_followed_by_letters() {
+        assert_eq!(vec![Identifier("2fa_enabled".into()), Eof], lenient_tokens("2fa_enabled"));
+        assert_eq!(vec![Identifier("2fa-code".into()), Eof], lenient_tokens("2fa-code"));
+        assert_eq!(vec![Number(404), Minus, Identifier("page".into()), Eof],
+                   lenient_tokens("404-page"));
+        assert_eq!(vec![Identifier("a".into()), Lbracket, Number(10), Rbracket, Eof],
+                   lenient_tokens("a[10]"));
+        assert_eq!(vec![Lbracket, Number(-1), Colon, Number(2), Rbracket, Eof],
+                   lenient_tokens("[-1:2]"));
+    }
+
+    #[test]
+    fn identifiers_are_strict_by_default() {
+        assert_eq!(vec![Identifier("a".into()), Minus, Identifier("b".into()), Eof],
+                   tokenize_queue("a-b").into_iter().map(|t| t.1).collect::<Vec<_>>());
+        assert_eq!(vec![Number(2), Identifier("fa".into()), Eof],
+                   tokenize_queue("2fa").into_iter().map(|t| t.1).collect::<Vec<_>>());
+        assert!(::parser::parse("2fa").is_err());
+    }
+
+    fn commented_tokens(expr: &str) -> Vec<Token> {
+        let options = ParseOptions { comments: true, ..ParseOptions::default() };
+        tokenize_with_options(expr, &options).unwrap().into_iter().map(|t| t.1).collect()
+    }
+
+    #[test]
+    fn skips_comments_when_enabled() {
+        assert_eq!(vec![Identifier("a".into()), Eof], commented_tokens("a # trailing"));
+        assert_eq!(vec![Identifier("a".into()), Eof], commented_tokens("# only\na#"));
+        let expr = "items[? # keep active items\n  active # and\n  && size > `1` # big\n]";
+        assert_eq!(vec![Identifier("items".into()), Filter, Identifier("active".into()), And,
+                        Identifier("size".into()), Gt,
+                        Literal(Rcvar::new(Variable::Number(1.0))), Rbracket, Eof],
+                   commented_tokens(expr));
+    }
+
+    #[test]
+    fn does_not_find_comments_inside_strings() {
+        assert_eq!(vec![QuotedIdentifier("a#b".into()), Eof], commented_tokens("\"a#b\""));
+        assert_eq!(vec![Literal(Rcvar::new(Variable::String("#".to_string()))), Eof],
+                   commented_tokens("'#'"));
+        assert_eq!(vec![Literal(Rcvar::new(Variable::String("#".to_string()))), Eof],
+                   commented_tokens("`\"#\"`"));
+    }
+
+    #[test]
+    fn rejects_comments_by_default() {
+        let err = tokenize("a # comment").unwrap_err();
+        assert_eq!(2, err.offset);
+        assert!(err.to_string().contains("Invalid character: #"));
+    }
+
+    fn legacy_literal(expr: &str) -> Result<Token, String> {
+        let options = ParseOptions { legacy_literals: true, ..ParseOptions::default() };
+        match tokenize_with_options(expr, &options) {
+            Ok(mut tokens) => Ok(tokens.pop_front().unwrap().1),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    #[test]
+    fn parses_bare_literals_as_strings_in_legacy_mode() {
+        let string = |s: &str| Ok(Literal(Rcvar::new(Variable::String(s.to_string()))));
+        assert_eq!(string("foo"), legacy_literal("`foo`"));
+        assert_eq!(string("foo\"bar"), legacy_literal("`foo\"bar`"));
+        assert_eq!(string("foo bar"), legacy_literal("` foo bar`"));
+        assert_eq!(Ok(Literal(Rcvar::new(Variable::Number(1.0)))), legacy_literal("`1`"));
+        assert_eq!(string("quoted"), legacy_literal("`\"quoted\"`"));
+        let err = legacy_literal("`[1, 2`").unwrap_err();
+        assert!(err.contains("Unable to parse literal JSON [1, 2"));
+        assert!(legacy_literal("`{\"a\": `").is_err());
+    }
+
+    #[test]
+    fn requires_json_literals_by_default() {
+        for expr in &["`foo`", "`foo\"bar`", "`[1, 2`"] {
+            let err = tokenize(expr).unwrap_err();
+            assert!(err.to_string().contains("Unable to parse literal JSON"));
+        }
+    }
+
+    // Asserts that the spans are contiguous and cover the whole expression.
+    fn assert_covers(expr: &str, spans: &[TokenSpan]) {
+        let mut end = 0;
+        for span in spans {
+            assert_eq!(end, span.start, "gap or overlap in {:?}: {:?}", expr, spans);
+            assert!(span.start < span.end, "empty span in {:?}: {:?}", expr, spans);
+            assert_eq!(span.kind == TokenKind::Error, span.error.is_some());
+            end = span.end;
+        }
+        assert_eq!(expr.len(), end, "{:?} is not fully covered: {:?}", expr, spans);
+    }
+
+    #[test]
+    fn classifies_every_byte() {
+        let expr = "foo[?bar == `{\"a\": [1]}` && \"b\\u00e9\" != 'x'] | [0:-1] ~ \"unclosed ";
+        let spans = lex_classify(expr);
+        assert_covers(expr, &spans);
+        let classified = spans.iter()
+            .filter(|span| span.kind != TokenKind::Whitespace)
+            .map(|span| (&expr[span.start..span.end], span.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![("foo", TokenKind::Identifier),
+                        ("[?", TokenKind::Bracket),
+                        ("bar", TokenKind::Identifier),
+                        ("==", TokenKind::Operator),
+                        ("`{\"a\": [1]}`", TokenKind::Literal),
+                        ("&&", TokenKind::Operator),
+                        ("\"b\\u00e9\"", TokenKind::QuotedIdentifier),
+                        ("!=", TokenKind::Operator),
+                        ("'x'", TokenKind::Literal),
+                        ("]", TokenKind::Bracket),
+                        ("|", TokenKind::Operator),
+                        ("[", TokenKind::Bracket),
+                        ("0", TokenKind::Number),
+                        (":", TokenKind::Operator),
+                        ("-1", TokenKind::Number),
+                        ("]", TokenKind::Bracket),
+                        ("~", TokenKind::Error),
+                        ("\"unclosed ", TokenKind::Error)],
+                   classified);
+        let errors = spans.iter().filter_map(|span| span.error.as_ref()).collect::<Vec<_>>();
+        assert_eq!("Invalid character: ~", errors[0]);
+        assert!(errors[1].starts_with("Unclosed \" delimiter"));
+    }
+
+    #[test]
+    fn classifies_errors_from_the_start_of_the_token() {
+        // The escape error is reported inside the quoted identifier, but the
+        // error span covers the whole token.
+        let expr = " \"a\\qb\".c";
+        let spans = lex_classify(expr);
+        assert_covers(expr, &spans);
+        assert_eq!(TokenSpan {
+                       start: 1,
+                       end: 7,
+                       kind: TokenKind::Error,
+                       error: Some("Invalid escape in quoted identifier: \\q".to_owned()),
+                   },
+                   spans[1]);
+    }
+
+    #[test]
+    fn classifies_arbitrary_input_without_panicking() {
+        assert!(lex_classify("").is_empty());
+        let expr = "a.\"\\ud800\" \t `[` 'b\\' \u{e9}\u{1f600} -0 99999999999999999999 $x = #";
+        for end in (0..expr.len() + 1).filter(|&end| expr.is_char_boundary(end)) {
+            for start in (0..end + 1).filter(|&start| expr.is_char_boundary(start)) {
+                assert_covers(&expr[start..end], &lex_classify(&expr[start..end]));
+            }
+        }
+    }
 }
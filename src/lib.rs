@@ -99,12 +99,34 @@ extern crate lazy_static;
 
 #[macro_use]
 extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 extern crate serde_json;
+extern crate indexmap;
+#[cfg(feature = "regex-functions")]
+extern crate regex;
+#[cfg(feature = "time-functions")]
+extern crate chrono;
+#[cfg(feature = "hash-functions")]
+extern crate md5;
+#[cfg(feature = "hash-functions")]
+extern crate sha1;
+#[cfg(feature = "hash-functions")]
+extern crate sha2;
 
+pub use completion::{complete, Completion, CompletionKind};
+pub use iter::{MapIter, FilterIter, FilterMapIter};
 pub use errors::{JmespathError, ErrorReason, RuntimeError};
-pub use parser::{parse, ParseResult};
-pub use runtime::Runtime;
-pub use variable::{Variable, to_variable};
+pub use explain::Explanation;
+pub use interpreter::SearchResult;
+pub use lexer::{lex_classify, tokenize, tokenize_all, tokenize_result, tokenize_with_options,
+                LexError, Token, TokenKind, TokenSpan, TokenTuple};
+pub use parser::{parse, parse_recoverable, parse_with_options, validate, ParseError,
+                 ParseOptions, ParseResult, DEFAULT_MAX_DEPTH};
+pub use policy::{check_policy, Policy, PolicyViolation};
+pub use provenance::{Path, PathSegment};
+pub use runtime::{Runtime, DEFAULT_MAX_EVALUATION_DEPTH};
+pub use variable::{Map, Variable, to_variable};
 
 pub mod ast;
 pub mod functions;
@@ -116,10 +138,16 @@ use serde_json::Value;
 
 use ast::Ast;
 use variable::Serializer;
-use interpreter::{interpret, SearchResult};
+use explain::Trace;
+use provenance::{locate, Provenance};
 
+mod completion;
+mod explain;
 mod interpreter;
+mod iter;
 mod parser;
+mod policy;
+mod provenance;
 mod lexer;
 mod runtime;
 mod errors;
@@ -152,6 +180,47 @@ pub fn compile(expression: &str) -> Result<Expression<'static>, JmespathError> {
     DEFAULT_RUNTIME.compile(expression)
 }
 
+/// Interprets a parsed AST against data using the default Runtime.
+///
+/// This evaluates an AST that was built or transformed by hand without
+/// printing and compiling it again. See `Runtime::interpret`.
+///
+/// ```
+/// use jmespath::ast::{Ast, Comparator};
+/// use jmespath::Variable;
+///
+/// let ast = Ast::Comparison {
+///     offset: 0,
+///     comparator: Comparator::GreaterThan,
+///     lhs: Box::new(Ast::Field { offset: 0, name: "a".to_owned() }),
+///     rhs: Box::new(Ast::Field { offset: 4, name: "b".to_owned() }),
+/// };
+/// let data = Variable::from_json(r#"{"a": 2, "b": 1}"#).unwrap();
+/// assert_eq!(Variable::Bool(true), *jmespath::interpret(&ast, data).unwrap());
+/// ```
+#[inline]
+pub fn interpret<T: ToJmespath>(ast: &Ast, data: T) -> SearchResult {
+    DEFAULT_RUNTIME.interpret(ast, data)
+}
+
+/// Interprets an AST that was parsed from `source` against data using the
+/// default Runtime, reporting errors against `source`. See
+/// `Runtime::interpret_with_source`.
+///
+/// ```
+/// use jmespath::Variable;
+///
+/// let source = "foo  |  abs(@)";
+/// let ast = jmespath::parse(source).unwrap();
+/// let data = Variable::from_json(r#"{"foo": "bar"}"#).unwrap();
+/// let err = jmespath::interpret_with_source(source, &ast, data).unwrap_err();
+/// assert_eq!(8, err.column);
+/// ```
+#[inline]
+pub fn interpret_with_source<T: ToJmespath>(source: &str, ast: &Ast, data: T) -> SearchResult {
+    DEFAULT_RUNTIME.interpret_with_source(source, ast, data)
+}
+
 /// Converts a value into a reference-counted JMESPath Variable.
 ///
 #[cfg_attr(feature = "specialized", doc = "\
@@ -348,6 +417,17 @@ impl ToJmespath for bool {
     }
 }
 
+/// Fails to compile if expressions or variables can no longer be shared
+/// between threads when the `sync` feature is enabled.
+#[cfg(feature = "sync")]
+#[allow(dead_code)]
+fn assert_sync_types_are_send_and_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Expression<'static>>();
+    assert_send_sync::<Rcvar>();
+    assert_send_sync::<JmespathError>();
+}
+
 /// A compiled JMESPath expression.
 ///
 /// The compiled expression can be used multiple times without incurring
@@ -385,8 +465,203 @@ impl<'a> Expression<'a> {
     /// Alternatively, Variable does implement Serde serialzation and
     /// deserialization, so it can easily be marshalled to another type.
     pub fn search<T: ToJmespath>(&self, data: T) -> SearchResult {
-        let mut ctx = Context::new(&self.expression, self.runtime);
-        interpret(&data.to_jmespath(), &self.ast, &mut ctx)
+        self.search_with_options(data, &SearchOptions::default())
+    }
+
+    /// Returns the result of searching data with the compiled expression
+    /// and the given options.
+    ///
+    /// A search with limits fails with a `ResultSizeExceeded` error as soon
+    /// as it builds more elements than the options allow.
+    ///
+    /// ```
+    /// use jmespath::{ErrorReason, RuntimeError, SearchOptions};
+    ///
+    /// let expr = jmespath::compile("a[*].b[*]").unwrap();
+    /// let data = jmespath::Variable::from_json(r#"{"a": [{"b": [1, 2]}, {"b": [3]}]}"#)
+    ///     .unwrap();
+    /// let options = SearchOptions { max_results: Some(4), ..SearchOptions::default() };
+    /// let err = expr.search_with_options(data, &options).unwrap_err();
+    /// assert_eq!(ErrorReason::Runtime(RuntimeError::ResultSizeExceeded { limit: 4 }),
+    ///            err.reason);
+    /// ```
+    ///
+    /// A strict search fails where a missing value would otherwise be null:
+    ///
+    /// ```
+    /// use jmespath::{ErrorReason, RuntimeError, SearchOptions};
+    ///
+    /// let expr = jmespath::compile("a.c").unwrap();
+    /// let data = jmespath::Variable::from_json(r#"{"a": {"b": 1}}"#).unwrap();
+    /// let options = SearchOptions { strict: true, ..SearchOptions::default() };
+    /// let err = expr.search_with_options(data, &options).unwrap_err();
+    /// assert_eq!(ErrorReason::Runtime(RuntimeError::MissingKey { key: "c".to_owned() }),
+    ///            err.reason);
+    /// ```
+    pub fn search_with_options<T: ToJmespath>(&self,
+                                              data: T,
+                                              options: &SearchOptions)
+                                              -> SearchResult {
+        let data = data.to_jmespath();
+        let mut ctx = Context::for_search(&self.expression, self.runtime, &data);
+        ctx.options = *options;
+        interpreter::interpret(&data, &self.ast, &mut ctx)
+    }
+
+    /// Returns the result of searching data with the compiled expression,
+    /// where `$name` refers to the value of `name` in vars.
+    ///
+    /// Variables are only read from vars, never from the data, so they can
+    /// pass values into an expression without interpolating them into its
+    /// string. Let expressions shadow variables of the same name, and a
+    /// reference to a variable that is bound by neither fails with an
+    /// `UndefinedVariable` error.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use jmespath::{Rcvar, Variable};
+    ///
+    /// let expr = jmespath::compile("people[?age >= $min].name").unwrap();
+    /// let data = Variable::from_json(r#"{"people": [
+    ///     {"name": "a", "age": 30},
+    ///     {"name": "b", "age": 20}
+    /// ]}"#)
+    ///     .unwrap();
+    /// let mut vars = HashMap::new();
+    /// vars.insert("min".to_owned(), Rcvar::new(Variable::Number(25.0)));
+    /// let result = expr.search_with_vars(data, &vars).unwrap();
+    /// assert_eq!("[\"a\"]", result.to_string());
+    /// ```
+    pub fn search_with_vars<'v, T, V>(&self, data: T, vars: V) -> SearchResult
+        where T: ToJmespath,
+              V: IntoIterator<Item = (&'v String, &'v Rcvar)>
+    {
+        let data = data.to_jmespath();
+        let mut ctx = Context::for_search(&self.expression, self.runtime, &data);
+        ctx.scopes.push(vars.into_iter().map(|(k, v)| (k.clone(), v.clone())).collect());
+        interpreter::interpret(&data, &self.ast, &mut ctx)
+    }
+
+    /// Returns the result of searching data with the compiled expression,
+    /// along with the path of each field and index that the search looked up
+    /// in the data, in the order they were first looked up.
+    ///
+    /// The lookups of expression references that functions evaluate are
+    /// recorded too, as are lookups of keys and indices that are missing.
+    /// A value that the search uses as a whole, such as the array that
+    /// `foo[*]` projects or the argument of `length(foo)`, is covered by the
+    /// path of the lookup that reached it (`foo`).
+    ///
+    /// ```
+    /// let expr = jmespath::compile("foo.bar[?x > `1`].y").unwrap();
+    /// let data = jmespath::Variable::from_json(r#"{"foo": {"bar": [{"x": 1}, {"x": 2}]}}"#)
+    ///     .unwrap();
+    /// let (result, paths) = expr.search_with_provenance(data).unwrap();
+    /// assert_eq!("[]", result.to_string());
+    /// let paths: Vec<String> = paths.iter().map(|path| path.to_string()).collect();
+    /// assert_eq!(vec!["foo", "foo.bar", "foo.bar[0].x", "foo.bar[1].x", "foo.bar[1].y"],
+    ///            paths);
+    /// ```
+    pub fn search_with_provenance<T: ToJmespath>(&self,
+                                                 data: T)
+                                                 -> Result<(Rcvar, Vec<Path>), JmespathError> {
+        let data = data.to_jmespath();
+        let mut ctx = Context::for_search(&self.expression, self.runtime, &data);
+        ctx.provenance = Some(Provenance::new(&data));
+        let result = try!(interpreter::interpret(&data, &self.ast, &mut ctx));
+        let paths = ctx.provenance.take().map_or(vec![], Provenance::into_paths);
+        Ok((result, paths))
+    }
+
+    /// Returns the paths of the values in data that make up the result of
+    /// searching it with the compiled expression.
+    ///
+    /// A result that is part of the data, such as the value of a field, has a
+    /// single path. Arrays and objects that the search builds have the paths
+    /// of their elements and values, in order, so a projection has a path for
+    /// each element that it yields. Any other value that the search builds,
+    /// such as a literal or the result of `length(foo)`, is not part of the
+    /// data and has no path.
+    ///
+    /// ```
+    /// let expr = jmespath::compile("people[?age > `20`].name").unwrap();
+    /// let data = jmespath::Variable::from_json(r#"{"people": [
+    ///     {"name": "a", "age": 30},
+    ///     {"name": "b", "age": 20},
+    ///     {"name": "c", "age": 40}
+    /// ]}"#)
+    ///     .unwrap();
+    /// let paths: Vec<String> = expr.locate(data)
+    ///     .unwrap()
+    ///     .iter()
+    ///     .map(|path| path.to_string())
+    ///     .collect();
+    /// assert_eq!(vec!["people[0].name", "people[2].name"], paths);
+    /// ```
+    pub fn locate<T: ToJmespath>(&self, data: T) -> Result<Vec<Path>, JmespathError> {
+        let data = data.to_jmespath();
+        let mut ctx = Context::for_search(&self.expression, self.runtime, &data);
+        let result = try!(interpreter::interpret(&data, &self.ast, &mut ctx));
+        Ok(locate(&data, &result))
+    }
+
+    /// Explains how searching data with the compiled expression evaluates
+    /// each node of the expression.
+    ///
+    /// The explanation is a tree that mirrors the AST, recording the input
+    /// and output of each node, how many elements each projection visited,
+    /// and how many elements each filter kept and dropped. It serializes to
+    /// JSON, and displays as an indented tree. A search that fails records
+    /// the error on the nodes that raised it.
+    ///
+    /// ```
+    /// let expr = jmespath::compile("a[?b].c").unwrap();
+    /// let data = jmespath::Variable::from_json(r#"{"a": [{"b": true, "c": 1}, {"c": 2}]}"#)
+    ///     .unwrap();
+    /// let explanation = expr.explain(data);
+    /// assert_eq!(Some("[1]".to_owned()), explanation.output);
+    /// println!("{}", explanation);
+    /// ```
+    pub fn explain<T: ToJmespath>(&self, data: T) -> Explanation {
+        let data = data.to_jmespath();
+        let mut ctx = Context::for_search(&self.expression, self.runtime, &data);
+        ctx.trace = Some(Trace::default());
+        // The trace records the error of a failed search.
+        let _ = interpreter::interpret(&data, &self.ast, &mut ctx);
+        let mut trace = ctx.trace.take().expect("the search has a trace");
+        trace.explain(&self.ast)
+    }
+
+    /// Returns an iterator that yields the search result of each item.
+    ///
+    /// The iterator is lazy, and runtime errors are yielded as `Err` items.
+    pub fn map_iter<'e, I>(&'e self, iter: I) -> MapIter<'e, 'a, I::IntoIter>
+        where I: IntoIterator,
+              I::Item: ToJmespath
+    {
+        MapIter::new(self, iter.into_iter())
+    }
+
+    /// Returns an iterator that yields each item for which the expression
+    /// returns a truthy value.
+    ///
+    /// The iterator is lazy, and runtime errors are yielded as `Err` items.
+    pub fn filter_iter<'e, I>(&'e self, iter: I) -> FilterIter<'e, 'a, I::IntoIter>
+        where I: IntoIterator,
+              I::Item: ToJmespath
+    {
+        FilterIter::new(self, iter.into_iter())
+    }
+
+    /// Returns an iterator that yields the search result of each item when
+    /// the result is truthy.
+    ///
+    /// The iterator is lazy, and runtime errors are yielded as `Err` items.
+    pub fn filter_map_iter<'e, I>(&'e self, iter: I) -> FilterMapIter<'e, 'a, I::IntoIter>
+        where I: IntoIterator,
+              I::Item: ToJmespath
+    {
+        FilterMapIter::new(self, iter.into_iter())
     }
 
     /// Returns the JMESPath expression from which the Expression was compiled.
@@ -424,6 +699,39 @@ impl<'a> PartialEq for Expression<'a> {
     }
 }
 
+/// Options that control how a search is evaluated: limits on the size of the
+/// values that it builds, and whether it is strict about missing values.
+///
+/// The default options impose no limits and yield null for missing values,
+/// as the specification describes.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SearchOptions {
+    /// Maximum number of elements that projections and flattens may collect
+    /// during a search, in total, or `None` for no limit.
+    pub max_results: Option<usize>,
+    /// Maximum number of elements in the arrays and objects that a search
+    /// builds, in total, or `None` for no limit. This counts the results of
+    /// projections, flattens, multi-selects, slices, and object wildcards,
+    /// as well as the arrays and objects that functions return.
+    pub max_allocations: Option<usize>,
+    /// Fails the search with a runtime error, rather than yielding null, when
+    /// a field is not in an object, when a field or index is selected from a
+    /// value of the wrong type, or when a projection, flatten, or slice is
+    /// applied to a value that it cannot project over. Null values in the
+    /// data and indexes past the end of an array still yield null.
+    ///
+    /// These errors evaluate to null in the left operand of `||` and in the
+    /// arguments of `not_null`, so that both can still provide fallbacks
+    /// (e.g., `nickname || name`). Defaults to `false`.
+    pub strict: bool,
+    /// Compares a string that holds a JSON number (e.g., `"42"`) as that
+    /// number when the other operand of a comparison is a number, so that
+    /// ``"42" > `10` `` is true rather than null. Strings compared with each
+    /// other and functions such as `contains` are not affected. Defaults to
+    /// `false`.
+    pub lenient_comparisons: bool,
+}
+
 /// Context object used for error reporting.
 ///
 /// The Context struct is mostly used when interacting between the
@@ -436,6 +744,30 @@ pub struct Context<'a> {
     pub runtime: &'a Runtime,
     /// Ast offset that is currently being evaluated.
     pub offset: usize,
+    /// Document that was passed to the search, as opposed to the value that
+    /// is currently being evaluated (e.g., an element of a projection).
+    ///
+    /// When this is None, `interpret` sets it to the data it is first called
+    /// with.
+    pub root: Option<Rcvar>,
+    /// Variables bound by the let expressions being evaluated, innermost
+    /// last, after the variables that the search was given.
+    pub(crate) scopes: Vec<variable::Map>,
+    /// Number of nested interpreter calls being evaluated.
+    pub(crate) depth: usize,
+    /// Options that the search is evaluated with.
+    pub(crate) options: SearchOptions,
+    /// Number of elements collected by projections and flattens so far.
+    pub(crate) results: usize,
+    /// Number of elements in the arrays and objects built so far.
+    pub(crate) allocations: usize,
+    /// Paths of the lookups made in the document, if they are recorded.
+    pub(crate) provenance: Option<Provenance>,
+    /// Records of the nodes that the search evaluates, if they are traced.
+    pub(crate) trace: Option<Trace>,
+    /// Regular expressions compiled during this search, keyed by pattern.
+    #[cfg(feature = "regex-functions")]
+    pub(crate) regex_cache: std::collections::HashMap<String, regex::Regex>,
 }
 
 impl<'a> Context<'a> {
@@ -446,8 +778,56 @@ impl<'a> Context<'a> {
             expression: expression,
             runtime: runtime,
             offset: 0,
+            root: None,
+            scopes: vec![],
+            depth: 0,
+            options: SearchOptions::default(),
+            results: 0,
+            allocations: 0,
+            provenance: None,
+            trace: None,
+            #[cfg(feature = "regex-functions")]
+            regex_cache: std::collections::HashMap::new(),
         }
     }
+
+    /// Creates a context for searching a document, which is its root.
+    pub(crate) fn for_search(expression: &'a str,
+                             runtime: &'a Runtime,
+                             root: &Rcvar)
+                             -> Context<'a> {
+        let mut ctx = Context::new(expression, runtime);
+        ctx.root = Some(root.clone());
+        ctx
+    }
+
+    /// Counts elements that a projection or flatten at `offset` collects
+    /// against the limits of the search.
+    pub(crate) fn collect(&mut self, offset: usize, count: usize) -> Result<(), JmespathError> {
+        self.results = self.results.saturating_add(count);
+        if let Some(limit) = self.options.max_results {
+            if self.results > limit {
+                return Err(self.size_exceeded(offset, limit));
+            }
+        }
+        self.allocate(offset, count)
+    }
+
+    /// Counts elements of an array or object that the node at `offset`
+    /// builds against the limits of the search.
+    pub(crate) fn allocate(&mut self, offset: usize, count: usize) -> Result<(), JmespathError> {
+        self.allocations = self.allocations.saturating_add(count);
+        match self.options.max_allocations {
+            Some(limit) if self.allocations > limit => Err(self.size_exceeded(offset, limit)),
+            _ => Ok(()),
+        }
+    }
+
+    fn size_exceeded(&mut self, offset: usize, limit: usize) -> JmespathError {
+        self.offset = offset;
+        let reason = RuntimeError::ResultSizeExceeded { limit: limit };
+        JmespathError::from_ctx(self, ErrorReason::Runtime(reason))
+    }
 }
 
 #[cfg(test)]
@@ -475,6 +855,45 @@ mod test {
         assert_eq!(Rcvar::new(Variable::Bool(true)), expr.search(var).unwrap());
     }
 
+    #[test]
+    fn extreme_indices_and_steps_do_not_overflow() {
+        let var = Variable::from_json("[0, 1, 2]").unwrap();
+        let search = |expr: &str| compile(expr).unwrap().search(var.clone()).unwrap();
+        assert_eq!(Rcvar::new(Variable::Null), search("[-2147483648]"));
+        assert_eq!(Variable::from_json("[1]").unwrap(), *search("[1::2147483647]"));
+        assert_eq!(Variable::from_json("[2]").unwrap(), *search("[-1::-2147483648]"));
+        assert_eq!(Rcvar::new(Variable::Null), search("[-9223372036854775808]"));
+        assert_eq!(Rcvar::new(Variable::Null), search("[9223372036854775807]"));
+        assert_eq!(Variable::from_json("[0]").unwrap(),
+                   *search("[-9223372036854775808:9223372036854775807:9223372036854775807]"));
+        assert_eq!(Variable::from_json("[2]").unwrap(), *search("[::-9223372036854775808]"));
+    }
+
+    #[test]
+    fn ordering_non_numbers_yields_null() {
+        let var = Variable::from_json(r#"{"people": [
+            {"name": "b", "age": 30, "active": true},
+            {"name": "a", "age": "20", "active": false},
+            {"name": "c", "active": null}
+        ]}"#)
+            .unwrap();
+        let search = |expr: &str| compile(expr).unwrap().search(var.clone()).unwrap();
+        assert_eq!(Rcvar::new(Variable::Null), search("people[0].name > 'a'"));
+        assert_eq!(Rcvar::new(Variable::Null), search("people[0].active >= `false`"));
+        assert_eq!(Rcvar::new(Variable::Null), search("people[0] < people[1]"));
+        assert_eq!(Rcvar::new(Variable::Null), search("people[2].age <= `1`"));
+        assert_eq!(Variable::from_json("[]").unwrap(), *search("people[?name > 'a'].name"));
+        assert_eq!(Variable::from_json("[\"b\"]").unwrap(),
+                   *search("people[?age > `10`].name"));
+        assert_eq!(Variable::from_json("[\"a\"]").unwrap(),
+                   *search("people[?age == '20'].name"));
+        // Null is falsy when combined with other conditions.
+        assert_eq!(Variable::from_json("[\"b\", \"a\", \"c\"]").unwrap(),
+                   *search("people[?!(name > 'a')].name"));
+        assert_eq!(Rcvar::new(Variable::Null), search("people[0].name > 'a' && `true`"));
+        assert_eq!(Variable::Bool(true), *search("people[0].name > 'a' || `true`"));
+    }
+
     #[test]
     fn can_get_expression_ast() {
         let expr = compile("foo").unwrap();
@@ -498,4 +917,457 @@ mod test {
         let _ = expr.clone();
     }
 
+    #[test]
+    fn functions_can_read_the_root_document() {
+        let mut runtime = Runtime::new();
+        runtime.register_builtin_functions();
+        runtime.register_function("lookup", Box::new(|args: &[Rcvar], ctx: &mut Context| {
+            let root = ctx.root.clone().unwrap();
+            let id = args[0].as_string().unwrap();
+            Ok(root.get_field("definitions").get_field(id))
+        }));
+        let data = Variable::from_json(r#"{
+            "definitions": {"a": "alpha", "b": "beta"},
+            "groups": [{"items": [{"ref": "a"}, {"ref": "b"}]}, {"items": [{"ref": "b"}]}]
+        }"#).unwrap();
+        let expr = runtime.compile("groups[*].items[*].lookup(ref)").unwrap();
+        assert_eq!(Variable::from_json(r#"[["alpha", "beta"], ["beta"]]"#).unwrap(),
+                   *expr.search(data).unwrap());
+    }
+
+    #[test]
+    fn root_node_defaults_to_the_first_interpreted_data() {
+        let expr = compile("a[*].[b, $.c]").unwrap();
+        let data = Rcvar::new(Variable::from_json(r#"{"a": [{"b": 1}], "c": 2}"#).unwrap());
+        let mut ctx = Context::new(expr.as_str(), &DEFAULT_RUNTIME);
+        assert_eq!(Variable::from_json("[[1, 2]]").unwrap(),
+                   *interpreter::interpret(&data, expr.as_ast(), &mut ctx).unwrap());
+    }
+
+    #[test]
+    fn runtime_errors_point_at_the_failing_function_call() {
+        let expr = compile("foo.length(bar)").unwrap();
+        let err = expr.search(Variable::from_json(r#"{"foo": {"bar": true}}"#).unwrap())
+            .unwrap_err();
+        assert_eq!(4, err.offset);
+        assert!(err.to_string().contains("(line 0, column 4)\nfoo.length(bar)\n    ^\n"));
+    }
+
+    #[test]
+    fn multi_select_hashes_keep_the_order_of_their_keys() {
+        let expr = compile("{name: a.name, id: a.id, created: a.ts}").unwrap();
+        let data = Variable::from_json(r#"{"a": {"ts": 3, "name": "x", "id": 1}}"#).unwrap();
+        let result = expr.search(data).unwrap();
+        assert_eq!(r#"{"name":"x","id":1,"created":3}"#, result.to_string());
+        let keys = compile("keys(@)").unwrap().search(result).unwrap();
+        assert_eq!(r#"["name","id","created"]"#, keys.to_string());
+    }
+
+    #[test]
+    fn repeated_multi_select_hash_keys_keep_their_first_position() {
+        let expr = compile("{z: `1`, a: `2`, z: `3`}").unwrap();
+        let result = expr.search(Variable::Null).unwrap();
+        assert_eq!("null", result.to_string());
+        let result = expr.search(Variable::Bool(true)).unwrap();
+        assert_eq!(r#"{"z":3,"a":2}"#, result.to_string());
+    }
+
+    fn compile_with_computed_keys(expr: &str) -> Expression<'static> {
+        let options = ParseOptions { computed_keys: true, ..ParseOptions::default() };
+        Expression::new(expr, parse_with_options(expr, &options).unwrap(), &DEFAULT_RUNTIME)
+    }
+
+    #[test]
+    fn evaluates_computed_multi_select_hash_keys() {
+        let expr = compile_with_computed_keys(
+            "reduce(things[*].{(id): status}, &merge(accumulated, current), `{}`)");
+        let data = Variable::from_json(r#"{"things": [{"id": "a", "status": "up"},
+                                                      {"id": "b", "status": "down"}]}"#)
+            .unwrap();
+        assert_eq!(r#"{"a":"up","b":"down"}"#, expr.search(data).unwrap().to_string());
+        let expr = compile_with_computed_keys("{(name): value, other: `1`}");
+        let data = Variable::from_json(r#"{"name": "alice", "value": 42}"#).unwrap();
+        assert_eq!(r#"{"alice":42,"other":1}"#, expr.search(data).unwrap().to_string());
+    }
+
+    #[test]
+    fn computed_multi_select_hash_keys_must_be_strings() {
+        let expr = compile_with_computed_keys("{a: b, (c.d): e}");
+        let err = expr.search(Variable::from_json(r#"{"c": {"d": 1}}"#).unwrap()).unwrap_err();
+        assert_eq!(ErrorReason::Runtime(RuntimeError::InvalidKey { actual: "number".to_owned() }),
+                   err.reason);
+        assert_eq!(9, err.offset);
+        let err = expr.search(Variable::from_json(r#"{"c": {}}"#).unwrap()).unwrap_err();
+        assert_eq!("Runtime error: Multi-select hash keys must be strings, given null",
+                   err.reason.to_string());
+    }
+
+    /// Wraps a node `depth` times.
+    fn nest<F>(depth: usize, node: Ast, wrap: F) -> Ast
+        where F: Fn(Ast) -> Ast
+    {
+        (0..depth).fold(node, |node, _| wrap(node))
+    }
+
+    /// Drops an AST without recursing once per level of nesting.
+    fn drop_iteratively(ast: Ast) {
+        let mut nodes = vec![ast];
+        while let Some(node) = nodes.pop() {
+            match node {
+                Ast::Subexpr { lhs, rhs, .. } |
+                Ast::Or { lhs, rhs, .. } |
+                Ast::And { lhs, rhs, .. } |
+                Ast::Projection { lhs, rhs, .. } => nodes.extend(vec![*lhs, *rhs]),
+                Ast::Not { node, .. } |
+                Ast::Flatten { node, .. } => nodes.push(*node),
+                _ => {}
+            }
+        }
+    }
+
+    fn field(name: &str) -> Ast {
+        Ast::Field {
+            offset: 0,
+            name: name.to_owned(),
+        }
+    }
+
+    fn search_deep(ast: Ast, data: &str) -> Rcvar {
+        let data = Rcvar::new(Variable::from_json(data).unwrap());
+        let mut ctx = Context::new("", &DEFAULT_RUNTIME);
+        let result = interpreter::interpret(&data, &ast, &mut ctx).unwrap();
+        drop_iteratively(ast);
+        result
+    }
+
+    #[test]
+    fn evaluates_deeply_nested_subexpressions() {
+        let depth = 100_000;
+        let left = nest(depth, field("a"), |node| {
+            Ast::Subexpr {
+                offset: 0,
+                lhs: Box::new(node),
+                rhs: Box::new(Ast::Identity { offset: 0 }),
+            }
+        });
+        assert_eq!(Variable::Number(1.0), *search_deep(left, r#"{"a": 1}"#));
+        let right = nest(depth, field("a"), |node| {
+            Ast::Subexpr {
+                offset: 0,
+                lhs: Box::new(Ast::Identity { offset: 0 }),
+                rhs: Box::new(node),
+            }
+        });
+        assert_eq!(Variable::Number(1.0), *search_deep(right, r#"{"a": 1}"#));
+    }
+
+    #[test]
+    fn evaluates_long_or_and_not_chains() {
+        let depth = 100_000;
+        let ors = nest(depth, field("missing"), |node| {
+            Ast::Or {
+                offset: 0,
+                lhs: Box::new(node),
+                rhs: Box::new(field("missing")),
+            }
+        });
+        let ors = Ast::Or {
+            offset: 0,
+            lhs: Box::new(ors),
+            rhs: Box::new(field("a")),
+        };
+        assert_eq!(Variable::Number(1.0), *search_deep(ors, r#"{"a": 1}"#));
+        let ands = nest(depth, field("a"), |node| {
+            Ast::And {
+                offset: 0,
+                lhs: Box::new(field("a")),
+                rhs: Box::new(node),
+            }
+        });
+        assert_eq!(Variable::Number(1.0), *search_deep(ands, r#"{"a": 1}"#));
+        let nots = nest(depth, field("a"), |node| {
+            Ast::Not {
+                offset: 0,
+                node: Box::new(node),
+            }
+        });
+        assert_eq!(Variable::Bool(true), *search_deep(nots, r#"{"a": 1}"#));
+    }
+
+    #[test]
+    fn evaluates_long_chains_of_flatten_projections() {
+        let flattens = nest(100_000, field("a"), |node| {
+            Ast::Projection {
+                offset: 0,
+                lhs: Box::new(Ast::Flatten {
+                    offset: 0,
+                    node: Box::new(node),
+                }),
+                rhs: Box::new(Ast::Identity { offset: 0 }),
+            }
+        });
+        assert_eq!(Variable::from_json("[1, 2, 3]").unwrap(),
+                   *search_deep(flattens, r#"{"a": [[1, [2]], 3]}"#));
+    }
+
+    /// Creates `{"a": [{"b": [{"c": [0, ...]}, ...]}, ...]}` with `width`
+    /// elements in each array.
+    fn cross_product(width: usize) -> Variable {
+        let leaves = Variable::Array((0..width).map(|i| Rcvar::new(Variable::Number(i as f64)))
+            .collect());
+        let object = |key: &str, value: Variable| {
+            let mut map = Map::new();
+            map.insert(key.to_owned(), Rcvar::new(value));
+            Variable::Object(map)
+        };
+        let repeat = |value: Variable| Variable::Array(vec![Rcvar::new(value); width]);
+        object("a", repeat(object("b", repeat(object("c", leaves)))))
+    }
+
+    fn limits(max_results: Option<usize>, max_allocations: Option<usize>) -> SearchOptions {
+        SearchOptions {
+            max_results: max_results,
+            max_allocations: max_allocations,
+            ..SearchOptions::default()
+        }
+    }
+
+    #[test]
+    fn searches_without_limits_by_default() {
+        let expr = compile("a[*].b[*].c[*]").unwrap();
+        assert_eq!(expr.search(cross_product(10)).unwrap(),
+                   expr.search_with_options(cross_product(10), &SearchOptions::default())
+                       .unwrap());
+    }
+
+    #[test]
+    fn limits_the_results_of_cross_product_projections() {
+        let expr = compile("a[*].b[*].c[*]").unwrap();
+        // The projections collect 10, 10 * 10, and 10 * 10 * 10 elements.
+        assert!(expr.search_with_options(cross_product(10), &limits(Some(1110), None)).is_ok());
+        let err = expr.search_with_options(cross_product(10), &limits(Some(1109), None))
+            .unwrap_err();
+        assert_eq!(ErrorReason::Runtime(RuntimeError::ResultSizeExceeded { limit: 1109 }),
+                   err.reason);
+        let err = expr.search_with_options(cross_product(10), &limits(Some(500), None))
+            .unwrap_err();
+        assert_eq!(12, err.offset);
+        let expr = compile("a[].b[].c[]").unwrap();
+        let err = expr.search_with_options(cross_product(10), &limits(Some(1000), None))
+            .unwrap_err();
+        assert_eq!(ErrorReason::Runtime(RuntimeError::ResultSizeExceeded { limit: 1000 }),
+                   err.reason);
+    }
+
+    #[test]
+    fn limits_the_elements_of_allocated_arrays_and_objects() {
+        let data = Variable::from_json(r#"{"a": [3, 1, 2], "b": {"c": 1, "d": 2}}"#).unwrap();
+        let search = |expression: &str, max_allocations: usize| {
+            compile(expression)
+                .unwrap()
+                .search_with_options(data.clone(), &limits(None, Some(max_allocations)))
+        };
+        // The multi-select list has 3 elements and sort returns 3 more.
+        assert!(search("[a, b, sort(a)]", 6).is_ok());
+        let err = search("[a, b, sort(a)]", 5).unwrap_err();
+        assert_eq!(ErrorReason::Runtime(RuntimeError::ResultSizeExceeded { limit: 5 }),
+                   err.reason);
+        assert_eq!(7, err.offset);
+        // Slices and `*` are projections, which collect the elements again.
+        assert!(search("{x: a[:2], y: b.*}", 10).is_ok());
+        assert!(search("{x: a[:2], y: b.*}", 9).is_err());
+        // Values of the document and literals are not allocated by the search.
+        assert!(search("[a, `[1, 2, 3]`][0]", 2).is_ok());
+    }
+
+    fn search_strictly(expression: &str, json: &str) -> SearchResult {
+        let options = SearchOptions { strict: true, ..SearchOptions::default() };
+        let data = Variable::from_json(json).unwrap();
+        compile(expression).unwrap().search_with_options(data, &options)
+    }
+
+    fn strict_error(expression: &str, json: &str) -> (RuntimeError, usize) {
+        let err = search_strictly(expression, json).unwrap_err();
+        match err.reason {
+            ErrorReason::Runtime(reason) => (reason, err.offset),
+            reason => panic!("{} failed to parse: {:?}", expression, reason),
+        }
+    }
+
+    #[test]
+    fn strict_searches_fail_on_missing_keys() {
+        let data = r#"{"foo": {"bar": 1}}"#;
+        assert_eq!(Variable::Number(1.0), *search_strictly("foo.bar", data).unwrap());
+        assert_eq!((RuntimeError::MissingKey { key: "baz".to_owned() }, 4),
+                   strict_error("foo.baz", data));
+        // The same search is null by default.
+        assert!(compile("foo.baz").unwrap().search(Variable::from_json(data).unwrap())
+            .unwrap()
+            .is_null());
+    }
+
+    #[test]
+    fn strict_searches_fail_on_type_mismatches() {
+        let data = r#"{"foo": {"bar": 1}, "list": [1, 2], "text": "abc"}"#;
+        let invalid_projection = |expected: &str, actual: &str| {
+            RuntimeError::InvalidProjection {
+                expected: expected.to_owned(),
+                actual: actual.to_owned(),
+            }
+        };
+        let cases = vec![("foo.bar.baz",
+                          RuntimeError::InvalidField {
+                              key: "baz".to_owned(),
+                              actual: "number".to_owned(),
+                          },
+                          8),
+                         ("foo[0]",
+                          RuntimeError::InvalidIndex {
+                              index: 0,
+                              actual: "object".to_owned(),
+                          },
+                          5),
+                         ("foo[*].bar", invalid_projection("array", "object"), 4),
+                         ("foo[?bar]", invalid_projection("array", "object"), 3),
+                         ("list.*", invalid_projection("object", "array"), 5),
+                         ("text[]", invalid_projection("array", "string"), 4),
+                         ("foo[1:]", invalid_projection("array", "object"), 3)];
+        for (expression, reason, offset) in cases {
+            assert_eq!((reason, offset), strict_error(expression, data), "{}", expression);
+        }
+    }
+
+    #[test]
+    fn strict_searches_keep_the_nulls_of_the_data() {
+        let data = r#"{"foo": null, "list": [1, 2]}"#;
+        assert!(search_strictly("foo", data).unwrap().is_null());
+        assert!(search_strictly("list[5]", data).unwrap().is_null());
+        assert_eq!(RuntimeError::InvalidField {
+                       key: "bar".to_owned(),
+                       actual: "null".to_owned(),
+                   },
+                   strict_error("foo.bar", data).0);
+    }
+
+    #[test]
+    fn or_and_not_null_fall_back_on_strict_errors() {
+        let data = r#"{"name": "a", "tags": {"x": 1}}"#;
+        let search = |expression: &str| search_strictly(expression, data).unwrap();
+        assert_eq!(Variable::String("a".to_owned()), *search("nickname || name"));
+        assert_eq!(Variable::String("a".to_owned()), *search("not_null(nickname, name)"));
+        assert_eq!(Variable::String("a".to_owned()), *search("tags[0] || nickname || name"));
+        assert_eq!(Variable::String("a".to_owned()), *search("map(&y, [tags]) || name"));
+        // Only the left operand of `||` falls back; the right one is strict.
+        assert_eq!(RuntimeError::MissingKey { key: "id".to_owned() },
+                   strict_error("nickname || id", data).0);
+        assert_eq!(RuntimeError::MissingKey { key: "nickname".to_owned() },
+                   strict_error("nickname && name", data).0);
+        assert_eq!(RuntimeError::MissingKey { key: "nickname".to_owned() },
+                   strict_error("!nickname", data).0);
+        // Other errors in the left operand still fail the search.
+        assert!(search_strictly("length(`1`) || name", data).is_err());
+    }
+
+    fn vars(pairs: &[(&str, Variable)]) -> Map {
+        pairs.iter()
+            .map(|&(name, ref value)| (name.to_owned(), Rcvar::new(value.clone())))
+            .collect()
+    }
+
+    #[test]
+    fn searches_with_vars_in_filters_and_function_arguments() {
+        let data = Variable::from_json(r#"{"people": [
+            {"name": "a", "age": 30},
+            {"name": "b", "age": 20},
+            {"name": "c", "age": 40}
+        ]}"#)
+            .unwrap();
+        let vars = vars(&[("min", Variable::Number(25.0)),
+                          ("sep", Variable::String("/".to_owned()))]);
+        let expr = compile("join($sep, people[?age > $min].name)").unwrap();
+        assert_eq!(Variable::String("a/c".to_owned()),
+                   *expr.search_with_vars(data.clone(), &vars).unwrap());
+        // Variables are visible in expression references too.
+        let expr = compile("max_by(people[?age < $min], &age).name").unwrap();
+        assert_eq!(Variable::String("b".to_owned()),
+                   *expr.search_with_vars(data, &vars).unwrap());
+    }
+
+    #[test]
+    fn let_expressions_shadow_vars() {
+        let vars = vars(&[("x", Variable::Number(1.0)), ("y", Variable::Number(2.0))]);
+        let expr = compile("[let $x = `10` in [$x, $y], $x]").unwrap();
+        assert_eq!(Variable::from_json("[[10, 2], 1]").unwrap(),
+                   *expr.search_with_vars(Variable::from_json("{}").unwrap(), &vars).unwrap());
+    }
+
+    #[test]
+    fn vars_are_not_read_from_the_data() {
+        let data = Variable::from_json(r#"{"x": 1, "$x": 2, "vars": {"x": 3}}"#).unwrap();
+        let vars = vars(&[("x", Variable::String("var".to_owned()))]);
+        let expr = compile(r#"[$x, x, "$x"]"#).unwrap();
+        assert_eq!(Variable::from_json(r#"["var", 1, 2]"#).unwrap(),
+                   *expr.search_with_vars(data.clone(), &vars).unwrap());
+        // Without a binding, the variable is undefined whatever the data holds.
+        let err = expr.search_with_vars(data.clone(), &Map::new()).unwrap_err();
+        assert_eq!(ErrorReason::Runtime(RuntimeError::UndefinedVariable("x".to_owned())),
+                   err.reason);
+        assert_eq!(1, err.offset);
+        assert!(compile("$vars").unwrap().search(data).is_err());
+    }
+
+    #[test]
+    fn exprefs_only_capture_the_vars_they_refer_to() {
+        let capture = |expression: &str| {
+            let expr = compile(expression).unwrap();
+            let mut ctx = Context::new(expression, &DEFAULT_RUNTIME);
+            ctx.scopes.push(vars(&[("x", Variable::Number(1.0)), ("y", Variable::Number(2.0))]));
+            let data = Rcvar::new(Variable::Null);
+            let expref = interpreter::interpret(&data, expr.as_ast(), &mut ctx).unwrap();
+            expref.as_expref().unwrap().clone()
+        };
+        // An expref that uses no variables is captured as it is.
+        assert_eq!(Ast::Field { offset: 1, name: "foo".to_owned() }, capture("&foo"));
+        match capture("&[$x, $x, foo]") {
+            Ast::Let { ref bindings, .. } => {
+                assert_eq!(vec!["x"], bindings.iter().map(|b| &b.name).collect::<Vec<_>>());
+            }
+            ast => panic!("expected a let expression, got {:?}", ast),
+        }
+    }
+
+    #[test]
+    fn compares_numeric_strings_with_lenient_comparisons() {
+        let data = Variable::from_json(r#"{"records": [
+            {"id": "a", "count": 42},
+            {"id": "b", "count": "42"},
+            {"id": "c", "count": "7"},
+            {"id": "d", "count": "many"},
+            {"id": "e", "count": " 42"}
+        ], "tags": ["42"]}"#)
+            .unwrap();
+        let lenient = SearchOptions { lenient_comparisons: true, ..SearchOptions::default() };
+        let search = |expression: &str, options: &SearchOptions| {
+            compile(expression).unwrap().search_with_options(data.clone(), options).unwrap()
+        };
+        let cases = vec![("records[?count > `10`].id", r#"["a"]"#, r#"["a", "b"]"#),
+                         ("records[?count == `42`].id", r#"["a"]"#, r#"["a", "b"]"#),
+                         ("records[?`7` != count].id",
+                          r#"["a", "b", "c", "d", "e"]"#,
+                          r#"["a", "b", "d", "e"]"#),
+                         ("records[?count == '42'].id", r#"["b"]"#, r#"["a", "b"]"#),
+                         ("records[?count == '42.0'].id", "[]", r#"["a"]"#),
+                         ("records[?count == $.records[0].count].id", r#"["a"]"#, r#"["a", "b"]"#),
+                         ("contains(tags, `42`)", "false", "false")];
+        for (expression, default, lenient_result) in cases {
+            assert_eq!(Variable::from_json(default).unwrap(),
+                       *search(expression, &SearchOptions::default()),
+                       "{}",
+                       expression);
+            assert_eq!(Variable::from_json(lenient_result).unwrap(),
+                       *search(expression, &lenient),
+                       "{}",
+                       expression);
+        }
+    }
 }
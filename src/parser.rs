@@ -5,41 +5,305 @@
 //! http://hall.org.ua/halls/wizzard/pdf/Vaughan.Pratt.TDOP.pdf
 
 use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
 
-use {JmespathError, ErrorReason};
-use ast::{Ast, KeyValuePair, Comparator};
-use lexer::{tokenize, Token, TokenTuple};
+use {Context, ErrorReason, JmespathError, RuntimeError, DEFAULT_RUNTIME};
+use ast::{Ast, Key, KeyValuePair, LetBinding, Comparator, ArithmeticOperator};
+use lexer::{tokenize_all, tokenize_with_options, Token, TokenTuple};
 
 /// Result of parsing an expression.
 pub type ParseResult = Result<Ast, JmespathError>;
 
+/// Options that control how expressions are tokenized and parsed.
+///
+/// The default options are strictly compliant with the JMESPath
+/// specification.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParseOptions {
+    /// Allows unquoted identifiers that contain dashes between identifier
+    /// characters (`content-type`, `a-1`) or that start with digits followed
+    /// by a letter or `_` (`2fa_enabled`).
+    ///
+    /// A dash that is not surrounded by identifier characters is still an
+    /// arithmetic operator, so `a - b` and `a -b` subtract.
+    pub lenient_identifiers: bool,
+    /// Treats `#` through the end of the line as whitespace, which allows
+    /// comments in multi-line expressions. A `#` inside of a quoted
+    /// identifier, raw string, or literal does not start a comment.
+    pub comments: bool,
+    /// Accepts literals that are not valid JSON as strings, like JMESPath
+    /// implementations did before JEP-12 (e.g., `` `foo` `` is `"foo"`).
+    ///
+    /// Literals that start with `[` or `{` are still parsed as JSON, so a
+    /// malformed array or object remains an error.
+    pub legacy_literals: bool,
+    /// Accepts a parenthesized expression as the key of a multi-select hash
+    /// (e.g., `{(name): value}`). The expression is evaluated against the
+    /// current node and must evaluate to a string.
+    ///
+    /// This is an extension to the JMESPath specification.
+    pub computed_keys: bool,
+    /// Maximum nesting depth of an expression, which bounds the recursion
+    /// of the parser. Each nested sub-expression, e.g., the contents of
+    /// brackets, parentheses, or function arguments, or the right hand side
+    /// of a projection, counts as a level. Defaults to `DEFAULT_MAX_DEPTH`.
+    pub max_depth: usize,
+}
+
+/// The default maximum nesting depth of an expression.
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+impl Default for ParseOptions {
+    fn default() -> ParseOptions {
+        ParseOptions {
+            lenient_identifiers: false,
+            comments: false,
+            legacy_literals: false,
+            computed_keys: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+}
+
 /// Parses a JMESPath expression into an AST.
 pub fn parse(expr: &str) -> ParseResult {
-    let tokens = try!(tokenize(expr));
-    Parser::new(tokens, expr).parse()
+    parse_with_options(expr, &ParseOptions::default())
+}
+
+/// Parses a JMESPath expression into an AST using the given options.
+///
+/// Use `Expression::new` to create an expression from the AST:
+///
+/// ```
+/// use jmespath::{Expression, ParseOptions, Runtime};
+///
+/// let mut runtime = Runtime::new();
+/// runtime.register_builtin_functions();
+/// let options = ParseOptions { lenient_identifiers: true, ..ParseOptions::default() };
+/// let ast = jmespath::parse_with_options("headers.content-type", &options).unwrap();
+/// let expr = Expression::new("headers.content-type", ast, &runtime);
+/// let data = jmespath::Variable::from_json(r#"{"headers": {"content-type": "text/plain"}}"#)
+///     .unwrap();
+/// assert_eq!("text/plain", expr.search(data).unwrap().as_string().unwrap());
+/// ```
+pub fn parse_with_options(expr: &str, options: &ParseOptions) -> ParseResult {
+    let tokens = try!(tokenize_with_options(expr, options));
+    let mut parser = Parser::new(tokens, expr);
+    parser.max_depth = options.max_depth;
+    parser.computed_keys = options.computed_keys;
+    parser.parse()
+}
+
+/// A syntax error reported by `parse_recoverable`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ParseError {
+    /// Span of the token at the error and of the tokens that were skipped
+    /// after it. The start is the byte offset of the error.
+    pub span: Range<usize>,
+    /// The error, including its line, column, and expected tokens.
+    pub error: JmespathError,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(fmt, "{}", self.error)
+    }
+}
+
+impl Error for ParseError {
+    fn description(&self) -> &str {
+        "Syntax error"
+    }
+}
+
+/// Parses a JMESPath expression, reporting every syntax error.
+///
+/// Rather than stopping at the first error like `parse`, the parser skips
+/// to the next `,`, `|`, closing bracket, or the end of the expression and
+/// keeps going. The parts that were skipped are replaced by `Ast::Error`
+/// nodes, so the AST can still be inspected (e.g., for completion). The
+/// AST is `None` when no part of the expression could be parsed.
+///
+/// Errors are ordered by their position in the expression and include
+/// lexical errors, e.g., invalid characters.
+///
+/// ```
+/// let (ast, errors) = jmespath::parse_recoverable("foo[0 | bar.");
+/// assert!(ast.is_some());
+/// assert_eq!(vec![6, 12], errors.iter().map(|e| e.span.start).collect::<Vec<_>>());
+/// ```
+pub fn parse_recoverable(expr: &str) -> (Option<Ast>, Vec<ParseError>) {
+    let (tokens, lex_errors) = tokenize_all(expr);
+    let mut parser = Parser::new(VecDeque::from(tokens), expr);
+    parser.recovering = true;
+    let ast = parser.parse_all();
+    let mut errors = parser.errors;
+    for lex_error in lex_errors {
+        let reason = ErrorReason::Parse(lex_error.message);
+        errors.push(ParseError {
+            error: JmespathError::new(expr, lex_error.span.start, reason),
+            span: lex_error.span,
+        });
+    }
+    errors.sort_by_key(|e| e.span.start);
+    match ast {
+        Ast::Error { .. } => (None, errors),
+        ast => (Some(ast), errors),
+    }
+}
+
+/// Checks that an expression is valid without compiling it.
+///
+/// Besides the syntax, calls to functions are checked against the builtin
+/// functions of the default runtime: calls to unknown functions and calls
+/// with the wrong number of arguments fail like they would when the
+/// expression is evaluated.
+///
+/// The expression is parsed in full and the AST is then discarded, so this
+/// is not faster than `compile`. Use it to catch function errors up front,
+/// which `compile` only reports when the expression is evaluated.
+///
+/// ```
+/// assert!(jmespath::validate("foo[?bar > `1`].length(baz)").is_ok());
+/// let err = jmespath::validate("foo.lenght(bar)").unwrap_err();
+/// assert_eq!(4..10, err.span);
+/// ```
+pub fn validate(expr: &str) -> Result<(), ParseError> {
+    let ast = match parse(expr) {
+        Ok(ast) => ast,
+        Err(err) => {
+            return Err(ParseError {
+                span: error_span(expr, err.offset),
+                error: err,
+            })
+        }
+    };
+    let mut ctx = Context::new(expr, &DEFAULT_RUNTIME);
+    ast.walk(&mut |node| {
+        if let Ast::Function { offset, ref name, ref args } = *node {
+            ctx.offset = offset;
+            let result = match DEFAULT_RUNTIME.get_function(name) {
+                None => {
+                    let reason = RuntimeError::UnknownFunction(name.to_owned());
+                    Err(JmespathError::from_ctx(&ctx, ErrorReason::Runtime(reason)))
+                }
+                Some(function) => {
+                    match function.signature() {
+                        Some(signature) => signature.validate_arity(args.len(), &ctx),
+                        None => Ok(()),
+                    }
+                }
+            };
+            try!(result.map_err(|err| {
+                ParseError {
+                    span: offset..offset + name.len(),
+                    error: err,
+                }
+            }));
+        }
+        Ok(())
+    })
+}
+
+/// Returns the span of the token that starts at the offset of an error.
+fn error_span(expr: &str, offset: usize) -> Range<usize> {
+    let (tokens, errors) = tokenize_all(expr);
+    tokens.into_iter()
+        .map(|(span, _)| span)
+        .chain(errors.into_iter().map(|e| e.span))
+        .find(|span| span.start == offset)
+        .unwrap_or(offset..offset)
 }
 
 /// The maximum binding power for a token that can stop a projection.
 const PROJECTION_STOP: usize = 10;
 
+/// The binding power used to parse the right hand side of a wildcard or
+/// slice projection.
+const PROJECTION_RBP: usize = 20;
+
+/// Tokens that can start an expression.
+const EXPRESSION_START: &'static [&'static str] = &["an identifier",
+                                                     "a quoted identifier",
+                                                     "`*`",
+                                                     "`[`",
+                                                     "`{`",
+                                                     "`[?`",
+                                                     "`[]`",
+                                                     "a literal",
+                                                     "`@`",
+                                                     "`$`",
+                                                     "a variable",
+                                                     "`&`",
+                                                     "`!`",
+                                                     "`-`",
+                                                     "`(`"];
+
+/// Tokens that can follow a complete expression.
+const EXPRESSION_END: &'static [&'static str] = &["`.`",
+                                                   "`[`",
+                                                   "`[?`",
+                                                   "`[]`",
+                                                   "`|`",
+                                                   "`||`",
+                                                   "`&&`",
+                                                   "a comparator",
+                                                   "an arithmetic operator",
+                                                   "the end of the expression"];
+
+/// Tokens that can follow a dot.
+const DOT_RHS: &'static [&'static str] = &["an identifier",
+                                            "a quoted identifier",
+                                            "`*`",
+                                            "`[`",
+                                            "`{`",
+                                            "`&`"];
+
+/// Tokens that can start a key of a multi-select hash.
+const KEY: &'static [&'static str] = &["an identifier", "a quoted identifier"];
+
+/// Tokens that can start a key of a multi-select hash when computed keys
+/// are allowed.
+const COMPUTED_KEY: &'static [&'static str] = &["an identifier", "a quoted identifier", "`(`"];
+
 struct Parser<'a> {
     /// Parsed tokens
-    token_queue: VecDeque<TokenTuple>,
+    token_queue: VecDeque<TokenTuple<'a>>,
     /// Shared EOF token
-    eof_token: Token,
+    eof_token: Token<'a>,
     /// Expression being parsed
     expr: &'a str,
     /// The current character offset in the expression
     offset: usize,
+    /// End of the last token that was consumed
+    end: usize,
+    /// Current nesting depth
+    depth: usize,
+    /// Maximum nesting depth
+    max_depth: usize,
+    /// Whether multi-select hash keys may be expressions
+    computed_keys: bool,
+    /// Whether errors are recorded and skipped rather than returned
+    recovering: bool,
+    /// Errors recorded while recovering
+    errors: Vec<ParseError>,
 }
 
 impl<'a> Parser<'a> {
-    fn new(tokens: VecDeque<TokenTuple>, expr: &'a str) -> Parser<'a> {
+    fn new(tokens: VecDeque<TokenTuple<'a>>, expr: &'a str) -> Parser<'a> {
         Parser {
             token_queue: tokens,
             eof_token: Token::Eof,
             offset: 0,
+            end: 0,
             expr: expr,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            computed_keys: false,
+            recovering: false,
+            errors: vec![],
         }
     }
 
@@ -50,29 +314,86 @@ impl<'a> Parser<'a> {
                 // After parsing the expr, we should reach the end of the stream.
                 match self.peek(0) {
                     &Token::Eof => Ok(result),
-                    t @ _ => Err(self.err(t, "Did not parse the complete expression", true)),
+                    t @ _ => Err(self.unexpected(t, EXPRESSION_END, true)),
                 }
             })
     }
 
+    /// Parses the whole expression, recording errors and skipping past
+    /// them. Tokens left over after a complete expression are reported and
+    /// skipped, except that a `|` continues the expression.
+    fn parse_all(&mut self) -> Ast {
+        let mut ast = match self.expr(0) {
+            Ok(ast) => ast,
+            Err(err) => self.synchronize(err),
+        };
+        while self.peek(0) != &Token::Eof {
+            if self.peek(0) == &Token::Pipe {
+                ast = match self.led(Box::new(ast)) {
+                    Ok(ast) => ast,
+                    Err(err) => self.synchronize(err),
+                };
+            } else {
+                let err = self.unexpected(self.peek(0), EXPRESSION_END, true);
+                // Synchronization tokens are not skipped on their own.
+                self.advance();
+                self.synchronize(err);
+            }
+        }
+        ast
+    }
+
+    /// Records an error, then skips to the next `,`, `|`, closing bracket,
+    /// or the end of the expression. Returns a placeholder for the skipped
+    /// part of the expression.
+    fn synchronize(&mut self, err: JmespathError) -> Ast {
+        let start = err.offset;
+        // The span covers the token at the error, if any.
+        let mut end = if self.offset == start { self.end } else { start };
+        if let Some(&(ref span, _)) = self.token_queue.front() {
+            if span.start == start {
+                end = span.end;
+            }
+        }
+        loop {
+            match *self.peek(0) {
+                Token::Comma | Token::Pipe | Token::Rbracket | Token::Rbrace | Token::Rparen |
+                Token::Eof => break,
+                _ => {
+                    self.advance();
+                    end = self.end;
+                }
+            }
+        }
+        // An error at the same offset is a consequence of the previous one.
+        if self.errors.last().map_or(true, |e| e.span.start != start) {
+            self.errors.push(ParseError {
+                span: start..end,
+                error: err,
+            });
+        }
+        Ast::Error { offset: start }
+    }
+
     #[inline]
-    fn advance(&mut self) -> Token {
+    fn advance(&mut self) -> Token<'a> {
         self.advance_with_pos().1
     }
 
     #[inline]
-    fn advance_with_pos(&mut self) -> (usize, Token) {
+    fn advance_with_pos(&mut self) -> (usize, Token<'a>) {
         match self.token_queue.pop_front() {
-            Some((pos, tok)) => {
-                self.offset = pos;
-                (pos, tok)
+            Some((span, tok)) => {
+                self.offset = span.start;
+                self.end = span.end;
+                (span.start, tok)
             }
             None => (self.offset, Token::Eof),
         }
     }
 
     #[inline]
-    fn peek(&self, lookahead: usize) -> &Token {
+    fn peek(&self, lookahead: usize) -> &Token<'a> {
         match self.token_queue.get(lookahead) {
             Some(&(_, ref t)) => t,
             None => &self.eof_token,
@@ -81,33 +402,77 @@ impl<'a> Parser<'a> {
 
     /// Returns a formatted error with the given message.
     fn err(&self, current_token: &Token, error_msg: &str, is_peek: bool) -> JmespathError {
-        let mut actual_pos = self.offset;
         let mut buff = error_msg.to_string();
         buff.push_str(&format!(" -- found {:?}", current_token));
-        if is_peek {
-            if let Some(&(p, _)) = self.token_queue.get(0) {
-                actual_pos = p;
-            }
+        JmespathError::new(&self.expr, self.error_pos(is_peek), ErrorReason::Parse(buff))
+    }
+
+    /// Returns an error for an unexpected token, listing the tokens that
+    /// would have been valid instead.
+    fn unexpected(&self, current_token: &Token, expected: &[&str], is_peek: bool) -> JmespathError {
+        let message = format!("Unexpected {}", describe(current_token));
+        let mut err = JmespathError::new(&self.expr,
+                                         self.error_pos(is_peek),
+                                         ErrorReason::Parse(message));
+        err.expected = expected.iter().map(|e| (*e).to_owned()).collect();
+        err
+    }
+
+    /// Returns the offset of the current token, or of the next one when
+    /// peeking.
+    fn error_pos(&self, is_peek: bool) -> usize {
+        match self.token_queue.get(0) {
+            Some(&(ref span, _)) if is_peek => span.start,
+            _ => self.offset,
         }
-        JmespathError::new(&self.expr, actual_pos, ErrorReason::Parse(buff))
     }
 
     /// Main parse function of the Pratt parser that parses while RBP < LBP
     fn expr(&mut self, rbp: usize) -> ParseResult {
+        // Every recursive call of the parser goes through here. The depth
+        // is not restored after an error since parsing stops.
+        if self.depth == self.max_depth {
+            let message = format!("Expression is nested more than {} levels deep",
+                                  self.max_depth);
+            let reason = ErrorReason::Parse(message);
+            return Err(JmespathError::new(&self.expr, self.error_pos(true), reason));
+        }
+        self.depth += 1;
         let mut left = self.nud();
         while rbp < self.peek(0).lbp() {
+            left = self.recovered(left);
             left = self.led(Box::new(try!(left)));
         }
-        left
+        self.depth -= 1;
+        self.recovered(left)
+    }
+
+    /// Replaces an error with a placeholder node if errors are being
+    /// recovered from, or returns it otherwise.
+    fn recovered(&mut self, result: ParseResult) -> ParseResult {
+        match result {
+            Err(err) if self.recovering => Ok(self.synchronize(err)),
+            result => result,
+        }
     }
 
     fn nud(&mut self) -> ParseResult {
         let (offset, token) = self.advance_with_pos();
         match token {
             Token::At => Ok(Ast::Identity { offset: offset }),
+            Token::Dollar => Ok(Ast::RootNode { offset: offset }),
+            Token::Variable(name) => {
+                Ok(Ast::VarRef {
+                    name: name.into_owned(),
+                    offset: offset,
+                })
+            }
+            // "let" is only a keyword when it is followed by a variable.
+            Token::Identifier(ref value) if value == "let" &&
+                                            self.peek(0).is_variable() => self.parse_let(offset),
             Token::Identifier(value) => {
                 Ok(Ast::Field {
-                    name: value,
+                    name: value.into_owned(),
                     offset: offset,
                 })
             }
@@ -119,7 +484,7 @@ impl<'a> Parser<'a> {
                     }
                     _ => {
                         Ok(Ast::Field {
-                            name: value,
+                            name: value.into_owned(),
                             offset: offset,
                         })
                     }
@@ -154,7 +519,7 @@ impl<'a> Parser<'a> {
                         Token::Rbrace => break,
                         // Skip commas as they are used to delineate kvps
                         Token::Comma => continue,
-                        ref t @ _ => return Err(self.err(t, "Expected '}' or ','", false)),
+                        ref t @ _ => return Err(self.unexpected(t, &["`,`", "`}`"], false)),
                     }
                 }
                 Ok(Ast::MultiHash {
@@ -175,15 +540,22 @@ impl<'a> Parser<'a> {
                     offset: offset,
                 })
             }
+            Token::Minus => {
+                // Binds tighter than any binary arithmetic operator.
+                Ok(Ast::Negate {
+                    node: Box::new(try!(self.expr(Token::Star.lbp()))),
+                    offset: offset,
+                })
+            }
             Token::Filter => self.parse_filter(Box::new(Ast::Identity { offset: offset })),
             Token::Lparen => {
                 let result = try!(self.expr(0));
                 match self.advance() {
                     Token::Rparen => Ok(result),
-                    ref t @ _ => Err(self.err(t, "Expected ')' to close '('", false)),
+                    ref t @ _ => Err(self.unexpected(t, &["`)`"], false)),
                 }
             }
-            ref t @ _ => Err(self.err(t, "Unexpected nud token", false)),
+            ref t @ _ => Err(self.unexpected(t, EXPRESSION_START, false)),
         }
     }
 
@@ -210,7 +582,7 @@ impl<'a> Parser<'a> {
                     &Token::Number(_) |
                     &Token::Colon => true,
                     &Token::Star => false,
-                    t @ _ => return Err(self.err(t, "Expected number, ':', or '*'", true)),
+                    t @ _ => return Err(self.unexpected(t, &["a number", "`:`", "`*`"], true)),
                 } {
                     true => {
                         Ok(Ast::Subexpr {
@@ -254,7 +626,8 @@ impl<'a> Parser<'a> {
             }
             Token::Lparen => {
                 match *left {
-                    Ast::Field { name: v, .. } => {
+                    // The function call starts at its name rather than at "(".
+                    Ast::Field { name: v, offset } => {
                         Ok(Ast::Function {
                             offset: offset,
                             name: v,
@@ -272,25 +645,70 @@ impl<'a> Parser<'a> {
             Token::Gte => self.parse_comparator(Comparator::GreaterThanEqual, left),
             Token::Lt => self.parse_comparator(Comparator::LessThan, left),
             Token::Lte => self.parse_comparator(Comparator::LessThanEqual, left),
-            ref t @ _ => Err(self.err(t, "Unexpected led token", false)),
+            t @ Token::Plus |
+            t @ Token::Minus |
+            t @ Token::Star |
+            t @ Token::Slash |
+            t @ Token::DoubleSlash |
+            t @ Token::Percent => self.parse_arithmetic(t, offset, left),
+            ref t @ _ => Err(self.unexpected(t, EXPRESSION_END, false)),
         }
     }
 
+    /// Parses a let expression (e.g., "let $a = foo, $b = bar in baz")
+    fn parse_let(&mut self, offset: usize) -> ParseResult {
+        let mut bindings = vec![];
+        loop {
+            match self.advance() {
+                Token::Variable(name) => {
+                    match self.advance() {
+                        Token::Assign => (),
+                        ref t @ _ => return Err(self.unexpected(t, &["`=`"], false)),
+                    }
+                    bindings.push(LetBinding {
+                        name: name.into_owned(),
+                        value: try!(self.expr(0)),
+                    });
+                }
+                ref t @ _ => return Err(self.unexpected(t, &["a variable"], false)),
+            }
+            match self.advance() {
+                Token::Comma => continue,
+                Token::Identifier(ref value) if value == "in" => break,
+                ref t @ _ => return Err(self.unexpected(t, &["`,`", "`in`"], false)),
+            }
+        }
+        Ok(Ast::Let {
+            offset: offset,
+            bindings: bindings,
+            expr: Box::new(try!(self.expr(0))),
+        })
+    }
+
     fn parse_kvp(&mut self) -> Result<KeyValuePair, JmespathError> {
-        match self.advance() {
+        let key = match self.advance() {
             Token::Identifier(value) |
-            Token::QuotedIdentifier(value) => {
-                if self.peek(0) == &Token::Colon {
-                    self.advance();
-                    Ok(KeyValuePair {
-                        key: value,
-                        value: try!(self.expr(0)),
-                    })
-                } else {
-                    Err(self.err(self.peek(0), "Expected ':' to follow key", true))
+            Token::QuotedIdentifier(value) => Key::Name(value.into_owned()),
+            Token::Lparen if self.computed_keys => {
+                let key = try!(self.expr(0));
+                match self.advance() {
+                    Token::Rparen => Key::Expression(Box::new(key)),
+                    ref t @ _ => return Err(self.unexpected(t, &["`)`"], false)),
                 }
             }
-            ref t @ _ => Err(self.err(t, "Expected Field to start key value pair", false)),
+            ref t @ _ => {
+                let expected = if self.computed_keys { COMPUTED_KEY } else { KEY };
+                return Err(self.unexpected(t, expected, false));
+            }
+        };
+        if self.peek(0) == &Token::Colon {
+            self.advance();
+            Ok(KeyValuePair {
+                key: key,
+                value: try!(self.expr(0)),
+            })
+        } else {
+            Err(self.unexpected(self.peek(0), &["`:`"], true))
         }
     }
 
@@ -298,6 +716,8 @@ impl<'a> Parser<'a> {
     /// side of the projection using a Condition node. If the Condition node
     /// returns a truthy value, then the value is yielded by the projection.
     fn parse_filter(&mut self, lhs: Box<Ast>) -> ParseResult {
+        // The projection starts at the filter token.
+        let offset = self.offset;
         // Parse the LHS of the condition node.
         let condition_lhs = Box::new(try!(self.expr(0)));
         // Eat the closing bracket.
@@ -305,25 +725,26 @@ impl<'a> Parser<'a> {
             Token::Rbracket => {
                 let condition_rhs = Box::new(try!(self.projection_rhs(Token::Filter.lbp())));
                 Ok(Ast::Projection {
-                    offset: self.offset,
+                    offset: offset,
                     lhs: lhs,
                     rhs: Box::new(Ast::Condition {
-                        offset: self.offset,
+                        offset: offset,
                         predicate: condition_lhs,
                         then: condition_rhs,
                     }),
                 })
             }
-            ref t @ _ => Err(self.err(t, "Expected ']'", false)),
+            ref t @ _ => Err(self.unexpected(t, &["`]`"], false)),
         }
     }
 
     fn parse_flatten(&mut self, lhs: Box<Ast>) -> ParseResult {
+        let offset = self.offset;
         let rhs = Box::new(try!(self.projection_rhs(Token::Flatten.lbp())));
         Ok(Ast::Projection {
-            offset: self.offset,
+            offset: offset,
             lhs: Box::new(Ast::Flatten {
-                offset: self.offset,
+                offset: offset,
                 node: lhs,
             }),
             rhs: rhs,
@@ -341,6 +762,18 @@ impl<'a> Parser<'a> {
         })
     }
 
+    /// Parses an arithmetic operator token into an Arithmetic node
+    /// (e.g., foo + bar)
+    fn parse_arithmetic(&mut self, token: Token, offset: usize, lhs: Box<Ast>) -> ParseResult {
+        let rhs = Box::new(try!(self.expr(token.lbp())));
+        Ok(Ast::Arithmetic {
+            offset: offset,
+            operator: ArithmeticOperator::from(token),
+            lhs: lhs,
+            rhs: rhs,
+        })
+    }
+
     /// Parses the right hand side of a dot expression.
     fn parse_dot(&mut self, lbp: usize) -> ParseResult {
         match match self.peek(0) {
@@ -350,9 +783,7 @@ impl<'a> Parser<'a> {
             &Token::Star |
             &Token::Lbrace |
             &Token::Ampersand => false,
-            t @ _ => {
-                return Err(self.err(t, "Expected identifier, '*', '{', '[', '&', or '[?'", true))
-            }
+            t @ _ => return Err(self.unexpected(t, DOT_RHS, true)),
         } {
             true => {
                 self.advance();
@@ -372,7 +803,7 @@ impl<'a> Parser<'a> {
                 return Ok(Ast::Identity { offset: self.offset });
             },
             ref t @ _ => {
-                return Err(self.err(t, "Expected '.', '[', or '[?'", true));
+                return Err(self.unexpected(t, &["`.`", "`[`", "`[?`"], true));
             },
         } {
             true => {
@@ -385,26 +816,28 @@ impl<'a> Parser<'a> {
 
     /// Creates a projection for "[*]"
     fn parse_wildcard_index(&mut self, lhs: Box<Ast>) -> ParseResult {
+        let offset = self.offset;
         match self.advance() {
             Token::Rbracket => {
-                let rhs = Box::new(try!(self.projection_rhs(Token::Star.lbp())));
+                let rhs = Box::new(try!(self.projection_rhs(PROJECTION_RBP)));
                 Ok(Ast::Projection {
-                    offset: self.offset,
+                    offset: offset,
                     lhs: lhs,
                     rhs: rhs,
                 })
             }
-            ref t @ _ => Err(self.err(t, "Expected ']' for wildcard index", false)),
+            ref t @ _ => Err(self.unexpected(t, &["`]`"], false)),
         }
     }
 
     /// Creates a projection for "*"
     fn parse_wildcard_values(&mut self, lhs: Box<Ast>) -> ParseResult {
-        let rhs = Box::new(try!(self.projection_rhs(Token::Star.lbp())));
+        let offset = self.offset;
+        let rhs = Box::new(try!(self.projection_rhs(PROJECTION_RBP)));
         Ok(Ast::Projection {
-            offset: self.offset,
+            offset: offset,
             lhs: Box::new(Ast::ObjectValues {
-                offset: self.offset,
+                offset: offset,
                 node: lhs,
             }),
             rhs: rhs,
@@ -413,6 +846,7 @@ impl<'a> Parser<'a> {
 
     /// Parses [0], [::-1], [0:-1], [0:1], etc...
     fn parse_index(&mut self) -> ParseResult {
+        let offset = self.offset;
         let mut parts = [None, None, None];
         let mut pos = 0;
         loop {
@@ -421,7 +855,7 @@ impl<'a> Parser<'a> {
                     parts[pos] = Some(value);
                     match self.peek(0) {
                         &Token::Colon | &Token::Rbracket => (),
-                        t @ _ => return Err(self.err(t, "Expected ':', or ']'", true)),
+                        t @ _ => return Err(self.unexpected(t, &["`:`", "`]`"], true)),
                     };
                 }
                 Token::Rbracket => break,
@@ -434,10 +868,12 @@ impl<'a> Parser<'a> {
                         &Token::Number(_) |
                         &Token::Colon |
                         &Token::Rbracket => continue,
-                        ref t @ _ => return Err(self.err(t, "Expected number, ':', or ']'", true)),
+                        ref t @ _ => {
+                            return Err(self.unexpected(t, &["a number", "`:`", "`]`"], true))
+                        }
                     };
                 }
-                ref t @ _ => return Err(self.err(t, "Expected number, ':', or ']'", false)),
+                ref t @ _ => return Err(self.unexpected(t, &["a number", "`:`", "`]`"], false)),
             }
         }
 
@@ -450,14 +886,14 @@ impl<'a> Parser<'a> {
         } else {
             // Sliced array from start (e.g., [2:])
             Ok(Ast::Projection {
-                offset: self.offset,
+                offset: offset,
                 lhs: Box::new(Ast::Slice {
-                    offset: self.offset,
+                    offset: offset,
                     start: parts[0],
                     stop: parts[1],
                     step: parts[2].unwrap_or(1),
                 }),
-                rhs: Box::new(try!(self.projection_rhs(Token::Star.lbp()))),
+                rhs: Box::new(try!(self.projection_rhs(PROJECTION_RBP))),
             })
         }
     }
@@ -485,11 +921,490 @@ impl<'a> Parser<'a> {
             if self.peek(0) == &Token::Comma {
                 self.advance();
                 if self.peek(0) == &closing {
-                    return Err(self.err(self.peek(0), "invalid token after ','", true));
+                    return Err(self.unexpected(self.peek(0), EXPRESSION_START, true));
                 }
+            } else if self.peek(0) != &closing {
+                let closing = describe(&closing);
+                return Err(self.unexpected(self.peek(0), &["`,`", &closing], true));
             }
         }
         self.advance();
         Ok(nodes)
     }
 }
+
+/// Describes a token for error messages.
+fn describe(token: &Token) -> String {
+    let symbol = match *token {
+        Token::Identifier(ref name) => return format!("identifier `{}`", name),
+        Token::QuotedIdentifier(ref name) => return format!("quoted identifier {:?}", name),
+        Token::Variable(ref name) => return format!("variable `${}`", name),
+        Token::Number(value) => return format!("number `{}`", value),
+        Token::Literal(_) => return "literal".to_owned(),
+        Token::Eof => return "the end of the expression".to_owned(),
+        Token::Dot => ".",
+        Token::Star => "*",
+        Token::Flatten => "[]",
+        Token::Plus => "+",
+        Token::Minus => "-",
+        Token::Slash => "/",
+        Token::DoubleSlash => "//",
+        Token::Percent => "%",
+        Token::And => "&&",
+        Token::Or => "||",
+        Token::Pipe => "|",
+        Token::Filter => "[?",
+        Token::Lbracket => "[",
+        Token::Rbracket => "]",
+        Token::Comma => ",",
+        Token::Colon => ":",
+        Token::Not => "!",
+        Token::Ne => "!=",
+        Token::Eq => "==",
+        Token::Gt => ">",
+        Token::Gte => ">=",
+        Token::Lt => "<",
+        Token::Lte => "<=",
+        Token::At => "@",
+        Token::Dollar => "$",
+        Token::Assign => "=",
+        Token::Ampersand => "&",
+        Token::Lparen => "(",
+        Token::Rparen => ")",
+        Token::Lbrace => "{",
+        Token::Rbrace => "}",
+    };
+    format!("`{}`", symbol)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Renders the operator structure of a parsed expression.
+    fn shape(node: &Ast) -> String {
+        match *node {
+            Ast::Arithmetic { ref operator, ref lhs, ref rhs, .. } => {
+                format!("{}({}, {})", operator.as_str(), shape(lhs), shape(rhs))
+            }
+            Ast::Negate { ref node, .. } => format!("neg({})", shape(node)),
+            Ast::Projection { ref lhs, ref rhs, .. } => {
+                format!("project({}, {})", shape(lhs), shape(rhs))
+            }
+            Ast::And { ref lhs, ref rhs, .. } => format!("and({}, {})", shape(lhs), shape(rhs)),
+            Ast::Or { ref lhs, ref rhs, .. } => format!("or({}, {})", shape(lhs), shape(rhs)),
+            Ast::Not { ref node, .. } => format!("not({})", shape(node)),
+            Ast::Comparison { ref lhs, ref rhs, .. } => {
+                format!("cmp({}, {})", shape(lhs), shape(rhs))
+            }
+            ref node => node.to_string(),
+        }
+    }
+
+    fn parsed(expr: &str) -> String {
+        shape(&parse(expr).unwrap())
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!("or(a, and(b, c))", parsed("a || b && c"));
+        assert_eq!("or(and(a, b), c)", parsed("a && b || c"));
+        assert_eq!("and(or(a, b), c)", parsed("(a || b) && c"));
+    }
+
+    #[test]
+    fn and_is_left_associative() {
+        assert_eq!("and(and(a, b), c)", parsed("a && b && c"));
+    }
+
+    #[test]
+    fn comparisons_bind_tighter_than_and() {
+        assert_eq!("and(a, cmp(b, c))", parsed("a && b == c"));
+        assert_eq!("and(cmp(a, b), cmp(c, d))", parsed("a < b && c != d"));
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and() {
+        assert_eq!("and(not(a), b)", parsed("!a && b"));
+        assert_eq!("not(and(a, b))", parsed("!(a && b)"));
+    }
+
+    #[test]
+    fn parses_and_inside_filters() {
+        match parse("foo[?a && b]").unwrap() {
+            Ast::Projection { ref rhs, .. } => match **rhs {
+                Ast::Condition { ref predicate, .. } => {
+                    assert_eq!("and(a, b)", shape(predicate));
+                }
+                ref node => panic!("expected a condition: {:?}", node),
+            },
+            ref node => panic!("expected a projection: {:?}", node),
+        }
+    }
+
+    #[test]
+    fn not_binds_tighter_than_comparisons() {
+        assert_eq!("cmp(not(a), b)", parsed("!a == b"));
+        assert_eq!("cmp(a, not(b))", parsed("a != !b"));
+        assert_eq!("not(cmp(a, b))", parsed("!(a == b)"));
+    }
+
+    #[test]
+    fn parses_double_negation() {
+        assert_eq!("not(not(a))", parsed("!!a"));
+        assert_eq!("or(not(not(a)), b)", parsed("!!a || b"));
+    }
+
+    #[test]
+    fn multiplicative_operators_bind_tighter_than_additive() {
+        assert_eq!("+(a, *(b, c))", parsed("a + b * c"));
+        assert_eq!("-(/(a, b), c)", parsed("a / b - c"));
+        assert_eq!("+(a, %(b, c))", parsed("a + b % c"));
+        assert_eq!("-(a, //(b, c))", parsed("a - b // c"));
+        assert_eq!("*(+(a, b), c)", parsed("(a + b) * c"));
+    }
+
+    #[test]
+    fn arithmetic_is_left_associative() {
+        assert_eq!("-(-(a, b), c)", parsed("a - b - c"));
+        assert_eq!("+(-(a, b), c)", parsed("a - b + c"));
+        assert_eq!("/(*(a, b), c)", parsed("a * b / c"));
+        assert_eq!("%(//(a, b), c)", parsed("a // b % c"));
+        assert_eq!("-(a, -(b, c))", parsed("a - (b - c)"));
+    }
+
+    #[test]
+    fn arithmetic_binds_tighter_than_comparisons() {
+        assert_eq!("cmp(+(a, b), *(c, d))", parsed("a + b == c * d"));
+        assert_eq!("or(cmp(/(a, b), c), d)", parsed("a / b > c || d"));
+        assert_eq!("and(not(a), -(b, c))", parsed("!a && b - c"));
+    }
+
+    #[test]
+    fn unary_minus_binds_tighter_than_binary_operators() {
+        assert_eq!("neg(a)", parsed("-a"));
+        assert_eq!("*(neg(a), b)", parsed("-a * b"));
+        assert_eq!("-(a, neg(b))", parsed("a - -b"));
+        assert_eq!("neg(neg(a))", parsed("--a"));
+        assert_eq!("neg(a.b)", parsed("-a.b"));
+        assert_eq!("cmp(neg(a), b)", parsed("-a == b"));
+    }
+
+    #[test]
+    fn arithmetic_stops_projections() {
+        assert_eq!("*(project(a, b), c)", parsed("a[*].b * c"));
+        assert_eq!("+(project(a[], @), c)", parsed("a[] + c"));
+        assert_eq!("project(*, b)", parsed("*.b"));
+        assert_eq!("project(a, b)", parsed("a[*].b"));
+    }
+
+    #[test]
+    fn rejects_incomplete_arithmetic() {
+        assert!(parse("a +").is_err());
+        assert!(parse("* b").is_err());
+        assert!(parse("a // / b").is_err());
+        assert!(parse("foo.-a").is_err());
+    }
+
+    #[test]
+    fn parses_commented_expressions() {
+        let options = ParseOptions { comments: true, ..ParseOptions::default() };
+        let expr = "people[?age > `20` # adults\n  && !retired] # still working\n.name # names";
+        let ast = parse_with_options(expr, &options).unwrap();
+        assert_eq!("people[?age > `20` && !retired].name", ast.to_string());
+        assert!(parse(expr).is_err());
+    }
+
+    fn expected(expr: &str) -> Vec<String> {
+        parse(expr).unwrap_err().expected
+    }
+
+    #[test]
+    fn lists_expected_tokens_at_expression_start() {
+        assert_eq!(EXPRESSION_START, &expected("]")[..]);
+        assert_eq!(EXPRESSION_START, &expected("")[..]);
+    }
+
+    #[test]
+    fn lists_expected_tokens_after_dot() {
+        assert_eq!(DOT_RHS, &expected("foo.]")[..]);
+        assert_eq!(DOT_RHS, &expected("foo.[?a]")[..]);
+    }
+
+    #[test]
+    fn lists_expected_tokens_after_filter() {
+        assert_eq!(EXPRESSION_START, &expected("foo[?]")[..]);
+        assert_eq!(vec!["`]`"], expected("foo[?a b]"));
+    }
+
+    #[test]
+    fn lists_expected_tokens_after_comma_in_multi_selects() {
+        assert_eq!(EXPRESSION_START, &expected("[a, ]")[..]);
+        assert_eq!(EXPRESSION_START, &expected("[a, ,]")[..]);
+        assert_eq!(KEY, &expected("{a: b, }")[..]);
+        assert_eq!(vec!["`,`", "`}`"], expected("{a: b c}"));
+    }
+
+    #[test]
+    fn lists_expected_tokens_after_comparator() {
+        assert_eq!(EXPRESSION_START, &expected("a == ]")[..]);
+        assert_eq!(EXPRESSION_START, &expected("a < ")[..]);
+    }
+
+    #[test]
+    fn lists_expected_tokens_in_brackets() {
+        assert_eq!(vec!["a number", "`:`", "`*`"], expected("a[b]"));
+        assert_eq!(vec!["`:`", "`]`"], expected("[0 1]"));
+        assert_eq!(vec!["a number", "`:`", "`]`"], expected("[0:a]"));
+        assert_eq!(vec!["`,`", "`]`"], expected("[*"));
+        assert_eq!(vec!["`,`", "`]`"], expected("[a b]"));
+        assert_eq!(vec!["`,`", "`)`"], expected("foo(a b)"));
+        assert_eq!(vec!["`.`", "`[`", "`[?`"], expected("a[*]{b: c}"));
+        assert_eq!(EXPRESSION_END, &expected("a b")[..]);
+        assert!(expected("@(a)").is_empty());
+    }
+
+    #[test]
+    fn displays_the_unexpected_token_and_expected_tokens() {
+        let err = parse("foo.[?a]").unwrap_err();
+        assert_eq!(4, err.offset);
+        assert_eq!("Parse error: Unexpected `[?`; expected an identifier, a quoted identifier, \
+                    `*`, `[`, `{`, or `&` (line 0, column 4)\nfoo.[?a]\n    ^\n",
+                   err.to_string());
+        let err = parse("[a, b").unwrap_err();
+        assert!(err.to_string().starts_with("Parse error: Unexpected the end of the expression;"));
+    }
+
+    fn parse_with_depth(expr: &str, max_depth: usize) -> ParseResult {
+        let options = ParseOptions { max_depth: max_depth, ..ParseOptions::default() };
+        parse_with_options(expr, &options)
+    }
+
+    #[test]
+    fn limits_the_nesting_depth() {
+        // Each pair of parentheses adds a level to the top-level expression.
+        let nested = |levels: usize| format!("{}a{}", "(".repeat(levels), ")".repeat(levels));
+        assert!(parse_with_depth(&nested(9), 10).is_ok());
+        let err = parse_with_depth(&nested(10), 10).unwrap_err();
+        assert_eq!(10, err.offset);
+        assert!(err.to_string().contains("Expression is nested more than 10 levels deep"));
+        assert!(parse_with_depth("a", 1).is_ok());
+        assert!(parse_with_depth("a", 0).is_err());
+    }
+
+    #[test]
+    fn long_chains_do_not_nest() {
+        let fields = (0..1000).map(|i| format!("f{}", i)).collect::<Vec<_>>();
+        assert!(parse(&fields.join(".")).is_ok());
+        assert!(parse(&fields.join(" || ")).is_ok());
+        assert!(parse(&fields.join(" | ")).is_ok());
+    }
+
+    #[test]
+    fn parses_deep_projections_within_the_default_limit() {
+        // The "deep projection 104" benchmark nests a level per projection.
+        let fields = (0..104).map(|i| format!("f{}[*]", i)).collect::<Vec<_>>();
+        let expr = fields.join(".");
+        assert!(parse(&expr).is_ok());
+        assert!(parse_with_depth(&expr, 104).is_ok());
+        assert!(parse_with_depth(&expr, 103).is_err());
+    }
+
+    #[test]
+    fn rejects_pathological_nesting_without_overflowing_the_stack() {
+        for expr in &["a(".repeat(10000), "[".repeat(10000), "!".repeat(100000),
+                      "{a: ".repeat(10000), "a[?".repeat(10000)] {
+            let err = parse(expr).unwrap_err();
+            assert!(err.to_string().contains("levels deep"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn records_offsets_of_function_calls_at_their_names() {
+        match parse("foo.length(bar)").unwrap() {
+            Ast::Subexpr { offset: 3, ref rhs, .. } => {
+                match **rhs {
+                    Ast::Function { offset, ref args, .. } => {
+                        assert_eq!(4, offset);
+                        assert_eq!(11, args[0].offset());
+                    }
+                    ref node => panic!("unexpected node: {:?}", node),
+                }
+            }
+            node => panic!("unexpected node: {:?}", node),
+        }
+    }
+
+    #[test]
+    fn records_offsets_inside_projections_and_exprefs() {
+        let function = match parse("a[*].sort_by(@, &b.c)").unwrap() {
+            Ast::Projection { rhs, .. } => *rhs,
+            node => panic!("unexpected node: {:?}", node),
+        };
+        match function {
+            Ast::Function { offset: 5, ref args, .. } => {
+                assert_eq!(13, args[0].offset());
+                match args[1] {
+                    Ast::Expref { offset: 16, ref ast } => {
+                        assert_eq!(18, ast.offset());
+                        match **ast {
+                            Ast::Subexpr { ref lhs, ref rhs, .. } => {
+                                assert_eq!((17, 19), (lhs.offset(), rhs.offset()))
+                            }
+                            ref node => panic!("unexpected node: {:?}", node),
+                        }
+                    }
+                    ref node => panic!("unexpected node: {:?}", node),
+                }
+            }
+            node => panic!("unexpected node: {:?}", node),
+        }
+    }
+
+    #[test]
+    fn parses_let_expressions() {
+        match parse("let $a = b, $c = d in $a").unwrap() {
+            Ast::Let { offset: 0, ref bindings, ref expr } => {
+                let names = bindings.iter().map(|b| b.name.as_str()).collect::<Vec<_>>();
+                assert_eq!(vec!["a", "c"], names);
+                assert_eq!(Ast::Field { offset: 9, name: "b".to_owned() }, bindings[0].value);
+                assert_eq!(Ast::VarRef { offset: 22, name: "a".to_owned() }, **expr);
+            }
+            node => panic!("unexpected node: {:?}", node),
+        }
+        // The body extends as far as possible.
+        match parse("let $a = b in c || d").unwrap() {
+            Ast::Let { ref expr, .. } => assert_eq!("c || d", expr.to_string()),
+            node => panic!("unexpected node: {:?}", node),
+        }
+    }
+
+    #[test]
+    fn let_and_in_are_only_keywords_in_let_expressions() {
+        assert_eq!(Ast::Field { offset: 0, name: "let".to_owned() }, parse("let").unwrap());
+        assert_eq!("let.in", parse("let.in").unwrap().to_string());
+        assert_eq!(vec!["`=`"], expected("let $a b"));
+        assert_eq!(vec!["`,`", "`in`"], expected("let $a = b c"));
+        assert_eq!(vec!["a variable"], expected("let $a = b, in c"));
+        assert_eq!(EXPRESSION_END, &expected("let a")[..]);
+    }
+
+    fn recovered(expr: &str) -> (Option<String>, Vec<(Range<usize>, String)>) {
+        let (ast, errors) = parse_recoverable(expr);
+        (ast.map(|ast| ast.to_string()),
+         errors.into_iter().map(|e| (e.span, e.error.reason.to_string())).collect())
+    }
+
+    #[test]
+    fn recovers_from_an_unclosed_bracket_and_a_bad_comparator() {
+        let expr = "foo[0 | bar <> baz";
+        let (ast, errors) = parse_recoverable(expr);
+        match ast.unwrap() {
+            Ast::Subexpr { ref lhs, ref rhs, .. } => {
+                assert_eq!(Ast::Error { offset: 6 }, **lhs);
+                assert_eq!("cmp(bar, <error>)", shape(rhs));
+            }
+            node => panic!("unexpected node: {:?}", node),
+        }
+        assert_eq!(2, errors.len());
+        assert_eq!(6..7, errors[0].span);
+        assert_eq!(vec!["`:`", "`]`"], errors[0].error.expected);
+        assert_eq!(parse(expr).unwrap_err(), errors[0].error);
+        assert_eq!(13..18, errors[1].span);
+        assert_eq!((0, 13), (errors[1].error.line, errors[1].error.column));
+        assert_eq!("Parse error: Unexpected `>`", errors[1].error.reason.to_string());
+    }
+
+    #[test]
+    fn recovers_inside_of_lists() {
+        let (ast, errors) = recovered("[a, , b].c");
+        assert_eq!(Some("[a, <error>].c".to_owned()), ast);
+        assert_eq!(vec![4..7], errors.into_iter().map(|e| e.0).collect::<Vec<_>>());
+        let (ast, errors) = recovered("f(a ]");
+        assert_eq!(None, ast);
+        assert_eq!(vec![4..5], errors.into_iter().map(|e| e.0).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn skips_tokens_after_a_complete_expression() {
+        let (ast, errors) = recovered("foo ] bar | baz");
+        assert_eq!(Some("foo.baz".to_owned()), ast);
+        assert_eq!(vec![4..9], errors.into_iter().map(|e| e.0).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn reports_lexical_errors_in_order() {
+        let (ast, errors) = recovered("a ~ b[?c <]");
+        assert_eq!(Some("a".to_owned()), ast);
+        let offsets = errors.iter().map(|e| e.0.start).collect::<Vec<_>>();
+        assert_eq!(vec![2, 4, 10], offsets);
+        assert_eq!("Parse error: Invalid character: ~", errors[0].1);
+    }
+
+    #[test]
+    fn recovering_does_not_change_valid_expressions() {
+        for expr in &["a.b[0] | c", "[a, {b: c}][?d > `1`].e", "let $x = a in $x"] {
+            assert_eq!((Some(parse(expr).unwrap()), vec![]), parse_recoverable(expr));
+        }
+        assert_eq!((None, vec![(0..0, "Parse error: Unexpected the end of the expression"
+                        .to_owned())]),
+                   recovered(""));
+    }
+
+    #[test]
+    fn parses_computed_keys_only_when_enabled() {
+        assert_eq!(KEY, &expected("{(a): b}")[..]);
+        let options = ParseOptions { computed_keys: true, ..ParseOptions::default() };
+        let ast = parse_with_options("{(a.b): c, d: e}", &options).unwrap();
+        match ast {
+            Ast::MultiHash { ref elements, .. } => {
+                match elements[0].key {
+                    Key::Expression(ref key) => assert_eq!(3, key.offset()),
+                    ref key => panic!("unexpected key: {:?}", key),
+                }
+                assert_eq!(Key::Name("d".to_owned()), elements[1].key);
+            }
+            ref node => panic!("unexpected node: {:?}", node),
+        }
+        assert_eq!("{(a.b): c, d: e}", ast.to_string());
+        let err = parse_with_options("{`1`: a}", &options).unwrap_err();
+        assert_eq!(COMPUTED_KEY, &err.expected[..]);
+        let err = parse_with_options("{(a b): c}", &options).unwrap_err();
+        assert_eq!(vec!["`)`"], err.expected);
+    }
+
+    fn invalid(expr: &str) -> (Range<usize>, String) {
+        let err = validate(expr).unwrap_err();
+        (err.span, err.error.reason.to_string())
+    }
+
+    #[test]
+    fn validates_expressions_without_evaluating_them() {
+        for expr in &["foo.bar", "a[?b > `1`].c | sort_by(@, &d)", "merge(a, b, c)"] {
+            assert_eq!(Ok(()), validate(expr));
+        }
+        assert_eq!((4..5, "Parse error: Unexpected `]`".to_owned()), invalid("foo.]"));
+        assert_eq!((4..5, "Parse error: Invalid character: ~".to_owned()), invalid("foo ~ bar"));
+        assert_eq!(parse("foo.]").unwrap_err(), validate("foo.]").unwrap_err().error);
+    }
+
+    #[test]
+    fn validates_function_names_and_arities() {
+        assert_eq!((4..10, "Runtime error: Call to undefined function lenght".to_owned()),
+                   invalid("foo.lenght(bar)"));
+        assert_eq!((12..18, "Runtime error: Call to undefined function lenght".to_owned()),
+                   invalid("sort_by(@, &lenght(a))"));
+        assert_eq!((0..6, "Runtime error: Too many arguments: expected 1, found 2".to_owned()),
+                   invalid("length(a, b)"));
+        assert_eq!((5..10, "Runtime error: Not enough arguments: expected 1, found 0".to_owned()),
+                   invalid("a || merge()"));
+        // Optional arguments count towards the maximum.
+        assert_eq!(Ok(()), validate("round(a, `2`)"));
+        assert_eq!((0..5, "Runtime error: Too many arguments: expected 2, found 3".to_owned()),
+                   invalid("round(`1`, `2`, `3`)"));
+        assert_eq!((0..5, "Runtime error: Too many arguments: expected 3, found 4".to_owned()),
+                   invalid("split('a', ',', `1`, `2`)"));
+        let err = validate("a |\n abs()").unwrap_err().error;
+        assert_eq!((1, 1), (err.line, err.column));
+    }
+}
@@ -0,0 +1,184 @@
+//! Restricts the constructs that a parsed expression may use.
+//!
+//! Expressions from untrusted sources can be parsed as usual and then
+//! checked against a `Policy` before they are evaluated. Function calls,
+//! expression references, and projections dominate the cost of evaluating
+//! an expression, so each of them can be limited.
+
+use std::error::Error;
+use std::fmt;
+
+use ast::Ast;
+
+/// Constructs that an expression is allowed to use.
+///
+/// The default policy allows everything.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Policy {
+    /// Allows function calls. Defaults to `true`.
+    pub functions: bool,
+    /// Names of the functions that may be called, or `None` to allow any
+    /// function. Only applies when `functions` is `true`.
+    pub allowed_functions: Option<Vec<String>>,
+    /// Allows expression references (e.g., `&foo`). Defaults to `true`.
+    pub exprefs: bool,
+    /// Allows filter projections (e.g., `foo[?bar]`). Defaults to `true`.
+    pub filters: bool,
+    /// Maximum number of projections in the expression, counting
+    /// wildcards, slices, flattens, and filters, or `None` for no limit.
+    pub max_projections: Option<usize>,
+}
+
+impl Default for Policy {
+    fn default() -> Policy {
+        Policy {
+            functions: true,
+            allowed_functions: None,
+            exprefs: true,
+            filters: true,
+            max_projections: None,
+        }
+    }
+}
+
+impl Policy {
+    /// Returns true if the policy allows calling the named function.
+    pub fn allows_function(&self, name: &str) -> bool {
+        self.functions &&
+        self.allowed_functions.as_ref().map_or(true, |names| names.iter().any(|n| n == name))
+    }
+}
+
+/// A construct that is not allowed by a `Policy`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct PolicyViolation {
+    /// Byte offset of the construct in the parsed expression.
+    pub offset: usize,
+    /// Description of the construct that is not allowed.
+    pub message: String,
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(fmt, "{} (at offset {})", self.message, self.offset)
+    }
+}
+
+impl Error for PolicyViolation {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+/// Checks that an expression only uses the constructs allowed by a policy,
+/// returning the first violation in the expression.
+///
+/// ```
+/// use jmespath::Policy;
+///
+/// let ast = jmespath::parse("sort_by(people, &age)").unwrap();
+/// let policy = Policy { functions: false, ..Policy::default() };
+/// let violation = jmespath::check_policy(&ast, &policy).unwrap_err();
+/// assert_eq!("Calls to function `sort_by` are not allowed (at offset 0)",
+///            violation.to_string());
+/// ```
+pub fn check_policy(ast: &Ast, policy: &Policy) -> Result<(), PolicyViolation> {
+    let mut projections = 0;
+    ast.walk(&mut |node| {
+        match *node {
+            Ast::Function { offset, ref name, .. } if !policy.allows_function(name) => {
+                violation(offset, format!("Calls to function `{}` are not allowed", name))
+            }
+            Ast::Expref { offset, .. } if !policy.exprefs => {
+                violation(offset, "Expression references are not allowed".to_owned())
+            }
+            Ast::Condition { offset, .. } if !policy.filters => {
+                violation(offset, "Filter projections are not allowed".to_owned())
+            }
+            Ast::Projection { offset, .. } => {
+                projections += 1;
+                match policy.max_projections {
+                    Some(max) if projections > max => {
+                        violation(offset,
+                                  format!("Expressions may contain at most {} projections", max))
+                    }
+                    _ => Ok(()),
+                }
+            }
+            _ => Ok(()),
+        }
+    })
+}
+
+fn violation(offset: usize, message: String) -> Result<(), PolicyViolation> {
+    Err(PolicyViolation {
+        offset: offset,
+        message: message,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {parse, parse_with_options, ParseOptions};
+
+    fn check(expr: &str, policy: &Policy) -> Result<(), String> {
+        check_policy(&parse(expr).unwrap(), policy).map_err(|v| v.to_string())
+    }
+
+    #[test]
+    fn allows_everything_by_default() {
+        let expr = "a[*].b[?c > `1`] | sort_by(@, &d)[].e[1:] | {f: *.g}";
+        assert_eq!(Ok(()), check(expr, &Policy::default()));
+    }
+
+    #[test]
+    fn rejects_function_calls() {
+        let policy = Policy { functions: false, ..Policy::default() };
+        assert_eq!(Err("Calls to function `sort_by` are not allowed (at offset 4)".to_owned()),
+                   check("foo.sort_by(people, &age)", &policy));
+        assert_eq!(Ok(()), check("foo.people[0].age", &policy));
+        let options = ParseOptions { computed_keys: true, ..ParseOptions::default() };
+        let ast = parse_with_options("{(to_string(a)): b}", &options).unwrap();
+        assert_eq!(2, check_policy(&ast, &policy).unwrap_err().offset);
+    }
+
+    #[test]
+    fn allows_listed_functions() {
+        let policy = Policy {
+            allowed_functions: Some(vec!["sort_by".to_owned()]),
+            ..Policy::default()
+        };
+        assert_eq!(Ok(()), check("foo.sort_by(people, &age)", &policy));
+        assert_eq!(Err("Calls to function `length` are not allowed (at offset 17)".to_owned()),
+                   check("sort_by(people, &length(name))", &policy));
+        // The allowlist does not apply when functions are not allowed.
+        let policy = Policy { functions: false, ..policy };
+        assert!(check("foo.sort_by(people, &age)", &policy).is_err());
+    }
+
+    #[test]
+    fn rejects_expression_references() {
+        let policy = Policy { exprefs: false, ..Policy::default() };
+        assert_eq!(Err("Expression references are not allowed (at offset 16)".to_owned()),
+                   check("sort_by(people, &age)", &policy));
+    }
+
+    #[test]
+    fn rejects_filters() {
+        let policy = Policy { filters: false, ..Policy::default() };
+        assert_eq!(Err("Filter projections are not allowed (at offset 7)".to_owned()),
+                   check("{a: foo[?bar].baz}", &policy));
+        assert_eq!(Ok(()), check("{a: foo[*].baz}", &policy));
+    }
+
+    #[test]
+    fn limits_the_number_of_projections() {
+        let policy = Policy { max_projections: Some(2), ..Policy::default() };
+        assert_eq!(Ok(()), check("a[*].b[]", &policy));
+        assert_eq!(Err("Expressions may contain at most 2 projections (at offset 10)".to_owned()),
+                   check("a[*].b[].c[:2]", &policy));
+        assert_eq!(Err("Expressions may contain at most 2 projections (at offset 25)".to_owned()),
+                   check("let $x = *.a in [$x[*], b[?c]]", &policy));
+    }
+}
@@ -0,0 +1,327 @@
+//! Relates a search to the paths of the values in its document.
+//!
+//! A search with provenance records the path of each field and index that it
+//! looks up in the document, including the lookups of the expression
+//! references that functions evaluate. The paths tell which parts of the
+//! document a result depends on, e.g., to audit a search or to invalidate a
+//! cached result when the document changes.
+//!
+//! Locating a search finds the paths of the values in its result instead,
+//! which tells where the matches of an expression live in the document.
+
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::fmt;
+
+use ast::identifier;
+use variable::Variable;
+
+/// Location of a value in a document, as the keys and indices that lead to
+/// it from the root of the document.
+///
+/// Paths are displayed as the expressions that select them (e.g.,
+/// `foo.bar[3].x`), and the root of the document as `@`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Path(pub Vec<PathSegment>);
+
+/// Step of a `Path` into an object or an array.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PathSegment {
+    /// Key of an object.
+    Key(String),
+    /// Index of an array.
+    Index(usize),
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        if self.0.is_empty() {
+            return write!(fmt, "@");
+        }
+        for (i, segment) in self.0.iter().enumerate() {
+            match *segment {
+                PathSegment::Key(ref key) if i == 0 => try!(write!(fmt, "{}", identifier(key))),
+                PathSegment::Key(ref key) => try!(write!(fmt, ".{}", identifier(key))),
+                PathSegment::Index(index) => try!(write!(fmt, "[{}]", index)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Paths of the values in a document.
+///
+/// Values are identified by their address, which the document keeps alive
+/// for as long as it is searched. A value that is shared by several parts of
+/// the document has one of its paths.
+struct Document {
+    /// Parent and segment of each value in the document. The root has none.
+    parents: HashMap<usize, Option<(usize, PathSegment)>>,
+}
+
+impl Document {
+    fn new(root: &Variable) -> Document {
+        let mut parents = HashMap::new();
+        parents.insert(address(root), None);
+        // Documents can be nested more deeply than the stack allows.
+        let mut pending = vec![root];
+        while let Some(value) = pending.pop() {
+            match *value {
+                Variable::Array(ref array) => {
+                    for (i, child) in array.iter().enumerate() {
+                        visit(&mut parents, &mut pending, value, PathSegment::Index(i), child);
+                    }
+                }
+                Variable::Object(ref map) => {
+                    for (key, child) in map {
+                        let segment = PathSegment::Key(key.clone());
+                        visit(&mut parents, &mut pending, value, segment, child);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Document { parents: parents }
+    }
+
+    /// Returns the path of a value followed by a segment, or None if the
+    /// value is not part of the document.
+    fn path(&self, value: &Variable, segment: Option<PathSegment>) -> Option<Path> {
+        let mut current = address(value);
+        if !self.parents.contains_key(&current) {
+            return None;
+        }
+        let mut segments: Vec<PathSegment> = segment.into_iter().collect();
+        while let Some(&Some((parent, ref segment))) = self.parents.get(&current) {
+            segments.push(segment.clone());
+            current = parent;
+        }
+        segments.reverse();
+        Some(Path(segments))
+    }
+}
+
+/// Paths of the lookups that a search makes in its document.
+pub(crate) struct Provenance {
+    document: Document,
+    recorded: HashSet<Path>,
+    paths: Vec<Path>,
+}
+
+impl Provenance {
+    /// Creates a provenance for the lookups in a document.
+    pub(crate) fn new(root: &Variable) -> Provenance {
+        Provenance {
+            document: Document::new(root),
+            recorded: HashSet::new(),
+            paths: vec![],
+        }
+    }
+
+    /// Records a lookup of a field, if the value is an object of the document.
+    pub(crate) fn record_field(&mut self, value: &Variable, name: &str) {
+        if value.is_object() {
+            self.record(value, Some(PathSegment::Key(name.to_owned())));
+        }
+    }
+
+    /// Records a lookup of an index, if the value is an array of the document.
+    ///
+    /// A negative index that is out of bounds depends on the length of the
+    /// array, so the path of the array itself is recorded.
+    pub(crate) fn record_index(&mut self, value: &Variable, index: i64) {
+        if let Some(array) = value.as_array() {
+            let index = if index >= 0 {
+                Some(index as usize)
+            } else {
+                // Negating through u64 cannot overflow, even for i64::MIN.
+                array.len().checked_sub((index as u64).wrapping_neg() as usize)
+            };
+            self.record(value, index.map(PathSegment::Index));
+        }
+    }
+
+    fn record(&mut self, value: &Variable, segment: Option<PathSegment>) {
+        // Values that the search built were not read from the document.
+        if let Some(path) = self.document.path(value, segment) {
+            if self.recorded.insert(path.clone()) {
+                self.paths.push(path);
+            }
+        }
+    }
+
+    /// Returns the recorded paths, in the order they were first looked up.
+    pub(crate) fn into_paths(self) -> Vec<Path> {
+        self.paths
+    }
+}
+
+/// Returns the paths of the values of a document that make up a result.
+///
+/// A result that is part of the document has its own path. Arrays and
+/// objects that the search built, such as the results of projections, have
+/// the paths of their elements and values, in order. Any other value that the
+/// search built, such as a literal or the number that a function returns,
+/// has no path and is left out.
+pub(crate) fn locate(root: &Variable, result: &Variable) -> Vec<Path> {
+    let document = Document::new(root);
+    let mut paths = vec![];
+    let mut pending = vec![result];
+    while let Some(value) = pending.pop() {
+        if let Some(path) = document.path(value, None) {
+            paths.push(path);
+            continue;
+        }
+        match *value {
+            Variable::Array(ref array) => pending.extend(array.iter().rev().map(|v| &**v)),
+            Variable::Object(ref map) => pending.extend(map.values().rev().map(|v| &**v)),
+            _ => {}
+        }
+    }
+    paths
+}
+
+/// Adds a child to the parents, and to the values left to visit unless it
+/// has been visited already.
+fn visit<'a>(parents: &mut HashMap<usize, Option<(usize, PathSegment)>>,
+             pending: &mut Vec<&'a Variable>,
+             parent: &Variable,
+             segment: PathSegment,
+             child: &'a Variable) {
+    if let Entry::Vacant(entry) = parents.entry(address(child)) {
+        entry.insert(Some((address(parent), segment)));
+        pending.push(child);
+    }
+}
+
+fn address(value: &Variable) -> usize {
+    value as *const Variable as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use compile;
+
+    fn paths(expression: &str, json: &str) -> Vec<String> {
+        let data = Variable::from_json(json).unwrap();
+        let (_, paths) = compile(expression).unwrap().search_with_provenance(data).unwrap();
+        paths.iter().map(|path| path.to_string()).collect()
+    }
+
+    fn set(paths: &[&str]) -> BTreeSet<String> {
+        paths.iter().map(|path| path.to_string()).collect()
+    }
+
+    #[test]
+    fn displays_paths_as_expressions() {
+        assert_eq!("@", Path::default().to_string());
+        let path = Path(vec![PathSegment::Key("foo".to_owned()),
+                             PathSegment::Index(3),
+                             PathSegment::Key("a b".to_owned()),
+                             PathSegment::Index(0)]);
+        assert_eq!("foo[3].\"a b\"[0]", path.to_string());
+        assert_eq!("[1].x",
+                   Path(vec![PathSegment::Index(1), PathSegment::Key("x".to_owned())])
+                       .to_string());
+    }
+
+    #[test]
+    fn records_the_lookups_of_projections_and_filters() {
+        let data = r#"{"foo": {"bar": [
+            {"x": 1, "y": "a"},
+            {"x": 2, "y": "b"},
+            {"y": "c"},
+            {"x": 3, "y": "d"}
+        ]}}"#;
+        // Keys missing from an object are recorded, since adding them could
+        // change the result.
+        assert_eq!(vec!["foo", "foo.bar", "foo.bar[0].x", "foo.bar[1].x", "foo.bar[1].y",
+                        "foo.bar[2].x", "foo.bar[3].x", "foo.bar[3].y"],
+                   paths("foo.bar[?x > `1`].y", data));
+        // The wildcard reads `foo.bar` as a whole without looking it up.
+        assert_eq!(set(&["foo", "foo.bar[0].y", "foo.bar[1].y", "foo.bar[2].y", "foo.bar[3].y"]),
+                   paths("foo.*[].y", data).into_iter().collect());
+    }
+
+    #[test]
+    fn records_the_lookups_of_expression_references() {
+        let data = r#"{"people": [{"name": "a", "age": 30}, {"name": "b", "age": 20}]}"#;
+        assert_eq!(set(&["people", "people[0].age", "people[1].age", "people[1].name"]),
+                   paths("sort_by(people, &age)[0].name", data).into_iter().collect());
+        assert_eq!(set(&["people", "people[0].name", "people[1].name"]),
+                   paths("map(&name, people)", data).into_iter().collect());
+    }
+
+    #[test]
+    fn records_the_lookups_of_indices() {
+        let data = r#"{"a": [[1, 2], [3]], "b": {"c": 1}}"#;
+        assert_eq!(vec!["a", "a[1]", "a[1][0]"], paths("a[-1][0]", data));
+        // An index past the end depends on the length of the array.
+        assert_eq!(vec!["a", "a[5]"], paths("a[5]", data));
+        assert_eq!(vec!["a"], paths("a[-5]", data));
+        // Lookups in values of the wrong type read nothing.
+        assert_eq!(vec!["b"], paths("b[0]", data));
+        assert_eq!(vec!["a"], paths("a.c", data));
+    }
+
+    #[test]
+    fn does_not_record_lookups_in_values_that_the_search_builds() {
+        let data = r#"{"foo": {"bar": 1}, "baz": 2}"#;
+        assert_eq!(vec!["foo", "foo.bar"], paths("{a: foo}.a.bar", data));
+        assert_eq!(vec!["baz"], paths("`{\"baz\": null}`.baz || baz", data));
+        assert_eq!(Vec::<String>::new(), paths("keys(@)", data));
+    }
+
+    fn locations(expression: &str, json: &str) -> Vec<String> {
+        let data = Variable::from_json(json).unwrap();
+        let paths = compile(expression).unwrap().locate(data).unwrap();
+        paths.iter().map(|path| path.to_string()).collect()
+    }
+
+    const RECORDS: &'static str = r#"{"foo": {"bar": [
+        {"x": 1, "y": "a"},
+        {"x": 2, "y": "b"},
+        {"y": "c"},
+        {"x": 3, "y": "d"}
+    ]}, "n": null}"#;
+
+    #[test]
+    fn locates_values_of_the_document() {
+        assert_eq!(vec!["foo.bar"], locations("foo.bar", RECORDS));
+        assert_eq!(vec!["foo.bar[3]"], locations("foo.bar[-1]", RECORDS));
+        assert_eq!(vec!["@"], locations("@", RECORDS));
+        // Null values of the document have a path, but missing values do not.
+        assert_eq!(vec!["n"], locations("n", RECORDS));
+        assert_eq!(Vec::<String>::new(), locations("foo.baz", RECORDS));
+    }
+
+    #[test]
+    fn locates_each_element_of_filters_and_slices() {
+        assert_eq!(vec!["foo.bar[1].y", "foo.bar[3].y"],
+                   locations("foo.bar[?x > `1`].y", RECORDS));
+        assert_eq!(vec!["foo.bar[3]", "foo.bar[1]"], locations("foo.bar[::-2]", RECORDS));
+        assert_eq!(vec!["foo.bar[1].x"], locations("foo.bar[1:3].x", RECORDS));
+        assert_eq!(vec!["foo.bar[0].x", "foo.bar[0].y", "foo.bar[2].y"],
+                   locations("foo.bar[0:3:2].[x, y]", RECORDS));
+    }
+
+    #[test]
+    fn locates_the_document_values_of_values_that_the_search_builds() {
+        assert_eq!(vec!["foo.bar[0].x"],
+                   locations("[foo.bar[0].x, `5`, length(foo.bar)]", RECORDS));
+        assert_eq!(vec!["foo.bar[1].y"], locations("{a: foo.bar[1].y, b: 'c'}", RECORDS));
+        assert_eq!(vec!["foo.bar[3]"], locations("max_by(foo.bar[?x], &x)", RECORDS));
+    }
+
+    #[test]
+    fn pipes_keep_the_paths_of_document_values_only() {
+        assert_eq!(vec!["foo.bar[1].y"], locations("foo.bar[*].y | [1]", RECORDS));
+        assert_eq!(vec!["foo.bar[3].y", "foo.bar[2].y"],
+                   locations("foo.bar[*].y | reverse(@)[:2]", RECORDS));
+        // Values computed from the document are not part of it.
+        assert_eq!(Vec::<String>::new(), locations("foo.bar[*].x | sum(@)", RECORDS));
+        assert_eq!(Vec::<String>::new(), locations("foo.bar[*].y | join(',', @)", RECORDS));
+    }
+}
@@ -1,23 +1,38 @@
 use std::collections::HashMap;
 
-use parse;
-use JmespathError;
-use Expression;
+use {interpreter, parse, Context, JmespathError, Expression, ToJmespath};
+use ast::Ast;
+use interpreter::SearchResult;
 use functions::*;
+use variable::JmespathType;
+
+/// The default maximum number of nested interpreter calls while evaluating
+/// an expression.
+pub const DEFAULT_MAX_EVALUATION_DEPTH: usize = 500;
 
 /// Compiles JMESPath expressions.
 ///
 /// Most use cases don't need to worry about how Runtime works.
 /// You really only need to create your own Runtimes if you are
 /// utilizing custom functions in your expressions.
+///
+/// A Runtime is `Send` and `Sync`, so it can be shared between threads
+/// using an `Arc<Runtime>`, with each thread compiling and evaluating its
+/// own expressions.
 pub struct Runtime {
     functions: HashMap<String, Box<Function>>,
+    string_slices: bool,
+    max_evaluation_depth: usize,
 }
 
 impl Runtime {
     /// Creates a new Runtime.
     pub fn new() -> Runtime {
-        Runtime { functions: HashMap::with_capacity(26) }
+        Runtime {
+            functions: HashMap::new(),
+            string_slices: false,
+            max_evaluation_depth: DEFAULT_MAX_EVALUATION_DEPTH,
+        }
     }
 
     /// Creates a new JMESPath expression from an expression string.
@@ -29,15 +44,49 @@ impl Runtime {
         parse(expression).map(|ast| Expression::new(expression, ast, self))
     }
 
+    /// Interprets a parsed AST against data, calling the functions of this
+    /// runtime.
+    ///
+    /// This evaluates an AST that was built or transformed by hand without
+    /// printing and compiling it again, and gives the same results as an
+    /// `Expression` of the AST. Errors refer to the AST printed as an
+    /// expression, so the offsets of the nodes must be positions in the
+    /// printed expression. Use `interpret_with_source` for an AST that was
+    /// parsed from an expression, whose offsets are positions in that
+    /// expression.
+    pub fn interpret<T: ToJmespath>(&self, ast: &Ast, data: T) -> SearchResult {
+        self.interpret_with_source(&ast.to_string(), ast, data)
+    }
+
+    /// Interprets an AST that was parsed from `source` against data, calling
+    /// the functions of this runtime.
+    ///
+    /// Errors refer to `source`, at the offsets of the nodes that raise them,
+    /// like the errors of an `Expression` compiled from `source`.
+    pub fn interpret_with_source<T: ToJmespath>(&self,
+                                                source: &str,
+                                                ast: &Ast,
+                                                data: T)
+                                                -> SearchResult {
+        let data = data.to_jmespath();
+        let mut ctx = Context::for_search(source, self, &data);
+        interpreter::interpret(&data, ast, &mut ctx)
+    }
+
     /// Adds a new function to the runtime.
+    ///
+    /// A function that is already registered under the same name, including
+    /// a builtin function, is replaced and returned.
     #[inline]
-    pub fn register_function(&mut self, name: &str, f: Box<Function>) {
-        self.functions.insert(name.to_owned(), f);
+    pub fn register_function(&mut self, name: &str, f: Box<Function>) -> Option<Box<Function>> {
+        self.functions.insert(name.to_owned(), f)
     }
 
     /// Removes a function from the runtime.
     ///
-    /// Returns the function that was removed if it was found.
+    /// Returns the function that was removed if it was found. Expressions
+    /// that call a removed function fail with an `UnknownFunction` error when
+    /// they are evaluated.
     pub fn deregister_function(&mut self, name: &str) -> Option<Box<Function>> {
         self.functions.remove(name)
     }
@@ -48,33 +97,498 @@ impl Runtime {
         self.functions.get(name)
     }
 
+    /// Returns the names of every registered function, sorted by name.
+    pub fn function_names(&self) -> Vec<&str> {
+        let mut names = self.functions.keys().map(|name| name.as_str()).collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    /// Returns the signature of a registered function.
+    ///
+    /// Returns None if the function is not registered or does not declare a
+    /// signature (e.g., a closure).
+    pub fn signature(&self, name: &str) -> Option<&Signature> {
+        self.functions.get(name).and_then(|f| f.signature())
+    }
+
+    /// Returns metadata for every registered function, sorted by name.
+    ///
+    /// The result is serializable with serde, which makes it useful for
+    /// tooling like editor completion and signature help.
+    pub fn metadata(&self) -> Vec<FunctionMetadata> {
+        let mut result = self.functions
+            .iter()
+            .map(|(name, f)| FunctionMetadata::new(name, &**f))
+            .collect::<Vec<_>>();
+        result.sort_by(|a, b| a.name.cmp(&b.name));
+        result
+    }
+
+    /// Allows slices of strings (e.g., `name[0:5]`) when `enabled` is true.
+    ///
+    /// This is an extension to the JMESPath specification, which evaluates
+    /// slices of anything but arrays to null. Strings are sliced by code
+    /// point using the same rules as arrays, including negative indices and
+    /// steps, and slices of strings evaluate to strings. Disabled by default.
+    pub fn set_string_slices(&mut self, enabled: bool) {
+        self.string_slices = enabled;
+    }
+
+    /// Returns true if slices of strings are allowed.
+    pub fn string_slices(&self) -> bool {
+        self.string_slices
+    }
+
+    /// Sets the maximum number of nested interpreter calls while evaluating
+    /// an expression, including the calls that functions make to evaluate
+    /// expression references. Searches that nest deeper fail with a
+    /// `RecursionLimitExceeded` error rather than overflowing the stack.
+    ///
+    /// Chains of subexpressions, pipes, and `||` and `&&` operators do not
+    /// add to the depth. Defaults to `DEFAULT_MAX_EVALUATION_DEPTH`, which
+    /// fits in the default 2 MiB stack of a spawned thread in optimized
+    /// builds. Unoptimized builds use several times more stack per call, so
+    /// lower the limit when evaluating untrusted expressions in them.
+    pub fn set_max_evaluation_depth(&mut self, depth: usize) {
+        self.max_evaluation_depth = depth;
+    }
+
+    /// Returns the maximum number of nested interpreter calls while
+    /// evaluating an expression.
+    pub fn max_evaluation_depth(&self) -> usize {
+        self.max_evaluation_depth
+    }
+
     /// Registers all of the builtin JMESPath functions with the runtime.
     pub fn register_builtin_functions(&mut self) {
         self.register_function("abs", Box::new(AbsFn::new()));
         self.register_function("avg", Box::new(AvgFn::new()));
+        self.register_function("avg_by", Box::new(AvgByFn::new()));
         self.register_function("ceil", Box::new(CeilFn::new()));
+        self.register_function("chunk", Box::new(ChunkFn::new()));
         self.register_function("contains", Box::new(ContainsFn::new()));
+        self.register_function("count", Box::new(CountFn::new()));
+        self.register_function("difference", Box::new(DifferenceFn::new()));
         self.register_function("ends_with", Box::new(EndsWithFn::new()));
+        self.register_function("enumerate", Box::new(EnumerateFn::new()));
+        self.register_function("find_first", Box::new(FindFirstFn::new()));
+        self.register_function("find_last", Box::new(FindLastFn::new()));
+        self.register_function("first", Box::new(FirstFn::new()));
+        self.register_function("flatten", Box::new(FlattenFn::new()));
         self.register_function("floor", Box::new(FloorFn::new()));
+        self.register_function("format", Box::new(FormatFn::new()));
+        self.register_function("get", Box::new(GetFn::new()));
+        self.register_function("group_by", Box::new(GroupByFn::new()));
+        self.register_function("index_of", Box::new(IndexOfFn::new()));
+        self.register_function("intersection", Box::new(IntersectionFn::new()));
+        self.register_function("invert", Box::new(InvertFn::new()));
+        self.register_function("items", Box::new(ItemsFn::new()));
         self.register_function("join", Box::new(JoinFn::new()));
+        self.register_function("json_parse", Box::new(JsonParseFn::new()));
+        self.register_function("json_serialize", Box::new(JsonSerializeFn::new()));
         self.register_function("keys", Box::new(KeysFn::new()));
+        self.register_function("last", Box::new(LastFn::new()));
         self.register_function("length", Box::new(LengthFn::new()));
+        self.register_function("lower", Box::new(LowerFn::new()));
         self.register_function("map", Box::new(MapFn::new()));
+        self.register_function("map_with_index", Box::new(MapWithIndexFn::new()));
         self.register_function("min", Box::new(MinFn::new()));
         self.register_function("max", Box::new(MaxFn::new()));
         self.register_function("max_by", Box::new(MaxByFn::new()));
         self.register_function("min_by", Box::new(MinByFn::new()));
         self.register_function("merge", Box::new(MergeFn::new()));
         self.register_function("not_null", Box::new(NotNullFn::new()));
+        self.register_function("partition", Box::new(PartitionFn::new()));
+        self.register_function("range", Box::new(RangeFn::new()));
+        self.register_function("reduce", Box::new(ReduceFn::new()));
+        self.register_function("replace", Box::new(ReplaceFn::new()));
         self.register_function("reverse", Box::new(ReverseFn::new()));
+        self.register_function("round", Box::new(RoundFn::new()));
         self.register_function("sort", Box::new(SortFn::new()));
         self.register_function("sort_by", Box::new(SortByFn::new()));
+        self.register_function("split", Box::new(SplitFn::new()));
         self.register_function("starts_with", Box::new(StartsWithFn::new()));
+        self.register_function("substring", Box::new(SubstringFn::new()));
         self.register_function("sum", Box::new(SumFn::new()));
+        self.register_function("sum_by", Box::new(SumByFn::new()));
         self.register_function("to_array", Box::new(ToArrayFn::new()));
         self.register_function("to_number", Box::new(ToNumberFn::new()));
         self.register_function("to_string", Box::new(ToStringFn::new()));
+        self.register_function("trim", Box::new(TrimFn::new()));
+        self.register_function("trim_left", Box::new(TrimLeftFn::new()));
+        self.register_function("trim_right", Box::new(TrimRightFn::new()));
         self.register_function("type", Box::new(TypeFn::new()));
+        self.register_function("union", Box::new(UnionFn::new()));
+        self.register_function("unique", Box::new(UniqueFn::new()));
+        self.register_function("upper", Box::new(UpperFn::new()));
         self.register_function("values", Box::new(ValuesFn::new()));
     }
+
+    /// Registers the optional math functions: pow, sqrt, log, and mod.
+    ///
+    /// These are not part of the JMESPath specification, so they are not
+    /// registered by `register_builtin_functions`. Results that are not finite
+    /// (e.g., `sqrt` of a negative number) are returned as null.
+    pub fn register_math_functions(&mut self) {
+        self.register_function("log", Box::new(LogFn::new()));
+        self.register_function("mod", Box::new(ModFn::new()));
+        self.register_function("pow", Box::new(PowFn::new()));
+        self.register_function("sqrt", Box::new(SqrtFn::new()));
+    }
+
+    /// Registers optional extension functions that are not part of the
+    /// JMESPath specification: byte_length and the is_string, is_number,
+    /// is_array, is_object, is_bool, and is_null type predicates.
+    pub fn register_extension_functions(&mut self) {
+        self.register_function("byte_length", Box::new(ByteLengthFn::new()));
+        self.register_function("is_array", Box::new(IsTypeFn::new(JmespathType::Array)));
+        self.register_function("is_bool", Box::new(IsTypeFn::new(JmespathType::Boolean)));
+        self.register_function("is_null", Box::new(IsTypeFn::new(JmespathType::Null)));
+        self.register_function("is_number", Box::new(IsTypeFn::new(JmespathType::Number)));
+        self.register_function("is_object", Box::new(IsTypeFn::new(JmespathType::Object)));
+        self.register_function("is_string", Box::new(IsTypeFn::new(JmespathType::String)));
+    }
+
+    /// Registers the optional statistics functions: median, percentile, and
+    /// stddev.
+    ///
+    /// Each accepts an array of numbers and returns null for an empty array.
+    /// Percentiles range from 0 to 100 and are linearly interpolated, and the
+    /// standard deviation is that of the whole population.
+    pub fn register_stats_functions(&mut self) {
+        self.register_function("median", Box::new(MedianFn::new()));
+        self.register_function("percentile", Box::new(PercentileFn::new()));
+        self.register_function("stddev", Box::new(StddevFn::new()));
+    }
+
+    /// Registers the optional encoding functions: url_encode and url_decode.
+    ///
+    /// These percent-encode and decode strings using the RFC 3986 unreserved
+    /// characters. They are not part of the JMESPath specification, so they
+    /// are not registered by `register_builtin_functions`.
+    pub fn register_encoding_functions(&mut self) {
+        self.register_function("url_decode", Box::new(UrlDecodeFn::new()));
+        self.register_function("url_encode", Box::new(UrlEncodeFn::new()));
+    }
+
+    /// Registers the optional hash functions: sha256, sha1, and md5.
+    ///
+    /// Each returns the lowercase hex digest of a string's UTF-8 bytes.
+    /// Requires the `hash-functions` feature.
+    #[cfg(feature = "hash-functions")]
+    pub fn register_hash_functions(&mut self) {
+        use functions::hash::*;
+        self.register_function("md5", Box::new(Md5Fn::new()));
+        self.register_function("sha1", Box::new(Sha1Fn::new()));
+        self.register_function("sha256", Box::new(Sha256Fn::new()));
+    }
+
+    /// Registers the optional regex functions: regex_match, regex_search, and
+    /// regex_replace.
+    ///
+    /// Requires the `regex-functions` feature. See `functions::regex` for
+    /// details on pattern limits.
+    #[cfg(feature = "regex-functions")]
+    pub fn register_regex_functions(&mut self) {
+        use functions::regex::*;
+        self.register_function("regex_match", Box::new(RegexMatchFn::new()));
+        self.register_function("regex_replace", Box::new(RegexReplaceFn::new()));
+        self.register_function("regex_search", Box::new(RegexSearchFn::new()));
+    }
+
+    /// Registers the optional time functions: parse_iso8601, format_iso8601,
+    /// and now.
+    ///
+    /// `now` reads the time from the given clock, which makes it possible to
+    /// inject a fixed clock for testing. Pass `None` to leave `now` out when
+    /// evaluation must be deterministic, or `functions::time::system_clock()`
+    /// to read the system time. Requires the `time-functions` feature.
+    #[cfg(feature = "time-functions")]
+    pub fn register_time_functions(&mut self, clock: Option<::functions::time::Clock>) {
+        use functions::time::*;
+        self.register_function("format_iso8601", Box::new(FormatIso8601Fn::new()));
+        self.register_function("parse_iso8601", Box::new(ParseIso8601Fn::new()));
+        if let Some(clock) = clock {
+            self.register_function("now", Box::new(NowFn::new(clock)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use {Context, ErrorReason, Rcvar, RuntimeError};
+    use variable::{Map, Variable};
+    use super::*;
+
+    fn runtime() -> Runtime {
+        let mut runtime = Runtime::new();
+        runtime.register_builtin_functions();
+        runtime
+    }
+
+    #[test]
+    fn registering_replaces_builtin_functions() {
+        let mut runtime = runtime();
+        let previous = runtime.register_function("length", Box::new(CustomFunction::new(
+            Signature::new(vec![ArgumentType::Any], None),
+            Box::new(|_: &[Rcvar], _: &mut Context| Ok(Rcvar::new(Variable::Number(-1.0)))))));
+        assert!(previous.unwrap().signature().is_some());
+        let expr = runtime.compile("length(@)").unwrap();
+        assert_eq!(Rcvar::new(Variable::Number(-1.0)), expr.search("abc").unwrap());
+        assert!(runtime.register_function("my_fn", Box::new(LengthFn::new())).is_none());
+    }
+
+    #[test]
+    fn deregistered_functions_are_unknown() {
+        let mut runtime = runtime();
+        assert!(runtime.deregister_function("length").is_some());
+        assert!(runtime.deregister_function("length").is_none());
+        assert!(runtime.get_function("length").is_none());
+        let expr = runtime.compile("[?length(@) > `1`]").unwrap();
+        let err = expr.search(vec!["ab"]).unwrap_err();
+        assert_eq!(ErrorReason::Runtime(RuntimeError::UnknownFunction("length".to_owned())),
+                   err.reason);
+    }
+
+    #[test]
+    fn reports_core_function_names_and_arities() {
+        let runtime = runtime();
+        let core = [("abs", 1, Some(1)), ("avg", 1, Some(1)), ("ceil", 1, Some(1)),
+                    ("contains", 2, Some(2)), ("ends_with", 2, Some(2)), ("floor", 1, Some(1)),
+                    ("join", 2, Some(2)), ("keys", 1, Some(1)), ("length", 1, Some(1)),
+                    ("map", 2, Some(2)), ("max", 1, Some(1)), ("max_by", 2, Some(2)),
+                    ("merge", 1, None), ("min", 1, Some(1)), ("min_by", 2, Some(2)),
+                    ("not_null", 1, None), ("reverse", 1, Some(1)), ("sort", 1, Some(1)),
+                    ("sort_by", 2, None), ("starts_with", 2, Some(2)), ("sum", 1, Some(1)),
+                    ("to_array", 1, Some(1)), ("to_number", 1, Some(1)),
+                    ("to_string", 1, Some(1)), ("type", 1, Some(1)), ("values", 1, Some(1))];
+        assert_eq!(26, core.len());
+        let names = runtime.function_names();
+        for &(name, min, max) in &core {
+            assert!(names.contains(&name), "missing {}", name);
+            let signature = runtime.signature(name).unwrap();
+            assert_eq!((min, max), (signature.min_arity(), signature.max_arity()), "{}", name);
+        }
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(sorted, names);
+    }
+
+    #[test]
+    fn closures_have_no_signature() {
+        let mut runtime = Runtime::new();
+        runtime.register_function("identity",
+            Box::new(|args: &[Rcvar], _: &mut Context| Ok(args[0].clone())));
+        assert_eq!(vec!["identity"], runtime.function_names());
+        assert!(runtime.signature("identity").is_none());
+        assert!(runtime.signature("missing").is_none());
+    }
+
+    #[test]
+    fn shares_a_runtime_with_custom_functions_across_threads() {
+        let mut runtime = runtime();
+        runtime.register_function("double", Box::new(CustomFunction::new(
+            Signature::new(vec![ArgumentType::Number], None),
+            Box::new(|args: &[Rcvar], _: &mut Context| {
+                Ok(Rcvar::new(Variable::Number(args[0].as_number().unwrap() * 2.0)))
+            }))));
+        let runtime = Arc::new(runtime);
+        let handles = (0..8)
+            .map(|t| {
+                let runtime = runtime.clone();
+                thread::spawn(move || {
+                    let expr = runtime.compile("sum(map(&double(@), @))").unwrap();
+                    (0..100)
+                        .map(|i| {
+                            let data = Variable::from_json(&format!("[{}, {}]", t, i)).unwrap();
+                            expr.search(data).unwrap().as_number().unwrap()
+                        })
+                        .sum::<f64>()
+                })
+            })
+            .collect::<Vec<_>>();
+        for (t, handle) in handles.into_iter().enumerate() {
+            assert_eq!((200 * t + 9900) as f64, handle.join().unwrap());
+        }
+    }
+
+    #[test]
+    fn slices_of_strings_are_null_by_default() {
+        let runtime = runtime();
+        assert!(!runtime.string_slices());
+        let expr = runtime.compile("name[0:2]").unwrap();
+        let data = Variable::from_json(r#"{"name": "alice"}"#).unwrap();
+        assert_eq!(Rcvar::new(Variable::Null), expr.search(data).unwrap());
+    }
+
+    #[test]
+    fn slices_strings_by_code_point_when_enabled() {
+        let mut runtime = runtime();
+        runtime.set_string_slices(true);
+        let data = Variable::from_json(r#"{"name": "héllo wörld", "names": ["ab", "cd"]}"#)
+            .unwrap();
+        let search = |expr: &str| {
+            runtime.compile(expr).unwrap().search(data.clone()).unwrap().to_string()
+        };
+        assert_eq!(r#""héllo""#, search("name[0:5]"));
+        assert_eq!(r#""wörld""#, search("name[-5:]"));
+        assert_eq!(r#""dlröw olléh""#, search("name[::-1]"));
+        assert_eq!(r#""hlowrd""#, search("name[::2]"));
+        assert_eq!(r#""ö""#, search("name[-4:-3]"));
+        assert_eq!(r#""héllo wörld""#, search("name[-100:100]"));
+        assert_eq!(r#""""#, search("name[5:2]"));
+        assert_eq!("5", search("length(name[:5])"));
+        // Slices of strings are not projected, while arrays still are.
+        assert_eq!("null", search("name[:5].foo"));
+        assert_eq!(r#"["a","c"]"#, search("names[*][:1]"));
+        assert_eq!(r#"[["ab"]]"#, search("[names[:1]]"));
+        // Only slices apply to strings.
+        assert_eq!("null", search("name[*]"));
+        assert_eq!("null", search("name[0]"));
+    }
+
+    /// Nests `depth` calls of `map`, e.g. `map(&map(&@, @), @)`.
+    fn nested_maps(depth: usize) -> String {
+        (0..depth).fold("@".to_owned(), |expr, _| format!("map(&{}, @)", expr))
+    }
+
+    /// Nests `depth` arrays around `1`.
+    fn nested_arrays(depth: usize) -> Variable {
+        Variable::from_json(&format!("{}1{}", "[".repeat(depth), "]".repeat(depth))).unwrap()
+    }
+
+    #[test]
+    fn limits_the_depth_of_nested_expression_references() {
+        let mut runtime = runtime();
+        assert_eq!(DEFAULT_MAX_EVALUATION_DEPTH, runtime.max_evaluation_depth());
+        runtime.set_max_evaluation_depth(20);
+        // Each map nests a call, and so does the innermost `@`.
+        let expr = runtime.compile(&nested_maps(19)).unwrap();
+        assert_eq!(nested_arrays(20), *expr.search(nested_arrays(20)).unwrap());
+        let expr = runtime.compile(&nested_maps(20)).unwrap();
+        let err = expr.search(nested_arrays(21)).unwrap_err();
+        assert_eq!(ErrorReason::Runtime(RuntimeError::RecursionLimitExceeded { limit: 20 }),
+                   err.reason);
+    }
+
+    #[test]
+    fn limits_the_depth_of_recursive_functions_over_nested_documents() {
+        use ast::Ast;
+        use interpreter::interpret;
+
+        // depth(@) counts the levels of `child` objects by calling itself.
+        let mut runtime = runtime();
+        runtime.register_function("depth", Box::new(CustomFunction::new(
+            Signature::new(vec![ArgumentType::Any], None),
+            Box::new(|args: &[Rcvar], ctx: &mut Context| {
+                let child = args[0].get_field("child");
+                if child.is_null() {
+                    return Ok(Rcvar::new(Variable::Number(0.0)));
+                }
+                let call = Ast::Function {
+                    offset: 0,
+                    name: "depth".to_owned(),
+                    args: vec![Ast::Identity { offset: 0 }],
+                };
+                let depth = try!(interpret(&child, &call, ctx)).as_number().unwrap();
+                Ok(Rcvar::new(Variable::Number(depth + 1.0)))
+            }))));
+        // Built directly, since JSON parsing limits the nesting of documents.
+        let document = |depth: usize| {
+            (0..depth).fold(Variable::Object(Map::new()), |child, _| {
+                let mut parent = Map::new();
+                parent.insert("child".to_owned(), Rcvar::new(child));
+                Variable::Object(parent)
+            })
+        };
+        runtime.set_max_evaluation_depth(100);
+        let expr = runtime.compile("depth(@)").unwrap();
+        assert_eq!(Variable::Number(50.0), *expr.search(document(50)).unwrap());
+        let err = expr.search(document(1000)).unwrap_err();
+        assert_eq!(ErrorReason::Runtime(RuntimeError::RecursionLimitExceeded { limit: 100 }),
+                   err.reason);
+    }
+
+    #[test]
+    fn interprets_hand_constructed_asts() {
+        use ast::Comparator;
+        let ast = Ast::Comparison {
+            offset: 2,
+            comparator: Comparator::LessThan,
+            lhs: Box::new(Ast::Field {
+                offset: 0,
+                name: "a".to_owned(),
+            }),
+            rhs: Box::new(Ast::Literal {
+                offset: 4,
+                value: Rcvar::new(Variable::Number(10.0)),
+            }),
+        };
+        let runtime = runtime();
+        let expr = Expression::new(ast.to_string(), ast.clone(), &runtime);
+        for json in &[r#"{"a": 5}"#, r#"{"a": 50}"#, r#"{"a": "5"}"#, "{}"] {
+            let data = Variable::from_json(json).unwrap();
+            assert_eq!(expr.search(data.clone()).unwrap(),
+                       runtime.interpret(&ast, data.clone()).unwrap(),
+                       "{}",
+                       json);
+            assert_eq!(expr.search(data.clone()).unwrap(),
+                       ::interpret(&ast, data).unwrap());
+        }
+    }
+
+    #[test]
+    fn interprets_parsed_asts_like_expressions() {
+        let data = Variable::from_json(r#"{"people": [
+            {"name": "a", "age": 30},
+            {"name": "b", "age": 20}
+        ]}"#)
+            .unwrap();
+        for expression in &["people[?age > `25`].name",
+                            "sort_by(people, &age)[*].name | join(',', @)",
+                            "$.people[0].{n: name}",
+                            "let $x = people[0].age in people[?age < $x].name"] {
+            let ast = parse(expression).unwrap();
+            assert_eq!(::compile(expression).unwrap().search(data.clone()).unwrap(),
+                       ::interpret(&ast, data.clone()).unwrap(),
+                       "{}",
+                       expression);
+        }
+    }
+
+    #[test]
+    fn interprets_asts_with_the_functions_of_the_runtime() {
+        let mut runtime = runtime();
+        runtime.register_function("double", Box::new(CustomFunction::new(
+            Signature::new(vec![ArgumentType::Number], None),
+            Box::new(|args: &[Rcvar], _: &mut Context| {
+                Ok(Rcvar::new(Variable::Number(args[0].as_number().unwrap() * 2.0)))
+            }))));
+        let ast = parse("double(a)").unwrap();
+        let data = Variable::from_json(r#"{"a": 4}"#).unwrap();
+        assert_eq!(Variable::Number(8.0), *runtime.interpret(&ast, data.clone()).unwrap());
+        // The default runtime does not know the function.
+        let err = ::interpret(&ast, data).unwrap_err();
+        assert_eq!(ErrorReason::Runtime(RuntimeError::UnknownFunction("double".to_owned())),
+                   err.reason);
+        assert_eq!("double(a)", err.expression);
+    }
+
+    #[test]
+    fn reports_errors_of_parsed_asts_against_their_source() {
+        let source = "a  |  b(@)";
+        let ast = parse(source).unwrap();
+        let data = Variable::from_json(r#"{"a": 1}"#).unwrap();
+        let err = runtime().interpret_with_source(source, &ast, data.clone()).unwrap_err();
+        assert_eq!(ErrorReason::Runtime(RuntimeError::UnknownFunction("b".to_owned())),
+                   err.reason);
+        assert_eq!(source, err.expression);
+        assert_eq!((0, 6), (err.line, err.column));
+        assert!(err.to_string().ends_with("a  |  b(@)\n      ^\n"), "{}", err);
+        assert_eq!(err, ::interpret_with_source(source, &ast, data).unwrap_err());
+    }
 }
@@ -8,18 +8,19 @@ use serde::{de, ser};
 use serde::de::IntoDeserializer;
 use serde_json::error::Error;
 use serde_json::value::Value;
-use std::collections::BTreeMap;
 use std::cmp::{max, Ordering};
 use std::fmt;
 use std::iter::Iterator;
 use std::string::ToString;
 
+use indexmap::IndexMap;
+
 use ToJmespath;
 use Rcvar;
 use ast::{Ast, Comparator};
 
 /// JMESPath types.
-#[derive(Debug, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Eq, Ord)]
 pub enum JmespathType {
     Null,
     String,
@@ -46,6 +47,13 @@ impl fmt::Display for JmespathType {
     }
 }
 
+/// Map of the keys of an object to their values.
+///
+/// Keys are kept in the order in which they were first inserted, e.g., the
+/// order in which they appear in a JSON document or in a multi-select hash.
+/// Maps compare equal regardless of the order of their keys.
+pub type Map = IndexMap<String, Rcvar>;
+
 /// JMESPath variable.
 #[derive(Clone, Debug)]
 pub enum Variable {
@@ -54,7 +62,7 @@ pub enum Variable {
     Bool(bool),
     Number(f64),
     Array(Vec<Rcvar>),
-    Object(BTreeMap<String, Rcvar>),
+    Object(Map),
     Expref(Ast),
 }
 
@@ -160,7 +168,7 @@ impl fmt::Display for Variable {
 fn convert_map<'a, T>(value: T) -> Variable
     where T: Iterator<Item = (&'a String, &'a Value)>
 {
-    let mut map: BTreeMap<String, Rcvar> = BTreeMap::new();
+    let mut map = Map::new();
     for kvp in value {
         map.insert(kvp.0.to_owned(), kvp.1.to_jmespath());
     }
@@ -224,9 +232,9 @@ impl Variable {
         self.as_object().is_some()
     }
 
-    /// If the value is an Object, returns the associated BTreeMap.
+    /// If the value is an Object, returns the associated map.
     /// Returns None otherwise.
-    pub fn as_object(&self) -> Option<&BTreeMap<String, Rcvar>> {
+    pub fn as_object(&self) -> Option<&Map> {
         match *self {
             Variable::Object(ref map) => Some(map),
             _ => None,
@@ -388,10 +396,43 @@ impl Variable {
         }
     }
 
+    /// Compares two Variable values using a comparator, comparing a string
+    /// that holds a JSON number as that number when the other value is a
+    /// number. Other values are compared as with `compare`.
+    pub fn compare_leniently(&self, cmp: &Comparator, value: &Variable) -> Option<bool> {
+        match (self, value) {
+            (&Variable::String(ref s), &Variable::Number(_)) => {
+                if let Some(n) = parse_number(s) {
+                    return Variable::Number(n).compare(cmp, value);
+                }
+            }
+            (&Variable::Number(_), &Variable::String(ref s)) => {
+                if let Some(n) = parse_number(s) {
+                    return self.compare(cmp, &Variable::Number(n));
+                }
+            }
+            _ => {}
+        }
+        self.compare(cmp, value)
+    }
+
     /// Returns a slice of the variable if the variable is an array.
-    pub fn slice(&self, start: &Option<i32>, stop: &Option<i32>, step: i32) -> Option<Vec<Rcvar>> {
+    pub fn slice(&self, start: &Option<i64>, stop: &Option<i64>, step: i64) -> Option<Vec<Rcvar>> {
         self.as_array().map(|a| slice(a, start, stop, step))
     }
+
+    /// Returns a slice of the code points of the variable if the variable
+    /// is a string, using the same rules as slices of arrays.
+    pub fn slice_string(&self,
+                        start: &Option<i64>,
+                        stop: &Option<i64>,
+                        step: i64)
+                        -> Option<String> {
+        self.as_string().map(|s| {
+            let chars = s.chars().collect::<Vec<_>>();
+            slice(&chars, start, stop, step).into_iter().collect()
+        })
+    }
 }
 
 impl Variable {
@@ -408,43 +449,53 @@ impl Variable {
     }
 }
 
+/// Parses a string that is exactly a JSON number.
+fn parse_number(s: &str) -> Option<f64> {
+    // serde_json allows whitespace around the number.
+    if s.trim() != s {
+        return None;
+    }
+    serde_json::from_str::<f64>(s).ok()
+}
+
 // ------------------------------------------
 // Variable slicing implementation
 // ------------------------------------------
 
-fn slice(array: &[Rcvar], start: &Option<i32>, stop: &Option<i32>, step: i32) -> Vec<Rcvar> {
+fn slice<T: Clone>(array: &[T], start: &Option<i64>, stop: &Option<i64>, step: i64) -> Vec<T> {
     let mut result = vec![];
-    let len = array.len() as i32;
+    let len = array.len() as i64;
     if len == 0 {
         return result;
     }
-    let a: i32 = match *start {
+    let a: i64 = match *start {
         Some(starting_index) => adjust_slice_endpoint(len, starting_index, step),
         _ if step < 0 => len - 1,
         _ => 0,
     };
-    let b: i32 = match *stop {
+    let b: i64 = match *stop {
         Some(ending_index) => adjust_slice_endpoint(len, ending_index, step),
         _ if step < 0 => -1,
         _ => len,
     };
-    let mut i = a;
+    // Stepping past the end of the i64 range means the slice is exhausted.
+    let mut i = Some(a);
     if step > 0 {
-        while i < b {
-            result.push(array[i as usize].clone());
-            i += step;
+        while let Some(idx) = i.filter(|&idx| idx < b) {
+            result.push(array[idx as usize].clone());
+            i = idx.checked_add(step);
         }
     } else {
-        while i > b {
-            result.push(array[i as usize].clone());
-            i += step;
+        while let Some(idx) = i.filter(|&idx| idx > b) {
+            result.push(array[idx as usize].clone());
+            i = idx.checked_add(step);
         }
     }
     result
 }
 
 #[inline]
-fn adjust_slice_endpoint(len: i32, mut endpoint: i32, step: i32) -> i32 {
+fn adjust_slice_endpoint(len: i64, mut endpoint: i64, step: i64) -> i64 {
     if endpoint < 0 {
         endpoint += len;
         if endpoint >= 0 {
@@ -563,7 +614,7 @@ impl<'de> de::Deserialize<'de> for Variable {
             fn visit_map<V>(self, mut visitor: V) -> Result<Variable, V::Error>
                 where V: de::MapAccess<'de>,
             {
-                let mut values = BTreeMap::new();
+                let mut values = Map::new();
 
                 while let Some((key, value)) = try!(visitor.next_entry()) {
                     values.insert(key, value);
@@ -811,7 +862,7 @@ impl<'de> de::SeqAccess<'de> for SeqDeserializer {
 }
 
 struct MapDeserializer {
-    iter: <BTreeMap<String, Rcvar> as IntoIterator>::IntoIter,
+    iter: <Map as IntoIterator>::IntoIter,
     value: Option<Variable>,
 }
 
@@ -876,7 +927,9 @@ impl ser::Serialize for Variable {
             Variable::Bool(v) => serializer.serialize_bool(v),
             Variable::Number(v) => {
                 // Serializes as an integer when the decimal is 0 (i.e., 0.0).
-                if v.floor() == v {
+                // Integral values outside of the i64 range would saturate, so
+                // they are serialized as floats (e.g., 1e+20).
+                if v.floor() == v && v >= -9223372036854775808.0 && v < 9223372036854775808.0 {
                     serializer.serialize_i64(v as i64)
                 } else {
                     serializer.serialize_f64(v)
@@ -906,12 +959,12 @@ pub struct TupleVariantState {
 #[doc(hidden)]
 pub struct StructVariantState {
     name: String,
-    map: BTreeMap<String, Rcvar>,
+    map: Map,
 }
 
 #[doc(hidden)]
 pub struct MapState {
-    map: BTreeMap<String, Rcvar>,
+    map: Map,
     next_key: Option<String>,
 }
 
@@ -1046,7 +1099,7 @@ impl ser::Serializer for Serializer {
     ) -> Result<Variable, Error>
         where T: ser::Serialize,
     {
-        let mut values = BTreeMap::new();
+        let mut values = Map::new();
         values.insert(String::from(variant), Rcvar::new(to_variable(&value)?));
         Ok(Variable::Object(values))
     }
@@ -1097,7 +1150,7 @@ impl ser::Serializer for Serializer {
 
     fn serialize_map(self, _len: Option<usize>) -> Result<MapState, Error> {
         Ok(MapState {
-            map: BTreeMap::new(),
+            map: Map::new(),
             next_key: None,
         })
     }
@@ -1119,7 +1172,7 @@ impl ser::Serializer for Serializer {
     ) -> Result<StructVariantState, Error> {
         Ok(StructVariantState {
             name: String::from(variant),
-            map: BTreeMap::new(),
+            map: Map::new(),
         })
     }
 }
@@ -1186,7 +1239,7 @@ impl ser::SerializeTupleVariant for TupleVariantState {
     }
 
     fn end(self) -> Result<Variable, Error> {
-        let mut object = BTreeMap::new();
+        let mut object = Map::new();
         object.insert(self.name, Rcvar::new(Variable::Array(self.vec)));
         Ok(Variable::Object(object))
     }
@@ -1251,7 +1304,7 @@ impl ser::SerializeStructVariant for StructVariantState {
     }
 
     fn end(self) -> Result<Variable, Error> {
-        let mut object = BTreeMap::new();
+        let mut object = Map::new();
         object.insert(self.name, Rcvar::new(Variable::Object(self.map)));
         Ok(Variable::Object(object))
     }
@@ -1260,9 +1313,8 @@ impl ser::SerializeStructVariant for StructVariantState {
 #[cfg(test)]
 mod tests {
     use ::Rcvar;
-    use std::collections::BTreeMap;
     use super::serde_json::{self, Value};
-    use super::{Variable, JmespathType};
+    use super::{Map, Variable, JmespathType};
     use ast::{Ast, Comparator};
 
     #[test]
@@ -1325,6 +1377,69 @@ mod tests {
         assert_eq!(Some(false), r.compare(&Comparator::LessThanEqual, &l));
     }
 
+    #[test]
+    fn ordering_non_numbers_is_undefined() {
+        let values = vec![Variable::Null,
+                          Variable::Bool(true),
+                          Variable::String("a".to_string()),
+                          Variable::from_json("[1]").unwrap(),
+                          Variable::from_json("{\"a\": 1}").unwrap()];
+        let number = Variable::Number(1.0);
+        let ordering = [Comparator::LessThan,
+                        Comparator::LessThanEqual,
+                        Comparator::GreaterThan,
+                        Comparator::GreaterThanEqual];
+        for value in &values {
+            for cmp in &ordering {
+                assert_eq!(None, value.compare(cmp, value), "{:?} {:?}", value, cmp);
+                assert_eq!(None, value.compare(cmp, &number), "{:?} {:?}", value, cmp);
+                assert_eq!(None, number.compare(cmp, value), "{:?} {:?}", value, cmp);
+            }
+            // Equality is defined for every pair of types.
+            assert_eq!(Some(true), value.compare(&Comparator::Equal, value));
+            assert_eq!(Some(false), value.compare(&Comparator::NotEqual, value));
+            assert_eq!(Some(false), value.compare(&Comparator::Equal, &number));
+            assert_eq!(Some(true), value.compare(&Comparator::NotEqual, &number));
+        }
+    }
+
+    #[test]
+    fn compares_numeric_strings_with_numbers_leniently() {
+        let number = Variable::Number(10.0);
+        let string = |s: &str| Variable::String(s.to_owned());
+        for s in &["42", "-0.5e3", "1E2", "10.5"] {
+            let value = string(s);
+            let expected = s.parse::<f64>().unwrap() > 10.0;
+            assert_eq!(Some(expected),
+                       value.compare_leniently(&Comparator::GreaterThan, &number),
+                       "{}",
+                       s);
+            assert_eq!(Some(!expected),
+                       number.compare_leniently(&Comparator::GreaterThanEqual, &value),
+                       "{}",
+                       s);
+            assert_eq!(None, value.compare(&Comparator::GreaterThan, &number));
+        }
+        assert_eq!(Some(true), string("10").compare_leniently(&Comparator::Equal, &number));
+        assert_eq!(Some(true), number.compare_leniently(&Comparator::Equal, &string("1e1")));
+        // Only strings that are exactly JSON numbers are coerced.
+        for s in &[" 42", "42 ", "0x10", "+42", "042", ".5", "NaN", "inf", "", "a"] {
+            assert_eq!(None,
+                       string(s).compare_leniently(&Comparator::GreaterThan, &number),
+                       "{}",
+                       s);
+            assert_eq!(Some(false),
+                       string(s).compare_leniently(&Comparator::Equal, &number),
+                       "{}",
+                       s);
+        }
+        // Strings are never coerced when compared with each other.
+        assert_eq!(Some(false),
+                   string("10").compare_leniently(&Comparator::Equal, &string("1e1")));
+        assert_eq!(None,
+                   string("10").compare_leniently(&Comparator::GreaterThan, &string("9")));
+    }
+
     #[test]
     fn gets_value_from_object() {
         let var = Variable::from_json("{\"foo\":1}").unwrap();
@@ -1442,8 +1557,8 @@ mod tests {
     #[test]
     fn test_parses_json_object() {
         let var = Variable::from_json("{\"a\": 1, \"b\": {\"c\": true}}").unwrap();
-        let mut expected = BTreeMap::new();
-        let mut sub_obj = BTreeMap::new();
+        let mut expected = Map::new();
+        let mut sub_obj = Map::new();
         expected.insert("a".to_string(), Rcvar::new(Variable::Number(1.0)));
         sub_obj.insert("c".to_string(), Rcvar::new(Variable::Bool(true)));
         expected.insert("b".to_string(), Rcvar::new(Variable::Object(sub_obj)));
@@ -1486,4 +1601,29 @@ mod tests {
         assert!(Variable::Number(0.0000000000000002) == Variable::Number(0.0000000000000002));
         assert!(Variable::Number(0.0000000000000002) != Variable::Number(0.0000000000000003));
     }
+
+    #[test]
+    fn converts_to_compact_json() {
+        let v = Variable::from_json(r#"{"b": [1, 2.5, {"d": null, "c": false}], "a": {}}"#);
+        assert_eq!(r#"{"b":[1,2.5,{"d":null,"c":false}],"a":{}}"#, v.unwrap().to_string());
+        let v = Variable::from_json(r#""caf\u00e9 \"q\" \\ \n\t\u0001 \u2603""#);
+        assert_eq!(r#""café \"q\" \\ \n\t\u0001 ☃""#, v.unwrap().to_string());
+    }
+
+    #[test]
+    fn converts_numbers_to_shortest_json() {
+        assert_eq!("3", Variable::Number(3.0).to_string());
+        assert_eq!("-3", Variable::Number(-3.0).to_string());
+        assert_eq!("0", Variable::Number(-0.0).to_string());
+        assert_eq!("0.1", Variable::Number(0.1).to_string());
+        assert_eq!("9007199254740992", Variable::from_json("9007199254740992").unwrap()
+                       .to_string());
+        assert_eq!("4611686018427387904", Variable::Number(4611686018427387904.0).to_string());
+        assert_eq!("1e+20", Variable::Number(1e20).to_string());
+        assert_eq!("-1e+300", Variable::Number(-1e300).to_string());
+        for n in &[1e20, 1.5e300, 9.3e18, -9.3e18, 0.30000000000000004] {
+            let json = Variable::Number(*n).to_string();
+            assert_eq!(Variable::Number(*n), Variable::from_json(&json).unwrap());
+        }
+    }
 }
@@ -2,15 +2,35 @@
 //!
 //! Test cases are generated using build.rs. This may eventually be exposed
 //! as a library (leading to possibilities like a compliance test runner CLI).
+//!
+//! Each case is also run by the `compliance_summary` test, which loads every
+//! suite in tests/compliance at runtime and prints a summary count. Known
+//! gaps are tracked in `SKIP_LIST` rather than being removed from the suites.
 
 extern crate serde_json;
 extern crate jmespath;
 
 use std::fmt;
+use std::fs::{self, File};
+use std::io::Read;
 use serde_json::Value;
 
 use jmespath::{compile, Variable, Rcvar, RuntimeError, Expression};
 
+/// Compliance cases that are known to fail, listed as tuples of suite
+/// filename, expression, and the reason the case is skipped.
+///
+/// A skipped case that starts passing is reported as a failure so that
+/// the entry gets removed from this list.
+const SKIP_LIST: &'static [(&'static str, &'static str, &'static str)] = &[];
+
+/// Returns the reason a case is skipped, if it is skipped.
+fn skip_reason(suite: &str, expression: &str) -> Option<&'static str> {
+    SKIP_LIST.iter()
+        .find(|&&(s, e, _)| suite.ends_with(s) && e == expression)
+        .map(|&(_, _, reason)| reason)
+}
+
 /// Avaliable benchmark types.
 pub enum BenchType {
     /// The benchmark must only parse an expression.
@@ -58,6 +78,8 @@ pub enum ErrorType {
     InvalidSlice,
     /// Ensures that the expression fails due to an unknown-function error.
     UnknownFunction,
+    /// Ensures that the expression fails due to an undefined-variable error.
+    UndefinedVariable,
     /// Ensures that an expression cannot be parsed due to a syntax error.
     SyntaxError,
 }
@@ -74,6 +96,7 @@ impl ErrorType {
                     "invalid-value" => Ok(ErrorType::InvalidSlice),
                     "invalid-arity" => Ok(ErrorType::InvalidArity),
                     "unknown-function" => Ok(ErrorType::UnknownFunction),
+                    "undefined-variable" => Ok(ErrorType::UndefinedVariable),
                     e @ _ => Err(TestCaseError::UnknownErrorType(e.to_string())),
                 }
             })
@@ -88,6 +111,7 @@ impl fmt::Display for ErrorType {
             &InvalidType => write!(fmt, "invalid-type"),
             &InvalidSlice => write!(fmt, "invalid-value"),
             &UnknownFunction => write!(fmt, "unknown-function"),
+            &UndefinedVariable => write!(fmt, "undefined-variable"),
             &SyntaxError => write!(fmt, "syntax"),
         }
     }
@@ -105,18 +129,23 @@ pub enum Assertion {
 
 impl Assertion {
     /// Runs the assertion of a test case
-    pub fn assert(&self, suite: &str, case: &TestCase, given: Rcvar) -> Result<(), String> {
+    pub fn assert(&self,
+                  suite: &str,
+                  index: usize,
+                  case: &TestCase,
+                  given: Rcvar)
+                  -> Result<(), String> {
         match self {
             &Assertion::Bench(_) => Ok(()),
             &Assertion::ValidResult(ref expected_result) => {
-                let expr = try!(self.try_parse(suite, case));
+                let expr = try!(self.try_parse(suite, index, case));
                 match expr.search(given) {
-                    Err(e) => Err(self.err_message(suite, case, format!("{}", e))),
+                    Err(e) => Err(self.err_message(suite, index, case, format!("{}", e))),
                     Ok(r) => {
                         if *r == **expected_result {
                             Ok(())
                         } else {
-                            Err(self.err_message(suite, case,
+                            Err(self.err_message(suite, index, case,
                                     format!("{:?}, {}", r, expr.as_ast())))
                         }
                     }
@@ -124,43 +153,50 @@ impl Assertion {
             }
             &Assertion::Error(ref error_type) => {
                 use jmespath::ErrorReason::*;
-                let result = self.try_parse(suite, case);
+                let result = self.try_parse(suite, index, case);
                 match error_type {
                     &ErrorType::InvalidArity => {
                         match try!(result).search(given).map_err(|e| e.reason) {
                             Err(Runtime(RuntimeError::NotEnoughArguments { .. })) => Ok(()),
                             Err(Runtime(RuntimeError::TooManyArguments { .. })) => Ok(()),
-                            Err(e) => Err(self.err_message(suite, case, format!("{}", e))),
-                            Ok(r) => Err(self.err_message(suite, case, r.to_string())),
+                            Err(e) => Err(self.err_message(suite, index, case, format!("{}", e))),
+                            Ok(r) => Err(self.err_message(suite, index, case, r.to_string())),
                         }
                     }
                     &ErrorType::InvalidType => {
                         match try!(result).search(given).map_err(|e| e.reason) {
                             Err(Runtime(RuntimeError::InvalidType { .. })) => Ok(()),
                             Err(Runtime(RuntimeError::InvalidReturnType { .. })) => Ok(()),
-                            Err(e) => Err(self.err_message(suite, case, format!("{}", e))),
-                            Ok(r) => Err(self.err_message(suite, case, r.to_string())),
+                            Err(e) => Err(self.err_message(suite, index, case, format!("{}", e))),
+                            Ok(r) => Err(self.err_message(suite, index, case, r.to_string())),
                         }
                     }
                     &ErrorType::InvalidSlice => {
                         match try!(result).search(given).map_err(|e| e.reason) {
                             Err(Runtime(RuntimeError::InvalidSlice)) => Ok(()),
-                            Err(e) => Err(self.err_message(suite, case, format!("{}", e))),
-                            Ok(r) => Err(self.err_message(suite, case, r.to_string())),
+                            Err(e) => Err(self.err_message(suite, index, case, format!("{}", e))),
+                            Ok(r) => Err(self.err_message(suite, index, case, r.to_string())),
                         }
                     }
                     &ErrorType::UnknownFunction => {
                         match try!(result).search(given).map_err(|e| e.reason) {
                             Err(Runtime(RuntimeError::UnknownFunction(_))) => Ok(()),
-                            Err(e) => Err(self.err_message(suite, case, format!("{}", e))),
-                            Ok(r) => Err(self.err_message(suite, case, r.to_string())),
+                            Err(e) => Err(self.err_message(suite, index, case, format!("{}", e))),
+                            Ok(r) => Err(self.err_message(suite, index, case, r.to_string())),
+                        }
+                    }
+                    &ErrorType::UndefinedVariable => {
+                        match try!(result).search(given).map_err(|e| e.reason) {
+                            Err(Runtime(RuntimeError::UndefinedVariable(_))) => Ok(()),
+                            Err(e) => Err(self.err_message(suite, index, case, format!("{}", e))),
+                            Ok(r) => Err(self.err_message(suite, index, case, r.to_string())),
                         }
                     }
                     &ErrorType::SyntaxError => {
                         match result {
                             Err(_) => Ok(()),
                             Ok(expr) => {
-                                Err(self.err_message(suite, case, format!("Parsed {:?}", expr)))
+                                Err(self.err_message(suite, index, case, format!("Parsed {:?}", expr)))
                             }
                         }
                     }
@@ -170,17 +206,23 @@ impl Assertion {
     }
 
     /// Attempts to parse an expression for a case, returning the expression or an error string.
-    fn try_parse(&self, suite: &str, case: &TestCase) -> Result<Expression, String> {
+    fn try_parse(&self,
+                 suite: &str,
+                 index: usize,
+                 case: &TestCase)
+                 -> Result<Expression, String> {
         match compile(&case.expression) {
-            Err(e) => Err(self.err_message(suite, case, format!("{}", e))),
+            Err(e) => Err(self.err_message(suite, index, case, format!("{}", e))),
             Ok(expr) => Ok(expr),
         }
     }
 
     /// Formats an error message for a test case failure.
-    fn err_message(&self, suite: &str, case: &TestCase, message: String) -> String {
-        format!("Test suite: {}\nExpression: {}\nAssertion: {}\nResult: {}\n==============",
+    fn err_message(&self, suite: &str, index: usize, case: &TestCase, message: String) -> String {
+        format!("Test suite: {}\nCase: {}\nExpression: {}\nAssertion: {}\nResult: {}\n\
+                 ==============",
                 suite,
+                index,
                 case.expression,
                 self,
                 message)
@@ -322,9 +364,65 @@ impl TestCase {
     }
 
     /// Perform the test case assertion against a given value.
-    pub fn assert(&self, suite_filename: &str, given: Rcvar) -> Result<(), String> {
-        self.assertion.assert(suite_filename, self, given)
+    ///
+    /// Cases found in the skip list pass as long as they keep failing.
+    pub fn assert(&self, suite_filename: &str, index: usize, given: Rcvar) -> Result<(), String> {
+        let result = self.assertion.assert(suite_filename, index, self, given);
+        match skip_reason(suite_filename, &self.expression) {
+            None => result,
+            Some(_) if result.is_ok() => {
+                Err(format!("Test suite: {}\nCase: {}\nExpression: {}\n\
+                             Case is in the skip list but passes; remove it from SKIP_LIST",
+                            suite_filename,
+                            index,
+                            self.expression))
+            }
+            Some(_) => Ok(()),
+        }
+    }
+}
+
+/// Runs every case of every suite and prints a summary count.
+#[test]
+fn compliance_summary() {
+    let (mut passed, mut skipped, mut benches) = (0, 0, 0);
+    let mut failures = vec![];
+    for entry in fs::read_dir("tests/compliance").expect("Invalid directory: tests/compliance") {
+        let path = entry.expect("Invalid file").path();
+        let filename = path.to_str().expect("Could not to_str file").to_string();
+        let mut file_data = String::new();
+        File::open(&path)
+            .and_then(|mut f| f.read_to_string(&mut file_data))
+            .expect("Unable to read test suite");
+        let suites = serde_json::from_str::<Value>(&file_data).expect("invalid JSON");
+        for suite in suites.as_array().expect("Test suite is not a JSON array") {
+            let suite = TestSuite::from_json(&filename, suite).unwrap();
+            for (index, case) in suite.cases.iter().enumerate() {
+                if let Assertion::Bench(_) = case.assertion {
+                    benches += 1;
+                    continue;
+                }
+                match case.assert(&filename, index, suite.given.clone()) {
+                    Err(e) => failures.push(e),
+                    Ok(_) => {
+                        match skip_reason(&filename, &case.expression) {
+                            Some(reason) => {
+                                println!("skipped {}: {} ({})", filename, case.expression, reason);
+                                skipped += 1;
+                            }
+                            None => passed += 1,
+                        }
+                    }
+                }
+            }
+        }
     }
+    println!("compliance: {} passed, {} failed, {} skipped, {} benchmarks",
+             passed,
+             failures.len(),
+             skipped,
+             benches);
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
 }
 
 include!(concat!(env!("OUT_DIR"), "/compliance_tests.rs"));
@@ -0,0 +1,93 @@
+//! Concurrency tests for expressions and results shared between threads.
+//!
+//! These tests require the `sync` feature: `cargo test --features sync`.
+
+#![cfg(feature = "sync")]
+
+extern crate jmespath;
+
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use jmespath::{compile, Expression, Rcvar, Variable};
+
+const THREADS: usize = 16;
+const SEARCHES: usize = 1_000;
+
+/// Creates a document such as `{"item": {"id": 10}}`.
+fn document(id: usize) -> Variable {
+    Variable::from_json(&format!("{{\"item\": {{\"id\": {}}}}}", id)).unwrap()
+}
+
+fn shared_expression() -> Arc<Expression<'static>> {
+    Arc::new(compile("item.id").unwrap())
+}
+
+#[test]
+fn shares_expression_across_threads() {
+    let expr = shared_expression();
+    let handles = (0..THREADS)
+        .map(|t| {
+            let expr = expr.clone();
+            thread::spawn(move || {
+                for i in 0..SEARCHES {
+                    let id = t * SEARCHES + i;
+                    let result = expr.search(document(id)).unwrap();
+                    assert_eq!(Some(id as f64), result.as_number());
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+#[test]
+fn sends_results_through_channels() {
+    let expr = shared_expression();
+    let (tx, rx) = mpsc::channel::<(usize, Rcvar)>();
+    for t in 0..THREADS {
+        let (expr, tx) = (expr.clone(), tx.clone());
+        thread::spawn(move || {
+            for i in 0..SEARCHES {
+                let id = t * SEARCHES + i;
+                tx.send((id, expr.search(document(id)).unwrap())).unwrap();
+            }
+        });
+    }
+    drop(tx);
+    let mut received = 0;
+    for (id, result) in rx {
+        assert_eq!(Some(id as f64), result.as_number());
+        received += 1;
+    }
+    assert_eq!(THREADS * SEARCHES, received);
+}
+
+#[test]
+fn aggregates_results_behind_a_mutex() {
+    let expr = shared_expression();
+    let results = Arc::new(Mutex::new(Vec::new()));
+    let handles = (0..THREADS)
+        .map(|t| {
+            let (expr, results) = (expr.clone(), results.clone());
+            thread::spawn(move || {
+                for i in 0..SEARCHES {
+                    let result = expr.search(document(t * SEARCHES + i)).unwrap();
+                    results.lock().unwrap().push(result);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    let mut ids = results.lock()
+        .unwrap()
+        .iter()
+        .map(|r| r.as_number().unwrap() as usize)
+        .collect::<Vec<_>>();
+    ids.sort();
+    assert_eq!((0..THREADS * SEARCHES).collect::<Vec<_>>(), ids);
+}
@@ -0,0 +1,71 @@
+//! Tests for the machine-readable function metadata exported by a Runtime.
+
+extern crate serde_json;
+extern crate jmespath;
+
+use std::fs::File;
+use std::io::Read;
+
+use serde_json::Value;
+
+use jmespath::{Runtime, Context, Rcvar, DEFAULT_RUNTIME};
+use jmespath::functions::{CustomFunction, Signature, ArgumentType};
+
+/// Golden file containing the metadata of the builtin functions.
+const GOLDEN_PATH: &'static str = "tests/fixtures/builtin_function_metadata.json";
+
+fn load_golden() -> Value {
+    let mut f = File::open(GOLDEN_PATH).expect("Unable to open golden file");
+    let mut buffer = String::new();
+    f.read_to_string(&mut buffer).expect("Unable to read golden file");
+    serde_json::from_str(&buffer).expect("Invalid golden JSON")
+}
+
+#[test]
+fn builtin_metadata_matches_golden_file() {
+    let actual = serde_json::to_value(&DEFAULT_RUNTIME.metadata()).unwrap();
+    assert_eq!(load_golden(), actual);
+}
+
+#[test]
+fn metadata_is_sorted_by_name() {
+    let names = DEFAULT_RUNTIME.metadata()
+        .into_iter()
+        .map(|m| m.name)
+        .collect::<Vec<_>>();
+    let mut sorted = names.clone();
+    sorted.sort();
+    assert_eq!(sorted, names);
+}
+
+#[test]
+fn includes_custom_function_signatures() {
+    let mut runtime = Runtime::new();
+    runtime.register_function("pad", Box::new(CustomFunction::new(
+        Signature::new(vec![ArgumentType::String],
+                       Some(ArgumentType::TypedArray(Box::new(ArgumentType::Number)))),
+        Box::new(|args: &[Rcvar], _: &mut Context| Ok(args[0].clone())))));
+    let metadata = runtime.metadata();
+    assert_eq!(1, metadata.len());
+    let expected: Value = serde_json::from_str(r#"[{
+        "name": "pad",
+        "signature": {
+            "min_arity": 1,
+            "max_arity": null,
+            "inputs": [{"type": "String", "display": "string"}],
+            "optional": [],
+            "variadic": {"type": {"TypedArray": "Number"}, "display": "array[number]"}
+        }
+    }]"#).unwrap();
+    assert_eq!(expected, serde_json::to_value(&metadata).unwrap());
+}
+
+#[test]
+fn closures_are_reported_without_a_signature() {
+    let mut runtime = Runtime::new();
+    runtime.register_function("identity",
+        Box::new(|args: &[Rcvar], _: &mut Context| Ok(args[0].clone())));
+    let metadata = runtime.metadata();
+    assert_eq!("identity", metadata[0].name);
+    assert!(metadata[0].signature.is_none());
+}
@@ -0,0 +1,139 @@
+//! Argument validation tests generated from function signatures.
+//!
+//! Every function registered in the default runtime that declares a
+//! `Signature` is called with too few arguments, too many arguments, and
+//! with each argument replaced by a value of every type the argument does
+//! not accept. Each call must fail with the matching `RuntimeError`.
+
+extern crate jmespath;
+
+use jmespath::{Expression, JmespathError, ErrorReason, Rcvar, RuntimeError, Variable,
+               DEFAULT_RUNTIME};
+use jmespath::ast::Ast;
+use jmespath::functions::{ArgumentType, Signature};
+
+/// One value of every type, including typed arrays and an expref built
+/// from a trivial AST.
+fn samples() -> Vec<Rcvar> {
+    vec![Variable::Null,
+         Variable::Bool(true),
+         Variable::Number(1.0),
+         Variable::String("a".to_owned()),
+         Variable::from_json("[]").unwrap(),
+         Variable::from_json("[1]").unwrap(),
+         Variable::from_json("[\"a\"]").unwrap(),
+         Variable::from_json("{\"a\": 1}").unwrap(),
+         Variable::Expref(Ast::Identity { offset: 0 })]
+        .into_iter()
+        .map(Rcvar::new)
+        .collect()
+}
+
+/// Returns a sample value accepted by the argument type.
+fn valid_value(argument_type: &ArgumentType) -> Rcvar {
+    samples()
+        .into_iter()
+        .find(|value| argument_type.is_valid(value))
+        .expect(&format!("No sample value is valid for {}", argument_type))
+}
+
+/// Returns the argument type of the given position.
+fn argument_type(signature: &Signature, position: usize) -> &ArgumentType {
+    signature.argument_type(position).unwrap()
+}
+
+/// Calls the function with literal arguments through the interpreter.
+fn call(name: &str, args: Vec<Rcvar>) -> JmespathError {
+    let ast = Ast::Function {
+        offset: 0,
+        name: name.to_owned(),
+        args: args.into_iter().map(|value| Ast::Literal { offset: 0, value: value }).collect(),
+    };
+    let text = ast.to_string();
+    let expr = Expression::new(text.as_str(), ast, &DEFAULT_RUNTIME);
+    match expr.search(()) {
+        Ok(result) => panic!("{} should have failed but returned {}", text, result),
+        Err(e) => e,
+    }
+}
+
+/// Asserts that the error has the expected reason and names the function.
+fn assert_error(name: &str, err: JmespathError, expected: RuntimeError) {
+    assert_eq!(ErrorReason::Runtime(expected), err.reason, "{}", err);
+    assert!(err.to_string().contains(name), "Error does not name {}: {}", name, err);
+}
+
+/// Functions that declare a signature, by name.
+fn signed_functions() -> Vec<(String, Signature)> {
+    DEFAULT_RUNTIME.metadata()
+        .into_iter()
+        .filter_map(|metadata| {
+            let function = DEFAULT_RUNTIME.get_function(&metadata.name).unwrap();
+            function.signature().map(|signature| (metadata.name.clone(), signature.clone()))
+        })
+        .collect()
+}
+
+#[test]
+fn every_builtin_declares_a_signature() {
+    assert_eq!(DEFAULT_RUNTIME.metadata().len(), signed_functions().len());
+}
+
+#[test]
+fn rejects_too_few_arguments() {
+    for (name, signature) in signed_functions() {
+        let min = signature.min_arity();
+        if min == 0 {
+            continue;
+        }
+        let args = (0..min - 1).map(|i| valid_value(argument_type(&signature, i))).collect();
+        let expected = RuntimeError::NotEnoughArguments {
+            expected: min,
+            actual: min - 1,
+        };
+        assert_error(&name, call(&name, args), expected);
+    }
+}
+
+#[test]
+fn rejects_too_many_arguments() {
+    for (name, signature) in signed_functions() {
+        if let Some(max) = signature.max_arity() {
+            let mut args = (0..max).map(|i| valid_value(argument_type(&signature, i))).collect::<Vec<_>>();
+            args.push(Rcvar::new(Variable::Null));
+            let expected = RuntimeError::TooManyArguments {
+                expected: max,
+                actual: max + 1,
+            };
+            assert_error(&name, call(&name, args), expected);
+        }
+    }
+}
+
+#[test]
+fn rejects_arguments_of_the_wrong_type() {
+    for (name, signature) in signed_functions() {
+        // Include every optional argument and one variadic argument so that
+        // their types are checked too.
+        let arity = signature.max_arity()
+            .unwrap_or(signature.min_arity() + signature.optional().len() + 1);
+        let valid = (0..arity).map(|i| valid_value(argument_type(&signature, i))).collect::<Vec<_>>();
+        for position in 0..arity {
+            let argument_type = argument_type(&signature, position);
+            for value in samples().into_iter().filter(|v| !argument_type.is_valid(v)) {
+                let mut args = valid.clone();
+                args[position] = value.clone();
+                let err = call(&name, args);
+                let expected = RuntimeError::InvalidType {
+                    expected: argument_type.to_string(),
+                    actual: value.get_type().to_string(),
+                    position: position,
+                };
+                assert!(err.to_string().contains(&format!("Argument {}", position)),
+                        "Error does not name the argument position: {}",
+                        err);
+                assert_error(&name, err, expected);
+            }
+        }
+    }
+}
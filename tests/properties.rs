@@ -0,0 +1,86 @@
+//! Property-based tests for evaluation invariants.
+//!
+//! Documents and expressions are generated by the strategies in the
+//! `support` module. Generated expressions that fail to parse are discarded.
+
+extern crate jmespath;
+#[macro_use]
+extern crate proptest;
+
+mod support;
+
+use jmespath::{compile, Rcvar, Variable};
+use proptest::prelude::*;
+use proptest::test_runner::{Config, RngAlgorithm, TestRng, TestRunner};
+
+/// Searches `data` with `expression`, rejecting expressions that do not parse.
+fn search(expression: &str, data: &Rcvar) -> Result<Option<Rcvar>, TestCaseError> {
+    let expr = match compile(expression) {
+        Ok(expr) => expr,
+        Err(_) => return Err(TestCaseError::reject("expression does not parse")),
+    };
+    Ok(expr.search(data).ok())
+}
+
+proptest! {
+    #![proptest_config(Config { cases: 512, max_global_rejects: 100_000, .. Config::default() })]
+
+    #[test]
+    fn evaluation_is_deterministic(expr in support::expression(), data in support::document()) {
+        let first = try!(search(&expr, &data));
+        let second = try!(search(&expr, &data));
+        prop_assert_eq!(first, second);
+    }
+
+    #[test]
+    fn type_agrees_with_result(expr in support::expression(), data in support::document()) {
+        if let Some(result) = try!(search(&expr, &data)) {
+            let typed = try!(search(&format!("type({})", expr), &data)).unwrap();
+            prop_assert_eq!(&Variable::String(result.get_type().to_string()), &*typed);
+        }
+    }
+
+    #[test]
+    fn to_array_yields_array(expr in support::expression(), data in support::document()) {
+        if let Some(result) = try!(search(&format!("to_array({})", expr), &data)) {
+            prop_assert!(result.is_array(), "to_array returned {}", result);
+        }
+    }
+
+    #[test]
+    fn reverse_is_an_involution(data in support::document()) {
+        for expr in &["@", "a", "foo.bar"] {
+            let original = try!(search(expr, &data)).unwrap();
+            if original.is_array() || original.is_string() {
+                let twice = try!(search(&format!("reverse(reverse({}))", expr), &data)).unwrap();
+                prop_assert_eq!(original, twice);
+            }
+        }
+    }
+
+    #[test]
+    fn sort_is_idempotent(data in support::sortable_array()) {
+        let once = try!(search("sort(@)", &data)).unwrap();
+        let twice = try!(search("sort(sort(@))", &data)).unwrap();
+        prop_assert_eq!(once, twice);
+    }
+}
+
+/// Runs the evaluation invariants with a fixed seed so that CI runs are
+/// reproducible regardless of the proptest environment.
+#[test]
+fn deterministic_seeded_run() {
+    let config = Config { cases: 256, failure_persistence: None, .. Config::default() };
+    let rng = TestRng::deterministic_rng(RngAlgorithm::ChaCha);
+    let mut runner = TestRunner::new_with_rng(config, rng);
+    runner.run(&(support::expression(), support::document()), |(expr, data)| {
+            let first = try!(search(&expr, &data));
+            prop_assert_eq!(&first, &try!(search(&expr, &data)));
+            if let Some(result) = first {
+                let typed = try!(search(&format!("type({})", expr), &data)).unwrap();
+                prop_assert_eq!(&Variable::String(result.get_type().to_string()), &*typed);
+            }
+            Ok(())
+        })
+        .unwrap();
+}
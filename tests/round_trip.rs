@@ -0,0 +1,252 @@
+//! Round-trips expressions through the AST printer.
+//!
+//! Every expression in the compliance suite, plus a list of expressions
+//! that are tricky to print, is parsed, printed, and parsed again. The two
+//! ASTs must be equal (ignoring offsets) and must produce the same result
+//! when evaluated against the suite's documents. Expressions generated by
+//! the `support` strategies are round-tripped as well.
+
+extern crate jmespath;
+#[macro_use]
+extern crate proptest;
+extern crate serde_json;
+
+mod support;
+
+use std::fs::{self, File};
+use std::io::Read;
+
+use jmespath::{parse, Expression, Rcvar, Variable, DEFAULT_RUNTIME};
+use jmespath::ast::{Ast, Key, KeyValuePair, LetBinding};
+use serde_json::Value;
+
+/// Expressions that exercise quoting, escaping, and parenthesization.
+const TRICKY: &'static [&'static str] = &[
+    r#""foo bar""#,
+    r#""with \"quotes\" and \\ slashes""#,
+    r#""é\t""#,
+    r#"'it\'s'"#,
+    r#"'back\slash'"#,
+    r#"`"json \` backtick"`"#,
+    "`[1, {\"a\": null}]`",
+    "{a: {b: [c, d.e], \"f g\": h}, i: [[j]]}",
+    "[[a, b], [c[0], d[-1]]]",
+    "[(*)]",
+    "foo[-1:-3:-1]",
+    "foo[::-2].bar",
+    "foo[:2][1:]",
+    "(a.b)[0]",
+    "(a || b).c",
+    "a || b && c",
+    "(a || b) && c",
+    "!(a == b)",
+    "!!a",
+    "(!a)[0]",
+    "a[*].b[*].c",
+    "(a[*].b)[*].c",
+    "a[*] | b[*]",
+    "a[] | [0]",
+    "a[][]",
+    "a.*.b.*",
+    "(a.*).b",
+    "a[?b > `1`].c[?d == 'e'] | [0]",
+    "[?a == b][]",
+    "sort_by(@, &a.b)",
+    "map(& &a, @)",
+    "map(&(a || b), c) || d",
+    "a.[b, c][0]",
+    "a.{b: c}.d",
+    "foo[*].[a, b]",
+    "foo[*].*",
+    "foo | length(@) | to_string(@)",
+    "a[?b > $.c].d",
+    "a + b * c - d / e",
+    "(a + b) * (c - d) // e % f",
+    "a - (b - c)",
+    "-a.b * -(c + d)",
+    "a[*].b + `1` > c",
+    "(a[*].b + `1`).c",
+    "-a | b",
+    "let $a = b, $c = d[*].e in $a.f[?g == $c]",
+    "(let $a = b in $a).c",
+    "a || let $a = b in $a | c",
+    "let $a = let $b = c in $b in $a",
+    "foo.let $a = b in $a",
+    "let.in",
+    "sort_by(a, &let $k = b in c[?d == $k])",
+];
+
+/// Returns a copy of the AST with every offset set to 0.
+fn strip(ast: &Ast) -> Ast {
+    let b = |node: &Ast| Box::new(strip(node));
+    match *ast {
+        Ast::Comparison { ref comparator, ref lhs, ref rhs, .. } => {
+            Ast::Comparison { offset: 0, comparator: comparator.clone(), lhs: b(lhs), rhs: b(rhs) }
+        }
+        Ast::Condition { ref predicate, ref then, .. } => {
+            Ast::Condition { offset: 0, predicate: b(predicate), then: b(then) }
+        }
+        Ast::Identity { .. } => Ast::Identity { offset: 0 },
+        Ast::RootNode { .. } => Ast::RootNode { offset: 0 },
+        Ast::Expref { ref ast, .. } => Ast::Expref { offset: 0, ast: b(ast) },
+        Ast::Flatten { ref node, .. } => Ast::Flatten { offset: 0, node: b(node) },
+        Ast::Function { ref name, ref args, .. } => {
+            Ast::Function { offset: 0, name: name.clone(), args: args.iter().map(strip).collect() }
+        }
+        Ast::Field { ref name, .. } => Ast::Field { offset: 0, name: name.clone() },
+        Ast::Index { idx, .. } => Ast::Index { offset: 0, idx: idx },
+        Ast::Literal { ref value, .. } => Ast::Literal { offset: 0, value: value.clone() },
+        Ast::MultiList { ref elements, .. } => {
+            Ast::MultiList { offset: 0, elements: elements.iter().map(strip).collect() }
+        }
+        Ast::MultiHash { ref elements, .. } => {
+            let elements = elements.iter()
+                .map(|kvp| {
+                    let key = match kvp.key {
+                        Key::Name(ref name) => Key::Name(name.clone()),
+                        Key::Expression(ref ast) => Key::Expression(b(ast)),
+                    };
+                    KeyValuePair { key: key, value: strip(&kvp.value) }
+                })
+                .collect();
+            Ast::MultiHash { offset: 0, elements: elements }
+        }
+        Ast::Let { ref bindings, ref expr, .. } => {
+            let bindings = bindings.iter()
+                .map(|b| LetBinding { name: b.name.clone(), value: strip(&b.value) })
+                .collect();
+            Ast::Let { offset: 0, bindings: bindings, expr: b(expr) }
+        }
+        Ast::VarRef { ref name, .. } => Ast::VarRef { offset: 0, name: name.clone() },
+        Ast::Not { ref node, .. } => Ast::Not { offset: 0, node: b(node) },
+        Ast::Negate { ref node, .. } => Ast::Negate { offset: 0, node: b(node) },
+        Ast::Arithmetic { ref operator, ref lhs, ref rhs, .. } => {
+            Ast::Arithmetic { offset: 0, operator: operator.clone(), lhs: b(lhs), rhs: b(rhs) }
+        }
+        Ast::Projection { ref lhs, ref rhs, .. } => {
+            Ast::Projection { offset: 0, lhs: b(lhs), rhs: b(rhs) }
+        }
+        Ast::ObjectValues { ref node, .. } => Ast::ObjectValues { offset: 0, node: b(node) },
+        Ast::And { ref lhs, ref rhs, .. } => Ast::And { offset: 0, lhs: b(lhs), rhs: b(rhs) },
+        Ast::Or { ref lhs, ref rhs, .. } => Ast::Or { offset: 0, lhs: b(lhs), rhs: b(rhs) },
+        Ast::Slice { start, stop, step, .. } => {
+            Ast::Slice { offset: 0, start: start, stop: stop, step: step }
+        }
+        Ast::Subexpr { ref lhs, ref rhs, .. } => {
+            Ast::Subexpr { offset: 0, lhs: b(lhs), rhs: b(rhs) }
+        }
+        Ast::Error { .. } => Ast::Error { offset: 0 },
+    }
+}
+
+/// Shows the lines of the pretty-printed ASTs that differ.
+fn diff(expected: &Ast, actual: &Ast) -> String {
+    let expected = format!("{:#?}", expected);
+    let actual = format!("{:#?}", actual);
+    let (expected, actual) = (expected.lines().collect::<Vec<_>>(),
+                              actual.lines().collect::<Vec<_>>());
+    let mut buffer = String::new();
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) if e == a => buffer.push_str(&format!("  {}\n", e)),
+            (e, a) => {
+                if let Some(e) = e {
+                    buffer.push_str(&format!("- {}\n", e));
+                }
+                if let Some(a) = a {
+                    buffer.push_str(&format!("+ {}\n", a));
+                }
+            }
+        }
+    }
+    buffer
+}
+
+/// Parses, prints, and re-parses an expression, then evaluates both forms
+/// against each of the given documents.
+fn round_trip(expression: &str, documents: &[Rcvar]) -> Result<(), String> {
+    let original = match parse(expression) {
+        Ok(ast) => ast,
+        Err(_) => return Ok(()),
+    };
+    let printed = original.to_string();
+    let reparsed = try!(parse(&printed).map_err(|e| {
+        format!("Original: {}\nPrinted: {}\nFailed to parse printed expression: {}",
+                expression,
+                printed,
+                e)
+    }));
+    if strip(&original) != strip(&reparsed) {
+        return Err(format!("Original: {}\nPrinted: {}\nAST diff:\n{}",
+                           expression,
+                           printed,
+                           diff(&strip(&original), &strip(&reparsed))));
+    }
+    let original = Expression::new(expression, original, &DEFAULT_RUNTIME);
+    let reparsed = Expression::new(printed.as_str(), reparsed, &DEFAULT_RUNTIME);
+    for document in documents {
+        let expected = original.search(document).map_err(|e| e.reason.to_string());
+        let actual = reparsed.search(document).map_err(|e| e.reason.to_string());
+        if expected != actual {
+            return Err(format!("Original: {}\nPrinted: {}\nGiven: {}\nExpected: {:?}\n\
+                                Actual: {:?}",
+                               expression,
+                               printed,
+                               document,
+                               expected,
+                               actual));
+        }
+    }
+    Ok(())
+}
+
+/// Loads every (expression, given) pair from the compliance suites.
+fn compliance_cases() -> Vec<(String, Rcvar)> {
+    let mut cases = vec![];
+    for entry in fs::read_dir("tests/compliance").expect("Invalid directory: tests/compliance") {
+        let path = entry.expect("Invalid file").path();
+        let mut file_data = String::new();
+        File::open(&path)
+            .and_then(|mut f| f.read_to_string(&mut file_data))
+            .expect("Unable to read test suite");
+        let suites = serde_json::from_str::<Value>(&file_data).expect("invalid JSON");
+        for suite in suites.as_array().expect("Test suite is not a JSON array") {
+            let given = Rcvar::new(Variable::from(suite["given"].clone()));
+            for case in suite["cases"].as_array().expect("cases is not an array") {
+                let expression = case["expression"].as_str().expect("expression is not a string");
+                cases.push((expression.to_owned(), given.clone()));
+            }
+        }
+    }
+    cases
+}
+
+#[test]
+fn round_trips_compliance_expressions() {
+    let failures = compliance_cases()
+        .into_iter()
+        .filter_map(|(expression, given)| round_trip(&expression, &[given]).err())
+        .collect::<Vec<_>>();
+    assert!(failures.is_empty(), "{}", failures.join("\n==============\n"));
+}
+
+#[test]
+fn round_trips_tricky_expressions() {
+    let documents = compliance_cases().into_iter().map(|(_, given)| given).collect::<Vec<_>>();
+    for expression in TRICKY {
+        assert!(parse(expression).is_ok(), "Does not parse: {}", expression);
+        if let Err(e) = round_trip(expression, &documents[..20]) {
+            panic!("{}", e);
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn round_trips_generated_expressions(expr in support::expression(),
+                                         data in support::document()) {
+        if let Err(e) = round_trip(&expr, &[data]) {
+            panic!("{}", e);
+        }
+    }
+}
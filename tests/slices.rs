@@ -0,0 +1,205 @@
+//! Slice semantics tests.
+//!
+//! Slices follow the semantics of Python slices, which the JMESPath
+//! reference implementation uses. Expected results are computed by `model`,
+//! a direct transcription of CPython's `PySlice_AdjustIndices`, which is
+//! itself checked against hand-verified Python results below.
+
+extern crate jmespath;
+extern crate serde_json;
+
+use std::fs::File;
+use std::io::Read;
+
+use jmespath::{compile, parse, ErrorReason, Rcvar, RuntimeError, Variable};
+use jmespath::ast::Ast;
+use serde_json::Value;
+
+/// Returns the indices selected by slicing an array of `len` elements, or
+/// None if the step is 0.
+fn model(len: i64, start: Option<i64>, stop: Option<i64>, step: Option<i64>) -> Option<Vec<i64>> {
+    let step = step.unwrap_or(1);
+    if step == 0 {
+        return None;
+    }
+    // Bounds that start and stop are clamped to.
+    let (lower, upper) = if step < 0 { (-1, len - 1) } else { (0, len) };
+    let adjust = |index: Option<i64>, default: i64| match index {
+        None => default,
+        Some(i) if i < 0 => (i + len).max(lower),
+        Some(i) => i.min(upper),
+    };
+    let start = adjust(start, if step < 0 { upper } else { lower });
+    let stop = adjust(stop, if step < 0 { lower } else { upper });
+    let mut indices = vec![];
+    let mut i = start;
+    while (step > 0 && i < stop) || (step < 0 && i > stop) {
+        indices.push(i);
+        i += step;
+    }
+    Some(indices)
+}
+
+/// Formats a slice expression, leaving out missing parts.
+fn slice_expression(start: Option<i64>, stop: Option<i64>, step: Option<i64>) -> String {
+    let show = |v: Option<i64>| v.map(|v| v.to_string()).unwrap_or_default();
+    match step {
+        Some(step) => format!("[{}:{}:{}]", show(start), show(stop), step),
+        None => format!("[{}:{}]", show(start), show(stop)),
+    }
+}
+
+/// Creates the array `[0, 1, ..., len - 1]`.
+fn array(len: i64) -> Rcvar {
+    Rcvar::new(Variable::Array((0..len).map(|i| Rcvar::new(Variable::Number(i as f64))).collect()))
+}
+
+#[test]
+fn model_matches_python() {
+    // Results verified by running `list(range(n))[start:stop:step]` in Python.
+    let cases: Vec<(i64, Option<i64>, Option<i64>, Option<i64>, Vec<i64>)> = vec![
+        (5, None, None, None, vec![0, 1, 2, 3, 4]),
+        (5, Some(1), Some(3), None, vec![1, 2]),
+        (5, None, None, Some(-1), vec![4, 3, 2, 1, 0]),
+        (5, Some(-2), None, None, vec![3, 4]),
+        (5, None, Some(-2), None, vec![0, 1, 2]),
+        (5, Some(10), None, None, vec![]),
+        (5, Some(-10), Some(2), None, vec![0, 1]),
+        (5, Some(4), Some(1), Some(-1), vec![4, 3, 2]),
+        (5, None, None, Some(2), vec![0, 2, 4]),
+        (5, None, None, Some(-2), vec![4, 2, 0]),
+        (5, Some(-1), Some(-6), Some(-1), vec![4, 3, 2, 1, 0]),
+        (5, Some(-10), None, Some(-1), vec![]),
+        (5, Some(10), None, Some(-2), vec![4, 2, 0]),
+        (5, Some(1), Some(1), None, vec![]),
+        (5, Some(3), Some(-10), Some(-3), vec![3, 0]),
+        (0, None, None, Some(-1), vec![]),
+        (1, Some(-1), None, Some(-3), vec![0]),
+    ];
+    for (len, start, stop, step, expected) in cases {
+        assert_eq!(Some(expected),
+                   model(len, start, stop, step),
+                   "len {} slice {}",
+                   len,
+                   slice_expression(start, stop, step));
+    }
+    assert_eq!(None, model(3, None, None, Some(0)));
+}
+
+#[test]
+fn sweeps_start_stop_and_step() {
+    let bounds = || Some(None).into_iter().chain((-6..7).map(Some));
+    let steps = Some(None).into_iter().chain((-3..4).map(Some));
+    for step in steps {
+        for start in bounds() {
+            for stop in bounds() {
+                let expression = slice_expression(start, stop, step);
+                let expr = compile(&expression).unwrap();
+                for len in 0..6 {
+                    let result = expr.search(array(len));
+                    match model(len, start, stop, step) {
+                        None => {
+                            assert_eq!(ErrorReason::Runtime(RuntimeError::InvalidSlice),
+                                       result.unwrap_err().reason)
+                        }
+                        Some(indices) => {
+                            let expected = Variable::Array(indices.into_iter()
+                                .map(|i| Rcvar::new(Variable::Number(i as f64)))
+                                .collect());
+                            assert_eq!(expected,
+                                       *result.unwrap(),
+                                       "{} on array of length {}",
+                                       expression,
+                                       len);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn slicing_non_arrays_returns_null() {
+    for json in &["null", "true", "1", "\"abc\"", "{\"a\": 1}"] {
+        let data = Variable::from_json(json).unwrap();
+        assert!(compile("[1:]").unwrap().search(data).unwrap().is_null(), "{}", json);
+    }
+}
+
+/// Returns the field name and slice of plain field slices such as `foo[1:9]`.
+fn field_slice(ast: Ast) -> Option<(String, Option<i64>, Option<i64>, i64)> {
+    if let Ast::Subexpr { lhs, rhs, .. } = ast {
+        if let (Ast::Field { name, .. }, Ast::Projection { lhs: slice, rhs: identity, .. }) =
+               (*lhs, *rhs) {
+            if let (Ast::Slice { start, stop, step, .. }, Ast::Identity { .. }) = (*slice,
+                                                                                *identity) {
+                return Some((name, start, stop, step));
+            }
+        }
+    }
+    None
+}
+
+#[test]
+fn model_agrees_with_compliance_slices() {
+    let mut data = String::new();
+    File::open("tests/compliance/slice.json")
+        .and_then(|mut f| f.read_to_string(&mut data))
+        .expect("Unable to read slice suite");
+    let suites: Value = serde_json::from_str(&data).unwrap();
+    let mut checked = 0;
+    for suite in suites.as_array().unwrap() {
+        for case in suite["cases"].as_array().unwrap() {
+            let expression = case["expression"].as_str().unwrap();
+            let (field, start, stop, step) = match parse(expression).ok().and_then(field_slice) {
+                Some(slice) => slice,
+                None => continue,
+            };
+            let given = &suite["given"][&field];
+            let len = match given.as_array() {
+                Some(values) => values.len() as i64,
+                None => continue,
+            };
+            let indices = model(len, start, stop, Some(step));
+            let expected = indices.map(|indices| {
+                indices.into_iter().map(|i| given[i as usize].clone()).collect::<Vec<_>>()
+            });
+            match expected {
+                Some(values) => assert_eq!(Value::Array(values), case["result"], "{}", expression),
+                None => assert_eq!("invalid-value", case["error"], "{}", expression),
+            }
+            checked += 1;
+        }
+    }
+    assert!(checked > 20, "Only checked {} compliance slices", checked);
+}
+
+#[test]
+fn evaluates_stepped_slices_of_fields() {
+    let data = Variable::from_json(r#"{"foo": [0, 1, 2, 3, 4, 5, 6, 7, 8, 9]}"#).unwrap();
+    let cases = vec![("foo[::2]", "[0, 2, 4, 6, 8]"),
+                     ("foo[::-1]", "[9, 8, 7, 6, 5, 4, 3, 2, 1, 0]"),
+                     ("foo[5:1:-2]", "[5, 3]"),
+                     ("foo[-3::-3]", "[7, 4, 1]"),
+                     ("foo[1:-1:4]", "[1, 5]"),
+                     ("foo[20:-20:-4]", "[9, 5, 1]"),
+                     ("foo[-20:20:5]", "[0, 5]"),
+                     ("foo[2:5:-1]", "[]"),
+                     ("foo[::-1] | [0]", "9")];
+    for (expression, expected) in cases {
+        let result = compile(expression).unwrap().search(data.clone()).unwrap();
+        assert_eq!(Variable::from_json(expected).unwrap(), *result, "{}", expression);
+    }
+    // Reversing produces a new array rather than changing the input.
+    assert_eq!(Variable::from_json("[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]").unwrap(),
+               *compile("foo").unwrap().search(data).unwrap());
+}
+
+#[test]
+fn a_step_of_zero_is_an_error_at_the_slice() {
+    let data = Variable::from_json(r#"{"foo": [0, 1, 2]}"#).unwrap();
+    let err = compile("foo[8:2:0]").unwrap().search(data).unwrap_err();
+    assert_eq!(ErrorReason::Runtime(RuntimeError::InvalidSlice), err.reason);
+    assert_eq!(3, err.offset);
+}
@@ -0,0 +1,116 @@
+//! Shared proptest generators for JMESPath documents and expressions.
+//!
+//! Include this module from an integration test with `mod support;`.
+//! Documents and expressions are bounded in depth and size so that
+//! failures shrink to small, readable cases.
+
+#![allow(dead_code)]
+
+use jmespath::{Rcvar, Variable};
+use proptest::prelude::*;
+
+/// Field names used by both the document and expression generators so that
+/// generated paths regularly hit generated keys.
+pub const FIELDS: &'static [&'static str] = &["a", "b", "c", "foo", "bar"];
+
+/// Functions that accept any value and are safe to wrap around any expression.
+const ANY_FUNCTIONS: &'static [&'static str] = &["type", "to_array", "to_string", "not_null"];
+
+/// Comparators usable in filter expressions.
+const COMPARATORS: &'static [&'static str] = &["==", "!=", "<", "<=", ">", ">="];
+
+/// Generates a field name from `FIELDS`.
+pub fn field() -> BoxedStrategy<String> {
+    prop::sample::select(FIELDS).prop_map(|f| f.to_owned()).boxed()
+}
+
+/// Generates a scalar: null, boolean, small integer-valued number, or string.
+pub fn scalar() -> BoxedStrategy<Variable> {
+    prop_oneof![
+        Just(Variable::Null),
+        any::<bool>().prop_map(Variable::Bool),
+        (-100i32..100).prop_map(|n| Variable::Number(n as f64)),
+        "[a-z]{0,4}".prop_map(Variable::String),
+    ].boxed()
+}
+
+/// Generates a document nested at most `depth` levels with at most `width`
+/// entries per array or object.
+pub fn document_with(depth: u32, width: usize) -> BoxedStrategy<Rcvar> {
+    scalar()
+        .prop_map(Rcvar::new)
+        .prop_recursive(depth, (width * depth as usize) as u32, width as u32, move |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..width)
+                    .prop_map(|v| Rcvar::new(Variable::Array(v))),
+                prop::collection::btree_map(field(), inner, 0..width)
+                    .prop_map(|m| Rcvar::new(Variable::Object(m.into_iter().collect()))),
+            ]
+        })
+        .boxed()
+}
+
+/// Generates a document with the default bounds.
+pub fn document() -> BoxedStrategy<Rcvar> {
+    document_with(4, 5)
+}
+
+/// Generates an array of numbers or an array of strings, the inputs that
+/// `sort` accepts.
+pub fn sortable_array() -> BoxedStrategy<Rcvar> {
+    prop_oneof![
+        prop::collection::vec((-100i32..100).prop_map(|n| Rcvar::new(Variable::Number(n as f64))),
+                              0..8),
+        prop::collection::vec("[a-z]{0,3}".prop_map(|s| Rcvar::new(Variable::String(s))), 0..8),
+    ].prop_map(|v| Rcvar::new(Variable::Array(v)))
+        .boxed()
+}
+
+/// Generates a JSON literal expression such as `` `1` `` or `` `"a"` ``.
+fn literal() -> BoxedStrategy<String> {
+    prop_oneof![
+        (-10i32..10).prop_map(|n| format!("`{}`", n)),
+        prop::sample::select(FIELDS).prop_map(|f| format!("'{}'", f)),
+        Just("`null`".to_owned()),
+        Just("`true`".to_owned()),
+    ].boxed()
+}
+
+/// Generates an index or slice bracket expression.
+fn bracket() -> BoxedStrategy<String> {
+    let bound = || prop::option::of(-4i32..4);
+    prop_oneof![
+        (-4i32..4).prop_map(|i| format!("[{}]", i)),
+        (bound(), bound(), prop::option::of(prop_oneof![-3i32..-1, 1i32..3])).prop_map(|(a, b, s)| {
+            let show = |v: Option<i32>| v.map(|v| v.to_string()).unwrap_or_default();
+            match s {
+                Some(s) => format!("[{}:{}:{}]", show(a), show(b), s),
+                None => format!("[{}:{}]", show(a), show(b)),
+            }
+        }),
+        Just("[*]".to_owned()),
+        Just("[]".to_owned()),
+    ].boxed()
+}
+
+/// Generates an expression from a restricted grammar: field paths, indexes,
+/// slices, projections, `||`, filters with comparators, and a few functions.
+pub fn expression() -> BoxedStrategy<String> {
+    let leaf = prop_oneof![
+        field(),
+        Just("@".to_owned()),
+        literal(),
+    ];
+    leaf.prop_recursive(4, 24, 3, |inner| {
+        prop_oneof![
+            (inner.clone(), field()).prop_map(|(l, r)| format!("{}.{}", l, r)),
+            (inner.clone(), bracket()).prop_map(|(l, b)| format!("{}{}", l, b)),
+            (inner.clone(), inner.clone()).prop_map(|(l, r)| format!("({}) || ({})", l, r)),
+            (inner.clone(), prop::sample::select(COMPARATORS), literal())
+                .prop_map(|(l, op, r)| format!("[?{} {} {}]", l, op, r)),
+            (prop::sample::select(ANY_FUNCTIONS), inner.clone())
+                .prop_map(|(f, arg)| format!("{}({})", f, arg)),
+            inner.prop_map(|e| format!("[{}]", e)),
+        ]
+    }).boxed()
+}